@@ -1,6 +1,13 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use minlz::minlz::{compress_level, decompress, Level};
-use minlz::{decode, encode, encode_best, encode_better, Encoder};
+use minlz::{
+    decode, encode, encode_best, encode_better, encode_better_with_options, encode_with_options,
+    BetterOptions, EncodeOptions, Encoder, HashKind, Reader, Writer, WriterPool,
+};
+use std::io::{Read, Write};
+
+#[cfg(feature = "concurrent")]
+use minlz::{ConcurrentWriter, PipelinedWriter};
 
 fn generate_test_data(size: usize, pattern: &str) -> Vec<u8> {
     match pattern {
@@ -31,6 +38,44 @@ fn bench_encode_standard(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_encode_hash(c: &mut Criterion) {
+    // Fixed 16-byte records: a running offset, a constant tag, a small
+    // value and a constant float.
+    let mut rng = 1u32;
+    let mut structured = Vec::with_capacity(128 * 1024);
+    for i in 0..8192u32 {
+        rng ^= rng << 13;
+        rng ^= rng >> 17;
+        rng ^= rng << 5;
+        structured.extend_from_slice(&(i * 16).to_le_bytes());
+        structured.extend_from_slice(&1u32.to_le_bytes());
+        structured.extend_from_slice(&(rng % 1000).to_le_bytes());
+        structured.extend_from_slice(&1.0f32.to_le_bytes());
+    }
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt").to_vec();
+
+    let mut group = c.benchmark_group("encode_hash");
+    for (pattern, data) in [("text", &text), ("structured", &structured)] {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        for (name, hash) in [
+            ("default", None),
+            ("hash4", Some(HashKind::Hash4)),
+            ("hash6", Some(HashKind::Hash6)),
+        ] {
+            let opts = EncodeOptions {
+                hash,
+                ..Default::default()
+            };
+            let ratio = encode_with_options(data, &opts).len() as f64 / data.len() as f64;
+            eprintln!("encode_hash/{}/{}: ratio {:.3}", name, pattern, ratio);
+            group.bench_with_input(BenchmarkId::new(name, pattern), data, |b, data| {
+                b.iter(|| encode_with_options(black_box(data), &opts));
+            });
+        }
+    }
+    group.finish();
+}
+
 fn bench_encode_better(c: &mut Criterion) {
     let mut group = c.benchmark_group("encode_better");
 
@@ -47,6 +92,44 @@ fn bench_encode_better(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_encode_better_stride(c: &mut Criterion) {
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt").to_vec();
+    // 1MB of the same words in pseudo-random order, so blocks are large
+    // enough for the full-size tables and matches stay short
+    let words: Vec<&[u8]> = text.split_inclusive(|&b| b == b' ').collect();
+    let mut rng = 1u32;
+    let mut shuffled = Vec::with_capacity(1 << 20);
+    while shuffled.len() < 1 << 20 {
+        rng ^= rng << 13;
+        rng ^= rng >> 17;
+        rng ^= rng << 5;
+        shuffled.extend_from_slice(words[rng as usize % words.len()]);
+    }
+
+    let mut group = c.benchmark_group("encode_better_stride");
+    for (pattern, data) in [("text", &text), ("words", &shuffled)] {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        for stride in [1, 2, 3, 4, 8] {
+            let opts = BetterOptions {
+                mid_index_stride: stride,
+            };
+            let ratio = encode_better_with_options(data, &opts).len() as f64 / data.len() as f64;
+            eprintln!(
+                "encode_better_stride/{}/{}: ratio {:.4}",
+                stride, pattern, ratio
+            );
+            group.bench_with_input(
+                BenchmarkId::new(stride.to_string(), pattern),
+                data,
+                |b, data| {
+                    b.iter(|| encode_better_with_options(black_box(data), &opts));
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
 fn bench_encode_best(c: &mut Criterion) {
     let mut group = c.benchmark_group("encode_best");
 
@@ -103,6 +186,196 @@ fn bench_roundtrip(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_stream_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stream_write");
+    group.sample_size(10);
+
+    let size = 16 << 20;
+    let data = generate_test_data(size, "text");
+    group.throughput(Throughput::Bytes(size as u64));
+
+    group.bench_function("writer", |b| {
+        b.iter(|| {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = Writer::new(&mut compressed);
+                writer.write_all(black_box(&data)).unwrap();
+            }
+            compressed
+        });
+    });
+    #[cfg(feature = "concurrent")]
+    group.bench_function("concurrent_1", |b| {
+        b.iter(|| {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = ConcurrentWriter::new(&mut compressed, 1);
+                writer.write_all(black_box(&data)).unwrap();
+            }
+            compressed
+        });
+    });
+    group.finish();
+}
+
+fn bench_stream_write_pipelined(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stream_write_pipelined");
+    group.sample_size(10);
+
+    let size = 100 << 20;
+    let data = generate_test_data(size, "text");
+    group.throughput(Throughput::Bytes(size as u64));
+
+    // Feed the writers in 64KB pieces, as from a stream source
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = Writer::new(&mut compressed);
+                for chunk in black_box(&data).chunks(64 << 10) {
+                    writer.write_all(chunk).unwrap();
+                }
+            }
+            compressed
+        });
+    });
+    #[cfg(feature = "concurrent")]
+    group.bench_function("double", |b| {
+        b.iter(|| {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = PipelinedWriter::new(&mut compressed);
+                for chunk in black_box(&data).chunks(64 << 10) {
+                    writer.write_all(chunk).unwrap();
+                }
+            }
+            compressed
+        });
+    });
+    group.finish();
+}
+
+fn bench_writer_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("writer_pool");
+    group.sample_size(10);
+
+    let responses: Vec<Vec<u8>> = (0..10_000)
+        .map(|i| generate_test_data(512 + i % 2048, "text"))
+        .collect();
+    let total: usize = responses.iter().map(|r| r.len()).sum();
+    group.throughput(Throughput::Bytes(total as u64));
+
+    group.bench_function("new_writer", |b| {
+        b.iter(|| {
+            let mut size = 0;
+            for response in &responses {
+                let mut compressed = Vec::new();
+                let mut writer = Writer::new(&mut compressed);
+                writer.write_all(black_box(response)).unwrap();
+                drop(writer);
+                size += compressed.len();
+            }
+            size
+        });
+    });
+    group.bench_function("pooled", |b| {
+        let pool = WriterPool::new();
+        b.iter(|| {
+            let mut size = 0;
+            for response in &responses {
+                let mut compressed = Vec::new();
+                let mut writer = pool.get(&mut compressed);
+                writer.write_all(black_box(response)).unwrap();
+                writer.finish().unwrap();
+                size += compressed.len();
+            }
+            size
+        });
+    });
+    group.finish();
+}
+
+fn bench_stream_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stream_read");
+    group.sample_size(10);
+
+    let size = 100 << 20;
+    let data = generate_test_data(size, "text");
+    let mut compressed = Vec::new();
+    {
+        let mut writer = Writer::with_block_size(&mut compressed, 1 << 20);
+        writer.write_all(&data).unwrap();
+    }
+
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(size);
+            Reader::new(black_box(&compressed[..]))
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        });
+    });
+    #[cfg(feature = "concurrent")]
+    group.bench_function("parallel", |b| {
+        let concurrency = std::thread::available_parallelism().map_or(4, |n| n.get());
+        b.iter(|| {
+            let mut out = Vec::with_capacity(size);
+            Reader::with_concurrency(black_box(&compressed[..]), concurrency)
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        });
+    });
+    group.finish();
+}
+
+fn bench_stream_read_file(c: &mut Criterion) {
+    // Reading straight from an unbuffered File: without a read buffer
+    // every chunk costs several small read syscalls.
+    let mut group = c.benchmark_group("stream_read_file");
+    group.sample_size(10);
+
+    let size = 16 << 20;
+    let data = generate_test_data(size, "text");
+    for block_size in [4 << 10, 64 << 10] {
+        let path = std::env::temp_dir().join(format!(
+            "minlz-bench-{}-{}.s2",
+            std::process::id(),
+            block_size
+        ));
+        {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut writer = Writer::with_block_size(file, block_size);
+            writer.write_all(&data).unwrap();
+        }
+
+        group.throughput(Throughput::Bytes(size as u64));
+        for read_buffer in [0, 64 << 10] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("read_buffer_{}", read_buffer), block_size),
+                &read_buffer,
+                |b, &read_buffer| {
+                    b.iter(|| {
+                        let file = std::fs::File::open(&path).unwrap();
+                        let mut reader = if read_buffer == 0 {
+                            Reader::new(file)
+                        } else {
+                            Reader::with_read_buffer(file, read_buffer)
+                        };
+                        let mut out = Vec::with_capacity(size);
+                        reader.read_to_end(&mut out).unwrap();
+                        out
+                    });
+                },
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
 fn bench_encoder_reused(c: &mut Criterion) {
     // Compare stateful Encoder against the free function on the same
     // patterns, capturing the buffer-reuse win on hot loops.
@@ -183,10 +456,17 @@ fn bench_minlz(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_encode_standard,
+    bench_encode_hash,
     bench_encode_better,
+    bench_encode_better_stride,
     bench_encode_best,
     bench_decode,
     bench_roundtrip,
+    bench_stream_read,
+    bench_stream_read_file,
+    bench_stream_write,
+    bench_stream_write_pipelined,
+    bench_writer_pool,
     bench_encoder_reused,
     bench_minlz,
 );
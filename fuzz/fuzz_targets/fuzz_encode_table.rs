@@ -0,0 +1,49 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minlz::{decode, encode_block_with_table, max_encoded_len};
+
+const TABLE_LENS: [usize; 4] = [0x100, 0x400, 0x1000, 0x4000];
+
+fuzz_target!(|data: &[u8]| {
+    // First byte picks the table width, the next four seed its contents.
+    if data.len() < 5 || data.len() > 1_000_000 {
+        return;
+    }
+    let table_len = TABLE_LENS[(data[0] & 3) as usize];
+    let mut seed = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    let src = &data[5..];
+
+    // Arbitrary (possibly out-of-range) positions must never produce
+    // a bad match.
+    let mut table: Vec<u32> = (0..table_len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed % (src.len() as u32 + 16)
+        })
+        .collect();
+
+    let Ok(max_len) = max_encoded_len(src.len()) else {
+        return;
+    };
+    let mut body = vec![0u8; max_len];
+    let n = encode_block_with_table(&mut body, src, &mut table);
+    if n == 0 {
+        return;
+    }
+
+    // The output is the block body only; prepend the length varint.
+    let mut block = Vec::with_capacity(n + 5);
+    let mut len = src.len() as u64;
+    while len >= 0x80 {
+        block.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    block.push(len as u8);
+    block.extend_from_slice(&body[..n]);
+
+    let decoded = decode(&block).expect("table-seeded block failed to decode");
+    assert_eq!(src, &decoded[..], "table-seeded roundtrip mismatch");
+});
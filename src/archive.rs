@@ -0,0 +1,326 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Named entries in a single S2 stream
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::constants::*;
+use crate::reader::Reader;
+use crate::writer::Writer;
+
+/// Longest entry name, in bytes
+const MAX_NAME_LEN: usize = u16::MAX as usize;
+
+/// ArchiveWriter stores several named buffers in one S2 stream
+///
+/// Each entry is written as a skippable chunk holding its name and length,
+/// followed by its data in blocks of its own, so entries never share a
+/// block. [`ArchiveReader`] lists the entries back; a plain [`Reader`]
+/// skips the entry chunks and returns all entries' data concatenated.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{ArchiveReader, ArchiveWriter};
+/// use std::io::Read;
+///
+/// let mut stream = Vec::new();
+/// let mut writer = ArchiveWriter::new(&mut stream);
+/// writer.add_entry("hello.txt", b"Hello, World!").unwrap();
+/// writer.add_entry("empty", b"").unwrap();
+/// writer.finish().unwrap();
+///
+/// let mut reader = ArchiveReader::new(&stream[..]);
+/// let entry = reader.next_entry().unwrap().unwrap();
+/// assert_eq!(entry.name, "hello.txt");
+/// let mut data = Vec::new();
+/// reader.read_to_end(&mut data).unwrap();
+/// assert_eq!(data, b"Hello, World!");
+/// assert_eq!(reader.next_entry().unwrap().unwrap().len, 0);
+/// assert!(reader.next_entry().unwrap().is_none());
+/// ```
+pub struct ArchiveWriter<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Create a new ArchiveWriter with the default block size (1MB)
+    pub fn new(writer: W) -> Self {
+        ArchiveWriter {
+            writer: Writer::new(writer),
+        }
+    }
+
+    /// Create a new ArchiveWriter with a specific block size
+    ///
+    /// Block size is clamped to 4KB..=4MB, as for [`Writer::with_block_size`].
+    pub fn with_block_size(writer: W, block_size: usize) -> Self {
+        ArchiveWriter {
+            writer: Writer::with_block_size(writer, block_size),
+        }
+    }
+
+    /// Append an entry named `name` holding `data`
+    ///
+    /// Names are free-form UTF-8 of at most 65535 bytes; they need not be
+    /// unique.
+    pub fn add_entry(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        if name.len() > MAX_NAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("entry name too long ({} bytes)", name.len()),
+            ));
+        }
+        let mut body = Vec::with_capacity(ARCHIVE_MAGIC.len() + 8 + name.len());
+        body.extend_from_slice(ARCHIVE_MAGIC);
+        body.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        body.extend_from_slice(name.as_bytes());
+        self.writer
+            .write_skippable_chunk(CHUNK_TYPE_ARCHIVE_ENTRY, &body)?;
+        self.writer.write_all(data)?;
+        self.writer.flush_block()
+    }
+
+    /// Finish the stream, reporting any error
+    ///
+    /// Dropping the ArchiveWriter does the same but ignores errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.close()
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+}
+
+/// An entry of an archive, as returned by [`ArchiveReader::next_entry`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Name given to [`ArchiveWriter::add_entry`]
+    pub name: String,
+    /// Length of the entry's data
+    pub len: u64,
+}
+
+/// ArchiveReader lists the entries of a stream written by [`ArchiveWriter`]
+///
+/// [`next_entry`](ArchiveReader::next_entry) moves to the next entry, and
+/// reading then returns that entry's data until its end. Data left unread
+/// is skipped by the next call. Data not preceded by any entry (as in a
+/// stream written with [`Writer`]) is skipped too.
+pub struct ArchiveReader<R: Read> {
+    reader: Reader<R>,
+    // Entry headers read and not yet taken, filled by the reader's
+    // skippable chunk callback
+    entries: Arc<Mutex<VecDeque<ArchiveEntry>>>,
+    // Data left in the current entry
+    remaining: u64,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Create a new ArchiveReader
+    pub fn new(reader: R) -> Self {
+        let mut reader = Reader::new(reader);
+        let entries = Arc::new(Mutex::new(VecDeque::new()));
+        let queue = entries.clone();
+        reader.on_skippable_chunk(move |chunk_type, payload| {
+            if chunk_type == CHUNK_TYPE_ARCHIVE_ENTRY {
+                if let Some(entry) = parse_entry(payload) {
+                    queue.lock().unwrap().push_back(entry);
+                }
+            }
+        });
+        ArchiveReader {
+            reader,
+            entries,
+            remaining: 0,
+        }
+    }
+
+    /// Take the oldest entry header read so far
+    fn take_entry(&self) -> Option<ArchiveEntry> {
+        self.entries.lock().unwrap().pop_front()
+    }
+
+    /// Whether an entry header has been read and not taken
+    fn has_entry(&self) -> bool {
+        !self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Move to the next entry
+    ///
+    /// Returns `None` at the end of the stream.
+    pub fn next_entry(&mut self) -> io::Result<Option<ArchiveEntry>> {
+        let rest = self.remaining;
+        self.remaining = 0;
+        let skipped = io::copy(&mut (&mut self.reader).take(rest), &mut io::sink())?;
+        if skipped < rest {
+            return Err(truncated_entry());
+        }
+
+        loop {
+            if let Some(entry) = self.take_entry() {
+                self.remaining = entry.len;
+                return Ok(Some(entry));
+            }
+            // An empty read loads the next data chunk, along with the entry
+            // headers before it, without consuming any data
+            let _ = self.reader.read(&mut [])?;
+            if self.has_entry() {
+                continue;
+            }
+            // Data outside any entry
+            let stray = self.reader.buffered_len() as u64;
+            if stray == 0 {
+                return Ok(None);
+            }
+            io::copy(&mut (&mut self.reader).take(stray), &mut io::sink())?;
+        }
+    }
+
+    /// Get a reference to the underlying [`Reader`]
+    pub fn get_ref(&self) -> &Reader<R> {
+        &self.reader
+    }
+}
+
+impl<R: Read> Read for ArchiveReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(truncated_entry());
+        }
+        if self.has_entry() {
+            // The read went past the next entry header
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "archive entry shorter than its recorded length",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Parse the payload of an entry header chunk; chunks without our magic
+/// and a UTF-8 name are left alone
+fn parse_entry(payload: &[u8]) -> Option<ArchiveEntry> {
+    let body = payload.strip_prefix(ARCHIVE_MAGIC)?;
+    if body.len() < 8 {
+        return None;
+    }
+    let (len, name) = body.split_at(8);
+    Some(ArchiveEntry {
+        name: std::str::from_utf8(name).ok()?.to_string(),
+        len: u64::from_le_bytes(len.try_into().unwrap()),
+    })
+}
+
+fn truncated_entry() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "stream ends before the end of the archive entry",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_archive() {
+        let big: Vec<u8> = (0..150_000u32).map(|i| (i % 239) as u8).collect();
+        let files: [(&str, &[u8]); 4] = [
+            ("notes.txt", b"short text entry"),
+            ("empty", b""),
+            ("data/big.bin", &big),
+            ("unicode \u{e9}", b"last"),
+        ];
+
+        let mut stream = Vec::new();
+        let mut writer = ArchiveWriter::with_block_size(&mut stream, 64 * 1024);
+        for (name, data) in files {
+            writer.add_entry(name, data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = ArchiveReader::new(&stream[..]);
+        let mut entries = HashMap::new();
+        let mut names = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            assert_eq!(data.len() as u64, entry.len);
+            names.push(entry.name.clone());
+            entries.insert(entry.name, data);
+        }
+        assert_eq!(names, files.map(|(name, _)| name));
+        for (name, data) in files {
+            assert_eq!(entries[name], data, "{}", name);
+        }
+
+        // Entries left unread or partly read are skipped
+        let mut reader = ArchiveReader::new(&stream[..]);
+        reader.next_entry().unwrap();
+        reader.next_entry().unwrap();
+        assert_eq!(reader.next_entry().unwrap().unwrap().name, "data/big.bin");
+        reader.read_exact(&mut [0u8; 100]).unwrap();
+        let last = reader.next_entry().unwrap().unwrap();
+        assert_eq!(last.name, "unicode \u{e9}");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"last");
+
+        // A plain Reader gets the data of all entries
+        let mut all = Vec::new();
+        Reader::new(&stream[..]).read_to_end(&mut all).unwrap();
+        assert_eq!(all, files.map(|(_, data)| data).concat());
+    }
+
+    #[test]
+    fn test_archive_stray_data() {
+        // Data written outside any entry is skipped
+        let mut stream = Vec::new();
+        {
+            let mut writer = Writer::new(&mut stream);
+            writer.write_all(b"not in an entry").unwrap();
+            writer.flush_block().unwrap();
+            let mut body = ARCHIVE_MAGIC.to_vec();
+            body.extend_from_slice(&3u64.to_le_bytes());
+            body.extend_from_slice(b"x");
+            writer
+                .write_skippable_chunk(CHUNK_TYPE_ARCHIVE_ENTRY, &body)
+                .unwrap();
+            writer.write_all(b"abc").unwrap();
+            writer.flush_block().unwrap();
+            writer.write_all(b"trailing").unwrap();
+        }
+
+        let mut reader = ArchiveReader::new(&stream[..]);
+        let entry = reader.next_entry().unwrap().unwrap();
+        assert_eq!(
+            entry,
+            ArchiveEntry {
+                name: "x".to_string(),
+                len: 3
+            }
+        );
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"abc");
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+}
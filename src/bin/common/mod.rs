@@ -0,0 +1,61 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Helpers shared by the s2c and s2d command-line tools
+
+use anyhow::{Context, Result};
+use minlz::Dict;
+use std::fs::{self, File};
+use std::io::Read;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Options controlling how input files are loaded
+#[derive(clap::Args)]
+pub struct InputArgs {
+    /// Memory-map input files instead of reading them into memory
+    #[cfg(feature = "mmap")]
+    #[arg(long)]
+    pub mmap: bool,
+}
+
+/// Load the dictionary given with --dict
+pub fn load_dict(path: &Path) -> Result<Dict> {
+    let data =
+        fs::read(path).with_context(|| format!("Failed to read dictionary: {}", path.display()))?;
+    Dict::new(&data).with_context(|| format!("Invalid dictionary: {}", path.display()))
+}
+
+/// Contents of an input file
+pub enum InputData {
+    Read(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for InputData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            InputData::Read(data) => data,
+            #[cfg(feature = "mmap")]
+            InputData::Mapped(map) => map,
+        }
+    }
+}
+
+/// Read the whole input file, or memory-map it with --mmap
+pub fn load_input(file: &mut File, path: &Path, args: &InputArgs) -> Result<InputData> {
+    #[cfg(feature = "mmap")]
+    if args.mmap {
+        // SAFETY: the file must not be modified while it is mapped
+        let map = unsafe { memmap2::Mmap::map(&*file) }
+            .with_context(|| format!("Failed to memory-map input file: {}", path.display()))?;
+        return Ok(InputData::Mapped(map));
+    }
+    #[cfg(not(feature = "mmap"))]
+    let _ = (path, args);
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(InputData::Read(data))
+}
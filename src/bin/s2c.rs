@@ -2,10 +2,18 @@
 // S2 compression command-line tool
 // Based on klauspost/compress/s2/cmd/s2c
 
+mod common;
+
 use anyhow::{Context, Result};
 use clap::Parser;
+use common::{load_dict, load_input, InputArgs};
 use indicatif::{ProgressBar, ProgressStyle};
-use minlz::{decode, encode, encode_best, encode_better, ConcurrentWriter, Reader, Writer};
+use minlz::{
+    decode, decode_with_dict, encode, encode_best, encode_best_with_dict, encode_better,
+    encode_better_with_dict, encode_with_dict, recommended_block_size, ConcurrentWriter, Dict,
+    Reader, Writer,
+};
+use std::borrow::Cow;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
@@ -47,9 +55,9 @@ struct Args {
     #[arg(short = 'q', long)]
     quiet: bool,
 
-    /// Block size (e.g., 64K, 256K, 1M, 4M)
-    #[arg(long, default_value = "4M")]
-    blocksize: String,
+    /// Block size (e.g., 64K, 256K, 1M, 4M); default picks one from the input size
+    #[arg(long)]
+    blocksize: Option<String>,
 
     /// Generate Snappy-compatible output
     #[arg(long)]
@@ -82,6 +90,13 @@ struct Args {
     /// Recompress Snappy or S2 input
     #[arg(long)]
     recomp: bool,
+
+    /// Compress using a dictionary file (as written by Dict::to_bytes)
+    #[arg(long)]
+    dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
 fn main() -> Result<()> {
@@ -100,6 +115,10 @@ fn main() -> Result<()> {
         anyhow::bail!("Cannot use -c with multiple input files");
     }
 
+    if args.dict.is_some() && args.cpu.is_some_and(|cpu| cpu > 1) {
+        anyhow::bail!("Cannot use --dict with --cpu > 1");
+    }
+
     // Parse pad size
     let pad_size = if args.pad != "1" {
         parse_size(&args.pad).context("Invalid pad size")?
@@ -107,8 +126,16 @@ fn main() -> Result<()> {
         1
     };
 
-    // Parse block size
-    let block_size = parse_size(&args.blocksize).context("Invalid block size")?;
+    // Parse block size; when unset it is chosen per input
+    let block_size = args
+        .blocksize
+        .as_deref()
+        .map(parse_size)
+        .transpose()
+        .context("Invalid block size")?;
+
+    let dict = args.dict.as_deref().map(load_dict).transpose()?;
+    let dict = dict.as_ref();
 
     // Handle benchmark mode
     if let Some(bench_count) = args.bench {
@@ -117,18 +144,18 @@ fn main() -> Result<()> {
 
     // Handle stdin/stdout case
     if args.files.len() == 1 && args.files[0] == "-" {
-        return compress_stdio(&args);
+        return compress_stdio(&args, dict);
     }
 
     // Compress each file
     for file in &args.files {
-        compress_file(file, &args, block_size, pad_size)?;
+        compress_file(file, &args, dict, block_size, pad_size)?;
     }
 
     Ok(())
 }
 
-fn run_benchmark(args: &Args, block_size: usize, iterations: usize) -> Result<()> {
+fn run_benchmark(args: &Args, block_size: Option<usize>, iterations: usize) -> Result<()> {
     use std::time::Instant;
 
     for file_path in &args.files {
@@ -148,6 +175,8 @@ fn run_benchmark(args: &Args, block_size: usize, iterations: usize) -> Result<()
             .read_to_end(&mut file_data)?;
 
         let file_size = file_data.len();
+        let block_size =
+            block_size.unwrap_or_else(|| recommended_block_size(Some(file_size as u64)));
 
         if args.block {
             // Block mode benchmark
@@ -208,7 +237,7 @@ fn run_benchmark(args: &Args, block_size: usize, iterations: usize) -> Result<()
     Ok(())
 }
 
-fn compress_stdio(args: &Args) -> Result<()> {
+fn compress_stdio(args: &Args, dict: Option<&Dict>) -> Result<()> {
     let stdin = io::stdin();
     let mut reader = stdin.lock();
     let stdout = io::stdout();
@@ -219,19 +248,17 @@ fn compress_stdio(args: &Args) -> Result<()> {
         let mut data = Vec::new();
         reader.read_to_end(&mut data)?;
 
-        let compressed = if args.slower {
-            encode_best(&data)
-        } else if args.faster {
-            encode(&data)
-        } else {
-            encode_better(&data)
-        };
+        let compressed = encode_block(&data, args, dict);
 
         writer.write_all(&compressed)?;
     } else {
         // Stream mode
-        let block_size = parse_size(&args.blocksize)?;
+        let block_size = match args.blocksize.as_deref() {
+            Some(size) => parse_size(size)?,
+            None => recommended_block_size(None),
+        };
         let mut s2_writer = Writer::with_block_size(&mut writer, block_size);
+        apply_dict(&mut s2_writer, dict);
         io::copy(&mut reader, &mut s2_writer)?;
         s2_writer.flush()?;
     }
@@ -239,7 +266,13 @@ fn compress_stdio(args: &Args) -> Result<()> {
     Ok(())
 }
 
-fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usize) -> Result<()> {
+fn compress_file(
+    input_path: &str,
+    args: &Args,
+    dict: Option<&Dict>,
+    block_size: Option<usize>,
+    pad_size: usize,
+) -> Result<()> {
     use std::time::Instant;
 
     let input = PathBuf::from(input_path);
@@ -273,6 +306,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
 
     // Get file size
     let file_size = fs::metadata(&input)?.len();
+    let block_size = block_size.unwrap_or_else(|| recommended_block_size(Some(file_size)));
 
     // Start timing
     let start_time = Instant::now();
@@ -293,25 +327,26 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
     let mut input_file = File::open(&input)
         .with_context(|| format!("Failed to open input file: {}", input.display()))?;
 
+    let input_data = load_input(&mut input_file, &input, &args.input)?;
+
     // Handle recompression if requested
-    let data_to_compress: Vec<u8> = if args.recomp {
+    let data_to_compress: Cow<[u8]> = if args.recomp {
         // Try to decompress the input file first
-        let mut compressed_data = Vec::new();
-        input_file.read_to_end(&mut compressed_data)?;
+        let compressed_data = &input_data[..];
 
         // Try to decompress as S2/Snappy stream format first
         let mut decompressed = Vec::new();
-        let mut reader = Reader::new(&compressed_data[..]);
+        let mut reader = Reader::new(compressed_data);
         match reader.read_to_end(&mut decompressed) {
             Ok(_) => {
                 if !args.quiet {
                     eprintln!("Recompressing (decompressed {} bytes)", decompressed.len());
                 }
-                decompressed
+                Cow::Owned(decompressed)
             }
             Err(_) => {
                 // Try block format
-                match minlz::decode_snappy(&compressed_data) {
+                match minlz::decode_snappy(compressed_data) {
                     Ok(decompressed) => {
                         if !args.quiet {
                             eprintln!(
@@ -319,11 +354,11 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                                 decompressed.len()
                             );
                         }
-                        decompressed
+                        Cow::Owned(decompressed)
                     }
                     Err(_) => {
                         // Try S2 block format
-                        match decode(&compressed_data) {
+                        match decode(compressed_data) {
                             Ok(decompressed) => {
                                 if !args.quiet {
                                     eprintln!(
@@ -331,7 +366,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                                         decompressed.len()
                                     );
                                 }
-                                decompressed
+                                Cow::Owned(decompressed)
                             }
                             Err(e) => {
                                 anyhow::bail!(
@@ -345,10 +380,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
             }
         }
     } else {
-        // Read normally
-        let mut data = Vec::new();
-        input_file.read_to_end(&mut data)?;
-        data
+        Cow::Borrowed(&input_data[..])
     };
 
     if args.block {
@@ -357,13 +389,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
             pb.set_position(data_to_compress.len() as u64);
         }
 
-        let compressed = if args.slower {
-            encode_best(&data_to_compress)
-        } else if args.faster {
-            encode(&data_to_compress)
-        } else {
-            encode_better(&data_to_compress)
-        };
+        let compressed = encode_block(&data_to_compress, args, dict);
 
         if output == Path::new("-") {
             io::stdout().write_all(&compressed)?;
@@ -387,6 +413,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                 &mut reader,
                 &mut stdout_lock,
                 args,
+                dict,
                 block_size,
                 pad_size,
                 pb.as_ref(),
@@ -400,6 +427,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                 &mut reader,
                 &mut output_file,
                 args,
+                dict,
                 block_size,
                 pad_size,
                 pb.as_ref(),
@@ -416,6 +444,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                 &mut reader,
                 &mut stdout_lock,
                 args,
+                dict,
                 block_size,
                 pad_size,
                 pb.as_ref(),
@@ -429,6 +458,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
                 &mut reader,
                 &mut output_file,
                 args,
+                dict,
                 block_size,
                 pad_size,
                 pb.as_ref(),
@@ -468,7 +498,7 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
 
     // Verify compressed file if requested
     if args.verify && output != Path::new("-") {
-        verify_compressed_file(&input, &output)?;
+        verify_compressed_file(&input, &output, args.block, dict)?;
     }
 
     // Remove source file if requested
@@ -480,7 +510,12 @@ fn compress_file(input_path: &str, args: &Args, block_size: usize, pad_size: usi
     Ok(())
 }
 
-fn verify_compressed_file(original: &Path, compressed: &Path) -> Result<()> {
+fn verify_compressed_file(
+    original: &Path,
+    compressed: &Path,
+    block: bool,
+    dict: Option<&Dict>,
+) -> Result<()> {
     // Read original file
     let mut original_data = Vec::new();
     File::open(original)
@@ -493,23 +528,41 @@ fn verify_compressed_file(original: &Path, compressed: &Path) -> Result<()> {
         .read_to_end(&mut original_data)?;
 
     // Decompress compressed file
-    let compressed_file = File::open(compressed).with_context(|| {
+    let mut compressed_file = File::open(compressed).with_context(|| {
         format!(
             "Failed to open compressed file for verification: {}",
             compressed.display()
         )
     })?;
 
-    let mut reader = Reader::new(compressed_file);
     let mut decompressed_data = Vec::new();
-    reader
-        .read_to_end(&mut decompressed_data)
+    if block {
+        let mut data = Vec::new();
+        compressed_file.read_to_end(&mut data)?;
+        decompressed_data = match dict {
+            Some(dict) => decode_with_dict(&data, dict),
+            None => decode(&data),
+        }
         .with_context(|| {
             format!(
                 "Failed to decompress file for verification: {}",
                 compressed.display()
             )
         })?;
+    } else {
+        let mut reader = match dict {
+            Some(dict) => Reader::with_dict(compressed_file, dict.clone()),
+            None => Reader::new(compressed_file),
+        };
+        reader
+            .read_to_end(&mut decompressed_data)
+            .with_context(|| {
+                format!(
+                    "Failed to decompress file for verification: {}",
+                    compressed.display()
+                )
+            })?;
+    }
 
     // Compare
     if original_data != decompressed_data {
@@ -527,6 +580,7 @@ fn compress_stream<R: Read, W: Write>(
     input: &mut R,
     output: &mut W,
     args: &Args,
+    dict: Option<&Dict>,
     block_size: usize,
     pad_size: usize,
     pb: Option<&ProgressBar>,
@@ -544,6 +598,9 @@ fn compress_stream<R: Read, W: Write>(
                 let mut temp_output = Vec::new();
                 let mut s2_writer =
                     ConcurrentWriter::with_block_size(&mut temp_output, block_size, cpu_count);
+                if args.index {
+                    s2_writer.enable_index();
+                }
 
                 loop {
                     let n = input.read(&mut buffer)?;
@@ -558,23 +615,19 @@ fn compress_stream<R: Read, W: Write>(
                 s2_writer.flush()?;
                 drop(s2_writer);
 
-                // Apply padding if needed
+                // Apply padding after the index
                 let padding_needed = calc_padding(temp_output.len(), pad_size);
                 if padding_needed > 0 {
                     write_padding(&mut temp_output, padding_needed)?;
                 }
 
-                // Apply index if needed
-                if args.index {
-                    // Note: Index support requires deeper integration with the writer
-                    // For now, we skip index when using concurrent compression
-                    eprintln!("Warning: --index is not supported with --cpu > 1");
-                }
-
                 output.write_all(&temp_output)?;
             } else {
                 let mut s2_writer =
                     ConcurrentWriter::with_block_size(output, block_size, cpu_count);
+                if args.index {
+                    s2_writer.enable_index();
+                }
 
                 loop {
                     let n = input.read(&mut buffer)?;
@@ -587,10 +640,6 @@ fn compress_stream<R: Read, W: Write>(
                     }
                 }
                 s2_writer.flush()?;
-
-                if args.index {
-                    eprintln!("Warning: --index is not supported with --cpu > 1");
-                }
             }
             return Ok(());
         }
@@ -598,9 +647,10 @@ fn compress_stream<R: Read, W: Write>(
 
     // Single-threaded compression
     if pad_size > 1 && args.index {
-        // Padding + index: need to use temp buffer
-        let mut temp_output = Vec::new();
-        let mut s2_writer = Writer::with_index_and_block_size(&mut temp_output, block_size);
+        // Padding + index: the writer pads after the index
+        let mut s2_writer = Writer::with_index_and_block_size(output, block_size);
+        s2_writer.set_padding(pad_size);
+        apply_dict(&mut s2_writer, dict);
 
         loop {
             let n = input.read(&mut buffer)?;
@@ -613,18 +663,10 @@ fn compress_stream<R: Read, W: Write>(
             }
         }
         s2_writer.flush()?;
-        drop(s2_writer);
-
-        // Apply padding manually after index
-        let padding_needed = calc_padding(temp_output.len(), pad_size);
-        if padding_needed > 0 {
-            write_padding(&mut temp_output, padding_needed)?;
-        }
-
-        output.write_all(&temp_output)?;
     } else if pad_size > 1 {
         // Padding only
         let mut s2_writer = Writer::with_padding(output, pad_size);
+        apply_dict(&mut s2_writer, dict);
 
         loop {
             let n = input.read(&mut buffer)?;
@@ -640,6 +682,7 @@ fn compress_stream<R: Read, W: Write>(
     } else if args.index {
         // Index only
         let mut s2_writer = Writer::with_index_and_block_size(output, block_size);
+        apply_dict(&mut s2_writer, dict);
 
         loop {
             let n = input.read(&mut buffer)?;
@@ -655,6 +698,7 @@ fn compress_stream<R: Read, W: Write>(
     } else {
         // No padding, no index
         let mut s2_writer = Writer::with_block_size(output, block_size);
+        apply_dict(&mut s2_writer, dict);
 
         loop {
             let n = input.read(&mut buffer)?;
@@ -672,6 +716,38 @@ fn compress_stream<R: Read, W: Write>(
     Ok(())
 }
 
+/// Compress every block of `writer` with the --dict dictionary, if any
+fn apply_dict<W: Write>(writer: &mut Writer<W>, dict: Option<&Dict>) {
+    if let Some(dict) = dict {
+        writer.set_dict(dict.clone());
+    }
+}
+
+/// Encode `data` as a single block at the level and with the dictionary
+/// selected by the arguments
+fn encode_block(data: &[u8], args: &Args, dict: Option<&Dict>) -> Vec<u8> {
+    match dict {
+        Some(dict) => {
+            if args.slower {
+                encode_best_with_dict(data, dict)
+            } else if args.faster {
+                encode_with_dict(data, dict)
+            } else {
+                encode_better_with_dict(data, dict)
+            }
+        }
+        None => {
+            if args.slower {
+                encode_best(data)
+            } else if args.faster {
+                encode(data)
+            } else {
+                encode_better(data)
+            }
+        }
+    }
+}
+
 fn calc_padding(written: usize, want_multiple: usize) -> usize {
     if want_multiple <= 1 {
         return 0;
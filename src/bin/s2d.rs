@@ -2,10 +2,13 @@
 // S2 decompression command-line tool
 // Based on klauspost/compress/s2/cmd/s2d
 
+mod common;
+
 use anyhow::{Context, Result};
 use clap::Parser;
+use common::{load_dict, load_input, InputArgs};
 use indicatif::{ProgressBar, ProgressStyle};
-use minlz::{decode, Reader};
+use minlz::{decode, decode_with_dict, Dict, Reader};
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
@@ -62,6 +65,17 @@ struct Args {
     /// Run benchmark n times (no output will be written)
     #[arg(long)]
     bench: Option<usize>,
+
+    /// Decompress using the dictionary file given to s2c --dict
+    ///
+    /// Streams are CRC checked, so a wrong dictionary is reported as an
+    /// error. Blocks (--block) have no checksum: a wrong dictionary is only
+    /// caught if the block fails to decode.
+    #[arg(long)]
+    dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
 fn main() -> Result<()> {
@@ -91,25 +105,29 @@ fn main() -> Result<()> {
         eprintln!("Warning: --cpu is not yet implemented (single-threaded decompression)");
     }
 
+    // Load the dictionary once, outside any timed loop
+    let dict = args.dict.as_deref().map(load_dict).transpose()?;
+    let dict = dict.as_ref();
+
     // Handle benchmark mode
     if let Some(bench_count) = args.bench {
-        return run_benchmark(&args, bench_count);
+        return run_benchmark(&args, dict, bench_count);
     }
 
     // Handle stdin/stdout case
     if args.files.len() == 1 && args.files[0] == "-" {
-        return decompress_stdio(&args);
+        return decompress_stdio(&args, dict);
     }
 
     // Decompress each file
     for file in &args.files {
-        decompress_file(file, &args)?;
+        decompress_file(file, &args, dict)?;
     }
 
     Ok(())
 }
 
-fn run_benchmark(args: &Args, iterations: usize) -> Result<()> {
+fn run_benchmark(args: &Args, dict: Option<&Dict>, iterations: usize) -> Result<()> {
     use std::time::Instant;
 
     for file_path in &args.files {
@@ -142,7 +160,7 @@ fn run_benchmark(args: &Args, iterations: usize) -> Result<()> {
             let start = Instant::now();
             let mut decompressed_size = 0;
             for _ in 0..iterations {
-                let decompressed = decode(&file_data)?;
+                let decompressed = decode_block_file(&file_data, dict)?;
                 decompressed_size = decompressed.len();
             }
             let elapsed = start.elapsed();
@@ -166,7 +184,7 @@ fn run_benchmark(args: &Args, iterations: usize) -> Result<()> {
             let start = Instant::now();
             let mut decompressed_size = 0;
             for _ in 0..iterations {
-                let mut s2_reader = Reader::new(&file_data[..]);
+                let mut s2_reader = new_reader(&file_data[..], dict);
                 let mut output = Vec::new();
                 s2_reader.read_to_end(&mut output)?;
                 decompressed_size = output.len();
@@ -186,7 +204,7 @@ fn run_benchmark(args: &Args, iterations: usize) -> Result<()> {
     Ok(())
 }
 
-fn decompress_stdio(args: &Args) -> Result<()> {
+fn decompress_stdio(args: &Args, dict: Option<&Dict>) -> Result<()> {
     let stdin = io::stdin();
     let mut reader = stdin.lock();
 
@@ -195,10 +213,17 @@ fn decompress_stdio(args: &Args) -> Result<()> {
         if args.block {
             let mut data = Vec::new();
             reader.read_to_end(&mut data)?;
-            let _ = decode(&data)?;
+            let _ = decode_block_file(&data, dict)?;
         } else {
-            let mut s2_reader = Reader::new(reader);
-            io::copy(&mut s2_reader, &mut io::sink())?;
+            let report = new_reader(reader, dict).verify()?;
+            if !args.quiet {
+                eprintln!(
+                    "Verified {} bytes in {} chunks; {:.1}MB/s",
+                    report.decompressed_bytes,
+                    report.data_chunks,
+                    report.throughput() / 1_048_576.0
+                );
+            }
         }
         if !args.quiet {
             eprintln!("Verification successful");
@@ -213,18 +238,18 @@ fn decompress_stdio(args: &Args) -> Result<()> {
         // Block mode: read all into memory and decompress
         let mut data = Vec::new();
         reader.read_to_end(&mut data)?;
-        let decompressed = decode(&data)?;
+        let decompressed = decode_block_file(&data, dict)?;
         writer.write_all(&decompressed)?;
     } else {
         // Stream mode
-        let mut s2_reader = Reader::new(reader);
+        let mut s2_reader = new_reader(reader, dict);
         io::copy(&mut s2_reader, &mut writer)?;
     }
 
     Ok(())
 }
 
-fn decompress_file(input_path: &str, args: &Args) -> Result<()> {
+fn decompress_file(input_path: &str, args: &Args, dict: Option<&Dict>) -> Result<()> {
     use std::time::Instant;
 
     let input = PathBuf::from(input_path);
@@ -270,6 +295,8 @@ fn decompress_file(input_path: &str, args: &Args) -> Result<()> {
     // Start timing
     let start_time = Instant::now();
 
+    // No bar when verifying: Reader::verify reads to the end in one call,
+    // with nothing to report progress from
     let pb = if !args.quiet && !args.stdout && !args.verify {
         let pb = ProgressBar::new(file_size);
         pb.set_style(
@@ -288,14 +315,13 @@ fn decompress_file(input_path: &str, args: &Args) -> Result<()> {
 
     if args.block {
         // Block mode: read all into memory
-        let mut data = Vec::new();
-        input_file.read_to_end(&mut data)?;
+        let data = load_input(&mut input_file, &input, &args.input)?;
 
         if let Some(ref pb) = pb {
             pb.set_position(file_size);
         }
 
-        let decompressed = decode(&data).context("Decompression failed")?;
+        let decompressed = decode_block_file(&data, dict).context("Decompression failed")?;
 
         if args.verify {
             if !args.quiet {
@@ -310,21 +336,26 @@ fn decompress_file(input_path: &str, args: &Args) -> Result<()> {
         }
     } else {
         // Stream mode
-        let mut s2_reader = Reader::new(input_file);
+        #[cfg(feature = "mmap")]
+        let mapped;
+        #[cfg(feature = "mmap")]
+        let input_file: Box<dyn Read> = if args.input.mmap {
+            mapped = load_input(&mut input_file, &input, &args.input)?;
+            Box::new(&mapped[..])
+        } else {
+            Box::new(input_file)
+        };
+        let mut s2_reader = new_reader(input_file, dict);
 
         if args.verify {
-            let mut buffer = vec![0u8; 128 * 1024];
-            loop {
-                let n = s2_reader.read(&mut buffer)?;
-                if n == 0 {
-                    break;
-                }
-                if let Some(ref pb) = pb {
-                    pb.inc(n as u64);
-                }
-            }
+            let report = s2_reader.verify()?;
             if !args.quiet {
-                println!("Verification successful: {}", input.display());
+                println!(
+                    "Verification successful: {} ({} bytes, {:.1}MB/s)",
+                    input.display(),
+                    report.decompressed_bytes,
+                    report.throughput() / 1_048_576.0
+                );
             }
         } else if output == Path::new("-") {
             let stdout = io::stdout();
@@ -395,3 +426,19 @@ fn decompress_file(input_path: &str, args: &Args) -> Result<()> {
 
     Ok(())
 }
+
+/// Create a stream reader, using the --dict dictionary if any
+fn new_reader<R: Read>(reader: R, dict: Option<&Dict>) -> Reader<R> {
+    match dict {
+        Some(dict) => Reader::with_dict(reader, dict.clone()),
+        None => Reader::new(reader),
+    }
+}
+
+/// Decode a single block, using the --dict dictionary if any
+fn decode_block_file(data: &[u8], dict: Option<&Dict>) -> Result<Vec<u8>> {
+    Ok(match dict {
+        Some(dict) => decode_with_dict(data, dict)?,
+        None => decode(data)?,
+    })
+}
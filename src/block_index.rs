@@ -0,0 +1,221 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Index for random access into concatenated raw blocks (no stream framing)
+
+use crate::constants::{MAX_BLOCK_SIZE, MIN_BLOCK_SIZE};
+use crate::decode::decode_block;
+use crate::encode::encode;
+use crate::error::{Error, Result};
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Start of one block in the compressed and uncompressed data
+#[derive(Debug, Clone, Copy)]
+struct BlockEntry {
+    compressed_offset: u64,
+    uncompressed_offset: u64,
+}
+
+/// BlockIndex maps uncompressed offsets to blocks in a run of raw S2 blocks
+///
+/// [`Index`](crate::Index) describes a stream and is stored in it as a
+/// skippable chunk. Blocks simply written one after the other, as by
+/// [`encode_blocks`], have no framing to store one in, so this keeps one
+/// entry per block in memory. Storing it alongside the data is up to the
+/// caller.
+#[derive(Debug, Clone, Default)]
+pub struct BlockIndex {
+    entries: Vec<BlockEntry>,
+    total_compressed: u64,
+    total_uncompressed: u64,
+}
+
+impl BlockIndex {
+    /// Create an empty BlockIndex
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the next block, of `compressed_len` bytes decoding to
+    /// `uncompressed_len` bytes
+    pub fn add(&mut self, compressed_len: usize, uncompressed_len: usize) {
+        self.entries.push(BlockEntry {
+            compressed_offset: self.total_compressed,
+            uncompressed_offset: self.total_uncompressed,
+        });
+        self.total_compressed += compressed_len as u64;
+        self.total_uncompressed += uncompressed_len as u64;
+    }
+
+    /// Number of blocks
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no block has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total size of the blocks
+    pub fn total_compressed(&self) -> u64 {
+        self.total_compressed
+    }
+
+    /// Total decoded size of the blocks
+    pub fn total_uncompressed(&self) -> u64 {
+        self.total_uncompressed
+    }
+
+    /// Find the block holding the wanted (uncompressed) offset
+    ///
+    /// Returns (compressed_offset, uncompressed_offset) of the start of that
+    /// block, as [`Index::find`](crate::Index::find) does.
+    pub fn find(&self, offset: u64) -> Result<(u64, u64)> {
+        let i = self.block_at(offset)?;
+        let e = self.entries[i];
+        Ok((e.compressed_offset, e.uncompressed_offset))
+    }
+
+    /// Decode `len` bytes at uncompressed `offset` from `blocks`
+    ///
+    /// `blocks` is the data this index describes. Only the blocks covering
+    /// the range are decoded.
+    pub fn decode_range(&self, blocks: &[u8], offset: u64, len: usize) -> Result<Vec<u8>> {
+        let end = offset
+            .checked_add(len as u64)
+            .filter(|&end| end <= self.total_uncompressed)
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "range {}+{} beyond end of data ({})",
+                    offset, len, self.total_uncompressed
+                ))
+            })?;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut out = Vec::with_capacity(len);
+        for i in self.block_at(offset)?..self.entries.len() {
+            let e = self.entries[i];
+            if e.uncompressed_offset >= end {
+                break;
+            }
+            let start = usize::try_from(e.compressed_offset).map_err(|_| Error::TooLarge)?;
+            let (block, _) = decode_block(blocks.get(start..).ok_or(Error::Corrupt)?)?;
+            let block_end = self
+                .entries
+                .get(i + 1)
+                .map_or(self.total_uncompressed, |next| next.uncompressed_offset);
+            if block.len() as u64 != block_end - e.uncompressed_offset {
+                return Err(Error::Corrupt);
+            }
+            let from = offset.saturating_sub(e.uncompressed_offset) as usize;
+            let to = (end.min(block_end) - e.uncompressed_offset) as usize;
+            out.extend_from_slice(&block[from..to]);
+        }
+        Ok(out)
+    }
+
+    /// Position of the block holding `offset`
+    fn block_at(&self, offset: u64) -> Result<usize> {
+        if offset >= self.total_uncompressed {
+            return Err(Error::InvalidInput(format!(
+                "offset {} beyond end of data ({})",
+                offset, self.total_uncompressed
+            )));
+        }
+        Ok(self
+            .entries
+            .partition_point(|e| e.uncompressed_offset <= offset)
+            - 1)
+    }
+}
+
+/// Compress `src` as raw blocks of `block_size` input bytes, one after the
+/// other, and index them
+///
+/// The block format counterpart of [`encode_chunked`](crate::encode_chunked):
+/// no stream identifier, chunk headers or CRCs, just what [`encode`]
+/// returns for each block. Each block can be decoded on its own with
+/// [`decode_block`], which also reports where the next one starts, and
+/// the returned [`BlockIndex`] locates the blocks for random access. The
+/// block size is clamped to 4KB..=4MB. Empty input gives no blocks.
+///
+/// # Example
+///
+/// ```
+/// use minlz::encode_blocks;
+///
+/// let data = b"split into blocks ".repeat(10_000);
+/// let (blocks, index) = encode_blocks(&data, 64 * 1024);
+///
+/// assert_eq!(index.len(), 3);
+/// assert_eq!(
+///     index.decode_range(&blocks, 100_000, 18).unwrap(),
+///     &data[100_000..100_018]
+/// );
+/// ```
+pub fn encode_blocks(src: &[u8], block_size: usize) -> (Vec<u8>, BlockIndex) {
+    let block_size = block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+    let mut out = Vec::new();
+    let mut index = BlockIndex::new();
+    for chunk in src.chunks(block_size) {
+        let block = encode(chunk);
+        index.add(block.len(), chunk.len());
+        out.extend_from_slice(&block);
+    }
+    (out, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_index() {
+        let data: Vec<u8> = (0..300_000u32)
+            .map(|i| ((i / 7) % 199) as u8 ^ (i >> 13) as u8)
+            .collect();
+        let (blocks, index) = encode_blocks(&data, 64 * 1024);
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.total_uncompressed(), data.len() as u64);
+        assert_eq!(index.total_compressed(), blocks.len() as u64);
+
+        // The entries agree with walking the blocks
+        let mut pos = 0;
+        let mut decoded = 0;
+        for _ in 0..index.len() {
+            assert_eq!(index.find(decoded).unwrap(), (pos as u64, decoded));
+            let (block, consumed) = decode_block(&blocks[pos..]).unwrap();
+            assert_eq!(
+                block,
+                &data[decoded as usize..decoded as usize + block.len()]
+            );
+            pos += consumed;
+            decoded += block.len() as u64;
+        }
+        assert_eq!(pos, blocks.len());
+        assert_eq!(index.find(70_000).unwrap().1, 65536);
+
+        for (offset, len) in [(0, 10), (65530, 20), (1000, 200_000), (299_990, 10), (5, 0)] {
+            assert_eq!(
+                index.decode_range(&blocks, offset as u64, len).unwrap(),
+                &data[offset..offset + len]
+            );
+        }
+        assert!(index.find(data.len() as u64).is_err());
+        assert!(index.decode_range(&blocks, 299_990, 11).is_err());
+        assert_eq!(
+            index.decode_range(&blocks[..blocks.len() - 1], 299_990, 10),
+            Err(Error::Corrupt)
+        );
+
+        let (empty, index) = encode_blocks(&[], 64 * 1024);
+        assert!(empty.is_empty() && index.is_empty());
+        assert!(index.decode_range(&empty, 0, 0).unwrap().is_empty());
+    }
+}
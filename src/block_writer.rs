@@ -0,0 +1,155 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Raw block writer for S2 compression (no stream framing)
+
+use std::io::{self, Write};
+
+use crate::encode::encode;
+
+/// Largest input a single S2 block can describe (its length header is a u32).
+const MAX_BLOCK_INPUT: u64 = 0xffffffff;
+
+/// BlockWriter compresses everything written to it into a single S2 block
+///
+/// Unlike [`Writer`](crate::Writer), no stream identifier, chunk headers or
+/// CRCs are emitted: the output is exactly what [`encode`] returns for the
+/// concatenation of all input. This suits storage systems that do their own
+/// framing but want a `Write`-style interface.
+///
+/// Input is buffered in memory until [`finish`](BlockWriter::finish) is
+/// called; [`Drop`] finishes on a best-effort basis.
+///
+/// # Example
+///
+/// ```
+/// use minlz::BlockWriter;
+/// use std::io::Write;
+///
+/// let mut writer = BlockWriter::new(Vec::new());
+/// writer.write_all(b"Hello, ").unwrap();
+/// writer.write_all(b"World!").unwrap();
+/// let block = writer.finish().unwrap();
+///
+/// assert_eq!(block, minlz::encode(b"Hello, World!"));
+/// ```
+pub struct BlockWriter<W: Write> {
+    /// `None` after `finish` has taken the inner writer.
+    writer: Option<W>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BlockWriter<W> {
+    /// Create a new BlockWriter
+    pub fn new(writer: W) -> Self {
+        BlockWriter {
+            writer: Some(writer),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Number of uncompressed bytes buffered so far
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Encode the buffered input as one block, write it, and return the
+    /// underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        Ok(self.writer.take().expect("writer present before finish"))
+    }
+
+    /// Encode and write the buffered input (no-op once finished)
+    fn write_block(&mut self) -> io::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        let block = encode(&self.buf);
+        writer.write_all(&block)?;
+        writer.flush()?;
+        self.buf = Vec::new();
+        Ok(())
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("writer present before finish")
+    }
+
+    /// Get a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer present before finish")
+    }
+}
+
+impl<W: Write> Write for BlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() as u64 + buf.len() as u64 > MAX_BLOCK_INPUT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "block input ({} bytes) exceeds limit ({})",
+                    self.buf.len() as u64 + buf.len() as u64,
+                    MAX_BLOCK_INPUT
+                ),
+            ));
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Flushes the underlying writer only; the block itself is written
+    /// by [`finish`](BlockWriter::finish).
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for BlockWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.write_block();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    #[test]
+    fn test_block_writer_matches_encode() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = BlockWriter::new(Vec::new());
+        for chunk in data.chunks(777) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.buffered(), data.len());
+        let block = writer.finish().unwrap();
+
+        assert_eq!(block, encode(&data));
+        assert_eq!(decode(&block).unwrap(), data);
+    }
+
+    #[test]
+    fn test_block_writer_empty() {
+        let block = BlockWriter::new(Vec::new()).finish().unwrap();
+        assert_eq!(block, encode(&[]));
+    }
+
+    #[test]
+    fn test_block_writer_drop_finishes() {
+        let mut out = Vec::new();
+        {
+            let mut writer = BlockWriter::new(&mut out);
+            writer.write_all(b"Hello, World!").unwrap();
+        }
+        assert_eq!(out, encode(b"Hello, World!"));
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Building S2 streams from already-compressed data, without recompression
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::constants::*;
+use crate::crc::crc;
+use crate::decode::{decode, decode_len};
+use crate::error::{Error, Result};
+use crate::index::Index;
+
+/// A chunk of a stream, as found by [`scan_stream`]
+struct Chunk<'a> {
+    /// Chunk type byte
+    chunk_type: u8,
+    /// Whole chunk, header included
+    bytes: &'a [u8],
+    /// Decoded size for data chunks, 0 otherwise
+    decoded_len: usize,
+}
+
+/// Concatenate two S2 streams without recompressing
+///
+/// Blocks in a stream are independent, so the result keeps every data chunk
+/// of `a` followed by every data chunk of `b` (minus its leading stream
+/// identifier), and decodes to the decoded contents of `a` followed by those
+/// of `b`.
+///
+/// Index and size chunks are dropped from both inputs, since they only
+/// describe their own stream. If either input carried one, a new one
+/// covering the combined stream is appended.
+///
+/// Both inputs are checked for well-formed framing (identifier, chunk
+/// lengths, chunk types and block length headers); block contents and CRCs
+/// are not verified. Snappy and S2 streams cannot be mixed.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{concat_streams, Reader, Writer};
+/// use std::io::{Read, Write};
+///
+/// let mut a = Vec::new();
+/// Writer::new(&mut a).write_all(b"Hello, ").unwrap();
+/// let mut b = Vec::new();
+/// Writer::new(&mut b).write_all(b"World!").unwrap();
+///
+/// let joined = concat_streams(&a, &b).unwrap();
+///
+/// let mut out = Vec::new();
+/// Reader::new(&joined[..]).read_to_end(&mut out).unwrap();
+/// assert_eq!(out, b"Hello, World!");
+/// ```
+pub fn concat_streams(a: &[u8], b: &[u8]) -> Result<Vec<u8>> {
+    let (magic_a, chunks_a) = scan_stream(a)?;
+    let (magic_b, chunks_b) = scan_stream(b)?;
+    if magic_a != magic_b {
+        return Err(Error::InvalidInput(
+            "cannot concatenate S2 and Snappy streams".to_string(),
+        ));
+    }
+
+    let with_index = chunks_a
+        .iter()
+        .chain(chunks_b.iter())
+        .any(|c| c.chunk_type == CHUNK_TYPE_INDEX);
+    let with_size = chunks_a
+        .iter()
+        .chain(chunks_b.iter())
+        .any(|c| c.chunk_type == CHUNK_TYPE_SIZE);
+    let max_block = chunks_a
+        .iter()
+        .chain(chunks_b.iter())
+        .map(|c| c.decoded_len)
+        .max()
+        .unwrap_or(0);
+
+    let mut index = Index::new();
+    index.reset(max_block.max(MIN_BLOCK_SIZE) as i64);
+
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    out.extend_from_slice(magic_a);
+    let mut uncompressed_total: i64 = 0;
+    for chunk in chunks_a.iter().chain(chunks_b.iter()) {
+        match chunk.chunk_type {
+            CHUNK_TYPE_INDEX | CHUNK_TYPE_SIZE => continue,
+            CHUNK_TYPE_COMPRESSED_DATA | CHUNK_TYPE_UNCOMPRESSED_DATA => {
+                if with_index {
+                    index.add(out.len() as i64, uncompressed_total)?;
+                }
+                uncompressed_total += chunk.decoded_len as i64;
+            }
+            _ => {}
+        }
+        out.extend_from_slice(chunk.bytes);
+    }
+
+    // Same order as Writer: size chunk, then index
+    if with_size {
+        out.push(CHUNK_TYPE_SIZE);
+        out.extend_from_slice(&((SIZE_CHUNK_LEN - CHUNK_HEADER_SIZE) as u32).to_le_bytes()[..3]);
+        out.extend_from_slice(&(uncompressed_total as u64).to_le_bytes());
+        out.extend_from_slice(SIZE_MAGIC);
+    }
+    if with_index {
+        let comp_total = out.len() as i64;
+        index.append_to(&mut out, uncompressed_total, comp_total)?;
+    }
+    Ok(out)
+}
+
+/// Wrap a raw block (as returned by [`encode`](crate::encode)) into a
+/// complete single-chunk stream
+///
+/// The result is the stream identifier followed by one compressed-data
+/// chunk holding `block` unchanged, readable by [`Reader`](crate::Reader).
+/// The chunk CRC covers the uncompressed data, so the block is decoded
+/// once to compute it, which also validates it.
+///
+/// Returns [`Error::TooLarge`] if the block decodes to more than 4MB, the
+/// largest block a stream may hold.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{block_to_stream, encode, Reader};
+/// use std::io::Read;
+///
+/// let stream = block_to_stream(&encode(b"Hello, World!")).unwrap();
+///
+/// let mut out = Vec::new();
+/// Reader::new(&stream[..]).read_to_end(&mut out).unwrap();
+/// assert_eq!(out, b"Hello, World!");
+/// ```
+pub fn block_to_stream(block: &[u8]) -> Result<Vec<u8>> {
+    let (dlen, _) = decode_len(block)?;
+    if dlen > MAX_BLOCK_SIZE {
+        return Err(Error::TooLarge);
+    }
+    let checksum = crc(&decode(block)?);
+
+    let chunk_len = CHECKSUM_SIZE + block.len();
+    if chunk_len > MAX_CHUNK_SIZE {
+        return Err(Error::TooLarge);
+    }
+    let mut out = Vec::with_capacity(MAGIC_CHUNK.len() + CHUNK_HEADER_SIZE + chunk_len);
+    out.extend_from_slice(MAGIC_CHUNK);
+    out.push(CHUNK_TYPE_COMPRESSED_DATA);
+    out.extend_from_slice(&(chunk_len as u32).to_le_bytes()[..3]);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out.extend_from_slice(block);
+    Ok(out)
+}
+
+/// Split a stream into its identifier and the chunks following it
+fn scan_stream(stream: &[u8]) -> Result<(&[u8], Vec<Chunk<'_>>)> {
+    let magic = stream.get(..MAGIC_CHUNK.len()).ok_or(Error::Corrupt)?;
+    if magic != MAGIC_CHUNK && magic != MAGIC_CHUNK_SNAPPY {
+        return Err(Error::InvalidInput("invalid stream identifier".to_string()));
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = magic.len();
+    while pos < stream.len() {
+        let header = stream
+            .get(pos..pos + CHUNK_HEADER_SIZE)
+            .ok_or(Error::Corrupt)?;
+        let chunk_type = header[0];
+        let chunk_len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+        let end = pos + CHUNK_HEADER_SIZE + chunk_len;
+        let bytes = stream.get(pos..end).ok_or(Error::Corrupt)?;
+        let body = &bytes[CHUNK_HEADER_SIZE..];
+
+        let decoded_len = match chunk_type {
+            CHUNK_TYPE_COMPRESSED_DATA => {
+                let block = body.get(CHECKSUM_SIZE..).ok_or(Error::Corrupt)?;
+                decode_len(block)?.0
+            }
+            CHUNK_TYPE_UNCOMPRESSED_DATA => body
+                .len()
+                .checked_sub(CHECKSUM_SIZE)
+                .ok_or(Error::Corrupt)?,
+            CHUNK_TYPE_STREAM_IDENTIFIER => {
+                if body != &magic[CHUNK_HEADER_SIZE..] {
+                    return Err(Error::Corrupt);
+                }
+                0
+            }
+            CHUNK_TYPE_INDEX | CHUNK_TYPE_PADDING | 0x80..=0xfd => 0,
+            _ => return Err(Error::Unsupported),
+        };
+
+        chunks.push(Chunk {
+            chunk_type,
+            bytes,
+            decoded_len,
+        });
+        pos = end;
+    }
+    Ok((magic, chunks))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{Reader, Writer};
+    use std::io::{Read, Write};
+
+    fn compress(data: &[u8], block_size: usize, index: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut out, block_size);
+            if index {
+                writer.enable_index();
+            }
+            writer.write_all(data).unwrap();
+        }
+        out
+    }
+
+    fn decompress(stream: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Reader::new(stream).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_concat_streams() {
+        let part_a: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let part_b: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 253) as u8).collect();
+        let a = compress(&part_a, 64 << 10, false);
+        let b = compress(&part_b, 64 << 10, false);
+
+        let joined = concat_streams(&a, &b).unwrap();
+        assert_eq!(joined.len(), a.len() + b.len() - MAGIC_CHUNK.len());
+
+        let mut expected = decompress(&a);
+        expected.extend_from_slice(&decompress(&b));
+        assert_eq!(decompress(&joined), expected);
+    }
+
+    #[test]
+    fn test_concat_streams_rebuilds_index() {
+        let part_a: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        let part_b: Vec<u8> = (0..2_000_000u32).map(|i| (i % 13) as u8).collect();
+        let a = compress(&part_a, 1 << 20, true);
+        let b = compress(&part_b, 1 << 20, true);
+
+        let joined = concat_streams(&a, &b).unwrap();
+        let mut expected = part_a.clone();
+        expected.extend_from_slice(&part_b);
+        assert_eq!(decompress(&joined), expected);
+
+        // The index now covers both parts and points at real block starts.
+        let (_, chunks) = scan_stream(&joined).unwrap();
+        let trailer = chunks.last().unwrap();
+        assert_eq!(trailer.chunk_type, CHUNK_TYPE_INDEX);
+        let mut index = Index::new();
+        index.load(trailer.bytes).unwrap();
+        assert_eq!(index.total_uncompressed, expected.len() as i64);
+
+        // Every index entry must name the start of a data chunk in the
+        // joined stream, together with the bytes decoded before it.
+        let mut block_starts = Vec::new();
+        let (mut c_pos, mut u_pos) = (MAGIC_CHUNK.len() as i64, 0i64);
+        for chunk in &chunks {
+            if chunk.chunk_type == CHUNK_TYPE_COMPRESSED_DATA {
+                block_starts.push((c_pos, u_pos));
+            }
+            c_pos += chunk.bytes.len() as i64;
+            u_pos += chunk.decoded_len as i64;
+        }
+        let found = index.find(part_a.len() as i64 + 1).unwrap();
+        assert!(block_starts.contains(&found));
+        assert!(found.1 > 0 && found.1 <= part_a.len() as i64 + 1);
+    }
+
+    #[test]
+    fn test_concat_streams_rebuilds_size() {
+        use std::io::{Cursor, Write};
+
+        let part_a = vec![1u8; 5000];
+        let part_b = vec![2u8; 300];
+        let sized = |data: &[u8]| {
+            let mut out = Vec::new();
+            Writer::with_size_header(&mut out).write_all(data).unwrap();
+            out
+        };
+        let total_size = |stream: Vec<u8>| Reader::new(Cursor::new(stream)).total_size_hint();
+
+        let joined = concat_streams(&sized(&part_a), &sized(&part_b)).unwrap();
+        let (_, chunks) = scan_stream(&joined).unwrap();
+        let sizes = chunks.iter().filter(|c| c.chunk_type == CHUNK_TYPE_SIZE);
+        assert_eq!(sizes.count(), 1);
+        assert_eq!(decompress(&joined).len(), 5300);
+        assert_eq!(total_size(joined).unwrap(), Some(5300));
+
+        // One sized input is enough, and the size still covers both
+        let joined = concat_streams(&compress(&part_a, 64 << 10, false), &sized(&part_b)).unwrap();
+        assert_eq!(total_size(joined).unwrap(), Some(5300));
+
+        // Alongside a rebuilt index
+        let joined = concat_streams(&compress(&part_a, 64 << 10, true), &sized(&part_b)).unwrap();
+        assert_eq!(decompress(&joined).len(), 5300);
+        assert_eq!(total_size(joined.clone()).unwrap(), Some(5300));
+        let (_, chunks) = scan_stream(&joined).unwrap();
+        assert_eq!(chunks.last().unwrap().chunk_type, CHUNK_TYPE_INDEX);
+
+        // Neither input sized: no size chunk
+        let joined = concat_streams(
+            &compress(&part_a, 64 << 10, false),
+            &compress(&part_b, 64 << 10, false),
+        )
+        .unwrap();
+        assert_eq!(total_size(joined).unwrap(), None);
+    }
+
+    #[test]
+    fn test_block_to_stream() {
+        use crate::encode::{encode, encode_best};
+
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        for block in [
+            encode(&data),
+            encode_best(&data),
+            encode(b""),
+            encode(b"tiny"),
+        ] {
+            let stream = block_to_stream(&block).unwrap();
+            assert_eq!(decompress(&stream), crate::decode::decode(&block).unwrap());
+        }
+
+        // The stream is a valid concat_streams input too.
+        let a = block_to_stream(&encode(b"left ")).unwrap();
+        let b = block_to_stream(&encode(b"right")).unwrap();
+        assert_eq!(decompress(&concat_streams(&a, &b).unwrap()), b"left right");
+
+        // Corrupt blocks and blocks too big for a stream are rejected.
+        assert!(block_to_stream(b"\x05\x10abc").is_err());
+        let big = encode(&vec![0u8; MAX_BLOCK_SIZE + 1]);
+        assert_eq!(block_to_stream(&big), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn test_concat_streams_rejects_malformed() {
+        let a = compress(b"hello", 4096, false);
+        let b = compress(b"world", 4096, false);
+
+        assert!(concat_streams(&a[..a.len() - 1], &b).is_err());
+        assert!(concat_streams(&a, &b[1..]).is_err());
+        assert!(concat_streams(&a, b"").is_err());
+
+        let mut snappy = MAGIC_CHUNK_SNAPPY.to_vec();
+        snappy.extend_from_slice(&b[MAGIC_CHUNK.len()..]);
+        assert!(concat_streams(&a, &snappy).is_err());
+    }
+}
@@ -13,6 +13,12 @@ use crate::constants::*;
 use crate::crc::crc;
 #[cfg(feature = "concurrent")]
 use crate::encode::encode;
+#[cfg(feature = "concurrent")]
+use crate::index::Index;
+#[cfg(feature = "concurrent")]
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "concurrent")]
+use std::thread::{self, JoinHandle};
 
 /// Concurrent writer that compresses blocks in parallel
 ///
@@ -36,21 +42,33 @@ use crate::encode::encode;
 pub struct ConcurrentWriter<W: Write> {
     writer: W,
     buffers: Vec<Vec<u8>>,
+    spare: Vec<Vec<u8>>, // Flushed buffers kept for reuse
     block_size: usize,
     concurrency: usize,
     wrote_header: bool,
+    index: Option<Index>,    // Optional index for seeking support
+    total_written: u64,      // Total bytes written to underlying writer
+    uncompressed_total: u64, // Total uncompressed bytes written
+    closed: bool,            // Set once the index trailer has been written
 }
 
 #[cfg(feature = "concurrent")]
 impl<W: Write> ConcurrentWriter<W> {
     /// Create a new concurrent writer with specified number of workers
     ///
-    /// `concurrency` determines how many blocks can be compressed in parallel
+    /// `concurrency` determines how many blocks can be compressed in parallel.
+    /// A `concurrency` of 0 or 1 compresses each block on the calling thread
+    /// as soon as it is full, without going through Rayon, so it performs
+    /// like [`Writer`](crate::Writer). The output does not depend on
+    /// `concurrency`.
     pub fn new(writer: W, concurrency: usize) -> Self {
         Self::with_block_size(writer, DEFAULT_BLOCK_SIZE, concurrency)
     }
 
     /// Create a new concurrent writer with specific block size and worker count
+    ///
+    /// Block size is clamped to 4KB..=4MB; `concurrency` is as for
+    /// [`new`](ConcurrentWriter::new).
     pub fn with_block_size(writer: W, block_size: usize, concurrency: usize) -> Self {
         let block_size = block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
         let concurrency = concurrency.max(1);
@@ -58,9 +76,27 @@ impl<W: Write> ConcurrentWriter<W> {
         ConcurrentWriter {
             writer,
             buffers: Vec::new(),
+            spare: Vec::new(),
             block_size,
             concurrency,
             wrote_header: false,
+            index: None,
+            total_written: 0,
+            uncompressed_total: 0,
+            closed: false,
+        }
+    }
+
+    /// Enable index tracking on this writer
+    ///
+    /// Blocks are written in order, so each one's offsets are recorded as
+    /// for [`Writer::enable_index`](crate::Writer::enable_index). The index
+    /// is appended when the writer is dropped.
+    pub fn enable_index(&mut self) {
+        if self.index.is_none() {
+            let mut index = Index::new();
+            index.reset(self.block_size as i64);
+            self.index = Some(index);
         }
     }
 
@@ -68,6 +104,7 @@ impl<W: Write> ConcurrentWriter<W> {
     fn write_header(&mut self) -> io::Result<()> {
         if !self.wrote_header {
             self.writer.write_all(MAGIC_CHUNK)?;
+            self.total_written += MAGIC_CHUNK.len() as u64;
             self.wrote_header = true;
         }
         Ok(())
@@ -81,19 +118,17 @@ impl<W: Write> ConcurrentWriter<W> {
 
         self.write_header()?;
 
-        // Compress all blocks in parallel
-        let compressed_blocks: Vec<(Vec<u8>, u32)> = self
-            .buffers
-            .par_iter()
-            .map(|buf| {
-                let compressed = encode(buf);
-                let checksum = crc(buf);
-                (compressed, checksum)
-            })
-            .collect();
+        let compress = |buf: &Vec<u8>| (encode(buf), crc(buf), buf.len());
+        let compressed_blocks: Vec<(Vec<u8>, u32, usize)> = if self.concurrency == 1 {
+            // Serial: skip the Rayon overhead
+            self.buffers.iter().map(compress).collect()
+        } else {
+            // Compress all blocks in parallel
+            self.buffers.par_iter().map(compress).collect()
+        };
 
         // Write compressed blocks in order
-        for (compressed, checksum) in compressed_blocks {
+        for (compressed, checksum, uncompressed_len) in compressed_blocks {
             let chunk_len = compressed.len() + CHECKSUM_SIZE;
             if chunk_len > MAX_CHUNK_SIZE {
                 return Err(io::Error::new(
@@ -102,6 +137,17 @@ impl<W: Write> ConcurrentWriter<W> {
                 ));
             }
 
+            // Record index entry before writing this block
+            if let Some(ref mut index) = self.index {
+                index
+                    .add(self.total_written as i64, self.uncompressed_total as i64)
+                    .map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("index error: {}", e))
+                    })?;
+            }
+            self.uncompressed_total += uncompressed_len as u64;
+            self.total_written += (CHUNK_HEADER_SIZE + chunk_len) as u64;
+
             // Write chunk header
             self.writer.write_all(&[CHUNK_TYPE_COMPRESSED_DATA])?;
 
@@ -120,9 +166,36 @@ impl<W: Write> ConcurrentWriter<W> {
             self.writer.write_all(&compressed)?;
         }
 
-        self.buffers.clear();
+        self.spare.extend(self.buffers.drain(..).map(|mut buf| {
+            buf.clear();
+            buf
+        }));
         Ok(())
     }
+
+    /// Flush the remaining blocks, then write the index trailer once
+    fn close(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.flush_blocks()?;
+        if let Some(ref mut index) = self.index {
+            let mut index_data = Vec::new();
+            index
+                .append_to(
+                    &mut index_data,
+                    self.uncompressed_total as i64,
+                    self.total_written as i64,
+                )
+                .map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("index error: {}", e))
+                })?;
+            self.writer.write_all(&index_data)?;
+            self.total_written += index_data.len() as u64;
+        }
+        self.writer.flush()
+    }
 }
 
 #[cfg(feature = "concurrent")]
@@ -133,7 +206,7 @@ impl<W: Write> Write for ConcurrentWriter<W> {
         while !remaining.is_empty() {
             // Get or create current buffer
             if self.buffers.is_empty() {
-                self.buffers.push(Vec::new());
+                self.buffers.push(self.spare.pop().unwrap_or_default());
             }
 
             let current = self.buffers.last_mut().unwrap();
@@ -141,7 +214,7 @@ impl<W: Write> Write for ConcurrentWriter<W> {
 
             if available == 0 {
                 // Current buffer is full, start a new one
-                self.buffers.push(Vec::new());
+                self.buffers.push(self.spare.pop().unwrap_or_default());
                 continue;
             }
 
@@ -149,9 +222,12 @@ impl<W: Write> Write for ConcurrentWriter<W> {
             let to_write = available.min(remaining.len());
             current.extend_from_slice(&remaining[..to_write]);
             remaining = &remaining[to_write..];
+            let full = current.len() == self.block_size;
 
-            // If we have enough buffers for parallel compression, flush them
-            if self.buffers.len() >= self.concurrency {
+            // Once enough blocks are full for parallel compression, flush
+            // them. Waiting for the last one to fill keeps the block
+            // boundaries independent of write sizes and concurrency.
+            if full && self.buffers.len() >= self.concurrency {
                 self.flush_blocks()?;
             }
         }
@@ -168,7 +244,204 @@ impl<W: Write> Write for ConcurrentWriter<W> {
 #[cfg(feature = "concurrent")]
 impl<W: Write> Drop for ConcurrentWriter<W> {
     fn drop(&mut self) {
-        let _ = self.flush();
+        let _ = self.close();
+    }
+}
+
+/// A compressed block handed back by the [`PipelinedWriter`] worker:
+/// the uncompressed buffer (for reuse), its encoding and its CRC
+#[cfg(feature = "concurrent")]
+type PipelinedBlock = (Vec<u8>, Vec<u8>, u32);
+
+/// Double-buffered writer that compresses on a background thread
+///
+/// Where [`Writer`](crate::Writer) compresses each full block inside the
+/// `write` call that filled it, this hands the block to a worker thread and
+/// returns so the caller can fill the next buffer meanwhile. At most one
+/// block is in flight: a `write` that fills the next buffer first waits for
+/// the previous block and writes it out, so blocks are written in order and
+/// the output is identical to that of [`Writer::with_block_size`](crate::Writer::with_block_size).
+///
+/// Unlike [`ConcurrentWriter`], which batches `concurrency` blocks and
+/// compresses them together, this overlaps compression of one block with
+/// the production of the next, so it helps even when the caller is slow.
+///
+/// # Example
+///
+/// ```ignore
+/// use minlz::PipelinedWriter;
+/// use std::io::Write;
+///
+/// let mut compressed = Vec::new();
+/// {
+///     let mut writer = PipelinedWriter::new(&mut compressed);
+///     writer.write_all(&vec![0u8; 1024 * 1024]).unwrap();
+///     writer.flush().unwrap();
+/// }
+/// ```
+#[cfg(feature = "concurrent")]
+pub struct PipelinedWriter<W: Write> {
+    writer: W,
+    buf: Vec<u8>,   // Buffer being filled by the caller
+    spare: Vec<u8>, // Buffer returned by the worker
+    block_size: usize,
+    wrote_header: bool,
+    in_flight: bool,               // A block is being compressed
+    jobs: Option<Sender<Vec<u8>>>, // Dropped on close to stop the worker
+    results: Receiver<PipelinedBlock>,
+    worker: Option<JoinHandle<()>>,
+    closed: bool,
+}
+
+#[cfg(feature = "concurrent")]
+impl<W: Write> PipelinedWriter<W> {
+    /// Create a new pipelined writer with the default block size
+    pub fn new(writer: W) -> Self {
+        Self::with_block_size(writer, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a new pipelined writer with a specific block size
+    ///
+    /// Block size is clamped to 4KB..=4MB. The worker thread is started
+    /// here and stops when the writer is dropped.
+    pub fn with_block_size(writer: W, block_size: usize) -> Self {
+        let block_size = block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+        let (jobs, job_rx) = mpsc::channel::<Vec<u8>>();
+        let (result_tx, results) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for block in job_rx {
+                let compressed = encode(&block);
+                let checksum = crc(&block);
+                if result_tx.send((block, compressed, checksum)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        PipelinedWriter {
+            writer,
+            buf: Vec::with_capacity(block_size),
+            spare: Vec::with_capacity(block_size),
+            block_size,
+            wrote_header: false,
+            in_flight: false,
+            jobs: Some(jobs),
+            results,
+            worker: Some(worker),
+            closed: false,
+        }
+    }
+
+    fn worker_stopped() -> io::Error {
+        io::Error::other("compression worker stopped")
+    }
+
+    /// Wait for the block in flight, if any, and write it out
+    fn wait(&mut self) -> io::Result<()> {
+        if !self.in_flight {
+            return Ok(());
+        }
+        self.in_flight = false;
+        let (mut block, compressed, checksum) =
+            self.results.recv().map_err(|_| Self::worker_stopped())?;
+
+        if !self.wrote_header {
+            self.writer.write_all(MAGIC_CHUNK)?;
+            self.wrote_header = true;
+        }
+
+        // Same choice between compressed and uncompressed chunks as Writer
+        let dst_limit = block
+            .len()
+            .saturating_sub(block.len() / 32)
+            .saturating_sub(5);
+        let (chunk_type, data) = if compressed.len() <= dst_limit {
+            (CHUNK_TYPE_COMPRESSED_DATA, &compressed[..])
+        } else {
+            (CHUNK_TYPE_UNCOMPRESSED_DATA, &block[..])
+        };
+        let chunk_len = data.len() + CHECKSUM_SIZE;
+        if chunk_len > MAX_CHUNK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "compressed block too large",
+            ));
+        }
+
+        let mut header = [0u8; CHUNK_HEADER_SIZE + CHECKSUM_SIZE];
+        header[0] = chunk_type;
+        header[1..4].copy_from_slice(&(chunk_len as u32).to_le_bytes()[..3]);
+        header[4..].copy_from_slice(&checksum.to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+
+        block.clear();
+        self.spare = block;
+        Ok(())
+    }
+
+    /// Hand the current buffer to the worker, after writing the previous one
+    fn submit(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.wait()?;
+        let block = std::mem::replace(&mut self.buf, std::mem::take(&mut self.spare));
+        self.jobs
+            .as_ref()
+            .ok_or_else(Self::worker_stopped)?
+            .send(block)
+            .map_err(|_| Self::worker_stopped())?;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Write all buffered data, then stop the worker
+    fn close(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let result = self.flush();
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        result
+    }
+}
+
+#[cfg(feature = "concurrent")]
+impl<W: Write> Write for PipelinedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut remaining = buf;
+
+        while !remaining.is_empty() {
+            let available = self.block_size - self.buf.len();
+            if available == 0 {
+                self.submit()?;
+                continue;
+            }
+
+            let to_write = available.min(remaining.len());
+            self.buf.extend_from_slice(&remaining[..to_write]);
+            remaining = &remaining[to_write..];
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.submit()?;
+        self.wait()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "concurrent")]
+impl<W: Write> Drop for PipelinedWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
     }
 }
 
@@ -261,4 +534,138 @@ mod tests {
         assert_eq!(decompressed1, decompressed2);
         assert_eq!(decompressed1, data);
     }
+
+    #[test]
+    fn test_concurrent_writer_concurrency_settings() {
+        use crate::Reader;
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| ((i / 5) % 199) as u8).collect();
+        let compress = |concurrency: usize| {
+            let mut compressed = Vec::new();
+            {
+                let mut writer =
+                    ConcurrentWriter::with_block_size(&mut compressed, 64 * 1024, concurrency);
+                for chunk in data.chunks(10_007) {
+                    writer.write_all(chunk).unwrap();
+                }
+            }
+            compressed
+        };
+
+        let serial = compress(1);
+        let mut decompressed = Vec::new();
+        Reader::new(&serial[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        for concurrency in [0, 2, 3, 4, 8] {
+            assert_eq!(compress(concurrency), serial, "concurrency {}", concurrency);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writer_with_index() {
+        use crate::{Index, Reader, Writer};
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..6_000_000u32)
+            .map(|i| ((i / 3) % 241) as u8 ^ (i >> 16) as u8)
+            .collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = ConcurrentWriter::with_block_size(&mut compressed, 256 * 1024, 4);
+            writer.enable_index();
+            writer.write_all(&data).unwrap();
+        }
+
+        // Same stream and index as the serial writer
+        let mut serial = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut serial, 256 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+        assert_eq!(compressed, serial);
+
+        let trailer = compressed.len() - 10;
+        let index_len =
+            u32::from_le_bytes(compressed[trailer..trailer + 4].try_into().unwrap()) as usize;
+        let mut index = Index::new();
+        index
+            .load(&compressed[compressed.len() - index_len..])
+            .unwrap();
+        assert_eq!(index.total_uncompressed, data.len() as i64);
+
+        for offset in [0, 1_500_000, 2_097_152, 4_000_001, data.len() - 1] {
+            let (comp, uncomp) = index.find(offset as i64).unwrap();
+            assert!(uncomp > 0 || offset < 1 << 20);
+            let mut reader = Reader::with_ignore_stream_id(&compressed[comp as usize..]);
+            let mut skip = vec![0u8; offset - uncomp as usize];
+            reader.read_exact(&mut skip).unwrap();
+            let mut byte = [0u8];
+            reader.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], data[offset], "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn test_pipelined_writer() {
+        use crate::{Reader, Writer};
+        use std::io::Read;
+
+        // Mixes compressible and random-looking blocks so both chunk types
+        // are written
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..700_000u32)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if (i >> 16) % 2 == 0 {
+                    ((i / 7) % 131) as u8
+                } else {
+                    (seed >> 16) as u8
+                }
+            })
+            .collect();
+
+        let mut serial = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut serial, 64 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        for write_size in [1000, 64 * 1024, 100_003, data.len()] {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = PipelinedWriter::with_block_size(&mut compressed, 64 * 1024);
+                for chunk in data.chunks(write_size) {
+                    writer.write_all(chunk).unwrap();
+                }
+            }
+            assert_eq!(compressed, serial, "write size {}", write_size);
+        }
+
+        let mut decompressed = Vec::new();
+        Reader::new(&serial[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        // Flushing mid-stream writes everything buffered so far
+        let mut compressed = Vec::new();
+        let mut writer = PipelinedWriter::new(&mut compressed);
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let mut decompressed = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, b"hello");
+
+        // Nothing written, nothing emitted
+        let mut compressed = Vec::new();
+        drop(PipelinedWriter::new(&mut compressed));
+        assert!(compressed.is_empty());
+    }
 }
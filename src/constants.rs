@@ -25,23 +25,70 @@ pub const MAX_BLOCK_SIZE: usize = 4 << 20;
 /// Minimum block size for compression (4KB)
 pub const MIN_BLOCK_SIZE: usize = 4 << 10;
 
+/// Minimum block size accepted by `Writer::with_block_size_unclamped` (512B)
+pub const MIN_UNCLAMPED_BLOCK_SIZE: usize = 512;
+
 /// Default block size (1MB)
 pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
 
 /// Maximum Snappy block size (64KB)
-#[allow(dead_code)]
+///
+/// The Snappy encoder compresses its input in fragments of this size, each
+/// with a fresh hash table, as Go's `snappy.Encode` does.
 pub const MAX_SNAPPY_BLOCK_SIZE: usize = 1 << 16;
 
+/// Input margin of the Snappy encoder
+pub const SNAPPY_INPUT_MARGIN: usize = 16 - 1;
+
+/// Minimum fragment size the Snappy encoder looks for matches in
+pub const SNAPPY_MIN_NON_LITERAL_BLOCK_SIZE: usize = 1 + 1 + SNAPPY_INPUT_MARGIN;
+
+/// Leading byte of an [`encode_guaranteed`](crate::encode_guaranteed)
+/// output holding an S2 block
+pub const GUARANTEED_S2: u8 = 0;
+
+/// Leading byte of an [`encode_guaranteed`](crate::encode_guaranteed)
+/// output holding the input as is
+pub const GUARANTEED_STORED: u8 = 1;
+
 /// Input margin for encoding
 pub const INPUT_MARGIN: usize = 8;
 
 /// Minimum non-literal block size
+///
+/// Every S2 encoder ([`encode`](crate::encode),
+/// [`encode_better`](crate::encode_better), [`encode_best`](crate::encode_best)
+/// and their dictionary and option variants) stores a shorter input as a
+/// single literal without looking for matches. Above it, the size picks
+/// the hash tables:
+///
+/// - `encode`: 8-bit table below 512 bytes, 10-bit below 4KB, 12-bit
+///   below 16KB, 14-bit from there.
+/// - `encode_better`: 10/8-bit long/short tables below 512 bytes, 12/10
+///   below 4KB, 14/12 below 16KB, 16/13 up to 64KB, 17/14 above.
+/// - `encode_best`: 19/16-bit tables at every size.
+///
+/// Any encoder still falls back to a literal when matching does not save
+/// enough.
 pub const MIN_NON_LITERAL_BLOCK_SIZE: usize = 32;
 
+// The encoders only look for matches in blocks of at least the minimum size
+// and compute `src.len() - INPUT_MARGIN`, which must not underflow.
+const _: () = assert!(MIN_NON_LITERAL_BLOCK_SIZE >= INPUT_MARGIN);
+// They also load 8 bytes at position 1 before looking for a match.
+const _: () = assert!(MIN_NON_LITERAL_BLOCK_SIZE > 8);
+const _: () = assert!(SNAPPY_MIN_NON_LITERAL_BLOCK_SIZE >= SNAPPY_INPUT_MARGIN);
+
 /// Chunk types for stream format
 pub const CHUNK_TYPE_COMPRESSED_DATA: u8 = 0x00;
 pub const CHUNK_TYPE_UNCOMPRESSED_DATA: u8 = 0x01;
 pub const CHUNK_TYPE_INDEX: u8 = 0x99;
+/// Skippable chunk recording the compression level (`Writer::with_level`)
+pub const CHUNK_TYPE_LEVEL: u8 = 0x9a;
+/// Skippable chunk recording the uncompressed size (`Writer::with_size_header`)
+pub const CHUNK_TYPE_SIZE: u8 = 0x9b;
+/// Skippable chunk opening an archive entry (`ArchiveWriter`)
+pub const CHUNK_TYPE_ARCHIVE_ENTRY: u8 = 0x9c;
 pub const CHUNK_TYPE_PADDING: u8 = 0xfe;
 pub const CHUNK_TYPE_STREAM_IDENTIFIER: u8 = 0xff;
 
@@ -57,6 +104,19 @@ pub const MAGIC_CHUNK: &[u8] = b"\xff\x06\x00\x00S2sTwO";
 /// Full magic chunk for Snappy streams (0xff 0x06 0x00 0x00 "sNaPpY")
 pub const MAGIC_CHUNK_SNAPPY: &[u8] = b"\xff\x06\x00\x00sNaPpY";
 
+/// Level chunk payload: this magic followed by the level id
+pub const LEVEL_MAGIC: &[u8] = b"MzLvl";
+
+/// Size chunk payload: the size as a little-endian u64 followed by this magic
+pub const SIZE_MAGIC: &[u8] = b"MzSiz";
+
+/// Archive entry chunk payload: this magic, the entry's length as a
+/// little-endian u64, then its name
+pub const ARCHIVE_MAGIC: &[u8] = b"MzEnt";
+
+/// Total length of a size chunk, header included
+pub const SIZE_CHUNK_LEN: usize = CHUNK_HEADER_SIZE + 8 + SIZE_MAGIC.len();
+
 /// Checksum size (CRC32)
 pub const CHECKSUM_SIZE: usize = 4;
 
@@ -27,6 +27,36 @@ pub fn crc(data: &[u8]) -> u32 {
     c.rotate_right(15).wrapping_add(0xa282ead8)
 }
 
+/// Checksum of a chunk's uncompressed data in the S2/Snappy stream format
+///
+/// CRC-32C (Castagnoli) with the masking of the Snappy framing format:
+/// rotated right by 15 bits, plus 0xa282ead8. Compressed and uncompressed
+/// chunks carry it in little-endian right after the chunk header, computed
+/// over the decompressed data. Use it to build or check chunks by hand.
+///
+/// # Example
+///
+/// Frame an uncompressed chunk after the stream identifier:
+///
+/// ```
+/// use minlz::{stream_checksum, Reader};
+/// use std::io::Read;
+///
+/// let data = b"framed by hand";
+/// let mut stream = b"\xff\x06\x00\x00S2sTwO".to_vec();
+/// stream.push(0x01); // uncompressed data chunk
+/// stream.extend_from_slice(&(4 + data.len() as u32).to_le_bytes()[..3]);
+/// stream.extend_from_slice(&stream_checksum(data).to_le_bytes());
+/// stream.extend_from_slice(data);
+///
+/// let mut out = Vec::new();
+/// Reader::new(&stream[..]).read_to_end(&mut out).unwrap();
+/// assert_eq!(out, data);
+/// ```
+pub fn stream_checksum(data: &[u8]) -> u32 {
+    crc(data)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -7,26 +7,27 @@ use crate::constants::*;
 use crate::dict::{Dict, MAX_DICT_SRC_OFFSET};
 use crate::error::{Error, Result};
 use crate::varint::decode_varint;
+use alloc::format;
 use alloc::vec::Vec;
 
 /// Decoder for S2 and Snappy compression
-pub struct Decoder {
-    /// Whether to allow Snappy format (no repeat offsets)
-    #[allow(dead_code)]
-    allow_snappy: bool,
-}
+///
+/// S2 is a superset of Snappy, so every decoder accepts both formats. Use
+/// [`decode_snappy_strict`] to check that a block is plain Snappy.
+pub struct Decoder;
 
 impl Decoder {
     /// Create a new decoder that accepts both S2 and Snappy formats
     pub fn new() -> Self {
-        Decoder { allow_snappy: true }
+        Decoder
     }
 
-    /// Create a decoder that only accepts S2 format
+    /// Create a decoder for S2 format
+    ///
+    /// Same as [`new`](Decoder::new): Snappy blocks are valid S2 blocks.
+    #[deprecated(note = "S2 decoding always accepts Snappy blocks; use Decoder::new")]
     pub fn new_s2_only() -> Self {
-        Decoder {
-            allow_snappy: false,
-        }
+        Decoder
     }
 }
 
@@ -42,6 +43,9 @@ impl Default for Decoder {
 ///
 /// This function accepts both S2 and Snappy format.
 /// The dst and src must not overlap. It is valid to pass an empty dst.
+///
+/// An empty block is the single byte `0x00` (decoded length 0). A `src`
+/// with no bytes at all has no length header and is `Error::Corrupt`.
 pub fn decode(src: &[u8]) -> Result<Vec<u8>> {
     let (dlen, header_len) = decode_len(src)?;
     let mut dst = alloc_uninit_dst(dlen)?;
@@ -49,12 +53,588 @@ pub fn decode(src: &[u8]) -> Result<Vec<u8>> {
     Ok(dst)
 }
 
+/// Decode a block that may be followed by other data
+///
+/// Returns the decoded bytes and the number of bytes of `src` the block
+/// occupied (length header plus tokens), so the caller can keep parsing
+/// right after it. Tokens are read only until the declared decoded length
+/// has been produced; a token reaching past that length, or `src` ending
+/// before it, is an error. Anything after the block is ignored.
+///
+/// [`decode`] instead requires `src` to hold exactly one block. This also
+/// reads blocks from fixed-size storage padded with trailing zeros, which
+/// `decode` would try to parse as more literals.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_block, encode};
+///
+/// let mut buf = encode(b"first block, first block");
+/// let first_len = buf.len();
+/// buf.extend_from_slice(&encode(b"second"));
+///
+/// let (first, consumed) = decode_block(&buf).unwrap();
+/// assert_eq!(first, b"first block, first block");
+/// assert_eq!(consumed, first_len);
+///
+/// let (second, _) = decode_block(&buf[consumed..]).unwrap();
+/// assert_eq!(second, b"second");
+/// ```
+#[doc(alias = "decode_allow_trailing")]
+pub fn decode_block(src: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let (dlen, header_len) = decode_len(src)?;
+    let tokens_len = block_tokens_len(&src[header_len..], dlen)?;
+    let mut dst = alloc_uninit_dst(dlen)?;
+    s2_decode(&mut dst, &src[header_len..header_len + tokens_len])?;
+    Ok((dst, header_len + tokens_len))
+}
+
+/// Decode the first `n` bytes of a block
+///
+/// Decodes tokens only until at least `n` bytes of output are produced,
+/// then truncates to `n`, so previewing the start of a large block costs
+/// little more than those bytes. Tokens up to that point are validated as
+/// by [`decode`]; the rest of the block is not read. If `n` is at least the
+/// decoded length, the whole block is decoded.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_prefix, encode};
+///
+/// let data = b"a long block whose start is all we want to see".repeat(1000);
+/// let compressed = encode(&data);
+/// assert_eq!(decode_prefix(&compressed, 10).unwrap(), b"a long blo");
+/// ```
+pub fn decode_prefix(src: &[u8], n: usize) -> Result<Vec<u8>> {
+    let (dlen, header_len) = decode_len(src)?;
+    let (tokens_len, produced) = block_tokens_until(&src[header_len..], dlen, n.min(dlen))?;
+    let mut dst = alloc_uninit_dst(produced)?;
+    s2_decode(&mut dst, &src[header_len..header_len + tokens_len])?;
+    dst.truncate(n);
+    Ok(dst)
+}
+
+/// Number of bytes of `src` taken by the tokens producing exactly `dlen`
+/// bytes of output. Only lengths are checked here; s2_decode validates
+/// offsets.
+fn block_tokens_len(src: &[u8], dlen: usize) -> Result<usize> {
+    block_tokens_until(src, dlen, dlen).map(|(s, _)| s)
+}
+
+/// Number of bytes of `src` taken by the tokens of a block of `dlen`
+/// bytes up to the first one ending at or past `stop`, and the output
+/// they produce. Lengths are checked as in [`block_tokens_len`].
+fn block_tokens_until(src: &[u8], dlen: usize, stop: usize) -> Result<(usize, usize)> {
+    let mut s = 0;
+    let mut d = 0;
+    while d < stop {
+        if s >= src.len() {
+            return Err(Error::Corrupt);
+        }
+        let (length, token_len) = match src[s] & 0x03 {
+            TAG_LITERAL => {
+                let (length, n) = decode_literal_length(&src[s..])?;
+                if length > src.len() - s - n {
+                    return Err(Error::Corrupt);
+                }
+                (length, n + length)
+            }
+            TAG_COPY1 => {
+                // The offset doesn't matter for the length.
+                let (_, length, n) = decode_copy1(&src[s..], 0)?;
+                (length, n)
+            }
+            TAG_COPY2 => (1 + (src[s] >> 2) as usize, 3),
+            _ => (1 + (src[s] >> 2) as usize, 5),
+        };
+        if length > dlen - d || token_len > src.len() - s {
+            return Err(Error::Corrupt);
+        }
+        s += token_len;
+        d += length;
+    }
+    Ok((s, d))
+}
+
+/// Whether `src` is exactly one block: a length header followed by tokens
+/// producing that length, with no bytes left over. Offsets are not checked.
+#[cfg(feature = "std")]
+pub(crate) fn is_whole_block(src: &[u8]) -> bool {
+    match decode_len(src) {
+        Ok((dlen, header_len)) => {
+            block_tokens_len(&src[header_len..], dlen) == Ok(src.len() - header_len)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Progress through a block decoded piecewise with [`decode_resume`]
+///
+/// Holds what the decoder needs to pick up where the previous call
+/// stopped: the decoded length once the header has been read, the output
+/// produced and source consumed so far, the last copy offset (which repeat
+/// copies refer back to), and the bytes of a length header or token split
+/// across calls.
+#[derive(Debug, Clone, Default)]
+pub struct BlockDecodeState {
+    dlen: Option<usize>,
+    // Output produced
+    d: usize,
+    // Source consumed
+    s: usize,
+    // Last copy offset
+    offset: usize,
+    // Start of a length header or token tag not complete yet
+    pending: Vec<u8>,
+    // Bytes of the current literal still to come
+    literal_left: usize,
+}
+
+impl BlockDecodeState {
+    /// Create a state for decoding a new block
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decoded length of the block, once its header has been read
+    pub fn decoded_len(&self) -> Option<usize> {
+        self.dlen
+    }
+
+    /// Number of bytes decoded so far
+    pub fn produced(&self) -> usize {
+        self.d
+    }
+
+    /// Number of block bytes consumed so far
+    pub fn consumed(&self) -> usize {
+        self.s
+    }
+
+    /// Offset of the last copy, used by the next repeat copy
+    pub fn last_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether the whole block has been decoded
+    pub fn is_complete(&self) -> bool {
+        self.dlen == Some(self.d) && self.literal_left == 0
+    }
+}
+
+/// Continue decoding a block from its next bytes
+///
+/// `src` holds the bytes of the block following those passed to earlier
+/// calls with the same `state`; they can be cut anywhere, even inside the
+/// length header or a token. Decoded bytes are appended to `dst`, which
+/// must hold exactly the output of the earlier calls, since copies refer
+/// back to it. A token split across calls is kept in `state` until its
+/// remaining bytes arrive.
+///
+/// Returns how many bytes of `src` were used. That is all of them until
+/// the block is complete; bytes after the end of the block are left
+/// unused, as by [`decode_block`]. Tokens are validated as by [`decode`].
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_resume, encode, BlockDecodeState};
+///
+/// let data = b"decoded as its bytes arrive, decoded as its bytes arrive";
+/// let block = encode(data);
+///
+/// let mut state = BlockDecodeState::new();
+/// let mut out = Vec::new();
+/// for piece in block.chunks(3) {
+///     assert_eq!(decode_resume(&mut state, &mut out, piece).unwrap(), piece.len());
+/// }
+/// assert!(state.is_complete());
+/// assert_eq!(out, data);
+/// ```
+pub fn decode_resume(state: &mut BlockDecodeState, dst: &mut Vec<u8>, src: &[u8]) -> Result<usize> {
+    if dst.len() != state.d {
+        return Err(Error::InvalidInput(format!(
+            "destination holds {} bytes, {} were decoded",
+            dst.len(),
+            state.d
+        )));
+    }
+
+    let mut used = 0;
+    let dlen = match state.dlen {
+        Some(dlen) => dlen,
+        None => {
+            while used < src.len() && state.pending.last().map_or(true, |&b| b >= 0x80) {
+                state.pending.push(src[used]);
+                used += 1;
+            }
+            state.s += used;
+            if state.pending.last().map_or(true, |&b| b >= 0x80) {
+                // decode_varint rejects overlong headers without waiting for
+                // their end
+                if state.pending.len() >= 10 {
+                    decode_len(&state.pending)?;
+                }
+                return Ok(used);
+            }
+            let (dlen, _) = decode_len(&state.pending)?;
+            if dlen > MAX_DECODE_DST_SIZE {
+                return Err(Error::TooLarge);
+            }
+            dst.try_reserve(dlen).map_err(|_| Error::TooLarge)?;
+            state.pending.clear();
+            state.dlen = Some(dlen);
+            dlen
+        }
+    };
+
+    let start = used;
+    loop {
+        if state.literal_left > 0 {
+            let n = state.literal_left.min(src.len() - used);
+            dst.extend_from_slice(&src[used..used + n]);
+            used += n;
+            state.d += n;
+            state.literal_left -= n;
+            if state.literal_left > 0 {
+                break;
+            }
+        }
+        if state.d == dlen || used == src.len() {
+            break;
+        }
+
+        // Gather the token's tag bytes, from src directly when all are there
+        let token = if state.pending.is_empty() && src.len() - used >= token_tag_len(&src[used..]) {
+            let n = token_tag_len(&src[used..]);
+            used += n;
+            &src[used - n..used]
+        } else {
+            while used < src.len() && state.pending.len() < token_tag_len(&state.pending) {
+                state.pending.push(src[used]);
+                used += 1;
+            }
+            if state.pending.len() < token_tag_len(&state.pending) {
+                break;
+            }
+            &state.pending[..]
+        };
+
+        let (offset, length) = match token[0] & 0x03 {
+            TAG_LITERAL => {
+                let (length, _) = decode_literal_length(token)?;
+                if length > dlen - state.d {
+                    return Err(Error::Corrupt);
+                }
+                state.literal_left = length;
+                state.pending.clear();
+                continue;
+            }
+            TAG_COPY1 => {
+                let (offset, length, _) = decode_copy1(token, state.offset)?;
+                (offset, length)
+            }
+            TAG_COPY2 => (
+                u16::from_le_bytes(token[1..3].try_into().unwrap()) as usize,
+                1 + (token[0] >> 2) as usize,
+            ),
+            _ => (
+                u32::from_le_bytes(token[1..5].try_into().unwrap()) as usize,
+                1 + (token[0] >> 2) as usize,
+            ),
+        };
+        state.pending.clear();
+        if offset == 0 || state.d < offset || length > dlen - state.d {
+            return Err(Error::Corrupt);
+        }
+        state.offset = offset;
+        dst.resize(state.d + length, 0);
+        copy_within(dst, state.d, offset, length);
+        state.d += length;
+    }
+    state.s += used - start;
+    Ok(used)
+}
+
+/// Number of bytes in the tag of the token starting `src`, as far as can be
+/// told from the bytes present: a COPY1 tag needs its second byte to tell a
+/// repeat, whose length takes more bytes.
+fn token_tag_len(src: &[u8]) -> usize {
+    let Some(&tag) = src.first() else {
+        return 1;
+    };
+    match tag & 0x03 {
+        TAG_LITERAL => match tag >> 2 {
+            x @ 60..=63 => x as usize - 58,
+            _ => 1,
+        },
+        TAG_COPY1 => match src.get(1) {
+            Some(0) if tag & 0xe0 == 0 => match (tag >> 2) & 0x7 {
+                5 => 3,
+                6 => 4,
+                7 => 5,
+                _ => 2,
+            },
+            _ => 2,
+        },
+        TAG_COPY2 => 3,
+        _ => 5,
+    }
+}
+
+/// Decode the output of [`encode_guaranteed`](crate::encode_guaranteed)
+///
+/// Returns `Error::Corrupt` if `src` is empty, starts with an unknown byte,
+/// or holds a corrupt block.
+pub fn decode_guaranteed(src: &[u8]) -> Result<Vec<u8>> {
+    match src.split_first() {
+        Some((&GUARANTEED_S2, block)) => decode(block),
+        Some((&GUARANTEED_STORED, data)) => {
+            if data.len() > MAX_DECODE_DST_SIZE {
+                return Err(Error::TooLarge);
+            }
+            Ok(data.to_vec())
+        }
+        _ => Err(Error::Corrupt),
+    }
+}
+
 /// Decode Snappy format data
 /// This is an alias for decode() since S2 decoder handles Snappy format
 pub fn decode_snappy(src: &[u8]) -> Result<Vec<u8>> {
     decode(src)
 }
 
+/// Decode a block the way a Snappy decoder would
+///
+/// Unlike [`decode_snappy`], rejects the S2-only repeat-offset copy (a
+/// COPY1 with a zero offset) with [`Error::Unsupported`], so it can check
+/// that a block is readable by plain Snappy decoders. The block is scanned
+/// for repeats before it is decoded.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_snappy_strict, encode_snappy};
+///
+/// let data = b"snappy snappy snappy snappy snappy".repeat(10);
+/// assert_eq!(decode_snappy_strict(&encode_snappy(&data)).unwrap(), data);
+/// ```
+pub fn decode_snappy_strict(src: &[u8]) -> Result<Vec<u8>> {
+    let (_, header_len) = decode_len(src)?;
+    check_no_repeats(&src[header_len..])?;
+    decode(src)
+}
+
+/// Fail with [`Error::Unsupported`] if the block tokens contain a repeat
+///
+/// Truncated tokens end the scan; the decoder reports them.
+fn check_no_repeats(src: &[u8]) -> Result<()> {
+    let mut s = 0;
+    while s < src.len() {
+        let token_len = match src[s] & 0x03 {
+            TAG_LITERAL => {
+                let (length, n) = decode_literal_length(&src[s..])?;
+                n + length
+            }
+            TAG_COPY1 => {
+                if s + 1 < src.len() && src[s] & 0xe0 == 0 && src[s + 1] == 0 {
+                    return Err(Error::Unsupported);
+                }
+                2
+            }
+            TAG_COPY2 => 3,
+            _ => 5,
+        };
+        s = s.saturating_add(token_len);
+    }
+    Ok(())
+}
+
+/// Decode a block, rejecting copies from further back than `max_offset`
+///
+/// For checking that a block fits a restricted profile before handing it
+/// to a limited decoder: a `max_offset` of 65535 rules out COPY4. Any
+/// offset above the limit fails with [`Error::Unsupported`]. Repeats reuse
+/// an offset that was already checked. The block is scanned before it is
+/// decoded.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_max_offset, encode};
+///
+/// let data = b"nearby nearby nearby nearby".repeat(10);
+/// assert_eq!(decode_max_offset(&encode(&data), 65535).unwrap(), data);
+/// ```
+pub fn decode_max_offset(src: &[u8], max_offset: usize) -> Result<Vec<u8>> {
+    let (_, header_len) = decode_len(src)?;
+    check_max_offset(&src[header_len..], max_offset)?;
+    decode(src)
+}
+
+/// Fail with [`Error::Unsupported`] if a copy in the block tokens has an
+/// offset above `max_offset`
+///
+/// Truncated tokens end the scan; the decoder reports them.
+fn check_max_offset(src: &[u8], max_offset: usize) -> Result<()> {
+    let mut s = 0;
+    while s < src.len() {
+        let (offset, token_len) = match src[s] & 0x03 {
+            TAG_LITERAL => {
+                let (length, n) = decode_literal_length(&src[s..])?;
+                (0, n + length)
+            }
+            TAG_COPY1 => match src.get(s + 1) {
+                Some(&b) => (((src[s] as usize & 0xe0) << 3) | b as usize, 2),
+                None => break,
+            },
+            TAG_COPY2 => match src.get(s + 1..s + 3) {
+                Some(b) => (u16::from_le_bytes(b.try_into().unwrap()) as usize, 3),
+                None => break,
+            },
+            _ => match src.get(s + 1..s + 5) {
+                Some(b) => (u32::from_le_bytes(b.try_into().unwrap()) as usize, 5),
+                None => break,
+            },
+        };
+        if offset > max_offset {
+            return Err(Error::Unsupported);
+        }
+        s = s.saturating_add(token_len);
+    }
+    Ok(())
+}
+
+/// Token statistics of a block, from [`analyze_block`]
+///
+/// The histograms are bucketed by bit length: `hist[i]` counts the values
+/// in `2^(i-1)..2^i` (so `hist[1]` counts 1, `hist[3]` counts 4 to 7), and
+/// each has as many buckets as its largest value needs. `hist[0]` is
+/// always 0, as lengths and offsets start at 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockAnalysis {
+    /// Length of the decoded block
+    pub decoded_len: usize,
+    /// Lengths of literal runs
+    pub literal_lengths: Vec<u64>,
+    /// Lengths of copies with an explicit offset (COPY1, COPY2 and COPY4)
+    pub copy_lengths: Vec<u64>,
+    /// Offsets of copies with an explicit offset
+    pub copy_offsets: Vec<u64>,
+    /// Lengths of repeats (copies reusing the previous offset)
+    pub repeat_lengths: Vec<u64>,
+    /// Bytes produced by literals
+    pub literal_bytes: u64,
+    /// Bytes produced by copies with an explicit offset
+    pub copy_bytes: u64,
+    /// Bytes produced by repeats
+    pub repeat_bytes: u64,
+}
+
+impl BlockAnalysis {
+    /// Count `v` in its bit-length bucket
+    fn record(hist: &mut Vec<u64>, v: usize) {
+        let bucket = (usize::BITS - v.leading_zeros()) as usize;
+        if hist.len() <= bucket {
+            hist.resize(bucket + 1, 0);
+        }
+        hist[bucket] += 1;
+    }
+}
+
+/// Walk the tokens of a block and collect statistics on them
+///
+/// A diagnostic for tuning encoders: it reports the literal lengths, copy
+/// lengths and offsets, and repeats the block is made of, without keeping
+/// any output. The block is validated as [`decode`] would, except for the
+/// bytes copied, so a corrupt block returns `Error::Corrupt`.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{analyze_block, encode};
+///
+/// let analysis = analyze_block(&encode(&[b'a'; 100_000])).unwrap();
+/// assert_eq!(analysis.decoded_len, 100_000);
+/// assert!(analysis.repeat_bytes > 99_000);
+/// ```
+pub fn analyze_block(src: &[u8]) -> Result<BlockAnalysis> {
+    let (dlen, header_len) = decode_len(src)?;
+    let src = &src[header_len..];
+    let mut a = BlockAnalysis {
+        decoded_len: dlen,
+        ..Default::default()
+    };
+
+    let mut s = 0;
+    let mut d = 0;
+    let mut offset = 0;
+    while s < src.len() {
+        let length = match src[s] & 0x03 {
+            TAG_LITERAL => {
+                let (length, n) = decode_literal_length(&src[s..])?;
+                if length > src.len() - s - n || length > dlen - d {
+                    return Err(Error::Corrupt);
+                }
+                BlockAnalysis::record(&mut a.literal_lengths, length);
+                a.literal_bytes += length as u64;
+                s += n + length;
+                d += length;
+                continue;
+            }
+            TAG_COPY1 => {
+                let repeat = src.len() - s >= 2 && src[s] & 0xe0 == 0 && src[s + 1] == 0;
+                let (new_offset, length, n) = decode_copy1(&src[s..], offset)?;
+                s += n;
+                offset = new_offset;
+                if repeat {
+                    BlockAnalysis::record(&mut a.repeat_lengths, length);
+                    a.repeat_bytes += length as u64;
+                    if offset == 0 || d < offset || length > dlen - d {
+                        return Err(Error::Corrupt);
+                    }
+                    d += length;
+                    continue;
+                }
+                length
+            }
+            TAG_COPY2 => {
+                if src.len() - s < 3 {
+                    return Err(Error::Corrupt);
+                }
+                offset = u16::from_le_bytes(src[s + 1..s + 3].try_into().unwrap()) as usize;
+                let length = 1 + (src[s] >> 2) as usize;
+                s += 3;
+                length
+            }
+            _ => {
+                if src.len() - s < 5 {
+                    return Err(Error::Corrupt);
+                }
+                offset = u32::from_le_bytes(src[s + 1..s + 5].try_into().unwrap()) as usize;
+                let length = 1 + (src[s] >> 2) as usize;
+                s += 5;
+                length
+            }
+        };
+        if offset == 0 || d < offset || length > dlen - d {
+            return Err(Error::Corrupt);
+        }
+        BlockAnalysis::record(&mut a.copy_lengths, length);
+        BlockAnalysis::record(&mut a.copy_offsets, offset);
+        a.copy_bytes += length as u64;
+        d += length;
+    }
+
+    if d != dlen {
+        return Err(Error::Corrupt);
+    }
+    Ok(a)
+}
+
 /// Decode with dictionary
 ///
 /// Decodes S2 data that was compressed with a dictionary.
@@ -83,6 +663,12 @@ pub fn decode_with_dict(src: &[u8], dict: &Dict) -> Result<Vec<u8>> {
 /// decode arbitrarily large payloads should use the stream
 /// [`Reader`](crate::Reader) API instead, which processes one capped
 /// block at a time.
+///
+/// On 32-bit targets the cap is 1/16 of the address space (and
+/// [`decode_len`] already rejects lengths of 2 GiB and up). A declared
+/// length under the cap that the allocator still cannot satisfy, as on
+/// small embedded heaps, is reported as [`Error::TooLarge`] by the
+/// fallible reservation rather than aborting.
 pub const MAX_DECODE_DST_SIZE: usize = 256 * 1024 * 1024;
 
 /// Allocate a `Vec<u8>` of length `n` whose bytes are *uninitialized*.
@@ -128,7 +714,26 @@ fn alloc_uninit_dst(n: usize) -> Result<Vec<u8>> {
 /// number of bytes written into `dst`.
 ///
 /// Useful for hot loops that decode into a reusable buffer without
-/// allocating a fresh `Vec` per call.
+/// allocating a fresh `Vec` per call, and for writing straight into
+/// memory that isn't a `Vec`, such as a pre-sized memory-mapped output
+/// file. Only `dst[..n]` is written, and copies are resolved within it,
+/// including overlapping ones (a copy whose offset is shorter than its
+/// length), so `dst` can be any region of a larger mapping.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_into, decode_len, encode};
+///
+/// let data = b"payload payload payload payload".repeat(100);
+/// let compressed = encode(&data);
+///
+/// // e.g. a region of a memory-mapped file sized from the header
+/// let (len, _) = decode_len(&compressed).unwrap();
+/// let mut mapping = vec![0u8; 4096 + len];
+/// let n = decode_into(&mut mapping[4096..], &compressed).unwrap();
+/// assert_eq!(&mapping[4096..4096 + n], &data[..]);
+/// ```
 pub fn decode_into(dst: &mut [u8], src: &[u8]) -> Result<usize> {
     let (dlen, header_len) = decode_len(src)?;
 
@@ -143,6 +748,9 @@ pub fn decode_into(dst: &mut [u8], src: &[u8]) -> Result<usize> {
 
 /// Returns the length of the decoded block and the number of bytes
 /// that the length header occupied.
+///
+/// Returns `Error::Corrupt` if `src` doesn't start with a complete length
+/// header, including when it is empty.
 pub fn decode_len(src: &[u8]) -> Result<(usize, usize)> {
     let (v, n) = decode_varint(src)?;
 
@@ -368,7 +976,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -401,7 +1009,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -433,7 +1041,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -489,7 +1097,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -521,7 +1129,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -553,7 +1161,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
                         return Err(Error::Corrupt);
                     }
 
-                    let dict_start = dict.data().len() - offset + d;
+                    let dict_start = dict.resolve_offset(offset, d).ok_or(Error::Corrupt)?;
                     if dict_start + length > dict.data().len() {
                         return Err(Error::Corrupt);
                     }
@@ -579,7 +1187,7 @@ fn s2_decode_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> Result<()> {
 
 /// Decode the length of a literal chunk
 /// Returns (length, bytes_consumed)
-fn decode_literal_length(src: &[u8]) -> Result<(usize, usize)> {
+pub(crate) fn decode_literal_length(src: &[u8]) -> Result<(usize, usize)> {
     let x = (src[0] >> 2) as u32;
 
     match x {
@@ -619,7 +1227,7 @@ fn decode_literal_length(src: &[u8]) -> Result<(usize, usize)> {
 
 /// Decode a COPY1 tag
 /// Returns (offset, length, bytes_consumed)
-fn decode_copy1(src: &[u8], last_offset: usize) -> Result<(usize, usize, usize)> {
+pub(crate) fn decode_copy1(src: &[u8], last_offset: usize) -> Result<(usize, usize, usize)> {
     if src.len() < 2 {
         return Err(Error::Corrupt);
     }
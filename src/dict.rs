@@ -8,16 +8,31 @@ use alloc::vec::Vec;
 /// Minimum dictionary size
 pub const MIN_DICT_SIZE: usize = 16;
 
+/// Minimum dictionary size accepted by [`make_dict_allow_small`]: the
+/// shortest match the encoder emits
+const MIN_SMALL_DICT_SIZE: usize = 4;
+
+/// Width of the better encoder's long-match dictionary table
+const BETTER_L_TABLE_BITS: u32 = 17;
+
 /// Maximum dictionary size
 pub const MAX_DICT_SIZE: usize = 65536;
 
 /// Maximum offset where a dictionary entry can start
 pub const MAX_DICT_SRC_OFFSET: usize = 65535;
 
+/// Prefix of the versioned serialization. Read as a legacy uvarint repeat
+/// offset it exceeds MAX_DICT_SIZE, so it never starts a valid legacy dict.
+const DICT_MAGIC: &[u8] = b"\xff\xff\xff\x7fs2dict";
+
+/// Version written by `Dict::to_bytes_versioned`
+const DICT_VERSION: u8 = 1;
+
 /// Dictionary for S2 compression
 ///
 /// A dictionary allows better compression of similar data by pre-seeding
 /// the compression hash tables with common patterns.
+#[derive(Clone)]
 pub struct Dict {
     /// Dictionary data
     dict: Vec<u8>,
@@ -41,9 +56,19 @@ pub struct Dict {
 impl Dict {
     /// Create a dictionary from serialized bytes
     ///
-    /// The format is: uvarint(repeat_offset) followed by dictionary bytes.
-    /// Returns None if the dictionary is invalid.
+    /// Accepts both the legacy format written by [`to_bytes`](Dict::to_bytes)
+    /// (uvarint(repeat_offset) followed by dictionary bytes) and the
+    /// versioned format written by
+    /// [`to_bytes_versioned`](Dict::to_bytes_versioned).
+    /// Returns None if the dictionary is invalid or its version is unknown.
     pub fn new(data: &[u8]) -> Option<Self> {
+        let data = match data.strip_prefix(DICT_MAGIC) {
+            Some(rest) => match rest.split_first() {
+                Some((&DICT_VERSION, payload)) => payload,
+                _ => return None,
+            },
+            None => data,
+        };
         if data.is_empty() {
             return None;
         }
@@ -86,6 +111,20 @@ impl Dict {
         result
     }
 
+    /// Serialize dictionary to bytes with a format version marker
+    ///
+    /// Output format: a fixed magic prefix, a version byte, then the same
+    /// bytes as [`to_bytes`](Dict::to_bytes). [`Dict::new`] reads both
+    /// formats; prefer this one for dictionaries persisted across crate
+    /// upgrades, so future format changes are detected instead of misparsed.
+    pub fn to_bytes_versioned(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(DICT_MAGIC.len() + 1 + 3 + self.dict.len());
+        result.extend_from_slice(DICT_MAGIC);
+        result.push(DICT_VERSION);
+        result.extend_from_slice(&self.to_bytes());
+        result
+    }
+
     /// Get dictionary data
     pub fn data(&self) -> &[u8] {
         &self.dict
@@ -96,6 +135,70 @@ impl Dict {
         self.repeat
     }
 
+    /// Dictionary index a copy refers to
+    ///
+    /// A copy at output position `output_pos` with offset `offset` reaches
+    /// back before the start of the output when `offset > output_pos`; the
+    /// dictionary is treated as if it came right before the output, so it
+    /// starts at `data().len() - offset + output_pos`. Returns `None` if the
+    /// copy stays within the output, or if it reaches back past the start
+    /// of the dictionary.
+    pub fn resolve_offset(&self, offset: usize, output_pos: usize) -> Option<usize> {
+        if offset <= output_pos {
+            return None;
+        }
+        (self.dict.len() + output_pos).checked_sub(offset)
+    }
+
+    /// Estimate how much of `sample` the dictionary could match, from 0
+    /// (nothing) to 1 (everything)
+    ///
+    /// Looks up 7-byte sequences at up to 4096 evenly spaced positions of
+    /// `sample` in a hash table of the dictionary's 7-byte sequences, and
+    /// returns the fraction found in the dictionary. The table is built
+    /// for the call unless the dictionary already holds one; the encoders
+    /// don't use it. This is cheap next to compressing, so
+    /// callers can skip a dictionary that scores low. Matches within the
+    /// sample itself, which compression finds without a dictionary, are not
+    /// counted. Samples shorter than 8 bytes score 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::make_dict;
+    ///
+    /// let dict = make_dict(&b"GET /index.html HTTP/1.1\r\nHost: ".repeat(20), None).unwrap();
+    /// assert!(dict.similarity(b"GET /index.html HTTP/1.1\r\nHost: example.com") > 0.5);
+    /// assert!(dict.similarity(&[0x5a; 1000]) < 0.1);
+    /// ```
+    pub fn similarity(&self, sample: &[u8]) -> f64 {
+        const MAX_PROBES: usize = 4096;
+        if sample.len() < 8 {
+            return 0.0;
+        }
+        let built;
+        let table = match self.better_table_long.as_ref() {
+            Some(table) => table,
+            None => {
+                built = self.build_better_long_table();
+                &built
+            }
+        };
+
+        let positions = sample.len() - 7;
+        let step = positions.div_ceil(MAX_PROBES);
+        let mut probes = 0;
+        let mut found = 0;
+        for i in (0..positions).step_by(step) {
+            probes += 1;
+            let candidate = table[hash7(load64(sample, i), BETTER_L_TABLE_BITS) as usize] as usize;
+            if self.dict.get(candidate..candidate + 7) == Some(&sample[i..i + 7]) {
+                found += 1;
+            }
+        }
+        found as f64 / probes as f64
+    }
+
     /// Get fast hash table, initializing if needed
     #[allow(dead_code)]
     pub(crate) fn get_fast_table(&mut self) -> &[u16; 1 << 14] {
@@ -152,23 +255,30 @@ impl Dict {
 
     /// Initialize better hash tables
     fn init_better(&mut self) {
-        const L_TABLE_BITS: u32 = 17;
         const S_TABLE_BITS: u32 = 14;
 
-        let mut l_table = Box::new([0u16; 1 << L_TABLE_BITS]);
         let mut s_table = Box::new([0u16; 1 << S_TABLE_BITS]);
 
         // Hash every byte
         for i in 0..self.dict.len().saturating_sub(8) {
             let cv = load64(&self.dict, i);
-            l_table[hash7(cv, L_TABLE_BITS) as usize] = i as u16;
             s_table[hash4(cv, S_TABLE_BITS) as usize] = i as u16;
         }
 
-        self.better_table_long = Some(l_table);
+        self.better_table_long = Some(self.build_better_long_table());
         self.better_table_short = Some(s_table);
     }
 
+    /// Long-match table: every 7-byte sequence of the dictionary
+    fn build_better_long_table(&self) -> Box<[u16; 1 << BETTER_L_TABLE_BITS]> {
+        let mut table = Box::new([0u16; 1 << BETTER_L_TABLE_BITS]);
+        for i in 0..self.dict.len().saturating_sub(8) {
+            let cv = load64(&self.dict, i);
+            table[hash7(cv, BETTER_L_TABLE_BITS) as usize] = i as u16;
+        }
+        table
+    }
+
     /// Initialize best hash tables
     fn init_best(&mut self) {
         const L_TABLE_BITS: u32 = 19;
@@ -198,10 +308,49 @@ impl Dict {
 /// Create a dictionary from data
 ///
 /// If `data` is longer than MAX_DICT_SIZE, only the last MAX_DICT_SIZE bytes are used.
+/// Returns None if the (trimmed) data is shorter than MIN_DICT_SIZE, which
+/// includes empty data.
+///
 /// If `search_start` is provided, the repeat offset will be set to the last occurrence
 /// of that pattern in the dictionary (or a shorter prefix if exact match not found).
-/// If no match >= 4 bytes is found, repeat is set to 0.
+/// Prefixes are tried from longest to 4 bytes, and a prefix whose last occurrence
+/// starts within the final 8 bytes of the dictionary is passed over for the next
+/// shorter one, as the repeat offset must leave 8 bytes to match against.
+/// If no match >= 4 bytes is found, including when `search_start` is shorter
+/// than 4 bytes, repeat is set to 0.
 pub fn make_dict(data: &[u8], search_start: Option<&[u8]>) -> Option<Dict> {
+    make_dict_min(data, search_start, MIN_DICT_SIZE)
+}
+
+/// Create a dictionary from data, allowing dictionaries down to 4 bytes
+///
+/// Same as [`make_dict`], for tiny structured records (e.g. a shared 8-byte
+/// header) where a [`MIN_DICT_SIZE`] dictionary would not fit. Caveats:
+/// - only [`encode_with_dict`](crate::encode_with_dict) finds matches in
+///   such a dictionary, and as for any block, blocks under 32 bytes are
+///   stored as literals and the first byte is never part of a match;
+/// - the repeat offset stays 0 unless `search_start` is found at least 8
+///   bytes before the end, so a dictionary of 8 bytes or less keeps 0;
+/// - [`Dict::new`] still rejects serialized dictionaries below
+///   [`MIN_DICT_SIZE`], so keep the raw bytes rather than
+///   [`to_bytes`](Dict::to_bytes) to recreate one.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_with_dict, encode_with_dict, make_dict_allow_small};
+///
+/// let dict = make_dict_allow_small(b"HDR01:", None).unwrap();
+/// let record = b"\x00HDR01:a short record body";
+/// let compressed = encode_with_dict(record, &dict);
+/// assert_eq!(decode_with_dict(&compressed, &dict).unwrap(), record);
+/// ```
+pub fn make_dict_allow_small(data: &[u8], search_start: Option<&[u8]>) -> Option<Dict> {
+    make_dict_min(data, search_start, MIN_SMALL_DICT_SIZE)
+}
+
+/// [`make_dict`] with the minimum size as a parameter
+fn make_dict_min(data: &[u8], search_start: Option<&[u8]>, min_size: usize) -> Option<Dict> {
     if data.is_empty() {
         return None;
     }
@@ -213,24 +362,11 @@ pub fn make_dict(data: &[u8], search_start: Option<&[u8]>) -> Option<Dict> {
         data
     };
 
-    if dict_data.len() < MIN_DICT_SIZE {
+    if dict_data.len() < min_size {
         return None;
     }
 
-    // Find repeat offset
-    let mut repeat = 0;
-    if let Some(search) = search_start {
-        // Find longest match, trying progressively shorter lengths
-        for len in (4..=search.len()).rev() {
-            if let Some(pos) = find_last_occurrence(dict_data, &search[..len]) {
-                // Make sure we have at least 8 bytes remaining
-                if pos <= dict_data.len().saturating_sub(8) {
-                    repeat = pos;
-                    break;
-                }
-            }
-        }
-    }
+    let repeat = search_start.map_or(0, |search| find_repeat(dict_data, search));
 
     // Ensure capacity for extra bytes
     let mut dict = Vec::with_capacity(dict_data.len() + 16);
@@ -273,20 +409,75 @@ pub fn make_dict_manual(data: &[u8], first_idx: u16) -> Option<Dict> {
     })
 }
 
+/// Repeat offset for `make_dict`: the last occurrence of the longest prefix
+/// of `search` (at least 4 bytes) that occurs at least 8 bytes before the
+/// end of `dict`, or 0
+///
+/// Same result as trying `search[..len]` for every `len` from the longest
+/// down, but with a single pass over the dictionary.
+fn find_repeat(dict: &[u8], search: &[u8]) -> usize {
+    if search.len() < 4 {
+        return 0;
+    }
+
+    // last[k]: last position where exactly k bytes of `search` match
+    let mut last = vec![None; search.len() + 1];
+    for (i, len) in prefix_match_lens(dict, search).into_iter().enumerate() {
+        last[len] = Some(i);
+    }
+
+    // The last occurrence of search[..len] is the last position matching
+    // len bytes or more
+    let limit = dict.len().saturating_sub(8);
+    let mut pos = None;
+    for len in (4..=search.len()).rev() {
+        pos = pos.max(last[len]);
+        match pos {
+            Some(p) if p <= limit => return p,
+            _ => {}
+        }
+    }
+    0
+}
+
+/// Length of the longest common prefix of `needle` and `haystack[i..]`,
+/// for every position `i` of `haystack`
+///
+/// Z-algorithm over `needle` followed by `haystack`, O(n + m).
+fn prefix_match_lens(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    let m = needle.len();
+    let s: Vec<u8> = needle.iter().chain(haystack).copied().collect();
+    let n = s.len();
+    let mut z = vec![0; n];
+    let (mut l, mut r) = (0, 0);
+    for i in 1..n {
+        let mut k = if i < r { z[i - l].min(r - i) } else { 0 };
+        while i + k < n && s[k] == s[i + k] {
+            k += 1;
+        }
+        z[i] = k;
+        if i + k > r {
+            l = i;
+            r = i + k;
+        }
+    }
+    // Matches running on past the needle don't count
+    z.drain(..m.min(n));
+    for len in &mut z {
+        *len = (*len).min(m);
+    }
+    z
+}
+
 /// Find last occurrence of needle in haystack
+#[cfg(test)]
 fn find_last_occurrence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     if needle.is_empty() || needle.len() > haystack.len() {
         return None;
     }
-
-    // Manual implementation is clearer than iterator chaining for this case
-    #[allow(clippy::manual_find)]
-    for i in (0..=haystack.len() - needle.len()).rev() {
-        if &haystack[i..i + needle.len()] == needle {
-            return Some(i);
-        }
-    }
-    None
+    prefix_match_lens(haystack, needle)
+        .iter()
+        .rposition(|&len| len == needle.len())
 }
 
 // Helper functions for loading and hashing (matching encode.rs patterns)
@@ -301,19 +492,19 @@ fn load64(data: &[u8], offset: usize) -> u64 {
 #[allow(dead_code)]
 #[inline(always)]
 fn hash4(v: u64, bits: u32) -> u32 {
-    ((v.wrapping_mul(0x1e35a7bd)) >> (32 - bits)) as u32
+    (v as u32).wrapping_mul(2654435761) >> (32 - bits)
 }
 
 #[allow(dead_code)]
 #[inline(always)]
 fn hash6(v: u64, bits: u32) -> u32 {
-    ((v.wrapping_mul(0xcf1bbcdcb7a56463)) >> (64 - bits)) as u32
+    (((v << 16).wrapping_mul(227718039650203)) >> (64 - bits)) as u32
 }
 
 #[allow(dead_code)]
 #[inline(always)]
 fn hash7(v: u64, bits: u32) -> u32 {
-    ((v.wrapping_mul(0xcf1bbcdcb7a56463)) >> (64 - bits)) as u32
+    (((v << 8).wrapping_mul(58295818150454627)) >> (64 - bits)) as u32
 }
 
 #[allow(dead_code)]
@@ -357,6 +548,68 @@ mod tests {
         let _c3 = crate::encode::encode_best_with_dict(data, &dict);
     }
 
+    #[test]
+    fn test_dict_resolve_offset() {
+        let dict = make_dict(
+            b"0123456789 the quick brown fox jumps over the lazy dog 0123456789",
+            None,
+        )
+        .unwrap();
+        let dict_data = dict.data();
+
+        assert_eq!(dict.resolve_offset(1, 0), Some(dict_data.len() - 1));
+        assert_eq!(dict.resolve_offset(dict_data.len(), 0), Some(0));
+        assert_eq!(dict.resolve_offset(dict_data.len() + 5, 5), Some(0));
+        assert_eq!(dict.resolve_offset(10, 3), Some(dict_data.len() - 7));
+        assert_eq!(dict.resolve_offset(3, 3), None); // start of the output
+        assert_eq!(dict.resolve_offset(2, 10), None);
+        assert_eq!(dict.resolve_offset(dict_data.len() + 1, 0), None);
+
+        // Walk the encoder's output: every copy reaching before the output
+        // must resolve to dictionary bytes equal to the data it produces.
+        use crate::decode::{decode_copy1, decode_len, decode_literal_length};
+        let data = b"0123456789 the quick brown fox jumps over the lazy dog, 0123456789!";
+        let compressed = crate::encode::encode_with_dict(data, &dict);
+        let (_, mut s) = decode_len(&compressed).unwrap();
+        let (mut d, mut offset, mut dict_copies) = (0, dict_data.len() - dict.repeat(), 0);
+        while s < compressed.len() {
+            let length = match compressed[s] & 0x03 {
+                0 => {
+                    let (length, n) = decode_literal_length(&compressed[s..]).unwrap();
+                    s += n + length;
+                    d += length;
+                    continue;
+                }
+                1 => {
+                    let (o, length, n) = decode_copy1(&compressed[s..], offset).unwrap();
+                    offset = o;
+                    s += n;
+                    length
+                }
+                2 => {
+                    offset = u16::from_le_bytes([compressed[s + 1], compressed[s + 2]]) as usize;
+                    s += 3;
+                    1 + (compressed[s - 3] >> 2) as usize
+                }
+                _ => {
+                    offset =
+                        u32::from_le_bytes(compressed[s + 1..s + 5].try_into().unwrap()) as usize;
+                    s += 5;
+                    1 + (compressed[s - 5] >> 2) as usize
+                }
+            };
+            if let Some(start) = dict.resolve_offset(offset, d) {
+                assert_eq!(&dict_data[start..start + length], &data[d..d + length]);
+                dict_copies += 1;
+            }
+            d += length;
+        }
+        assert_eq!(d, data.len());
+        assert!(dict_copies > 0);
+
+        assert_eq!(decode_with_dict(&compressed, &dict).unwrap(), data);
+    }
+
     #[test]
     fn test_dict_new() {
         // Create a simple dictionary
@@ -380,6 +633,30 @@ mod tests {
         assert_eq!(dict.repeat(), dict2.repeat());
     }
 
+    #[test]
+    fn test_dict_versioned_serialization() {
+        let data = b"Test dictionary data for versioned serialization";
+        let dict = make_dict(data, Some(b"data")).unwrap();
+
+        let versioned = dict.to_bytes_versioned();
+        assert!(versioned.starts_with(DICT_MAGIC));
+        let dict2 = Dict::new(&versioned).unwrap();
+        assert_eq!(dict.data(), dict2.data());
+        assert_eq!(dict.repeat(), dict2.repeat());
+
+        // Legacy bytes still load, and both formats describe the same dict.
+        let legacy = Dict::new(&dict.to_bytes()).unwrap();
+        assert_eq!(legacy.data(), dict2.data());
+        assert_eq!(legacy.repeat(), dict2.repeat());
+
+        // Unknown versions and a bare header are rejected, not misparsed.
+        let mut future = versioned.clone();
+        future[DICT_MAGIC.len()] = DICT_VERSION + 1;
+        assert!(Dict::new(&future).is_none());
+        assert!(Dict::new(DICT_MAGIC).is_none());
+        assert!(Dict::new(&versioned[..DICT_MAGIC.len() + 1]).is_none());
+    }
+
     #[test]
     fn test_dict_size_limits() {
         // Too small
@@ -400,6 +677,35 @@ mod tests {
         assert_eq!(dict.data().len(), MAX_DICT_SIZE);
     }
 
+    #[test]
+    fn test_make_dict_allow_small() {
+        use crate::{decode_with_dict, encode, encode_with_dict};
+
+        assert!(make_dict_allow_small(b"abc", None).is_none());
+        assert!(make_dict(b"HDR01:", None).is_none());
+        let dict = make_dict_allow_small(b"HDR01:", Some(b"HDR0")).unwrap();
+        assert_eq!(dict.data(), b"HDR01:");
+        assert_eq!(dict.repeat(), 0);
+
+        // A record that only matches the dictionary
+        let record = b"\x07HDR01:qwertyuiopasdfghjklzxcvbnm,./";
+        let compressed = encode_with_dict(record, &dict);
+        assert_eq!(decode_with_dict(&compressed, &dict).unwrap(), record);
+        assert!(compressed.len() < encode(record).len());
+
+        for len in 4..16 {
+            let dict = make_dict_allow_small(&b"0123456789abcdef"[..len], None).unwrap();
+            let data = b"x0123456789abcdef0123456789abcdef".repeat(3);
+            let compressed = encode_with_dict(&data, &dict);
+            assert_eq!(
+                decode_with_dict(&compressed, &dict).unwrap(),
+                data,
+                "{}",
+                len
+            );
+        }
+    }
+
     #[test]
     fn test_make_dict_with_search() {
         let data = b"The quick brown fox jumps over the lazy dog. The quick brown fox.";
@@ -414,6 +720,33 @@ mod tests {
         assert!(dict.data()[dict.repeat()..].starts_with(b"The quick"));
     }
 
+    #[test]
+    fn test_make_dict_edge_cases() {
+        let data = b"The quick brown fox jumps over the lazy dog.";
+
+        assert!(make_dict(&[], None).is_none());
+        assert!(make_dict(&[], Some(b"The quick")).is_none());
+        assert!(make_dict(&data[..MIN_DICT_SIZE - 1], None).is_none());
+
+        // Searches too short to match fall back to offset 0.
+        for search in [&b""[..], b"T", b"fox", b"dog"] {
+            assert_eq!(make_dict(data, Some(search)).unwrap().repeat(), 0);
+        }
+        assert_eq!(make_dict(data, Some(b"dog.")).unwrap().repeat(), 0);
+
+        // A match can start exactly 8 bytes before the end...
+        let mut data = vec![b'.'; 32];
+        data.extend_from_slice(b"ABCDEFGH");
+        let dict = make_dict(&data, Some(b"ABCDEFGH")).unwrap();
+        assert_eq!(dict.repeat(), data.len() - 8);
+
+        // ...but not later, even if an earlier, shorter match exists.
+        let mut data = vec![b'.'; 32];
+        data.extend_from_slice(b"ABCDxxxxxxxxxxxxABCDEFG");
+        let dict = make_dict(&data, Some(b"ABCDEFG")).unwrap();
+        assert_eq!(dict.repeat(), 0);
+    }
+
     #[test]
     fn test_make_dict_manual() {
         let data = vec![b'A'; 100];
@@ -430,5 +763,95 @@ mod tests {
         assert_eq!(find_last_occurrence(haystack, b"hello"), Some(25));
         assert_eq!(find_last_occurrence(haystack, b"Hello"), Some(0));
         assert_eq!(find_last_occurrence(haystack, b"xyz"), None);
+
+        // Empty or oversized needles and empty haystacks never underflow.
+        assert_eq!(find_last_occurrence(haystack, b""), None);
+        assert_eq!(find_last_occurrence(b"", b""), None);
+        assert_eq!(find_last_occurrence(b"", b"a"), None);
+        assert_eq!(find_last_occurrence(b"abc", b"abcd"), None);
+        assert_eq!(find_last_occurrence(b"abc", b"abc"), Some(0));
+    }
+
+    #[test]
+    fn test_dict_similarity() {
+        let twain = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+        let (train, sample) = twain.split_at(10_000);
+        let related = make_dict(train, None).unwrap();
+        // Same size, unrelated content
+        let mut x = 7u32;
+        let noise: Vec<u8> = (0..10_000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        let unrelated = make_dict(&noise, None).unwrap();
+
+        // A dictionary made from the sample itself matches nearly all of it
+        let own = make_dict(&twain[..MAX_DICT_SIZE.min(twain.len())], None).unwrap();
+        assert!(own.similarity(sample) > 0.9, "{}", own.similarity(sample));
+        // Other text from the same book shares some phrases
+        let score = related.similarity(sample);
+        assert!(score > 0.05, "related {}", score);
+        let score = unrelated.similarity(sample);
+        assert!(score < 0.01, "unrelated {}", score);
+
+        // Same result whether or not the table was built for encoding
+        let mut warmed = make_dict(train, None).unwrap();
+        warmed.get_better_tables();
+        assert_eq!(warmed.similarity(sample), related.similarity(sample));
+
+        assert_eq!(related.similarity(b"short"), 0.0);
+        // Long samples are sampled, not scanned in full
+        let long = sample.repeat(100);
+        assert!((own.similarity(&long) - own.similarity(sample)).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_make_dict_long_search() {
+        // The search scan used before find_repeat, as the reference
+        fn naive_repeat(dict: &[u8], search: &[u8]) -> usize {
+            for len in (4..=search.len()).rev() {
+                let needle = &search[..len];
+                if let Some(pos) = dict.windows(len).rposition(|w| w == needle) {
+                    if pos <= dict.len().saturating_sub(8) {
+                        return pos;
+                    }
+                }
+            }
+            0
+        }
+
+        let mut seed = 3u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        };
+        // Low-entropy data, so prefixes of many lengths occur
+        let mut data: Vec<u8> = (0..MAX_DICT_SIZE)
+            .map(|_| b"ab"[next() as usize & 1])
+            .collect();
+        let search: Vec<u8> = data[40_000..41_024].to_vec();
+        let start = std::time::Instant::now();
+        let dict = make_dict(&data, Some(&search)).unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(dict.repeat(), naive_repeat(&data, &search));
+        assert!(dict.data()[dict.repeat()..].starts_with(&search[..4]));
+        assert!(elapsed < std::time::Duration::from_secs(1), "{:?}", elapsed);
+
+        // Only a short prefix near the end: passed over for an earlier one
+        data.truncate(1000);
+        data.extend_from_slice(b"zzzzQRSTuvwx");
+        let search = b"zzzzQRSTuvwxyz".to_vec();
+        for (dict, search) in [
+            (&data[..], &search[..]),
+            (&data[..1005], &search[..]),
+            (&b"QRST0123QRST"[..], &b"QRST"[..]),
+            (&b"abcdefgh"[..], &b"abcdefgh"[..]),
+        ] {
+            assert_eq!(find_repeat(dict, search), naive_repeat(dict, search));
+        }
     }
 }
@@ -4,7 +4,7 @@
 // license that can be found in the LICENSE file.
 
 use crate::constants::*;
-use crate::dict::Dict;
+use crate::dict::{Dict, MAX_DICT_SRC_OFFSET};
 use crate::error::{Error, Result};
 use crate::varint::encode_varint;
 use alloc::vec::Vec;
@@ -55,7 +55,7 @@ impl Encoder {
     /// the free `encode()` function but reuses internal hash-table
     /// storage across calls.
     pub fn encode(&mut self, src: &[u8]) -> Vec<u8> {
-        encode_inner(src, &mut self.standard_table)
+        encode_inner(src, &mut self.standard_table, &EncodeOptions::default())
     }
 
     /// Encode `src` using the better-compression algorithm. Equivalent
@@ -68,6 +68,7 @@ impl Encoder {
             &mut self.better_s16,
             &mut self.better_l32,
             &mut self.better_s32,
+            &BetterOptions::default(),
         )
     }
 
@@ -76,7 +77,12 @@ impl Encoder {
     /// reuse. Buffer reuse matters most here because the best-mode
     /// hash tables are 4.5 MiB total.
     pub fn encode_best(&mut self, src: &[u8]) -> Vec<u8> {
-        encode_best_inner(src, &mut self.best_l, &mut self.best_s)
+        encode_best_inner(
+            src,
+            &mut self.best_l,
+            &mut self.best_s,
+            &BestOptions::default(),
+        )
     }
 
     /// Encode `src` in Snappy-compatible format. Equivalent to the
@@ -161,11 +167,112 @@ fn alloc_uninit_dst(n: usize) -> Vec<u8> {
 /// The encoding is compatible with the Go s2 implementation.
 pub fn encode(src: &[u8]) -> Vec<u8> {
     let mut table = Vec::new();
-    encode_inner(src, &mut table)
+    encode_inner(src, &mut table, &EncodeOptions::default())
 }
 
-fn encode_inner(src: &[u8], table_buf: &mut Vec<u32>) -> Vec<u8> {
-    let max_len = max_encoded_len(src.len()).expect("source too large");
+/// Table settings for [`encode_with_options`]
+///
+/// The default reproduces [`encode`] exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeOptions {
+    /// Force the hash table width, in bits, instead of choosing it from the
+    /// input size (8 bits below 512 bytes, 10 below 4KB, 12 below 16KB,
+    /// 14 above). Each width comes with its own hash and search stride, so
+    /// blocks encoded with the same forced width use the same algorithm
+    /// whatever their size, which keeps output reproducible across block
+    /// sizes (e.g. for dedup testing).
+    ///
+    /// Widths are rounded up to the next of 8, 10, 12 or 14; larger values
+    /// are treated as 14. Forcing a narrow table on large inputs reduces
+    /// the ratio; forcing 14 bits on small inputs costs speed, as the
+    /// 64KB table is cleared for every block. Default: `None`.
+    pub table_bits: Option<u32>,
+    /// Force the hash used to find matches instead of the one paired with
+    /// the table width (4 bytes for 8 and 10 bits, 5 for 12, 6 for 14).
+    ///
+    /// [`HashKind::Hash4`] also finds the short matches that make up much
+    /// of the gain on text, at some cost in speed. [`HashKind::Hash6`]
+    /// only finds matches whose first 6 bytes agree, which is faster and
+    /// keeps 4-byte prefixes repeating at aligned offsets in structured
+    /// binary data from crowding out the longer matches. The default
+    /// pairing sits between the two on both kinds of data (see the
+    /// `encode_hash` benchmark) and keeps the output identical to Go's
+    /// s2. Default: `None`.
+    pub hash: Option<HashKind>,
+    /// Keep the token-encoded block even when it does not save at least
+    /// 1/32 of the input, instead of falling back to storing the input as
+    /// a single literal. The output may then be slightly larger than
+    /// [`encode`]'s, but always reflects what the match finder did, which
+    /// is what benchmarks of the matcher on marginal data want to see.
+    /// Inputs under 32 bytes are still stored as a literal, as there is
+    /// nothing to search. Default: `false`.
+    pub force: bool,
+}
+
+/// Hash function for the standard encoder's match finder, see
+/// [`EncodeOptions::hash`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashKind {
+    /// Hash the next 4 bytes
+    Hash4,
+    /// Hash the next 6 bytes
+    Hash6,
+}
+
+/// Encode using the standard algorithm with custom table settings
+///
+/// With `EncodeOptions::default()` this is identical to [`encode`].
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode, encode_with_options, EncodeOptions};
+///
+/// let data = b"the same table width for every block size".repeat(20);
+/// let opts = EncodeOptions {
+///     table_bits: Some(14),
+///     ..Default::default()
+/// };
+/// let compressed = encode_with_options(&data, &opts);
+/// assert_eq!(decode(&compressed).unwrap(), data);
+/// ```
+pub fn encode_with_options(src: &[u8], opts: &EncodeOptions) -> Vec<u8> {
+    let mut table = Vec::new();
+    encode_inner(src, &mut table, opts)
+}
+
+/// Encode src as tokens even when that barely compresses it
+///
+/// [`encode`] stores a block as a single literal when matching saves less
+/// than 1/32 of it. This keeps the matches found instead, as with
+/// [`EncodeOptions::force`], so the result can be a few bytes larger than
+/// `encode`'s. It decodes the same way.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode, encode_force};
+///
+/// let data = b"marginal data, marginal gains";
+/// let compressed = encode_force(data);
+/// assert_eq!(decode(&compressed).unwrap(), data);
+/// ```
+pub fn encode_force(src: &[u8]) -> Vec<u8> {
+    encode_with_options(
+        src,
+        &EncodeOptions {
+            force: true,
+            ..Default::default()
+        },
+    )
+}
+
+fn encode_inner(src: &[u8], table_buf: &mut Vec<u32>, opts: &EncodeOptions) -> Vec<u8> {
+    let mut max_len = max_encoded_len(src.len()).expect("source too large");
+    if opts.force {
+        // Room for the unchecked token stream, see encode_block_fast_table
+        max_len += src.len();
+    }
     let mut dst = alloc_uninit_dst(max_len);
 
     // Write the varint-encoded length of the decompressed bytes
@@ -182,7 +289,7 @@ fn encode_inner(src: &[u8], table_buf: &mut Vec<u32>) -> Vec<u8> {
         return dst;
     }
 
-    let n = encode_block(&mut dst[d..], src, table_buf);
+    let n = encode_block(&mut dst[d..], src, table_buf, opts);
     if n > 0 {
         dst.truncate(d + n);
         return dst;
@@ -196,11 +303,54 @@ fn encode_inner(src: &[u8], table_buf: &mut Vec<u32>) -> Vec<u8> {
 
 /// EncodeBetter provides better compression than Encode but is slower
 pub fn encode_better(src: &[u8]) -> Vec<u8> {
+    encode_better_with_options(src, &BetterOptions::default())
+}
+
+/// Match-finding settings for [`encode_better_with_options`]
+///
+/// The default reproduces [`encode_better`] exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BetterOptions {
+    /// Distance between the positions inside each match that are added to
+    /// the long-match table once the match is emitted. On text, 1 (every
+    /// position) gives about 0.5% smaller output for about 7% more time,
+    /// while 3 and above lose ratio without a measurable speedup, since
+    /// this loop is a small part of the work; the `encode_better_stride`
+    /// benchmark prints ratio and speed for a few values. Values below 1
+    /// are treated as 1. Default: 2, as in Go's s2.
+    pub mid_index_stride: usize,
+}
+
+impl Default for BetterOptions {
+    fn default() -> Self {
+        BetterOptions {
+            mid_index_stride: 2,
+        }
+    }
+}
+
+/// Encode using the better-compression algorithm with custom settings
+///
+/// With `BetterOptions::default()` this is identical to [`encode_better`].
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode, encode_better_with_options, BetterOptions};
+///
+/// let data = b"an example an example an example of a sparser index".repeat(20);
+/// let sparse = BetterOptions {
+///     mid_index_stride: 4,
+/// };
+/// let compressed = encode_better_with_options(&data, &sparse);
+/// assert_eq!(decode(&compressed).unwrap(), data);
+/// ```
+pub fn encode_better_with_options(src: &[u8], opts: &BetterOptions) -> Vec<u8> {
     let mut l16 = Vec::new();
     let mut s16 = Vec::new();
     let mut l32 = Vec::new();
     let mut s32 = Vec::new();
-    encode_better_inner(src, &mut l16, &mut s16, &mut l32, &mut s32)
+    encode_better_inner(src, &mut l16, &mut s16, &mut l32, &mut s32, opts)
 }
 
 fn encode_better_inner(
@@ -209,6 +359,7 @@ fn encode_better_inner(
     s16: &mut Vec<u16>,
     l32: &mut Vec<u32>,
     s32: &mut Vec<u32>,
+    opts: &BetterOptions,
 ) -> Vec<u8> {
     let max_len = max_encoded_len(src.len()).expect("source too large");
     let mut dst = alloc_uninit_dst(max_len);
@@ -227,7 +378,7 @@ fn encode_better_inner(
         return dst;
     }
 
-    let n = encode_block_better(&mut dst[d..], src, l16, s16, l32, s32);
+    let n = encode_block_better(&mut dst[d..], src, l16, s16, l32, s32, opts);
     if n > 0 {
         dst.truncate(d + n);
         return dst;
@@ -292,11 +443,44 @@ pub fn encode_best_with_dict(src: &[u8], _dict: &Dict) -> Vec<u8> {
     encode_best(src)
 }
 
+/// Encode `src` so the output is never more than one byte larger than it
+///
+/// The output is a leading byte followed by either an S2 block (0), when
+/// [`encode`] makes the data smaller, or `src` itself (1). It is not an S2
+/// block: read it back with [`decode_guaranteed`](crate::decode_guaranteed).
+/// For storage that has to bound the stored size of incompressible data,
+/// which a plain block can grow by a few bytes.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_guaranteed, encode_guaranteed};
+///
+/// let data = [0x5a, 0x13, 0xc7, 0x80];
+/// let stored = encode_guaranteed(&data);
+/// assert_eq!(stored.len(), data.len() + 1);
+/// assert_eq!(decode_guaranteed(&stored).unwrap(), data);
+/// ```
+pub fn encode_guaranteed(src: &[u8]) -> Vec<u8> {
+    let block = encode(src);
+    let mut dst = Vec::with_capacity(1 + block.len().min(src.len()));
+    if block.len() < src.len() {
+        dst.push(GUARANTEED_S2);
+        dst.extend_from_slice(&block);
+    } else {
+        dst.push(GUARANTEED_STORED);
+        dst.extend_from_slice(src);
+    }
+    dst
+}
+
 /// Encode using Snappy-compatible format (no repeat offsets)
 ///
 /// This produces output compatible with the original Snappy format,
-/// which can be decompressed by both S2 and Snappy decoders.
-/// The encoding is less efficient than S2 as it doesn't use repeat offsets.
+/// which can be decompressed by both S2 and Snappy decoders. The output
+/// is byte-for-byte identical to Go's `snappy.Encode`: the input is split
+/// into independent 64KB fragments, each compressed with the Snappy
+/// algorithm.
 pub fn encode_snappy(src: &[u8]) -> Vec<u8> {
     let mut table = Vec::new();
     encode_snappy_inner(src, &mut table)
@@ -307,39 +491,184 @@ fn encode_snappy_inner(src: &[u8], table_buf: &mut Vec<u32>) -> Vec<u8> {
     let mut dst = alloc_uninit_dst(max_len);
 
     // Write the varint-encoded length of the decompressed bytes
-    let d = encode_varint(&mut dst, src.len() as u64);
+    let mut d = encode_varint(&mut dst, src.len() as u64);
 
-    if src.is_empty() {
-        dst.truncate(d);
-        return dst;
+    // Like Go's snappy.Encode: independent 64KB fragments, with no
+    // fallback to a single literal for incompressible input.
+    for fragment in src.chunks(MAX_SNAPPY_BLOCK_SIZE) {
+        if fragment.len() < SNAPPY_MIN_NON_LITERAL_BLOCK_SIZE {
+            d += emit_literal(&mut dst[d..], fragment);
+        } else {
+            d += encode_block_snappy(&mut dst[d..], fragment, table_buf);
+        }
     }
+    dst.truncate(d);
+    dst
+}
 
-    if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
-        let n = emit_literal(&mut dst[d..], src);
-        dst.truncate(d + n);
-        return dst;
-    }
+/// Encode in Snappy-compatible format with the better-compression algorithm
+///
+/// Like [`encode_snappy`], the output has no repeat codes and is split into
+/// independent 64KB fragments, so any Snappy decoder reads it. Matches are
+/// found as by [`encode_better`], with a long and a short hash table, which
+/// gives smaller output than `encode_snappy` at some cost in speed. Port of
+/// Go's `s2.EncodeSnappyBetter` match finding; the output is not meant to
+/// be byte-for-byte identical to it.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_snappy, encode_snappy, encode_snappy_better};
+///
+/// let data = b"Snappy compatible, but with better matches. ".repeat(100);
+/// let compressed = encode_snappy_better(&data);
+/// assert!(compressed.len() <= encode_snappy(&data).len());
+/// assert_eq!(decode_snappy(&compressed).unwrap(), data);
+/// ```
+pub fn encode_snappy_better(src: &[u8]) -> Vec<u8> {
+    let max_len = max_encoded_len(src.len()).expect("source too large");
+    let mut dst = alloc_uninit_dst(max_len);
+    let mut d = encode_varint(&mut dst, src.len() as u64);
 
-    let n = encode_block_snappy(&mut dst[d..], src, table_buf);
-    if n > 0 {
-        dst.truncate(d + n);
-        return dst;
+    let mut l_table = Vec::new();
+    let mut s_table = Vec::new();
+    for fragment in src.chunks(MAX_SNAPPY_BLOCK_SIZE) {
+        if fragment.len() < MIN_NON_LITERAL_BLOCK_SIZE {
+            d += emit_literal(&mut dst[d..], fragment);
+        } else {
+            d += encode_block_snappy_better(&mut dst[d..], fragment, &mut l_table, &mut s_table);
+        }
     }
-
-    // Not compressible
-    let n = emit_literal(&mut dst[d..], src);
-    dst.truncate(d + n);
+    dst.truncate(d);
     dst
 }
 
 /// EncodeBest provides the best compression but is the slowest
 pub fn encode_best(src: &[u8]) -> Vec<u8> {
+    encode_best_with_options(src, &BestOptions::default())
+}
+
+/// Compression level, selecting between [`encode`], [`encode_better`] and
+/// [`encode_best`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Level {
+    /// [`encode`]: fastest, lowest ratio
+    #[default]
+    Fast,
+    /// [`encode_better`]: better ratio, slower
+    Better,
+    /// [`encode_best`]: best ratio, slowest
+    Best,
+}
+
+// Used by the stream writer and reader
+#[cfg(feature = "std")]
+impl Level {
+    /// Encode `src` as a block at this level
+    pub(crate) fn encode(self, src: &[u8]) -> Vec<u8> {
+        match self {
+            Level::Fast => encode(src),
+            Level::Better => encode_better(src),
+            Level::Best => encode_best(src),
+        }
+    }
+
+    /// Encode `src` as a block at this level, using `dict`
+    pub(crate) fn encode_with_dict(self, src: &[u8], dict: &Dict) -> Vec<u8> {
+        match self {
+            Level::Fast => encode_with_dict(src, dict),
+            Level::Better => encode_better_with_dict(src, dict),
+            Level::Best => encode_best_with_dict(src, dict),
+        }
+    }
+
+    /// Id of the level as stored in a stream's level chunk
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Level::Fast => 0,
+            Level::Better => 1,
+            Level::Best => 2,
+        }
+    }
+
+    /// Level for an id read from a level chunk
+    pub(crate) fn from_id(id: u8) -> Option<Level> {
+        match id {
+            0 => Some(Level::Fast),
+            1 => Some(Level::Better),
+            2 => Some(Level::Best),
+            _ => None,
+        }
+    }
+}
+
+/// Search effort settings for [`encode_best_with_options`]
+///
+/// The defaults reproduce [`encode_best`] exactly. Lowering `max_skip` and
+/// enabling `extra_candidate_positions` spends more time looking for
+/// matches, which usually (but, as match selection is greedy, not always)
+/// gives smaller output; the opposite settings trade ratio for speed.
+/// Every setting produces a valid block for the standard decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BestOptions {
+    /// Largest step taken between probed positions while no match is found.
+    /// The step grows with the length of the pending literal run up to this
+    /// cap, so smaller values search incompressible stretches more densely.
+    /// Values below 1 are treated as 1. Default: 64.
+    pub max_skip: usize,
+    /// Once a match is found, also probe the two following positions and
+    /// the position near the end of the match for a better one.
+    /// Default: true.
+    pub extra_candidate_positions: bool,
+    /// Score bonus, in bytes, for a match that starts right where the
+    /// previous one ended and so needs no literals before it. Higher values
+    /// favor back-to-back copies over a later, longer match reached after a
+    /// short literal run; 0 scores only the bytes saved. Values from 0 to
+    /// about 4 are useful, beyond that weak matches win over better ones.
+    /// Default: 1.
+    pub no_literal_bonus: i32,
+}
+
+impl Default for BestOptions {
+    fn default() -> Self {
+        BestOptions {
+            max_skip: 64,
+            extra_candidate_positions: true,
+            no_literal_bonus: 1,
+        }
+    }
+}
+
+/// Encode using the best-compression algorithm with custom search effort
+///
+/// With `BestOptions::default()` this is identical to [`encode_best`].
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode, encode_best_with_options, BestOptions};
+///
+/// let data = b"an example an example an example of tunable effort".repeat(20);
+/// let fast = BestOptions {
+///     max_skip: 256,
+///     extra_candidate_positions: false,
+///     ..BestOptions::default()
+/// };
+/// let compressed = encode_best_with_options(&data, &fast);
+/// assert_eq!(decode(&compressed).unwrap(), data);
+/// ```
+pub fn encode_best_with_options(src: &[u8], opts: &BestOptions) -> Vec<u8> {
     let mut l = Vec::new();
     let mut s = Vec::new();
-    encode_best_inner(src, &mut l, &mut s)
+    encode_best_inner(src, &mut l, &mut s, opts)
 }
 
-fn encode_best_inner(src: &[u8], l_buf: &mut Vec<u64>, s_buf: &mut Vec<u64>) -> Vec<u8> {
+fn encode_best_inner(
+    src: &[u8],
+    l_buf: &mut Vec<u64>,
+    s_buf: &mut Vec<u64>,
+    opts: &BestOptions,
+) -> Vec<u8> {
     let max_len = max_encoded_len(src.len()).expect("source too large");
     let mut dst = alloc_uninit_dst(max_len);
 
@@ -357,7 +686,7 @@ fn encode_best_inner(src: &[u8], l_buf: &mut Vec<u64>, s_buf: &mut Vec<u64>) ->
         return dst;
     }
 
-    let n = encode_block_best(&mut dst[d..], src, l_buf, s_buf);
+    let n = encode_block_best(&mut dst[d..], src, l_buf, s_buf, opts);
     if n > 0 {
         dst.truncate(d + n);
         return dst;
@@ -493,68 +822,17 @@ fn emit_literal(dst: &mut [u8], lit: &[u8]) -> usize {
     i + lit.len()
 }
 
-/// Emit a copy chunk without repeat optimization and return the number of bytes written
-fn emit_copy_no_repeat(dst: &mut [u8], offset: usize, length: usize) -> usize {
-    if offset >= 65536 {
-        let mut i = 0;
-        let mut remaining = length;
-
-        if remaining > 64 {
-            dst[0] = ((63 << 2) | TAG_COPY4 as usize) as u8;
-            let bytes = (offset as u32).to_le_bytes();
-            dst[1] = bytes[0];
-            dst[2] = bytes[1];
-            dst[3] = bytes[2];
-            dst[4] = bytes[3];
-            remaining -= 64;
-            i = 5;
-
-            if remaining >= 4 {
-                return i + emit_copy_no_repeat(&mut dst[i..], offset, remaining);
-            }
-        }
-
-        if remaining == 0 {
-            return i;
-        }
-
-        dst[i] = (((remaining - 1) << 2) | TAG_COPY4 as usize) as u8;
-        let bytes = (offset as u32).to_le_bytes();
-        dst[i + 1] = bytes[0];
-        dst[i + 2] = bytes[1];
-        dst[i + 3] = bytes[2];
-        dst[i + 4] = bytes[3];
-        return i + 5;
-    }
-
-    // Offset no more than 2 bytes
-    if length > 64 {
-        // Emit a length 60 copy, encoded as 3 bytes
-        dst[2] = (offset >> 8) as u8;
-        dst[1] = offset as u8;
-        dst[0] = ((59 << 2) | TAG_COPY2 as usize) as u8;
-        let remaining = length - 60;
-        // Emit remaining, at least 4 bytes remain
-        return 3 + emit_copy_no_repeat(&mut dst[3..], offset, remaining);
-    }
-
-    if length >= 12 || offset >= 2048 {
-        // Emit the remaining copy, encoded as 3 bytes
-        dst[2] = (offset >> 8) as u8;
-        dst[1] = offset as u8;
-        dst[0] = (((length - 1) << 2) | TAG_COPY2 as usize) as u8;
-        return 3;
-    }
-
-    // Emit the remaining copy, encoded as 2 bytes
-    dst[1] = offset as u8;
-    dst[0] = ((offset >> 8) << 5 | ((length - 4) << 2) | TAG_COPY1 as usize) as u8;
-    2
-}
-
 /// Emit a COPY1 tag (11-bit offset)
 #[allow(dead_code)]
 fn emit_copy1(dst: &mut [u8], offset: usize, length: usize) -> usize {
+    debug_assert!(
+        (1..2048).contains(&offset),
+        "emit_copy1: offset {offset} out of range"
+    );
+    debug_assert!(
+        (4..=11).contains(&length),
+        "emit_copy1: length {length} out of range"
+    );
     dst[0] = ((offset >> 8) << 5 | ((length - 4) << 2) | TAG_COPY1 as usize) as u8;
     dst[1] = offset as u8;
     2
@@ -563,6 +841,14 @@ fn emit_copy1(dst: &mut [u8], offset: usize, length: usize) -> usize {
 /// Emit a COPY2 tag (16-bit offset)
 #[allow(dead_code)]
 fn emit_copy2(dst: &mut [u8], offset: usize, length: usize) -> usize {
+    debug_assert!(
+        (1..65536).contains(&offset),
+        "emit_copy2: offset {offset} out of range"
+    );
+    debug_assert!(
+        (1..=64).contains(&length),
+        "emit_copy2: length {length} out of range"
+    );
     dst[0] = (((length - 1) << 2) | TAG_COPY2 as usize) as u8;
     let bytes = (offset as u16).to_le_bytes();
     dst[1] = bytes[0];
@@ -572,6 +858,8 @@ fn emit_copy2(dst: &mut [u8], offset: usize, length: usize) -> usize {
 
 /// Emit a COPY4 tag (32-bit offset)
 fn emit_copy4(dst: &mut [u8], offset: usize, length: usize) -> usize {
+    debug_assert!(offset >= 1, "emit_copy4: zero offset");
+    debug_assert!(length >= 1, "emit_copy4: zero length");
     let mut i = 0;
 
     // If length > 64, split into multiple copies
@@ -606,7 +894,12 @@ fn emit_copy4(dst: &mut [u8], offset: usize, length: usize) -> usize {
 }
 
 /// Emit a repeat (reuse of the last offset)
+///
+/// Callers must only emit a repeat after an earlier copy in the same block
+/// (or with a dictionary-seeded offset); the decoder starts with no offset.
 fn emit_repeat(dst: &mut [u8], offset: usize, length: usize) -> usize {
+    debug_assert!(offset >= 1, "emit_repeat: zero offset");
+    debug_assert!(length >= 4, "emit_repeat: length {length} below minimum 4");
     let mut len = length - 4;
 
     if len <= 4 {
@@ -640,15 +933,27 @@ fn emit_repeat(dst: &mut [u8], offset: usize, length: usize) -> usize {
         return 4;
     }
 
+    // The length must fit in 3 bytes; longer repeats are split.
     len -= 1 << 16;
+    let mut left = 0;
+    if len > MAX_REPEAT {
+        left = len - MAX_REPEAT + 4;
+        len = MAX_REPEAT - 4;
+    }
     dst[0] = ((7 << 2) | TAG_COPY1 as usize) as u8;
     dst[1] = 0;
     dst[2] = len as u8;
     dst[3] = (len >> 8) as u8;
     dst[4] = (len >> 16) as u8;
+    if left > 0 {
+        return 5 + emit_repeat(&mut dst[5..], offset, left);
+    }
     5
 }
 
+/// Largest length field of a 5-byte repeat
+const MAX_REPEAT: usize = (1 << 24) - 1;
+
 /// Hash function for matching
 #[inline]
 fn hash(data: &[u8], shift: u32) -> usize {
@@ -671,13 +976,6 @@ fn hash5(u: u64, h: u8) -> u32 {
     (((u << (64 - 40)).wrapping_mul(PRIME_5_BYTES)) >> ((64 - h) & 63)) as u32
 }
 
-/// Hash function for 6 bytes (Better algorithm)
-#[inline]
-fn hash6(u: u64, h: u32) -> u32 {
-    const PRIME_6_BYTES: u64 = 0xcf1bbcdcb7a56463;
-    ((u.wrapping_mul(PRIME_6_BYTES)) >> (64 - h)) as u32
-}
-
 /// Hash function for 7 bytes (Better algorithm)
 #[inline]
 fn hash7(u: u64, h: u8) -> u32 {
@@ -744,11 +1042,52 @@ fn load64(data: &[u8], offset: usize) -> u64 {
 }
 
 /// Encode a block using the S2 algorithm
-fn encode_block(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
+///
+/// `opts` can force the table variant and the hash (see [`EncodeOptions`])
+/// instead of picking them from the input size.
+fn encode_block(
+    dst: &mut [u8],
+    src: &[u8],
+    table_buf: &mut Vec<u32>,
+    opts: &EncodeOptions,
+) -> usize {
     if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
         return 0;
     }
 
+    // Same configurations as the size-selected variants below, with the
+    // hash swapped if one is forced.
+    if opts.table_bits.is_some() || opts.hash.is_some() || opts.force {
+        let table_bits = opts.table_bits.unwrap_or(match src.len() {
+            0..=511 => 8,
+            512..=4095 => 10,
+            4096..=16383 => 12,
+            _ => 14,
+        });
+        use HashKind::*;
+        return match (table_bits, opts.hash) {
+            (0..=8, None | Some(Hash4)) => {
+                encode_block_fast::<8, 4, 4>(dst, src, table_buf, opts.force)
+            }
+            (0..=8, Some(Hash6)) => encode_block_fast::<8, 4, 6>(dst, src, table_buf, opts.force),
+            (9..=10, None | Some(Hash4)) => {
+                encode_block_fast::<10, 5, 4>(dst, src, table_buf, opts.force)
+            }
+            (9..=10, Some(Hash6)) => encode_block_fast::<10, 5, 6>(dst, src, table_buf, opts.force),
+            (11..=12, None) => encode_block_fast::<12, 5, 5>(dst, src, table_buf, opts.force),
+            (11..=12, Some(Hash4)) => {
+                encode_block_fast::<12, 5, 4>(dst, src, table_buf, opts.force)
+            }
+            (11..=12, Some(Hash6)) => {
+                encode_block_fast::<12, 5, 6>(dst, src, table_buf, opts.force)
+            }
+            (_, Some(Hash4)) => encode_block_fast::<14, 6, 4>(dst, src, table_buf, opts.force),
+            (_, None | Some(Hash6)) => {
+                encode_block_fast::<14, 6, 6>(dst, src, table_buf, opts.force)
+            }
+        };
+    }
+
     // Asm-port path matching klauspost/compress/s2's
     // encodeBlockAsm{8B,10B,12B,4MB} byte-for-byte. The 8B variant
     // handles inputs in [MIN_NON_LITERAL_BLOCK_SIZE, 512); 10B
@@ -787,16 +1126,163 @@ fn encode_block(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
 fn encode_block_10b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
     debug_assert!(src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
     debug_assert!(src.len() < 4096);
+    encode_block_fast::<10, 5, 4>(dst, src, table_buf, false)
+}
 
-    const TABLE_BITS: u32 = 10;
-    const TABLE_SIZE: usize = 1 << TABLE_BITS;
-    const SKIP_SHIFT: u32 = 5;
+/// Port of `encodeBlockAsm8B` — used for src < 512 B. Same shape as
+/// the 10B variant; 4-byte Knuth hash, 8-bit table, skip shift 4.
+fn encode_block_8b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
+    debug_assert!(src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
+    debug_assert!(src.len() < 512);
+    encode_block_fast::<8, 4, 4>(dst, src, table_buf, false)
+}
 
-    ensure_zeroed_u32(table_buf, TABLE_SIZE);
-    let table = table_buf.as_mut_slice();
+/// Port of `encodeBlockAsm12B` — used for 4 KiB ≤ src < 16 KiB.
+/// Same algorithm shape as the 10B variant; differs only in:
+///   - hash is `hash5_asm` (5-byte window)
+///   - table is 12-bit (4096 u32 entries)
+///   - skip shift stays at 5
+fn encode_block_12b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
+    debug_assert!(src.len() >= 4096);
+    debug_assert!(src.len() < 16384);
+    encode_block_fast::<12, 5, 5>(dst, src, table_buf, false)
+}
+
+/// Port of `encodeBlockAsm4MB` — used for 16 KiB ≤ src < 4 MiB.
+/// 6-byte hash with prime6bytes (`hash6_asm`), 14-bit table, skip
+/// shift 6. Algorithm shape identical to 10B/12B.
+fn encode_block_4mb_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
+    debug_assert!(src.len() >= 16384);
+    encode_block_fast::<14, 6, 6>(dst, src, table_buf, false)
+}
+
+/// Hash the low `HASH_BYTES` (4, 5 or 6) bytes of `cv` for the fast
+/// match loop.
+#[inline(always)]
+fn hash_fast<const HASH_BYTES: u32>(cv: u64, table_bits: u32) -> usize {
+    match HASH_BYTES {
+        4 => hash4_asm(cv as u32, table_bits),
+        5 => hash5_asm(cv, table_bits),
+        _ => hash6_asm(cv, table_bits),
+    }
+}
+
+/// Extend a match forward: `src[s..]` matches `src[cand..]` up to the
+/// returned position. Requires `cand < s`.
+#[inline(always)]
+fn extend_match(src: &[u8], mut s: usize, mut cand: usize) -> usize {
+    while s + 8 <= src.len() {
+        let diff = load64(src, s) ^ load64(src, cand);
+        if diff != 0 {
+            return s + (diff.trailing_zeros() / 8) as usize;
+        }
+        s += 8;
+        cand += 8;
+    }
+    // Tail bytes the 8-byte loop ran out of room for.
+    while s < src.len() && src[s] == src[cand] {
+        s += 1;
+        cand += 1;
+    }
+    s
+}
+
+/// Shared search/extend/emit loop behind the fast S2 encoders.
+///
+/// Table configuration is `TABLE_BITS` (hash table size), `SKIP_SHIFT`
+/// (how fast the probe stride grows over a literal run) and
+/// `HASH_BYTES` (hash window, see [`hash_fast`]).
+///
+/// Returns 0 if the block does not compress well enough to be worth
+/// emitting, unless `force` is set (see [`EncodeOptions::force`]).
+fn encode_block_fast<const TABLE_BITS: u32, const SKIP_SHIFT: u32, const HASH_BYTES: u32>(
+    dst: &mut [u8],
+    src: &[u8],
+    table_buf: &mut Vec<u32>,
+    force: bool,
+) -> usize {
+    ensure_zeroed_u32(table_buf, 1 << TABLE_BITS);
+    encode_block_fast_table::<TABLE_BITS, SKIP_SHIFT, HASH_BYTES, false>(dst, src, table_buf, force)
+}
+
+/// Encode a block with the fast S2 encoder, starting from `table` as is
+///
+/// Like [`encode`]'s block path, but the hash table is supplied by the
+/// caller instead of being zeroed, so its initial state can be controlled
+/// (e.g. by a fuzzer). Its length picks the table width and must be
+/// `1 << 8`, `1 << 10`, `1 << 12` or `1 << 14`; these are the widths
+/// [`encode`] uses for inputs under 512 bytes, under 4KB, under 16KB and
+/// larger. With a zeroed table of the width `encode` would pick, the
+/// output is identical to `encode`'s.
+///
+/// Entries are positions in `src`. Any values are accepted: entries that
+/// don't point before the current position are ignored, and the others
+/// are only used once the bytes they point to are checked to match. The
+/// table is left in its final state.
+///
+/// `dst` must hold at least [`max_encoded_len`] bytes. Returns the number
+/// of bytes written, or 0 if `src` is too short or doesn't compress well
+/// enough to be worth emitting.
+///
+/// # Panics
+/// If the table length is not one of the supported sizes.
+///
+/// Only built for tests and under `--cfg fuzzing` (as set by cargo-fuzz),
+/// where it is re-exported from the crate root for the fuzz targets.
+#[cfg(any(test, fuzzing))]
+pub fn encode_block_with_table(dst: &mut [u8], src: &[u8], table: &mut [u32]) -> usize {
+    if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
+        return 0;
+    }
+    match table.len() {
+        0x100 => encode_block_fast_table::<8, 4, 4, true>(dst, src, table, false),
+        0x400 => encode_block_fast_table::<10, 5, 4, true>(dst, src, table, false),
+        0x1000 => encode_block_fast_table::<12, 5, 5, true>(dst, src, table, false),
+        0x4000 => encode_block_fast_table::<14, 6, 6, true>(dst, src, table, false),
+        n => panic!("unsupported hash table length {}", n),
+    }
+}
+
+/// Body of [`encode_block_fast`], on a table of `1 << TABLE_BITS` entries
+///
+/// The table normally starts zeroed, so every entry is a position already
+/// visited. `CHECKED` is for tables with arbitrary contents: candidates
+/// that don't lie before the position being looked up are replaced by 0,
+/// as in a zeroed table.
+///
+/// With `force`, the block is kept however little it saves. `dst` must
+/// then hold `src.len()` more bytes than [`max_encoded_len`]. Tokens take
+/// at most 1.4 times the input (7 bytes for a 1-byte literal followed by a
+/// 4-byte match at a long offset), so the limit of twice the input is
+/// never reached.
+fn encode_block_fast_table<
+    const TABLE_BITS: u32,
+    const SKIP_SHIFT: u32,
+    const HASH_BYTES: u32,
+    const CHECKED: bool,
+>(
+    dst: &mut [u8],
+    src: &[u8],
+    table: &mut [u32],
+    force: bool,
+) -> usize {
+    debug_assert!(src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
+    debug_assert_eq!(table.len(), 1 << TABLE_BITS);
+    let get = |table: &[u32], hash: usize, pos: usize| {
+        let candidate = table[hash] as usize;
+        if CHECKED && candidate >= pos {
+            0
+        } else {
+            candidate
+        }
+    };
 
     let s_limit = src.len() - INPUT_MARGIN;
-    let dst_limit = src.len() - src.len() / 32 - 5;
+    let dst_limit = if force {
+        2 * src.len() - 5
+    } else {
+        src.len() - src.len() / 32 - 5
+    };
     let mut next_emit: usize = 0;
     let mut s: usize = 1;
     let mut cv = load64(src, s);
@@ -812,14 +1298,14 @@ fn encode_block_10b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) ->
                 break 'outer;
             }
 
-            // Three 4-byte hashes covering s, s+1, s+2.
-            let hash0 = hash4_asm(cv as u32, TABLE_BITS);
-            let hash1 = hash4_asm((cv >> 8) as u32, TABLE_BITS);
-            candidate = table[hash0] as usize;
-            let candidate2 = table[hash1] as usize;
+            // Three hashes covering s, s+1, s+2.
+            let hash0 = hash_fast::<HASH_BYTES>(cv, TABLE_BITS);
+            let hash1 = hash_fast::<HASH_BYTES>(cv >> 8, TABLE_BITS);
+            candidate = get(table, hash0, s);
+            let candidate2 = get(table, hash1, s + 1);
             table[hash0] = s as u32;
             table[hash1] = (s + 1) as u32;
-            let hash2 = hash4_asm((cv >> 16) as u32, TABLE_BITS);
+            let hash2 = hash_fast::<HASH_BYTES>(cv >> 16, TABLE_BITS);
 
             // Repeat-first check: cv >> 8 (u32 at src[s+1]) vs the
             // u32 starting `repeat` bytes back from s+1.
@@ -840,27 +1326,8 @@ fn encode_block_10b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) ->
                 // Extend forwards from after the matched 5 bytes
                 // (4-byte initial match + the checkRep offset).
                 //
-                // Asm bounds this on `src.len()`, not `s_limit`. The
-                // tail loop matches the asm's 8/4/2/1-byte chunked
-                // walk so we cover the last 0-7 bytes the 8-byte
-                // loop misses.
-                let mut cand = s - repeat + 4 + 1;
-                s += 4 + 1;
-                while s + 8 <= src.len() {
-                    let diff = load64(src, s) ^ load64(src, cand);
-                    if diff != 0 {
-                        let k = (diff.trailing_zeros() / 8) as usize;
-                        s += k;
-                        cand += k;
-                        break;
-                    }
-                    s += 8;
-                    cand += 8;
-                }
-                while s < src.len() && src[s] == src[cand] {
-                    s += 1;
-                    cand += 1;
-                }
+                // Asm bounds this on `src.len()`, not `s_limit`.
+                s = extend_match(src, s + 4 + 1, s - repeat + 4 + 1);
 
                 if next_emit > 0 {
                     d += emit_repeat(&mut dst[d..], repeat, s - base);
@@ -877,11 +1344,15 @@ fn encode_block_10b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) ->
                 continue 'search;
             }
 
-            // Regular hash candidates: positions s, s+1, s+2.
+            // Regular hash candidates: positions s, s+1, s+2. Table
+            // entries only ever hold positions already visited (or 0 from
+            // the zeroed table; `get` enforces this for CHECKED tables), so
+            // every candidate is below s + 2 <= s_limit + 2 and its 4-byte
+            // load stays inside src.
             if (cv as u32) == load32(src, candidate) {
                 break 'search; // match at s
             }
-            candidate = table[hash2] as usize;
+            candidate = get(table, hash2, s + 2);
             if (cv >> 8) as u32 == load32(src, candidate2) {
                 table[hash2] = (s + 2) as u32;
                 candidate = candidate2;
@@ -890,995 +1361,60 @@ fn encode_block_10b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) ->
             }
             table[hash2] = (s + 2) as u32;
             if (cv >> 16) as u32 == load32(src, candidate) {
-                s += 2;
-                break 'search;
-            }
-
-            // No match — advance and reload.
-            cv = load64(src, next_s);
-            s = next_s;
-        }
-
-        // Match found at `s`, offset = s - candidate. Extend backwards.
-        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
-            candidate -= 1;
-            s -= 1;
-        }
-        if d + (s - next_emit) > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
-
-        // Emit-copies chain: emit one copy, then look for an immediate
-        // follow-on match and chain it.
-        loop {
-            let base = s;
-            let offset = base - candidate;
-            s += 4;
-            let mut cand = candidate + 4;
-            while s <= src.len() - 8 {
-                let diff = load64(src, s) ^ load64(src, cand);
-                if diff != 0 {
-                    let k = (diff.trailing_zeros() / 8) as usize;
-                    s += k;
-                    cand += k;
-                    break;
-                }
-                s += 8;
-                cand += 8;
-            }
-            // Tail bytes (if the 8-byte loop ran out of room).
-            while s < src.len() && cand < s && src[s] == src[cand] {
-                s += 1;
-                cand += 1;
-            }
-
-            if offset == repeat && next_emit > 0 {
-                d += emit_repeat(&mut dst[d..], offset, s - base);
-            } else {
-                d += emit_copy(&mut dst[d..], offset, s - base);
-                repeat = offset;
-            }
-            next_emit = s;
-            if s >= s_limit {
-                break 'outer;
-            }
-            if d > dst_limit {
-                return 0;
-            }
-
-            // Index the position 2 bytes inside the match end so the
-            // table reflects positions covered by the match.
-            let x = load64(src, s - 2);
-            let prev_hash = hash4_asm(x as u32, TABLE_BITS);
-            table[prev_hash] = (s - 2) as u32;
-            let curr_hash = hash4_asm((x >> 16) as u32, TABLE_BITS);
-            candidate = table[curr_hash] as usize;
-            table[curr_hash] = s as u32;
-            if (x >> 16) as u32 != load32(src, candidate) {
-                cv = load64(src, s + 1);
-                s += 1;
-                break;
-            }
-            // Immediate follow-on match — chain it.
-        }
-    }
-
-    if next_emit < src.len() {
-        if d + src.len() - next_emit > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..]);
-    }
-    if d >= src.len() - src.len() / 32 {
-        return 0;
-    }
-    d
-}
-
-/// Port of `encodeBlockAsm8B` — used for src < 512 B. Same shape as
-/// the 10B variant; 4-byte Knuth hash, 8-bit table, skip shift 4.
-fn encode_block_8b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
-    debug_assert!(src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
-    debug_assert!(src.len() < 512);
-
-    const TABLE_BITS: u32 = 8;
-    const TABLE_SIZE: usize = 1 << TABLE_BITS;
-    const SKIP_SHIFT: u32 = 4;
-
-    ensure_zeroed_u32(table_buf, TABLE_SIZE);
-    let table = table_buf.as_mut_slice();
-
-    let s_limit = src.len() - INPUT_MARGIN;
-    let dst_limit = src.len() - src.len() / 32 - 5;
-    let mut next_emit: usize = 0;
-    let mut s: usize = 1;
-    let mut cv = load64(src, s);
-    let mut repeat: usize = 1;
-    let mut d: usize = 0;
-
-    'outer: loop {
-        let mut candidate: usize;
-
-        'search: loop {
-            let next_s = s + ((s - next_emit) >> SKIP_SHIFT) + 4;
-            if next_s > s_limit {
-                break 'outer;
-            }
-            let hash0 = hash4_asm(cv as u32, TABLE_BITS);
-            let hash1 = hash4_asm((cv >> 8) as u32, TABLE_BITS);
-            candidate = table[hash0] as usize;
-            let candidate2 = table[hash1] as usize;
-            table[hash0] = s as u32;
-            table[hash1] = (s + 1) as u32;
-            let hash2 = hash4_asm((cv >> 16) as u32, TABLE_BITS);
-
-            if (cv >> 8) as u32 == load32(src, s + 1 - repeat) {
-                let mut base = s + 1;
-                let mut i = base - repeat;
-                while base > next_emit && i > 0 && src[i - 1] == src[base - 1] {
-                    i -= 1;
-                    base -= 1;
-                }
-                if d + (base - next_emit) + 3 > dst_limit {
-                    return 0;
-                }
-                d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-                let mut cand = s - repeat + 4 + 1;
-                s += 4 + 1;
-                while s + 8 <= src.len() {
-                    let diff = load64(src, s) ^ load64(src, cand);
-                    if diff != 0 {
-                        let k = (diff.trailing_zeros() / 8) as usize;
-                        s += k;
-                        cand += k;
-                        break;
-                    }
-                    s += 8;
-                    cand += 8;
-                }
-                while s < src.len() && src[s] == src[cand] {
-                    s += 1;
-                    cand += 1;
-                }
-                if next_emit > 0 {
-                    d += emit_repeat(&mut dst[d..], repeat, s - base);
-                } else {
-                    d += emit_copy(&mut dst[d..], repeat, s - base);
-                }
-                next_emit = s;
-                if s >= s_limit {
-                    break 'outer;
-                }
-                cv = load64(src, s);
-                continue 'search;
-            }
-
-            if (cv as u32) == load32(src, candidate) {
-                break 'search;
-            }
-            candidate = table[hash2] as usize;
-            if (cv >> 8) as u32 == load32(src, candidate2) {
-                table[hash2] = (s + 2) as u32;
-                candidate = candidate2;
-                s += 1;
-                break 'search;
-            }
-            table[hash2] = (s + 2) as u32;
-            if (cv >> 16) as u32 == load32(src, candidate) {
-                s += 2;
-                break 'search;
-            }
-
-            cv = load64(src, next_s);
-            s = next_s;
-        }
-
-        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
-            candidate -= 1;
-            s -= 1;
-        }
-        if d + (s - next_emit) > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
-
-        loop {
-            let base = s;
-            let offset = base - candidate;
-            s += 4;
-            let mut cand = candidate + 4;
-            while s + 8 <= src.len() {
-                let diff = load64(src, s) ^ load64(src, cand);
-                if diff != 0 {
-                    let k = (diff.trailing_zeros() / 8) as usize;
-                    s += k;
-                    cand += k;
-                    break;
-                }
-                s += 8;
-                cand += 8;
-            }
-            while s < src.len() && src[s] == src[cand] {
-                s += 1;
-                cand += 1;
-            }
-
-            if offset == repeat && next_emit > 0 {
-                d += emit_repeat(&mut dst[d..], offset, s - base);
-            } else {
-                d += emit_copy(&mut dst[d..], offset, s - base);
-                repeat = offset;
-            }
-            next_emit = s;
-            if s >= s_limit {
-                break 'outer;
-            }
-            if d > dst_limit {
-                return 0;
-            }
-
-            let x = load64(src, s - 2);
-            let prev_hash = hash4_asm(x as u32, TABLE_BITS);
-            table[prev_hash] = (s - 2) as u32;
-            let curr_hash = hash4_asm((x >> 16) as u32, TABLE_BITS);
-            candidate = table[curr_hash] as usize;
-            table[curr_hash] = s as u32;
-            if (x >> 16) as u32 != load32(src, candidate) {
-                cv = load64(src, s + 1);
-                s += 1;
-                break;
-            }
-        }
-    }
-
-    if next_emit < src.len() {
-        if d + src.len() - next_emit > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..]);
-    }
-    if d >= src.len() - src.len() / 32 {
-        return 0;
-    }
-    d
-}
-
-/// Port of `encodeBlockAsm12B` — used for 4 KiB ≤ src < 16 KiB.
-/// Same algorithm shape as the 10B variant; differs only in:
-///   - hash is `hash5_asm` (5-byte window)
-///   - table is 12-bit (4096 u32 entries)
-///   - skip shift stays at 5
-fn encode_block_12b_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
-    debug_assert!(src.len() >= 4096);
-    debug_assert!(src.len() < 16384);
-
-    const TABLE_BITS: u32 = 12;
-    const TABLE_SIZE: usize = 1 << TABLE_BITS;
-    const SKIP_SHIFT: u32 = 5;
-
-    ensure_zeroed_u32(table_buf, TABLE_SIZE);
-    let table = table_buf.as_mut_slice();
-
-    let s_limit = src.len() - INPUT_MARGIN;
-    let dst_limit = src.len() - src.len() / 32 - 5;
-    let mut next_emit: usize = 0;
-    let mut s: usize = 1;
-    let mut cv = load64(src, s);
-    let mut repeat: usize = 1;
-    let mut d: usize = 0;
-
-    'outer: loop {
-        let mut candidate: usize;
-
-        'search: loop {
-            let next_s = s + ((s - next_emit) >> SKIP_SHIFT) + 4;
-            if next_s > s_limit {
-                break 'outer;
-            }
-            let hash0 = hash5_asm(cv, TABLE_BITS);
-            let hash1 = hash5_asm(cv >> 8, TABLE_BITS);
-            candidate = table[hash0] as usize;
-            let candidate2 = table[hash1] as usize;
-            table[hash0] = s as u32;
-            table[hash1] = (s + 1) as u32;
-            let hash2 = hash5_asm(cv >> 16, TABLE_BITS);
-
-            if (cv >> 8) as u32 == load32(src, s + 1 - repeat) {
-                let mut base = s + 1;
-                let mut i = base - repeat;
-                while base > next_emit && i > 0 && src[i - 1] == src[base - 1] {
-                    i -= 1;
-                    base -= 1;
-                }
-                if d + (base - next_emit) + 3 > dst_limit {
-                    return 0;
-                }
-                d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-                let mut cand = s - repeat + 4 + 1;
-                s += 4 + 1;
-                while s + 8 <= src.len() {
-                    let diff = load64(src, s) ^ load64(src, cand);
-                    if diff != 0 {
-                        let k = (diff.trailing_zeros() / 8) as usize;
-                        s += k;
-                        cand += k;
-                        break;
-                    }
-                    s += 8;
-                    cand += 8;
-                }
-                while s < src.len() && src[s] == src[cand] {
-                    s += 1;
-                    cand += 1;
-                }
-                if next_emit > 0 {
-                    d += emit_repeat(&mut dst[d..], repeat, s - base);
-                } else {
-                    d += emit_copy(&mut dst[d..], repeat, s - base);
-                }
-                next_emit = s;
-                if s >= s_limit {
-                    break 'outer;
-                }
-                cv = load64(src, s);
-                continue 'search;
-            }
-
-            if (cv as u32) == load32(src, candidate) {
-                break 'search;
-            }
-            candidate = table[hash2] as usize;
-            if (cv >> 8) as u32 == load32(src, candidate2) {
-                table[hash2] = (s + 2) as u32;
-                candidate = candidate2;
-                s += 1;
-                break 'search;
-            }
-            table[hash2] = (s + 2) as u32;
-            if (cv >> 16) as u32 == load32(src, candidate) {
-                s += 2;
-                break 'search;
-            }
-
-            cv = load64(src, next_s);
-            s = next_s;
-        }
-
-        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
-            candidate -= 1;
-            s -= 1;
-        }
-        if d + (s - next_emit) > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
-
-        loop {
-            let base = s;
-            let offset = base - candidate;
-            s += 4;
-            let mut cand = candidate + 4;
-            while s + 8 <= src.len() {
-                let diff = load64(src, s) ^ load64(src, cand);
-                if diff != 0 {
-                    let k = (diff.trailing_zeros() / 8) as usize;
-                    s += k;
-                    cand += k;
-                    break;
-                }
-                s += 8;
-                cand += 8;
-            }
-            while s < src.len() && src[s] == src[cand] {
-                s += 1;
-                cand += 1;
-            }
-
-            if offset == repeat && next_emit > 0 {
-                d += emit_repeat(&mut dst[d..], offset, s - base);
-            } else {
-                d += emit_copy(&mut dst[d..], offset, s - base);
-                repeat = offset;
-            }
-            next_emit = s;
-            if s >= s_limit {
-                break 'outer;
-            }
-            if d > dst_limit {
-                return 0;
-            }
-
-            let x = load64(src, s - 2);
-            let prev_hash = hash5_asm(x, TABLE_BITS);
-            table[prev_hash] = (s - 2) as u32;
-            let curr_hash = hash5_asm(x >> 16, TABLE_BITS);
-            candidate = table[curr_hash] as usize;
-            table[curr_hash] = s as u32;
-            if (x >> 16) as u32 != load32(src, candidate) {
-                cv = load64(src, s + 1);
-                s += 1;
-                break;
-            }
-        }
-    }
-
-    if next_emit < src.len() {
-        if d + src.len() - next_emit > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..]);
-    }
-    if d >= src.len() - src.len() / 32 {
-        return 0;
-    }
-    d
-}
-
-/// Port of `encodeBlockAsm4MB` — used for 16 KiB ≤ src < 4 MiB.
-/// 6-byte hash with prime6bytes (`hash6_asm`), 14-bit table, skip
-/// shift 6. Algorithm shape identical to 10B/12B.
-fn encode_block_4mb_asm(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
-    debug_assert!(src.len() >= 16384);
-
-    const TABLE_BITS: u32 = 14;
-    const TABLE_SIZE: usize = 1 << TABLE_BITS;
-    const SKIP_SHIFT: u32 = 6;
-
-    ensure_zeroed_u32(table_buf, TABLE_SIZE);
-    let table = table_buf.as_mut_slice();
-
-    let s_limit = src.len() - INPUT_MARGIN;
-    let dst_limit = src.len() - src.len() / 32 - 5;
-    let mut next_emit: usize = 0;
-    let mut s: usize = 1;
-    let mut cv = load64(src, s);
-    let mut repeat: usize = 1;
-    let mut d: usize = 0;
-
-    'outer: loop {
-        let mut candidate: usize;
-
-        'search: loop {
-            let next_s = s + ((s - next_emit) >> SKIP_SHIFT) + 4;
-            if next_s > s_limit {
-                break 'outer;
-            }
-            let hash0 = hash6_asm(cv, TABLE_BITS);
-            let hash1 = hash6_asm(cv >> 8, TABLE_BITS);
-            candidate = table[hash0] as usize;
-            let candidate2 = table[hash1] as usize;
-            table[hash0] = s as u32;
-            table[hash1] = (s + 1) as u32;
-            let hash2 = hash6_asm(cv >> 16, TABLE_BITS);
-
-            if (cv >> 8) as u32 == load32(src, s + 1 - repeat) {
-                let mut base = s + 1;
-                let mut i = base - repeat;
-                while base > next_emit && i > 0 && src[i - 1] == src[base - 1] {
-                    i -= 1;
-                    base -= 1;
-                }
-                if d + (base - next_emit) + 3 > dst_limit {
-                    return 0;
-                }
-                d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-                let mut cand = s - repeat + 4 + 1;
-                s += 4 + 1;
-                while s + 8 <= src.len() {
-                    let diff = load64(src, s) ^ load64(src, cand);
-                    if diff != 0 {
-                        let k = (diff.trailing_zeros() / 8) as usize;
-                        s += k;
-                        cand += k;
-                        break;
-                    }
-                    s += 8;
-                    cand += 8;
-                }
-                while s < src.len() && src[s] == src[cand] {
-                    s += 1;
-                    cand += 1;
-                }
-                if next_emit > 0 {
-                    d += emit_repeat(&mut dst[d..], repeat, s - base);
-                } else {
-                    d += emit_copy(&mut dst[d..], repeat, s - base);
-                }
-                next_emit = s;
-                if s >= s_limit {
-                    break 'outer;
-                }
-                cv = load64(src, s);
-                continue 'search;
-            }
-
-            if (cv as u32) == load32(src, candidate) {
-                break 'search;
-            }
-            candidate = table[hash2] as usize;
-            if (cv >> 8) as u32 == load32(src, candidate2) {
-                table[hash2] = (s + 2) as u32;
-                candidate = candidate2;
-                s += 1;
-                break 'search;
-            }
-            table[hash2] = (s + 2) as u32;
-            if (cv >> 16) as u32 == load32(src, candidate) {
-                s += 2;
-                break 'search;
-            }
-
-            cv = load64(src, next_s);
-            s = next_s;
-        }
-
-        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
-            candidate -= 1;
-            s -= 1;
-        }
-        if d + (s - next_emit) > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
-
-        loop {
-            let base = s;
-            let offset = base - candidate;
-            s += 4;
-            let mut cand = candidate + 4;
-            while s + 8 <= src.len() {
-                let diff = load64(src, s) ^ load64(src, cand);
-                if diff != 0 {
-                    let k = (diff.trailing_zeros() / 8) as usize;
-                    s += k;
-                    cand += k;
-                    break;
-                }
-                s += 8;
-                cand += 8;
-            }
-            while s < src.len() && src[s] == src[cand] {
-                s += 1;
-                cand += 1;
-            }
-
-            if offset == repeat && next_emit > 0 {
-                d += emit_repeat(&mut dst[d..], offset, s - base);
-            } else {
-                d += emit_copy(&mut dst[d..], offset, s - base);
-                repeat = offset;
-            }
-            next_emit = s;
-            if s >= s_limit {
-                break 'outer;
-            }
-            if d > dst_limit {
-                return 0;
-            }
-
-            let x = load64(src, s - 2);
-            let prev_hash = hash6_asm(x, TABLE_BITS);
-            table[prev_hash] = (s - 2) as u32;
-            let curr_hash = hash6_asm(x >> 16, TABLE_BITS);
-            candidate = table[curr_hash] as usize;
-            table[curr_hash] = s as u32;
-            if (x >> 16) as u32 != load32(src, candidate) {
-                cv = load64(src, s + 1);
-                s += 1;
-                break;
-            }
-        }
-    }
-
-    if next_emit < src.len() {
-        if d + src.len() - next_emit > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..]);
-    }
-    if d >= src.len() - src.len() / 32 {
-        return 0;
-    }
-    d
-}
-
-/// Encode a block using the Better S2 algorithm with dual hash tables
-fn encode_block_better(
-    dst: &mut [u8],
-    src: &[u8],
-    l16: &mut Vec<u16>,
-    s16: &mut Vec<u16>,
-    l32: &mut Vec<u32>,
-    s32: &mut Vec<u32>,
-) -> usize {
-    if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
-        return 0;
-    }
-
-    // Match Go's AMD64 assembly thresholds for table sizes
-    const LIMIT_12B: usize = 16 << 10; // 16KB
-    const LIMIT_10B: usize = 4 << 10; // 4KB
-    const LIMIT_8B: usize = 512;
-
-    // Use appropriate table size based on input size
-    if src.len() < LIMIT_8B {
-        return encode_block_better_8b(dst, src, l16, s16);
-    }
-    if src.len() < LIMIT_10B {
-        return encode_block_better_10b(dst, src, l16, s16);
-    }
-    if src.len() < LIMIT_12B {
-        return encode_block_better_12b(dst, src, l16, s16);
-    }
-    if src.len() <= 64 * 1024 {
-        return encode_block_better_64k(dst, src, l16, s16);
-    }
-
-    // Initialize the hash tables.
-    const L_TABLE_BITS: u8 = 17; // Long hash matches
-    const S_TABLE_BITS: u8 = 14; // Short hash matches
-    const L_TABLE_SIZE: usize = 1 << L_TABLE_BITS;
-    const S_TABLE_SIZE: usize = 1 << S_TABLE_BITS;
-
-    ensure_zeroed_u32(l32, L_TABLE_SIZE);
-    ensure_zeroed_u32(s32, S_TABLE_SIZE);
-    let l_table = l32.as_mut_slice();
-    let s_table = s32.as_mut_slice();
-
-    // Bail if we can't compress to at least this.
-    let dst_limit = src.len() - src.len() / 32 - 6;
-
-    let s_limit = src.len() - INPUT_MARGIN;
-    let mut next_emit = 0;
-    let mut s = 1;
-    let mut d = 0;
-    let mut repeat = 0;
-
-    if src.len() < 8 {
-        // Too small for Better algorithm, fallback to simple literal
-        return 0;
-    }
-
-    let mut cv = load64(src, s);
-
-    'outer: loop {
-        let mut candidate_l;
-        let mut next_s;
-
-        // Find a match
-        loop {
-            // Next src position to check
-            next_s = s + (s - next_emit) / 128 + 1;
-            if next_s > s_limit {
-                break 'outer;
-            }
-
-            let hash_l = hash7(cv, L_TABLE_BITS) as usize;
-            let hash_s = hash4(cv, S_TABLE_BITS) as usize;
-            candidate_l = l_table[hash_l] as usize;
-            let candidate_s = s_table[hash_s] as usize;
-            l_table[hash_l] = s as u32;
-            s_table[hash_s] = s as u32;
-
-            let val_long = load64(src, candidate_l);
-            let val_short = load64(src, candidate_s);
-
-            // If long matches at least 8 bytes, use that.
-            if cv == val_long {
-                break;
-            }
-            if cv == val_short {
-                candidate_l = candidate_s;
-                break;
-            }
-
-            // Long likely matches 7, so take that.
-            if (cv as u32) == (val_long as u32) {
-                break;
-            }
-
-            // Check our short candidate
-            if (cv as u32) == (val_short as u32) {
-                // Try a long candidate at s+1
-                let hash_l = hash7(cv >> 8, L_TABLE_BITS) as usize;
-                let candidate_l_next = l_table[hash_l] as usize;
-                l_table[hash_l] = (s + 1) as u32;
-                if (cv >> 8) as u32 == load32(src, candidate_l_next) {
-                    s += 1;
-                    candidate_l = candidate_l_next;
-                    break;
-                }
-                // Use our short candidate.
-                candidate_l = candidate_s;
-                break;
-            }
-
-            cv = load64(src, next_s);
-            s = next_s;
-        }
-
-        // Extend backwards
-        while candidate_l > 0 && s > next_emit && src[candidate_l - 1] == src[s - 1] {
-            candidate_l -= 1;
-            s -= 1;
-        }
-
-        // Bail if we exceed the maximum size.
-        if d + (s - next_emit) > dst_limit {
-            return 0;
-        }
-
-        let base = s;
-        let offset = base - candidate_l;
-
-        // Extend the 4-byte match as long as possible.
-        s += 4;
-        candidate_l += 4;
-        while s < src.len() {
-            if src.len() - s < 8 {
-                if src[s] == src[candidate_l] {
-                    s += 1;
-                    candidate_l += 1;
-                    continue;
-                }
-                break;
-            }
-            let diff = load64(src, s) ^ load64(src, candidate_l);
-            if diff != 0 {
-                s += (diff.trailing_zeros() / 8) as usize;
-                break;
-            }
-            s += 8;
-            candidate_l += 8;
-        }
-
-        // Bail if the match is equal or worse to the encoding for large offsets.
-        if offset > 65535 && s - base <= 5 && repeat != offset {
-            s = next_s + 1;
-            if s >= s_limit {
-                break;
-            }
-            cv = load64(src, s);
-            continue;
-        }
-
-        // Emit literal
-        d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-
-        // Emit copy
-        if repeat == offset {
-            d += emit_repeat(&mut dst[d..], offset, s - base);
-        } else {
-            d += emit_copy(&mut dst[d..], offset, s - base);
-            repeat = offset;
-        }
-
-        next_emit = s;
-        if s >= s_limit {
-            break;
-        }
-
-        if d > dst_limit {
-            // Do we have space for more, if not bail.
-            return 0;
-        }
-
-        // Index short & long
-        let index0 = base + 1;
-        let index1 = s - 2;
-
-        let cv0 = load64(src, index0);
-        let cv1 = load64(src, index1);
-        l_table[hash7(cv0, L_TABLE_BITS) as usize] = index0 as u32;
-        s_table[hash4(cv0 >> 8, S_TABLE_BITS) as usize] = (index0 + 1) as u32;
-
-        l_table[hash7(cv1, L_TABLE_BITS) as usize] = index1 as u32;
-        s_table[hash4(cv1 >> 8, S_TABLE_BITS) as usize] = (index1 + 1) as u32;
-        let mut index0 = index0 + 1;
-        let index1 = index1 - 1;
-        cv = load64(src, s);
-
-        // Index large values sparsely in between.
-        // We do two starting from different offsets for speed.
-        let mut index2 = (index0 + index1 + 1) >> 1;
-        while index2 < index1 {
-            l_table[hash7(load64(src, index0), L_TABLE_BITS) as usize] = index0 as u32;
-            l_table[hash7(load64(src, index2), L_TABLE_BITS) as usize] = index2 as u32;
-            index0 += 2;
-            index2 += 2;
-        }
-    }
-
-    // Emit remaining
-    if next_emit < src.len() {
-        // Bail if we exceed the maximum size.
-        if d + src.len() - next_emit > dst_limit {
-            return 0;
-        }
-        d += emit_literal(&mut dst[d..], &src[next_emit..]);
-    }
-
-    d
-}
-
-/// Encode a block using the Better S2 algorithm with 10-bit tables (< 512 bytes)
-/// Matches Go's encodeBetterBlockAsm8B which uses lTableBits=10, sTableBits=8, skipLog=4
-fn encode_block_better_8b(
-    dst: &mut [u8],
-    src: &[u8],
-    l16: &mut Vec<u16>,
-    s16: &mut Vec<u16>,
-) -> usize {
-    encode_block_better_small::<10, 8, 4>(dst, src, l16, s16)
-}
-
-/// Encode a block using the Better S2 algorithm with 12-bit tables (512-4KB)
-/// Matches Go's encodeBetterBlockAsm10B which uses lTableBits=12, sTableBits=10, skipLog=5
-fn encode_block_better_10b(
-    dst: &mut [u8],
-    src: &[u8],
-    l16: &mut Vec<u16>,
-    s16: &mut Vec<u16>,
-) -> usize {
-    encode_block_better_small::<12, 10, 5>(dst, src, l16, s16)
-}
-
-/// Encode a block using the Better S2 algorithm with 14-bit tables (4KB-16KB)
-/// Matches Go's encodeBetterBlockAsm12B which uses lTableBits=14, sTableBits=12, skipLog=5
-fn encode_block_better_12b(
-    dst: &mut [u8],
-    src: &[u8],
-    l16: &mut Vec<u16>,
-    s16: &mut Vec<u16>,
-) -> usize {
-    encode_block_better_small::<14, 12, 5>(dst, src, l16, s16)
-}
-
-/// Generic implementation for small input better compression
-fn encode_block_better_small<const L_BITS: u8, const S_BITS: u8, const SKIP_LOG: u8>(
-    dst: &mut [u8],
-    src: &[u8],
-    l16: &mut Vec<u16>,
-    s16: &mut Vec<u16>,
-) -> usize {
-    let s_limit = src.len() - INPUT_MARGIN;
-    if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
-        return 0;
-    }
-
-    const fn table_size(bits: u8) -> usize {
-        1 << bits
-    }
-
-    ensure_zeroed_u16(l16, table_size(L_BITS));
-    ensure_zeroed_u16(s16, table_size(S_BITS));
-    let l_table = l16.as_mut_slice();
-    let s_table = s16.as_mut_slice();
-
-    let dst_limit = src.len() - src.len() / 32 - 6;
-    let mut next_emit = 0;
-    let mut s = 1;
-    let mut cv = load64(src, s);
-    let mut repeat = 0;
-    let mut d = 0;
-
-    'outer: loop {
-        let mut candidate_l;
-        let mut next_s;
-
-        loop {
-            next_s = s + (s - next_emit) / (1 << SKIP_LOG) + 1;
-            if next_s > s_limit {
-                break 'outer;
-            }
-
-            let hash_l = hash7(cv, L_BITS) as usize;
-            let hash_s = hash4(cv, S_BITS) as usize;
-            candidate_l = l_table[hash_l] as usize;
-            let candidate_s = s_table[hash_s] as usize;
-            l_table[hash_l] = s as u16;
-            s_table[hash_s] = s as u16;
-
-            let val_long = load64(src, candidate_l);
-            let val_short = load64(src, candidate_s);
-
-            if cv == val_long {
-                break;
-            }
-            if cv == val_short {
-                candidate_l = candidate_s;
-                break;
-            }
-            if (cv as u32) == (val_long as u32) {
-                break;
-            }
-            if (cv as u32) == (val_short as u32) {
-                let hash_l = hash7(cv >> 8, L_BITS) as usize;
-                let candidate_l_next = l_table[hash_l] as usize;
-                l_table[hash_l] = (s + 1) as u16;
-                if (cv >> 8) as u32 == load32(src, candidate_l_next) {
-                    s += 1;
-                    candidate_l = candidate_l_next;
-                    break;
-                }
-                candidate_l = candidate_s;
-                break;
+                s += 2;
+                break 'search;
             }
 
+            // No match — advance and reload.
             cv = load64(src, next_s);
             s = next_s;
         }
 
-        while candidate_l > 0 && s > next_emit && src[candidate_l - 1] == src[s - 1] {
-            candidate_l -= 1;
+        // Match found at `s`, offset = s - candidate. Extend backwards.
+        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
+            candidate -= 1;
             s -= 1;
         }
-
         if d + (s - next_emit) > dst_limit {
             return 0;
         }
+        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
 
-        let base = s;
-        let offset = base - candidate_l;
+        // Emit-copies chain: emit one copy, then look for an immediate
+        // follow-on match and chain it.
+        loop {
+            let base = s;
+            let offset = base - candidate;
+            s = extend_match(src, s + 4, candidate + 4);
 
-        s += 4;
-        candidate_l += 4;
-        while s < src.len() {
-            if src.len() - s < 8 {
-                if src[s] == src[candidate_l] {
-                    s += 1;
-                    candidate_l += 1;
-                    continue;
-                }
-                break;
+            if offset == repeat && next_emit > 0 {
+                d += emit_repeat(&mut dst[d..], offset, s - base);
+            } else {
+                d += emit_copy(&mut dst[d..], offset, s - base);
+                repeat = offset;
             }
-            let diff = load64(src, s) ^ load64(src, candidate_l);
-            if diff != 0 {
-                s += (diff.trailing_zeros() / 8) as usize;
-                break;
+            next_emit = s;
+            if s >= s_limit {
+                break 'outer;
+            }
+            if d > dst_limit {
+                return 0;
             }
-            s += 8;
-            candidate_l += 8;
-        }
-
-        d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-        if repeat == offset {
-            d += emit_repeat(&mut dst[d..], offset, s - base);
-        } else {
-            d += emit_copy(&mut dst[d..], offset, s - base);
-            repeat = offset;
-        }
-
-        next_emit = s;
-        if s >= s_limit {
-            break;
-        }
-
-        if d > dst_limit {
-            return 0;
-        }
-
-        let index0 = base + 1;
-        let index1 = s - 2;
-
-        let cv0 = load64(src, index0);
-        let cv1 = load64(src, index1);
-        l_table[hash7(cv0, L_BITS) as usize] = index0 as u16;
-        s_table[hash4(cv0 >> 8, S_BITS) as usize] = (index0 + 1) as u16;
-
-        l_table[hash7(cv1, L_BITS) as usize] = index1 as u16;
-        s_table[hash4(cv1 >> 8, S_BITS) as usize] = (index1 + 1) as u16;
-        let mut index0 = index0 + 1;
-        let index1 = index1 - 1;
-        cv = load64(src, s);
 
-        let mut index2 = (index0 + index1 + 1) >> 1;
-        while index2 < index1 {
-            l_table[hash7(load64(src, index0), L_BITS) as usize] = index0 as u16;
-            l_table[hash7(load64(src, index2), L_BITS) as usize] = index2 as u16;
-            index0 += 2;
-            index2 += 2;
+            // Index the position 2 bytes inside the match end so the
+            // table reflects positions covered by the match.
+            let x = load64(src, s - 2);
+            let prev_hash = hash_fast::<HASH_BYTES>(x, TABLE_BITS);
+            table[prev_hash] = (s - 2) as u32;
+            let curr_hash = hash_fast::<HASH_BYTES>(x >> 16, TABLE_BITS);
+            candidate = get(table, curr_hash, s);
+            table[curr_hash] = s as u32;
+            if (x >> 16) as u32 != load32(src, candidate) {
+                cv = load64(src, s + 1);
+                s += 1;
+                break;
+            }
+            // Immediate follow-on match — chain it.
         }
     }
 
@@ -1888,71 +1424,182 @@ fn encode_block_better_small<const L_BITS: u8, const S_BITS: u8, const SKIP_LOG:
         }
         d += emit_literal(&mut dst[d..], &src[next_emit..]);
     }
-
+    if !force && d >= src.len() - src.len() / 32 {
+        return 0;
+    }
     d
 }
 
-/// Encode a block using the Better S2 algorithm optimized for ≤64KB inputs
-/// Uses smaller hash tables and different skip logic than the full version
-fn encode_block_better_64k(
+/// Encode a block using the Better S2 algorithm with dual hash tables
+///
+/// The tables grow with the input, as in Go's encodeBetterBlockAsm*
+/// variants; inputs up to 64KB use 16-bit entries.
+fn encode_block_better(
     dst: &mut [u8],
     src: &[u8],
     l16: &mut Vec<u16>,
     s16: &mut Vec<u16>,
+    l32: &mut Vec<u32>,
+    s32: &mut Vec<u32>,
+    opts: &BetterOptions,
 ) -> usize {
-    let s_limit = src.len() - INPUT_MARGIN;
     if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
         return 0;
     }
+    let stride = opts.mid_index_stride.max(1);
 
-    // Initialize the hash tables with smaller sizes for 64K inputs
-    const L_TABLE_BITS: u8 = 16;
-    const S_TABLE_BITS: u8 = 13;
-    const L_TABLE_SIZE: usize = 1 << L_TABLE_BITS;
-    const S_TABLE_SIZE: usize = 1 << S_TABLE_BITS;
+    // Match Go's AMD64 assembly thresholds for table sizes
+    const LIMIT_12B: usize = 16 << 10; // 16KB
+    const LIMIT_10B: usize = 4 << 10; // 4KB
+    const LIMIT_8B: usize = 512;
+
+    /// Zero the 16-bit tables and run the loop on them
+    fn small<const L_BITS: u8, const S_BITS: u8, const SKIP_LOG: u8>(
+        dst: &mut [u8],
+        src: &[u8],
+        l16: &mut Vec<u16>,
+        s16: &mut Vec<u16>,
+        stride: usize,
+    ) -> usize {
+        ensure_zeroed_u16(l16, 1 << L_BITS);
+        ensure_zeroed_u16(s16, 1 << S_BITS);
+        encode_block_better_loop::<u16, L_BITS, S_BITS, SKIP_LOG, false>(dst, src, l16, s16, stride)
+    }
+
+    // Table bits (long, short) and skip log of each size class
+    if src.len() < LIMIT_8B {
+        return small::<10, 8, 4>(dst, src, l16, s16, stride);
+    }
+    if src.len() < LIMIT_10B {
+        return small::<12, 10, 5>(dst, src, l16, s16, stride);
+    }
+    if src.len() < LIMIT_12B {
+        return small::<14, 12, 5>(dst, src, l16, s16, stride);
+    }
+    if src.len() <= 64 * 1024 {
+        return small::<16, 13, 6>(dst, src, l16, s16, stride);
+    }
+
+    const L_TABLE_BITS: u8 = 17; // Long hash matches
+    const S_TABLE_BITS: u8 = 14; // Short hash matches
+    ensure_zeroed_u32(l32, 1 << L_TABLE_BITS);
+    ensure_zeroed_u32(s32, 1 << S_TABLE_BITS);
+    encode_block_better_loop::<u32, L_TABLE_BITS, S_TABLE_BITS, 7, false>(
+        dst, src, l32, s32, stride,
+    )
+}
+
+/// Position stored in a better encoder hash table
+///
+/// Tables for inputs up to 64KB hold 16-bit positions, halving their size.
+trait TablePos: Copy {
+    fn from_pos(pos: usize) -> Self;
+    fn pos(self) -> usize;
+}
+
+impl TablePos for u16 {
+    #[inline(always)]
+    fn from_pos(pos: usize) -> Self {
+        pos as u16
+    }
+    #[inline(always)]
+    fn pos(self) -> usize {
+        self as usize
+    }
+}
+
+impl TablePos for u32 {
+    #[inline(always)]
+    fn from_pos(pos: usize) -> Self {
+        pos as u32
+    }
+    #[inline(always)]
+    fn pos(self) -> usize {
+        self as usize
+    }
+}
 
-    ensure_zeroed_u16(l16, L_TABLE_SIZE);
-    ensure_zeroed_u16(s16, S_TABLE_SIZE);
-    let l_table = l16.as_mut_slice();
-    let s_table = s16.as_mut_slice();
+/// Shared search/extend/emit loop behind the better S2 and Snappy encoders
+///
+/// Table configuration is `L_BITS` and `S_BITS` (long- and short-match
+/// table sizes, on zeroed tables of that many entries) and `SKIP_LOG` (how
+/// fast the probe stride grows over a literal run). Once a match is
+/// emitted, positions inside it are added to the long table every
+/// `stride` bytes.
+///
+/// `SNAPPY` is the copy policy of [`encode_snappy_better`], a port of Go's
+/// `encodeBlockBetterSnappyGo`: no repeat codes, 4-byte candidates are
+/// taken without preferring 8-byte ones, the probe stride is capped, every
+/// second position inside a match is indexed from both ends (`stride` is
+/// unused), and the encoded length is always returned. Otherwise returns 0
+/// if the block does not compress well enough to be worth emitting.
+fn encode_block_better_loop<
+    T: TablePos,
+    const L_BITS: u8,
+    const S_BITS: u8,
+    const SKIP_LOG: u8,
+    const SNAPPY: bool,
+>(
+    dst: &mut [u8],
+    src: &[u8],
+    l_table: &mut [T],
+    s_table: &mut [T],
+    stride: usize,
+) -> usize {
+    // Cap on the probe stride of the Snappy policy
+    const MAX_SKIP: usize = 100;
+    debug_assert!(src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
+    debug_assert_eq!(l_table.len(), 1 << L_BITS);
+    debug_assert_eq!(s_table.len(), 1 << S_BITS);
 
     // Bail if we can't compress to at least this.
     let dst_limit = src.len() - src.len() / 32 - 6;
 
+    let s_limit = src.len() - INPUT_MARGIN;
     let mut next_emit = 0;
     let mut s = 1;
-    let mut cv = load64(src, s);
-    let mut repeat = 0;
     let mut d = 0;
+    // 0 never equals a real offset, so the first copy of the block is never
+    // emitted as a repeat: the decoder has no previous offset to reuse.
+    let mut repeat = 0;
+
+    let mut cv = load64(src, s);
 
     'outer: loop {
         let mut candidate_l;
         let mut next_s;
 
+        // Find a match
         loop {
-            // Next src position to check - uses >>6 for 64K version
-            next_s = s + (s - next_emit) / 64 + 1;
+            // Next src position to check
+            let mut step = ((s - next_emit) >> SKIP_LOG) + 1;
+            if SNAPPY {
+                step = step.min(MAX_SKIP);
+            }
+            next_s = s + step;
             if next_s > s_limit {
                 break 'outer;
             }
 
-            let hash_l = hash7(cv, L_TABLE_BITS) as usize;
-            let hash_s = hash4(cv, S_TABLE_BITS) as usize;
-            candidate_l = l_table[hash_l] as usize;
-            let candidate_s = s_table[hash_s] as usize;
-            l_table[hash_l] = s as u16;
-            s_table[hash_s] = s as u16;
+            let hash_l = hash7(cv, L_BITS) as usize;
+            let hash_s = hash4(cv, S_BITS) as usize;
+            candidate_l = l_table[hash_l].pos();
+            let candidate_s = s_table[hash_s].pos();
+            l_table[hash_l] = T::from_pos(s);
+            s_table[hash_s] = T::from_pos(s);
 
             let val_long = load64(src, candidate_l);
             let val_short = load64(src, candidate_s);
 
-            // If long matches at least 8 bytes, use that.
-            if cv == val_long {
-                break;
-            }
-            if cv == val_short {
-                candidate_l = candidate_s;
-                break;
+            if !SNAPPY {
+                // If long matches at least 8 bytes, use that.
+                if cv == val_long {
+                    break;
+                }
+                if cv == val_short {
+                    candidate_l = candidate_s;
+                    break;
+                }
             }
 
             // Long likely matches 7, so take that.
@@ -1963,9 +1610,9 @@ fn encode_block_better_64k(
             // Check our short candidate
             if (cv as u32) == (val_short as u32) {
                 // Try a long candidate at s+1
-                let hash_l = hash7(cv >> 8, L_TABLE_BITS) as usize;
-                let candidate_l_next = l_table[hash_l] as usize;
-                l_table[hash_l] = (s + 1) as u16;
+                let hash_l = hash7(cv >> 8, L_BITS) as usize;
+                let candidate_l_next = l_table[hash_l].pos();
+                l_table[hash_l] = T::from_pos(s + 1);
                 if (cv >> 8) as u32 == load32(src, candidate_l_next) {
                     s += 1;
                     candidate_l = candidate_l_next;
@@ -1987,7 +1634,7 @@ fn encode_block_better_64k(
         }
 
         // Bail if we exceed the maximum size.
-        if d + (s - next_emit) > dst_limit {
+        if !SNAPPY && d + (s - next_emit) > dst_limit {
             return 0;
         }
 
@@ -1995,28 +1642,26 @@ fn encode_block_better_64k(
         let offset = base - candidate_l;
 
         // Extend the 4-byte match as long as possible.
-        s += 4;
-        candidate_l += 4;
-        while s < src.len() {
-            if src.len() - s < 8 {
-                if src[s] == src[candidate_l] {
-                    s += 1;
-                    candidate_l += 1;
-                    continue;
-                }
-                break;
-            }
-            let diff = load64(src, s) ^ load64(src, candidate_l);
-            if diff != 0 {
-                s += (diff.trailing_zeros() / 8) as usize;
+        s = extend_match(src, s + 4, candidate_l + 4);
+
+        // Bail if the match is equal or worse to the encoding for large
+        // offsets. Only inputs over 64KB have such offsets.
+        if !SNAPPY && offset > 65535 && s - base <= 5 && repeat != offset {
+            s = next_s + 1;
+            if s >= s_limit {
                 break;
             }
-            s += 8;
-            candidate_l += 8;
+            cv = load64(src, s);
+            continue;
         }
 
+        // Emit literal
         d += emit_literal(&mut dst[d..], &src[next_emit..base]);
-        if repeat == offset {
+
+        // Emit copy
+        if SNAPPY {
+            d += emit_copy_snappy(&mut dst[d..], offset, s - base);
+        } else if repeat == offset {
             d += emit_repeat(&mut dst[d..], offset, s - base);
         } else {
             d += emit_copy(&mut dst[d..], offset, s - base);
@@ -2028,7 +1673,8 @@ fn encode_block_better_64k(
             break;
         }
 
-        if d > dst_limit {
+        if !SNAPPY && d > dst_limit {
+            // Do we have space for more, if not bail.
             return 0;
         }
 
@@ -2038,29 +1684,41 @@ fn encode_block_better_64k(
 
         let cv0 = load64(src, index0);
         let cv1 = load64(src, index1);
-        l_table[hash7(cv0, L_TABLE_BITS) as usize] = index0 as u16;
-        s_table[hash4(cv0 >> 8, S_TABLE_BITS) as usize] = (index0 + 1) as u16;
+        l_table[hash7(cv0, L_BITS) as usize] = T::from_pos(index0);
+        s_table[hash4(cv0 >> 8, S_BITS) as usize] = T::from_pos(index0 + 1);
 
-        l_table[hash7(cv1, L_TABLE_BITS) as usize] = index1 as u16;
-        s_table[hash4(cv1 >> 8, S_TABLE_BITS) as usize] = (index1 + 1) as u16;
+        l_table[hash7(cv1, L_BITS) as usize] = T::from_pos(index1);
+        s_table[hash4(cv1 >> 8, S_BITS) as usize] = T::from_pos(index1 + 1);
         let mut index0 = index0 + 1;
-        let index1 = index1 - 1;
+        let mut index1 = index1 - 1;
         cv = load64(src, s);
 
+        if SNAPPY {
+            // Index every second long value in between
+            while index0 < index1 {
+                l_table[hash7(load64(src, index0), L_BITS) as usize] = T::from_pos(index0);
+                l_table[hash7(load64(src, index1), L_BITS) as usize] = T::from_pos(index1);
+                index0 += 2;
+                index1 -= 2;
+            }
+            continue;
+        }
+
         // Index large values sparsely in between.
+        // We do two starting from different offsets for speed.
         let mut index2 = (index0 + index1 + 1) >> 1;
         while index2 < index1 {
-            l_table[hash7(load64(src, index0), L_TABLE_BITS) as usize] = index0 as u16;
-            l_table[hash7(load64(src, index2), L_TABLE_BITS) as usize] = index2 as u16;
-            index0 += 2;
-            index2 += 2;
+            l_table[hash7(load64(src, index0), L_BITS) as usize] = T::from_pos(index0);
+            l_table[hash7(load64(src, index2), L_BITS) as usize] = T::from_pos(index2);
+            index0 += stride;
+            index2 += stride;
         }
     }
 
     // Emit remaining
     if next_emit < src.len() {
         // Bail if we exceed the maximum size.
-        if d + src.len() - next_emit > dst_limit {
+        if !SNAPPY && d + src.len() - next_emit > dst_limit {
             return 0;
         }
         d += emit_literal(&mut dst[d..], &src[next_emit..]);
@@ -2071,6 +1729,10 @@ fn encode_block_better_64k(
 
 /// Emit a copy with potential repeat optimization
 fn emit_copy(dst: &mut [u8], offset: usize, length: usize) -> usize {
+    // A zero offset would be read back as a repeat (COPY1) or rejected as
+    // corrupt (COPY2/COPY4) by the decoder; catch it here during fuzzing.
+    debug_assert!(offset >= 1, "emit_copy: zero offset");
+    debug_assert!(length >= 4, "emit_copy: length {length} below minimum 4");
     if offset >= 65536 {
         return emit_copy4(dst, offset, length);
     }
@@ -2161,15 +1823,10 @@ fn emit_repeat_size(offset: usize, length: usize) -> usize {
     if length < (1 << 16) + (1 << 8) + 4 {
         return 4;
     }
-    const MAX_REPEAT: usize = (1 << 24) - 1;
-    let remaining = length - ((1 << 16) - 4);
-    let left = if remaining > MAX_REPEAT {
-        remaining - MAX_REPEAT + 4
-    } else {
-        0
-    };
-    if left > 0 {
-        return 5 + emit_repeat_size(offset, left);
+    // Split as emit_repeat does
+    let len = length - 4 - (1 << 16);
+    if len > MAX_REPEAT {
+        return 5 + emit_repeat_size(offset, len - MAX_REPEAT + 4);
     }
     5
 }
@@ -2181,6 +1838,7 @@ fn encode_block_best(
     src: &[u8],
     l_buf: &mut Vec<u64>,
     s_buf: &mut Vec<u64>,
+    opts: &BestOptions,
 ) -> usize {
     if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
         return 0;
@@ -2191,7 +1849,7 @@ fn encode_block_best(
     const S_TABLE_BITS: u8 = 16;
     const L_TABLE_SIZE: usize = 1 << L_TABLE_BITS;
     const S_TABLE_SIZE: usize = 1 << S_TABLE_BITS;
-    const MAX_SKIP: usize = 64;
+    let max_skip = opts.max_skip.max(1); // Go: maxSkip = 64
 
     // Hash tables store uint64: current position in lower 32 bits, previous in upper 32 bits
     ensure_zeroed_u64(l_buf, L_TABLE_SIZE);
@@ -2207,10 +1865,6 @@ fn encode_block_best(
     let mut d = 0;
     let mut repeat = 1; // Start at 1 like Go
 
-    if src.len() < 8 {
-        return 0;
-    }
-
     #[derive(Clone, Copy, Debug)]
     struct Match {
         offset: usize,
@@ -2242,7 +1896,7 @@ fn encode_block_best(
         let mut score = (m.length as i32) - (m.s as i32);
         if next_emit == m.s {
             // If we don't need to emit literals, we save 1 byte
-            score += 1;
+            score += opts.no_literal_bonus;
         }
         let offset = m.s - m.offset;
         if m.rep {
@@ -2330,8 +1984,8 @@ fn encode_block_best(
 
         loop {
             let mut next_s = (s - next_emit) / 256 + 1;
-            if next_s > MAX_SKIP {
-                next_s = s + MAX_SKIP;
+            if next_s > max_skip {
+                next_s = s + max_skip;
             } else {
                 next_s += s;
             }
@@ -2413,7 +2067,7 @@ fn encode_block_best(
             }
 
             // If we found a match, check positions s+1 and s+2 for better matches
-            if best.length > 0 {
+            if best.length > 0 && opts.extra_candidate_positions {
                 // Check s+1
                 let hash_s = hash4(cv >> 8, S_TABLE_BITS) as usize;
                 let next_short = s_table[hash_s];
@@ -2691,118 +2345,137 @@ fn encode_block_best(
     d
 }
 
-/// Encode a block using Snappy-compatible algorithm (no repeat offsets)
+/// Encode a fragment of at most 64KB with the Snappy algorithm
 ///
-/// This is similar to encode_block but uses emit_copy_no_repeat instead of
-/// the S2 repeat offset optimization, making it compatible with Snappy decoders.
+/// Port of Go's `snappy` `encodeBlock`, so the output is byte-for-byte that
+/// of `snappy.Encode` (and of the C++ library it follows): one hash probe
+/// per step, with the step growing by one byte every 32 misses, and no
+/// repeat codes. Always returns the encoded length, even if the fragment
+/// did not compress.
 fn encode_block_snappy(dst: &mut [u8], src: &[u8], table_buf: &mut Vec<u32>) -> usize {
-    if src.len() < MIN_NON_LITERAL_BLOCK_SIZE {
-        return 0;
-    }
-
-    // Size the hash table to the input — Snappy doesn't constrain table
-    // size (output is a function of hashing decisions, not exact size)
-    // and round-trip is all snappy_compat asserts. Smaller tables save
-    // the per-call calloc memset, which dominates 1 KB encode cost.
-    let table_bits: u32 = if src.len() < 1024 {
-        10
-    } else if src.len() < 8 * 1024 {
-        12
-    } else {
-        14
-    };
-    let table_size = 1usize << table_bits;
-    let shift = 32 - table_bits;
+    const MAX_TABLE_SIZE: usize = 1 << 14;
+    debug_assert!(src.len() >= SNAPPY_MIN_NON_LITERAL_BLOCK_SIZE);
+    debug_assert!(src.len() <= MAX_SNAPPY_BLOCK_SIZE);
 
+    // The table grows with the fragment from 1<<8 to 1<<14 entries.
+    let mut shift = 32 - 8;
+    let mut table_size = 1 << 8;
+    while table_size < MAX_TABLE_SIZE && table_size < src.len() {
+        table_size *= 2;
+        shift -= 1;
+    }
     ensure_zeroed_u32(table_buf, table_size);
     let table = table_buf.as_mut_slice();
 
-    let s_limit = src.len() - INPUT_MARGIN;
+    let s_limit = src.len() - SNAPPY_INPUT_MARGIN;
     let mut next_emit = 0;
-    let mut s = 1;
     let mut d = 0;
-    #[allow(unused_assignments)]
-    let mut repeat = 1;
 
-    #[allow(unused_variables)]
-    let cv = load64(src, s);
+    // The output must start with a literal, so matching starts at 1.
+    let mut s = 1;
+    let mut next_hash = hash(&src[s..], shift);
 
     'outer: loop {
-        let mut candidate;
         let mut skip = 32;
-
+        let mut next_s = s;
+        let mut candidate;
         loop {
-            let next_s = s + (skip >> 5);
-            skip += 1;
-
+            s = next_s;
+            let bytes_between_hash_lookups = skip >> 5;
+            next_s = s + bytes_between_hash_lookups;
+            skip += bytes_between_hash_lookups;
             if next_s > s_limit {
                 break 'outer;
             }
-
-            let h = hash(&src[s..], shift);
-            candidate = table[h] as usize;
-            table[h] = s as u32;
-
+            candidate = table[next_hash] as usize;
+            table[next_hash] = s as u32;
+            next_hash = hash(&src[next_s..], shift);
             if load32(src, s) == load32(src, candidate) {
                 break;
             }
-
-            s = next_s;
         }
 
-        // Extend backwards
-        while candidate > 0 && s > next_emit && src[candidate - 1] == src[s - 1] {
-            candidate -= 1;
-            s -= 1;
-        }
+        // A 4-byte match at s; everything before it is literal.
+        d += emit_literal(&mut dst[d..], &src[next_emit..s]);
 
-        // Emit literal
-        if s > next_emit {
-            d += emit_literal(&mut dst[d..], &src[next_emit..s]);
-        }
+        // Emit copies as long as the input right after one matches again.
+        loop {
+            let base = s;
+            s = extend_match(src, s + 4, candidate + 4);
+            d += emit_copy_snappy(&mut dst[d..], base - candidate, s - base);
+            next_emit = s;
+            if s >= s_limit {
+                break 'outer;
+            }
 
-        // Extend the match forward
-        let base = s;
-        repeat = base - candidate;
-        s += 4;
-        candidate += 4;
-
-        while s <= src.len() - 8 {
-            if load64(src, s) != load64(src, candidate) {
-                let diff = (load64(src, s) ^ load64(src, candidate)).trailing_zeros() / 8;
-                s += diff as usize;
+            // Index s - 1 and s, and check for a match at s right away.
+            let x = load64(src, s - 1);
+            let prev_hash = hash(&x.to_le_bytes(), shift);
+            table[prev_hash] = (s - 1) as u32;
+            let curr_hash = hash(&(x >> 8).to_le_bytes(), shift);
+            candidate = table[curr_hash] as usize;
+            table[curr_hash] = s as u32;
+            if (x >> 8) as u32 != load32(src, candidate) {
+                next_hash = hash(&(x >> 16).to_le_bytes(), shift);
+                s += 1;
                 break;
             }
-            s += 8;
-            candidate += 8;
-        }
-
-        // Use emit_copy_no_repeat for Snappy compatibility (no repeat offset optimization)
-        d += emit_copy_no_repeat(&mut dst[d..], repeat, s - base);
-        next_emit = s;
-
-        if s >= s_limit {
-            break;
         }
-
-        // Update hash table
-        let h1 = hash(&src[s - 1..], shift);
-        table[h1] = (s - 1) as u32;
-
-        s += 1;
     }
 
-    // Emit remaining
     if next_emit < src.len() {
         d += emit_literal(&mut dst[d..], &src[next_emit..]);
     }
+    d
+}
 
-    // Check if compression was worthwhile
-    if d >= src.len() - src.len() / 32 {
-        return 0;
-    }
+/// Encode a fragment of at most 64KB with the better algorithm, without
+/// repeat codes
+///
+/// Port of Go's `encodeBlockBetterSnappyGo`, on the shared better loop.
+/// Like `encode_block_snappy`, always returns the encoded length.
+fn encode_block_snappy_better(
+    dst: &mut [u8],
+    src: &[u8],
+    l_buf: &mut Vec<u32>,
+    s_buf: &mut Vec<u32>,
+) -> usize {
+    const L_TABLE_BITS: u8 = 16;
+    const S_TABLE_BITS: u8 = 14;
+    debug_assert!(src.len() <= MAX_SNAPPY_BLOCK_SIZE);
 
-    d
+    ensure_zeroed_u32(l_buf, 1 << L_TABLE_BITS);
+    ensure_zeroed_u32(s_buf, 1 << S_TABLE_BITS);
+    encode_block_better_loop::<u32, L_TABLE_BITS, S_TABLE_BITS, 7, true>(dst, src, l_buf, s_buf, 2)
+}
+
+/// Emit a copy the way Go's snappy `emitCopy` does
+///
+/// Offsets are below 64KB within a Snappy fragment. Long copies are split
+/// into 64-byte pieces, then one of 60 so at least 4 bytes remain.
+fn emit_copy_snappy(dst: &mut [u8], offset: usize, mut length: usize) -> usize {
+    debug_assert!((1..MAX_SNAPPY_BLOCK_SIZE).contains(&offset));
+    let mut i = 0;
+    while length >= 68 {
+        dst[i] = (63 << 2) | TAG_COPY2;
+        dst[i + 1..i + 3].copy_from_slice(&(offset as u16).to_le_bytes());
+        i += 3;
+        length -= 64;
+    }
+    if length > 64 {
+        dst[i] = (59 << 2) | TAG_COPY2;
+        dst[i + 1..i + 3].copy_from_slice(&(offset as u16).to_le_bytes());
+        i += 3;
+        length -= 60;
+    }
+    if length >= 12 || offset >= 2048 {
+        dst[i] = (((length - 1) << 2) as u8) | TAG_COPY2;
+        dst[i + 1..i + 3].copy_from_slice(&(offset as u16).to_le_bytes());
+        return i + 3;
+    }
+    dst[i] = (((offset >> 8) << 5) as u8) | (((length - 4) << 2) as u8) | TAG_COPY1;
+    dst[i + 1] = offset as u8;
+    i + 2
 }
 
 /// Encode a block using dictionary for better compression
@@ -2822,11 +2495,11 @@ fn encode_block_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> usize {
     let dict_data = dict.data();
     let dict_len = dict_data.len();
 
-    // Hash dictionary entries - mark as negative offsets to distinguish from source
+    // Hash dictionary entries - mark as negative offsets to distinguish from source.
+    // They must be hashed like source positions, or lookups never find them.
     let mut i = 0;
-    while i < dict_len.saturating_sub(8) {
-        let cv = load64(dict_data, i);
-        let h = hash6(cv, TABLE_BITS) as usize;
+    while i + 4 <= dict_len {
+        let h = hash(&dict_data[i..], shift);
         // Store as negative offset: -(dict_len - i)
         // This allows us to distinguish dictionary matches from source matches
         table[h] = (dict_len - i) as u32 | 0x80000000;
@@ -2837,11 +2510,10 @@ fn encode_block_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> usize {
     let mut next_emit = 0;
     let mut s = 1;
     let mut d = 0;
-    let mut repeat = dict_len - dict.repeat(); // Initialize repeat from dictionary
-
-    if src.len() < 8 {
-        return 0;
-    }
+    // Initialize repeat from dictionary. Unlike the dictionary-less encoders,
+    // a repeat may be the first copy of the block: `decode_with_dict` seeds
+    // its last offset with this same value.
+    let mut repeat = dict_len - dict.repeat();
 
     let mut cv = load64(src, s);
 
@@ -2874,10 +2546,17 @@ fn encode_block_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> usize {
                 }
                 candidate_pos = dict_len - dict_offset;
 
-                // Verify match in dictionary
-                if candidate_pos < dict_len.saturating_sub(8) {
-                    let dict_cv = load64(dict_data, candidate_pos);
-                    if cv == dict_cv {
+                // Verify match in dictionary. The decoder only copies from the
+                // dictionary up to MAX_DICT_SRC_OFFSET into the block.
+                // Near its end (or in a dictionary under 8 bytes) only 4
+                // bytes are left to compare.
+                if s + 4 <= MAX_DICT_SRC_OFFSET + 1 {
+                    let matched = if candidate_pos + 8 <= dict_len {
+                        load64(dict_data, candidate_pos) == cv
+                    } else {
+                        dict_data[candidate_pos..candidate_pos + 4] == src[s..s + 4]
+                    };
+                    if matched {
                         break;
                     }
                 }
@@ -2911,14 +2590,14 @@ fn encode_block_dict(dst: &mut [u8], src: &[u8], dict: &Dict) -> usize {
             length = 4;
             let dict_remain = dict_len - candidate_pos;
             let src_remain = src.len() - s;
-            let max_len = dict_remain.min(src_remain);
+            let max_len = dict_remain.min(src_remain).min(MAX_DICT_SRC_OFFSET + 1 - s);
 
             while length < max_len && dict_data[candidate_pos + length] == src[s + length] {
                 length += 1;
             }
 
             // Calculate offset for dictionary match
-            // When decoding: dict_start = dict.data().len() - offset + d
+            // When decoding: dict_start = dict.data().len() + d - offset
             // So: offset = dict.data().len() - dict_start + d
             // Where dict_start is candidate_pos and d is s (current output position)
             let offset = dict_len - candidate_pos + s;
@@ -2997,11 +2676,31 @@ pub mod test_helpers {
         emit_literal(dst, lit)
     }
 
+    /// Test wrapper for encode_block_with_table
+    pub fn test_encode_block_with_table(dst: &mut [u8], src: &[u8], table: &mut [u32]) -> usize {
+        encode_block_with_table(dst, src, table)
+    }
+
     /// Test wrapper for emit_copy
     pub fn test_emit_copy(dst: &mut [u8], offset: usize, length: usize) -> usize {
         emit_copy(dst, offset, length)
     }
 
+    /// Test wrapper for emit_copy_size
+    pub fn test_emit_copy_size(offset: usize, length: usize) -> usize {
+        emit_copy_size(offset, length)
+    }
+
+    /// Test wrapper for emit_repeat
+    pub fn test_emit_repeat(dst: &mut [u8], offset: usize, length: usize) -> usize {
+        emit_repeat(dst, offset, length)
+    }
+
+    /// Test wrapper for emit_repeat_size
+    pub fn test_emit_repeat_size(offset: usize, length: usize) -> usize {
+        emit_repeat_size(offset, length)
+    }
+
     /// Match length function for testing
     /// Counts the number of matching bytes at the beginning of two slices
     pub fn test_match_len(a: &[u8], b: &[u8]) -> usize {
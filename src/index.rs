@@ -8,9 +8,10 @@ use alloc::vec::Vec;
 
 /// S2 Index header and trailer constants
 const S2_INDEX_HEADER: &[u8] = b"s2idx\x00";
-const S2_INDEX_TRAILER: &[u8] = b"\x00xdi2s";
+pub(crate) const S2_INDEX_TRAILER: &[u8] = b"\x00xdi2s";
 const MAX_INDEX_ENTRIES: usize = 1 << 16;
 const MIN_INDEX_DIST: i64 = 1 << 20; // 1MB minimum distance between entries
+const MIN_INDEX_DIST_FLOOR: i64 = 4 << 10; // Lowest distance set_min_dist accepts
 const SKIPPABLE_FRAME_HEADER: usize = 4;
 
 /// Entry in the index mapping compressed to uncompressed offsets
@@ -20,6 +21,21 @@ struct IndexEntry {
     uncompressed_offset: i64,
 }
 
+/// Block range returned by [`Index::find_range`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexRange {
+    /// Compressed offset of the entry at or before the wanted offset
+    pub compressed_offset: i64,
+    /// Uncompressed offset of the same entry
+    pub uncompressed_offset: i64,
+    /// Compressed offset of the following entry, or `None` if the range
+    /// extends to the end of the stream
+    pub compressed_end: Option<i64>,
+    /// Uncompressed offset of the following entry, or `None` if the range
+    /// extends to the end of the stream
+    pub uncompressed_end: Option<i64>,
+}
+
 /// Index represents an S2/Snappy index for seeking support.
 ///
 /// An index allows random access to compressed data by storing
@@ -34,6 +50,8 @@ pub struct Index {
     info: Vec<IndexEntry>,
     /// Estimated block uncompressed size
     est_block_uncomp: i64,
+    /// Minimum uncompressed distance between entries
+    min_dist: i64,
 }
 
 impl Index {
@@ -44,6 +62,7 @@ impl Index {
             total_compressed: -1,
             info: Vec::new(),
             est_block_uncomp: 0,
+            min_dist: MIN_INDEX_DIST,
         }
     }
 
@@ -55,6 +74,23 @@ impl Index {
         self.info.clear();
     }
 
+    /// Set the minimum uncompressed distance between entries
+    ///
+    /// [`add`](Index::add) skips offsets closer than this to the last entry,
+    /// and serializing thins the entries to keep them this far apart. The
+    /// default is 1MB; lowering it (e.g. to 64KB) gives [`find`](Index::find)
+    /// a finer granularity for random access, as long as the stream's
+    /// blocks are at most that large. Values below 4KB are raised to 4KB.
+    ///
+    /// The serialized index grows with the number of entries, which a 64KB
+    /// spacing multiplies by 16: about a byte per entry when blocks
+    /// compress evenly, several when they vary. The index still holds at
+    /// most 65536 entries; beyond that entries are dropped evenly
+    /// regardless of this setting. Kept across [`reset`](Index::reset).
+    pub fn set_min_dist(&mut self, dist: i64) {
+        self.min_dist = dist.max(MIN_INDEX_DIST_FLOOR);
+    }
+
     /// Add a compressed/uncompressed offset pair.
     /// Entries must be added in order.
     pub fn add(&mut self, compressed_offset: i64, uncompressed_offset: i64) -> Result<()> {
@@ -77,7 +113,7 @@ impl Index {
             }
 
             // Only add entry if distance is large enough
-            if last.uncompressed_offset + MIN_INDEX_DIST > uncompressed_offset {
+            if last.uncompressed_offset + self.min_dist > uncompressed_offset {
                 return Ok(());
             }
         }
@@ -89,6 +125,69 @@ impl Index {
         Ok(())
     }
 
+    /// Append the entries of `other`, an index of a stream that follows
+    /// this one, shifted by the given bases.
+    ///
+    /// `compressed_base` and `uncompressed_base` are where `other`'s stream
+    /// starts in the combined stream, e.g. the sizes of everything before
+    /// it when indexing concatenated streams or resuming a run. Every
+    /// shifted entry must come after this index's last entry, else
+    /// [`Error::Corrupt`] is returned and the index is left unchanged.
+    /// Entries are kept as they are, without the spacing [`add`](Index::add)
+    /// enforces.
+    ///
+    /// The totals become the bases plus `other`'s totals, or unknown (-1)
+    /// if `other`'s are.
+    pub fn extend(
+        &mut self,
+        other: &Index,
+        compressed_base: i64,
+        uncompressed_base: i64,
+    ) -> Result<()> {
+        if compressed_base < 0 || uncompressed_base < 0 {
+            return Err(Error::Corrupt);
+        }
+
+        let mut last = self.info.last().copied();
+        for entry in &other.info {
+            let shifted = IndexEntry {
+                compressed_offset: entry
+                    .compressed_offset
+                    .checked_add(compressed_base)
+                    .ok_or(Error::Corrupt)?,
+                uncompressed_offset: entry
+                    .uncompressed_offset
+                    .checked_add(uncompressed_base)
+                    .ok_or(Error::Corrupt)?,
+            };
+            if let Some(last) = last {
+                if shifted.uncompressed_offset <= last.uncompressed_offset
+                    || shifted.compressed_offset < last.compressed_offset
+                {
+                    return Err(Error::Corrupt);
+                }
+            }
+            last = Some(shifted);
+        }
+
+        self.info.extend(other.info.iter().map(|e| IndexEntry {
+            compressed_offset: e.compressed_offset + compressed_base,
+            uncompressed_offset: e.uncompressed_offset + uncompressed_base,
+        }));
+        self.total_compressed = if other.total_compressed < 0 {
+            -1
+        } else {
+            compressed_base + other.total_compressed
+        };
+        self.total_uncompressed = if other.total_uncompressed < 0 {
+            -1
+        } else {
+            uncompressed_base + other.total_uncompressed
+        };
+        self.est_block_uncomp = self.est_block_uncomp.max(other.est_block_uncomp);
+        Ok(())
+    }
+
     /// Find the offset at or before the wanted (uncompressed) offset.
     ///
     /// If offset is 0 or positive it is the offset from the beginning of the file.
@@ -97,6 +196,43 @@ impl Index {
     ///
     /// Returns (compressed_offset, uncompressed_offset) tuple.
     pub fn find(&self, offset: i64) -> Result<(i64, i64)> {
+        let idx = self.floor_entry(offset)?;
+        Ok(idx.map_or((0, 0), |i| {
+            (
+                self.info[i].compressed_offset,
+                self.info[i].uncompressed_offset,
+            )
+        }))
+    }
+
+    /// Find the entries bracketing the wanted (uncompressed) offset.
+    ///
+    /// The start of the returned range is the same entry [`find`](Index::find)
+    /// returns, and its end is the following entry, if any. Decoding from
+    /// `compressed_offset` up to `compressed_end` covers `offset`; when there
+    /// is no following entry the range extends to the end of the stream.
+    ///
+    /// Offsets are interpreted as for [`find`](Index::find).
+    pub fn find_range(&self, offset: i64) -> Result<IndexRange> {
+        let idx = self.floor_entry(offset)?;
+        let (compressed_offset, uncompressed_offset) = idx.map_or((0, 0), |i| {
+            (
+                self.info[i].compressed_offset,
+                self.info[i].uncompressed_offset,
+            )
+        });
+        let next = self.info.get(idx.map_or(0, |i| i + 1));
+        Ok(IndexRange {
+            compressed_offset,
+            uncompressed_offset,
+            compressed_end: next.map(|e| e.compressed_offset),
+            uncompressed_end: next.map(|e| e.uncompressed_offset),
+        })
+    }
+
+    /// Position of the last entry at or before `offset`, after resolving
+    /// negative offsets and checking bounds.
+    fn floor_entry(&self, offset: i64) -> Result<Option<usize>> {
         if self.total_uncompressed < 0 {
             return Err(Error::Corrupt);
         }
@@ -115,49 +251,24 @@ impl Index {
             return Err(Error::InvalidInput("offset beyond end".to_string()));
         }
 
-        // For large indexes, use binary search
-        if self.info.len() > 200 {
-            let idx = match self
-                .info
-                .binary_search_by_key(&offset, |e| e.uncompressed_offset)
-            {
-                Ok(i) => i,
-                Err(i) => {
-                    if i == 0 {
-                        0
-                    } else {
-                        i - 1
-                    }
-                }
-            };
-            let entry = self.info[idx];
-            return Ok((entry.compressed_offset, entry.uncompressed_offset));
-        }
-
-        // For small indexes, linear search
-        let mut compressed_off = 0;
-        let mut uncompressed_off = 0;
-        for entry in &self.info {
-            if entry.uncompressed_offset > offset {
-                break;
-            }
-            compressed_off = entry.compressed_offset;
-            uncompressed_off = entry.uncompressed_offset;
-        }
-        Ok((compressed_off, uncompressed_off))
+        // Entries are added in order, so they are sorted by uncompressed offset.
+        let n = self
+            .info
+            .partition_point(|e| e.uncompressed_offset <= offset);
+        Ok(n.checked_sub(1))
     }
 
     /// Reduce index size to stay below MAX_INDEX_ENTRIES
     fn reduce(&mut self) {
-        if self.info.len() < MAX_INDEX_ENTRIES && self.est_block_uncomp >= MIN_INDEX_DIST {
+        if self.info.len() < MAX_INDEX_ENTRIES && self.est_block_uncomp >= self.min_dist {
             return;
         }
 
         // Keep 1, remove removeN entries
         let mut remove_n = (self.info.len() + 1) / MAX_INDEX_ENTRIES;
 
-        // Each block should be at least 1MB, but don't reduce below 1000 entries
-        while self.est_block_uncomp * (remove_n as i64 + 1) < MIN_INDEX_DIST
+        // Entries should be at least min_dist apart, but don't reduce below 1000 entries
+        while self.est_block_uncomp * (remove_n as i64 + 1) < self.min_dist
             && self.info.len() / (remove_n + 1) > 1000
         {
             remove_n += 1;
@@ -269,6 +380,10 @@ impl Index {
     }
 
     /// Load index from bytes
+    ///
+    /// `data` starts with the index chunk. Returns the bytes following it.
+    /// The total size stored before the trailer must match the size of the
+    /// chunk, or `Error::Corrupt` is returned.
     pub fn load<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8]> {
         if data.len() <= 4 + S2_INDEX_HEADER.len() + S2_INDEX_TRAILER.len() {
             return Err(Error::BufferTooSmall);
@@ -359,9 +474,12 @@ impl Index {
             let comp_off = if i == 0 {
                 v
             } else {
+                // Predict with the old estimate, then update it with half
+                // the error, mirroring append_to.
                 let prev_comp = self.info[i - 1].compressed_offset;
+                let comp_off = prev_comp + c_predict + v;
                 c_predict += v / 2;
-                prev_comp + c_predict + v
+                comp_off
             };
 
             self.info.push(IndexEntry {
@@ -370,19 +488,22 @@ impl Index {
             });
         }
 
-        // Validate trailer
+        // Total size and trailer follow the entries
         if b.len() < 4 + S2_INDEX_TRAILER.len() {
             return Err(Error::Corrupt);
         }
+        let total_size = u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+        b = &b[4..];
 
-        let total_size_pos = b.len() - S2_INDEX_TRAILER.len() - 4;
-        let trailer_pos = b.len() - S2_INDEX_TRAILER.len();
-
-        if &b[trailer_pos..trailer_pos + S2_INDEX_TRAILER.len()] != S2_INDEX_TRAILER {
+        if !b.starts_with(S2_INDEX_TRAILER) {
             return Err(Error::Corrupt);
         }
+        let remaining = &b[S2_INDEX_TRAILER.len()..];
 
-        let remaining = &b[total_size_pos + 4 + S2_INDEX_TRAILER.len()..];
+        // The total size covers the whole chunk, header included
+        if total_size != data.len() - remaining.len() {
+            return Err(Error::Corrupt);
+        }
         Ok(remaining)
     }
 }
@@ -407,21 +528,32 @@ fn encode_varint(buf: &mut Vec<u8>, value: i64) {
 }
 
 /// Decode a signed varint, returns (value, bytes_read)
+///
+/// Index data comes from the stream and is untrusted: a varint that runs
+/// past 10 bytes, or whose 10th byte carries bits beyond the 64th, is
+/// rejected with [`Error::Corrupt`] instead of being silently truncated.
 fn decode_varint(data: &[u8]) -> Result<(i64, usize)> {
     let mut result: u64 = 0;
     let mut shift = 0;
-    let mut i = 0;
 
-    while i < data.len() && i < 10 {
-        let byte = data[i];
-        result |= ((byte & 0x7f) as u64) << shift;
-        i += 1;
+    for (i, &byte) in data.iter().enumerate() {
+        if i >= 10 {
+            // The 10th byte still had its continuation bit set.
+            return Err(Error::Corrupt);
+        }
 
         if byte < 0x80 {
+            // Only the lowest bit of the 10th byte fits in a u64.
+            if i == 9 && byte > 1 {
+                return Err(Error::Corrupt);
+            }
+            result |= (byte as u64) << shift;
             // Zig-zag decode
             let value = ((result >> 1) as i64) ^ -((result & 1) as i64);
-            return Ok((value, i));
+            return Ok((value, i + 1));
         }
+
+        result |= ((byte & 0x7f) as u64) << shift;
         shift += 7;
     }
 
@@ -460,6 +592,209 @@ mod tests {
         assert_eq!(u, 1024 * 1024);
     }
 
+    #[test]
+    fn test_index_find_range() {
+        let mut index = Index::new();
+        index.reset(1 << 20);
+        index.total_uncompressed = 4 << 20;
+        for (c, u) in [
+            (10, 0),
+            (300_000, 1 << 20),
+            (600_000, 2 << 20),
+            (900_000, 3 << 20),
+        ] {
+            index.add(c, u).unwrap();
+        }
+
+        // Mid-stream: the floor block and the one after it.
+        let range = index.find_range((2 << 20) + 12_345).unwrap();
+        assert_eq!(
+            range,
+            IndexRange {
+                compressed_offset: 600_000,
+                uncompressed_offset: 2 << 20,
+                compressed_end: Some(900_000),
+                uncompressed_end: Some(3 << 20),
+            }
+        );
+        assert_eq!(
+            (range.compressed_offset, range.uncompressed_offset),
+            index.find((2 << 20) + 12_345).unwrap()
+        );
+
+        // Exactly on an entry.
+        let range = index.find_range(1 << 20).unwrap();
+        assert_eq!(range.compressed_offset, 300_000);
+        assert_eq!(range.uncompressed_offset, 1 << 20);
+        assert_eq!(range.compressed_end, Some(600_000));
+        assert_eq!(range.uncompressed_end, Some(2 << 20));
+
+        // Last block has no following entry.
+        let range = index.find_range(-1).unwrap();
+        assert_eq!(range.compressed_offset, 900_000);
+        assert_eq!(range.uncompressed_offset, 3 << 20);
+        assert_eq!(range.compressed_end, None);
+        assert_eq!(range.uncompressed_end, None);
+
+        assert!(index.find_range((4 << 20) + 1).is_err());
+    }
+
+    #[test]
+    fn test_index_extend() {
+        let build = |offsets: &[(i64, i64)], total_c: i64, total_u: i64| {
+            let mut index = Index::new();
+            index.reset(1 << 20);
+            for &(c, u) in offsets {
+                index.add(c, u).unwrap();
+            }
+            index.total_compressed = total_c;
+            index.total_uncompressed = total_u;
+            index
+        };
+        let first_offsets = [(10, 0), (300_000, 1 << 20), (600_000, 2 << 20)];
+        let second_offsets = [(10, 0), (200_000, 1 << 20)];
+        let mut index = build(&first_offsets, 800_000, 3 << 20);
+        let second = build(&second_offsets, 350_000, (3 << 20) / 2);
+
+        index.extend(&second, 800_000, 3 << 20).unwrap();
+        assert_eq!(index.total_compressed, 1_150_000);
+        assert_eq!(index.total_uncompressed, (9 << 20) / 2);
+
+        // Just before, at and after the boundary.
+        assert_eq!(index.find((3 << 20) - 1).unwrap(), (600_000, 2 << 20));
+        assert_eq!(index.find(3 << 20).unwrap(), (800_010, 3 << 20));
+        let range = index.find_range((4 << 20) + 1).unwrap();
+        assert_eq!(range.compressed_offset, 1_000_000);
+        assert_eq!(range.uncompressed_offset, 4 << 20);
+        assert_eq!(range.compressed_end, None);
+        assert_eq!(index.find(-1).unwrap(), (1_000_000, 4 << 20));
+
+        // The merged index survives serialization.
+        let mut buf = Vec::new();
+        index
+            .append_to(&mut buf, index.total_uncompressed, index.total_compressed)
+            .unwrap();
+        let mut loaded = Index::new();
+        loaded.load(&buf).unwrap();
+        assert_eq!(loaded.find(3 << 20).unwrap(), (800_010, 3 << 20));
+
+        // Entries that would go backwards are rejected without changes.
+        let mut index = build(&first_offsets, 800_000, 3 << 20);
+        assert!(matches!(
+            index.extend(&second, 800_000, 2 << 20),
+            Err(Error::Corrupt)
+        ));
+        assert!(index.extend(&second, 500_000, 3 << 20).is_err());
+        assert!(index.extend(&second, -1, 3 << 20).is_err());
+        assert_eq!(index.info.len(), 3);
+        assert_eq!(index.total_uncompressed, 3 << 20);
+
+        // Unknown totals stay unknown.
+        let mut index = build(&first_offsets, 800_000, 3 << 20);
+        index
+            .extend(&build(&second_offsets, -1, -1), 800_000, 3 << 20)
+            .unwrap();
+        assert_eq!(index.total_uncompressed, -1);
+        assert_eq!(index.info.len(), 5);
+    }
+
+    #[test]
+    fn test_index_append_load_roundtrip() {
+        // Compressed sizes far from the est/2 prediction exercise the
+        // prediction update on both sides.
+        let offsets = [(10, 0), (281, 1 << 20), (552, 2 << 20), (900_000, 3 << 20)];
+        let mut index = Index::new();
+        index.reset(1 << 20);
+        for &(c, u) in &offsets {
+            index.add(c, u).unwrap();
+        }
+        let mut buf = Vec::new();
+        index.append_to(&mut buf, 4 << 20, 1_000_000).unwrap();
+
+        let mut loaded = Index::new();
+        assert!(loaded.load(&buf).unwrap().is_empty());
+        for &(c, u) in &offsets {
+            assert_eq!(loaded.find(u).unwrap(), (c, u));
+        }
+    }
+
+    #[test]
+    fn test_index_load_total_size() {
+        let mut index = Index::new();
+        index.reset(1 << 20);
+        for i in 0..4 {
+            index.add(i * 300_000, i << 20).unwrap();
+        }
+        let mut buf = Vec::new();
+        index.append_to(&mut buf, 4 << 20, 1_200_000).unwrap();
+        let size_pos = buf.len() - S2_INDEX_TRAILER.len() - 4;
+        assert_eq!(
+            u32::from_le_bytes(buf[size_pos..size_pos + 4].try_into().unwrap()) as usize,
+            buf.len()
+        );
+
+        // Data after the index is returned
+        let mut followed = buf.clone();
+        followed.extend_from_slice(b"next chunk");
+        let mut loaded = Index::new();
+        assert_eq!(loaded.load(&followed).unwrap(), b"next chunk");
+        assert_eq!(loaded.info.len(), 4);
+
+        for delta in [1u32, u32::MAX] {
+            let mut corrupt = buf.clone();
+            let size = u32::from_le_bytes(corrupt[size_pos..size_pos + 4].try_into().unwrap());
+            corrupt[size_pos..size_pos + 4]
+                .copy_from_slice(&size.wrapping_add(delta).to_le_bytes());
+            assert!(matches!(Index::new().load(&corrupt), Err(Error::Corrupt)));
+        }
+    }
+
+    #[test]
+    fn test_index_set_min_dist() {
+        // A 16MB stream of 64KB blocks, each compressing to 20000 bytes
+        let block = 64 << 10;
+        let blocks = 256i64;
+        let build = |min_dist: Option<i64>| {
+            let mut index = Index::new();
+            index.reset(block);
+            if let Some(dist) = min_dist {
+                index.set_min_dist(dist);
+            }
+            for i in 0..blocks {
+                index.add(10 + i * 20000, i * block).unwrap();
+            }
+            let mut buf = Vec::new();
+            index
+                .append_to(&mut buf, blocks * block, 10 + blocks * 20000)
+                .unwrap();
+            let mut loaded = Index::new();
+            loaded.load(&buf).unwrap();
+            (loaded, buf.len())
+        };
+        let (sparse, sparse_len) = build(None);
+        let (dense, dense_len) = build(Some(block));
+        assert_eq!(sparse.info.len(), 16);
+        assert_eq!(dense.info.len(), 256);
+        assert!(dense_len > sparse_len * 2, "{} {}", dense_len, sparse_len);
+
+        // The dense index lands on the block holding the offset
+        let offset = 5_000_000;
+        assert_eq!(sparse.find(offset).unwrap(), (10 + 64 * 20000, 64 * block));
+        assert_eq!(dense.find(offset).unwrap(), (10 + 76 * 20000, 76 * block));
+        for offset in (0..blocks * block).step_by(777_777) {
+            let (_, u) = dense.find(offset).unwrap();
+            assert!(offset - u < block, "{}", offset);
+        }
+
+        // Distances are floored at 4KB
+        let mut index = Index::new();
+        index.set_min_dist(1);
+        index.add(0, 0).unwrap();
+        index.add(1, 4095).unwrap();
+        index.add(2, 4096).unwrap();
+        assert_eq!(index.info.len(), 2);
+    }
+
     #[test]
     fn test_varint_roundtrip() {
         let test_values = vec![0, 1, -1, 127, -127, 128, -128, 65535, -65535];
@@ -472,4 +807,45 @@ mod tests {
             assert_eq!(n, buf.len());
         }
     }
+
+    #[test]
+    fn test_varint_extremes_roundtrip() {
+        for &val in &[i64::MAX, i64::MIN, i64::MAX - 1, i64::MIN + 1] {
+            let mut buf = Vec::new();
+            encode_varint(&mut buf, val);
+            assert_eq!(buf.len(), 10);
+            let (decoded, n) = decode_varint(&buf).unwrap();
+            assert_eq!(decoded, val, "Failed for value {}", val);
+            assert_eq!(n, 10);
+        }
+    }
+
+    #[test]
+    fn test_invalid_varint() {
+        let test_cases = vec![
+            ("empty input", vec![]),
+            ("final byte has continuation bit set", vec![0xff]),
+            (
+                "10-byte varint whose final byte has continuation bit set",
+                vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x81],
+            ),
+            (
+                "value overflows 64 bits",
+                vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02],
+            ),
+            (
+                "11-byte varint",
+                vec![
+                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+                ],
+            ),
+        ];
+
+        for (desc, input) in test_cases {
+            match decode_varint(&input) {
+                Err(Error::Corrupt) => {}
+                other => panic!("{}: expected Corrupt, got {:?}", desc, other),
+            }
+        }
+    }
 }
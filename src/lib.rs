@@ -60,6 +60,10 @@ pub mod crc;
 // compatibility; also re-exported under the `s2` module below.
 // ----------------------------------------------------------------------------
 
+#[cfg(feature = "s2")]
+mod block_index;
+#[cfg(feature = "s2")]
+mod concat;
 #[cfg(feature = "s2")]
 mod constants;
 #[cfg(feature = "s2")]
@@ -71,37 +75,81 @@ mod encode;
 #[cfg(feature = "s2")]
 mod index;
 
+#[cfg(all(feature = "std", feature = "s2"))]
+mod archive;
+#[cfg(all(feature = "std", feature = "s2"))]
+mod block_writer;
+#[cfg(all(feature = "std", feature = "s2"))]
+mod maybe_reader;
 #[cfg(all(feature = "std", feature = "s2"))]
 mod reader;
 #[cfg(all(feature = "std", feature = "s2"))]
+mod record_reader;
+#[cfg(all(feature = "std", feature = "s2"))]
+mod split_writer;
+#[cfg(all(feature = "std", feature = "s2"))]
+mod tee_writer;
+#[cfg(all(feature = "std", feature = "s2"))]
 mod writer;
 
 #[cfg(feature = "concurrent")]
 mod concurrent;
 
+#[cfg(feature = "s2")]
+pub use block_index::{encode_blocks, BlockIndex};
+#[cfg(feature = "s2")]
+pub use concat::{block_to_stream, concat_streams};
+#[cfg(feature = "s2")]
+pub use crc::stream_checksum;
 #[cfg(feature = "s2")]
 pub use decode::{
-    decode, decode_into, decode_len, decode_snappy, decode_with_dict, Decoder, MAX_DECODE_DST_SIZE,
+    analyze_block, decode, decode_block, decode_guaranteed, decode_into, decode_len,
+    decode_max_offset, decode_prefix, decode_resume, decode_snappy, decode_snappy_strict,
+    decode_with_dict, BlockAnalysis, BlockDecodeState, Decoder, MAX_DECODE_DST_SIZE,
 };
 #[cfg(feature = "s2")]
 pub use dict::{
-    make_dict, make_dict_manual, Dict, MAX_DICT_SIZE, MAX_DICT_SRC_OFFSET, MIN_DICT_SIZE,
+    make_dict, make_dict_allow_small, make_dict_manual, Dict, MAX_DICT_SIZE, MAX_DICT_SRC_OFFSET,
+    MIN_DICT_SIZE,
 };
+#[cfg(all(feature = "s2", fuzzing))]
+#[doc(hidden)]
+pub use encode::encode_block_with_table;
 #[cfg(feature = "s2")]
 pub use encode::{
-    encode, encode_best, encode_best_with_dict, encode_better, encode_better_with_dict,
-    encode_snappy, encode_with_dict, max_encoded_len, Encoder,
+    encode, encode_best, encode_best_with_dict, encode_best_with_options, encode_better,
+    encode_better_with_dict, encode_better_with_options, encode_force, encode_guaranteed,
+    encode_snappy, encode_snappy_better, encode_with_dict, encode_with_options, max_encoded_len,
+    BestOptions, BetterOptions, EncodeOptions, Encoder, HashKind, Level,
 };
 #[cfg(feature = "s2")]
-pub use index::Index;
+pub use index::{Index, IndexRange};
 
 #[cfg(all(feature = "std", feature = "s2"))]
-pub use reader::Reader;
+pub use archive::{ArchiveEntry, ArchiveReader, ArchiveWriter};
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use block_writer::BlockWriter;
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use maybe_reader::MaybeReader;
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use reader::{
+    decode_stream_recoverable, stream_decoded_len, verify_stream, Reader, VerifyReport,
+};
 #[cfg(all(feature = "std", feature = "s2"))]
-pub use writer::Writer;
+pub use record_reader::RecordReader;
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use split_writer::SplitWriter;
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use tee_writer::TeeWriter;
+#[cfg(all(feature = "std", feature = "s2"))]
+pub use writer::{
+    encode_chunked, encode_reader, recommended_block_size, PooledWriter, Writer, WriterPool,
+};
 
 #[cfg(feature = "concurrent")]
-pub use concurrent::ConcurrentWriter;
+pub use concurrent::{ConcurrentWriter, PipelinedWriter};
+#[cfg(feature = "concurrent")]
+pub use reader::decode_range_concurrent;
 
 /// The S2 codec (Snappy-compatible), namespaced.
 ///
@@ -110,26 +158,51 @@ pub use concurrent::ConcurrentWriter;
 /// make the format explicit now that the crate also ships a [`minlz`] codec.
 #[cfg(feature = "s2")]
 pub mod s2 {
+    pub use crate::block_index::{encode_blocks, BlockIndex};
+    pub use crate::concat::{block_to_stream, concat_streams};
+    pub use crate::crc::stream_checksum;
     pub use crate::decode::{
-        decode, decode_into, decode_len, decode_snappy, decode_with_dict, Decoder,
-        MAX_DECODE_DST_SIZE,
+        analyze_block, decode, decode_block, decode_guaranteed, decode_into, decode_len,
+        decode_max_offset, decode_prefix, decode_resume, decode_snappy, decode_snappy_strict,
+        decode_with_dict, BlockAnalysis, BlockDecodeState, Decoder, MAX_DECODE_DST_SIZE,
     };
     pub use crate::dict::{
-        make_dict, make_dict_manual, Dict, MAX_DICT_SIZE, MAX_DICT_SRC_OFFSET, MIN_DICT_SIZE,
+        make_dict, make_dict_allow_small, make_dict_manual, Dict, MAX_DICT_SIZE,
+        MAX_DICT_SRC_OFFSET, MIN_DICT_SIZE,
     };
     pub use crate::encode::{
-        encode, encode_best, encode_best_with_dict, encode_better, encode_better_with_dict,
-        encode_snappy, encode_with_dict, max_encoded_len, Encoder,
+        encode, encode_best, encode_best_with_dict, encode_best_with_options, encode_better,
+        encode_better_with_dict, encode_better_with_options, encode_force, encode_guaranteed,
+        encode_snappy, encode_snappy_better, encode_with_dict, encode_with_options,
+        max_encoded_len, BestOptions, BetterOptions, EncodeOptions, Encoder, HashKind, Level,
     };
-    pub use crate::index::Index;
+    pub use crate::index::{Index, IndexRange};
 
     #[cfg(feature = "std")]
-    pub use crate::reader::Reader;
+    pub use crate::archive::{ArchiveEntry, ArchiveReader, ArchiveWriter};
+    #[cfg(feature = "std")]
+    pub use crate::block_writer::BlockWriter;
+    #[cfg(feature = "std")]
+    pub use crate::maybe_reader::MaybeReader;
     #[cfg(feature = "std")]
-    pub use crate::writer::Writer;
+    pub use crate::reader::{
+        decode_stream_recoverable, stream_decoded_len, verify_stream, Reader, VerifyReport,
+    };
+    #[cfg(feature = "std")]
+    pub use crate::record_reader::RecordReader;
+    #[cfg(feature = "std")]
+    pub use crate::split_writer::SplitWriter;
+    #[cfg(feature = "std")]
+    pub use crate::tee_writer::TeeWriter;
+    #[cfg(feature = "std")]
+    pub use crate::writer::{
+        encode_chunked, encode_reader, recommended_block_size, PooledWriter, Writer, WriterPool,
+    };
 
     #[cfg(feature = "concurrent")]
-    pub use crate::concurrent::ConcurrentWriter;
+    pub use crate::concurrent::{ConcurrentWriter, PipelinedWriter};
+    #[cfg(feature = "concurrent")]
+    pub use crate::reader::decode_range_concurrent;
 }
 
 // ----------------------------------------------------------------------------
@@ -0,0 +1,163 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Reader that decompresses S2/Snappy streams and passes other input through
+
+use std::io::{self, Chain, Cursor, Read};
+
+use crate::constants::*;
+use crate::reader::Reader;
+
+/// Input with the peeked prefix put back in front
+type Prefixed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+enum Inner<R: Read> {
+    Pending(R),
+    Compressed(Box<Reader<Prefixed<R>>>),
+    Raw(Prefixed<R>),
+    // Only seen if reading the prefix failed
+    Poisoned,
+}
+
+/// MaybeReader decompresses its input if it is an S2 or Snappy stream and
+/// passes it through unchanged otherwise
+///
+/// The first read peeks at the start of the input: if it is the S2 or
+/// Snappy stream identifier, the input is decoded with a [`Reader`];
+/// anything else, including input shorter than the identifier, is
+/// returned as is. The peeked bytes are buffered and handed out first, so
+/// nothing is lost either way.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{MaybeReader, Writer};
+/// use std::io::{Read, Write};
+///
+/// let mut compressed = Vec::new();
+/// Writer::new(&mut compressed).write_all(b"Hello, World!").unwrap();
+///
+/// for input in [&compressed[..], &b"Hello, World!"[..]] {
+///     let mut out = Vec::new();
+///     MaybeReader::new(input).read_to_end(&mut out).unwrap();
+///     assert_eq!(out, b"Hello, World!");
+/// }
+/// ```
+pub struct MaybeReader<R: Read> {
+    inner: Inner<R>,
+}
+
+impl<R: Read> MaybeReader<R> {
+    /// Create a new MaybeReader over `reader`
+    pub fn new(reader: R) -> Self {
+        MaybeReader {
+            inner: Inner::Pending(reader),
+        }
+    }
+
+    /// Whether the input is a compressed stream
+    ///
+    /// Peeks at the input if no read has been done yet.
+    pub fn is_compressed(&mut self) -> io::Result<bool> {
+        self.peek()?;
+        Ok(matches!(self.inner, Inner::Compressed(_)))
+    }
+
+    /// Read the start of the input and pick the branch
+    fn peek(&mut self) -> io::Result<()> {
+        let mut reader = match std::mem::replace(&mut self.inner, Inner::Poisoned) {
+            Inner::Pending(reader) => reader,
+            inner => {
+                self.inner = inner;
+                return Ok(());
+            }
+        };
+
+        let mut prefix = vec![0u8; MAGIC_CHUNK.len()];
+        let mut n = 0;
+        while n < prefix.len() {
+            match reader.read(&mut prefix[n..]) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        prefix.truncate(n);
+
+        let compressed = prefix == MAGIC_CHUNK || prefix == MAGIC_CHUNK_SNAPPY;
+        let input = Cursor::new(prefix).chain(reader);
+        self.inner = if compressed {
+            Inner::Compressed(Box::new(Reader::new(input)))
+        } else {
+            Inner::Raw(input)
+        };
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for MaybeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.peek()?;
+        match self.inner {
+            Inner::Compressed(ref mut reader) => reader.read(buf),
+            Inner::Raw(ref mut input) => input.read(buf),
+            _ => Err(io::Error::other("input failed while peeking")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Writer;
+    use std::io::Write;
+
+    /// Hands out at most `step` bytes per read
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.step).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_maybe_reader() {
+        let text = b"plain text, or maybe not: plain text, or maybe not\n".repeat(2000);
+        let mut compressed = Vec::new();
+        Writer::new(&mut compressed).write_all(&text).unwrap();
+        let snappy = [MAGIC_CHUNK_SNAPPY, &compressed[MAGIC_CHUNK.len()..]].concat();
+
+        for (input, want_compressed) in [
+            (&compressed[..], true),
+            (&snappy[..], true),
+            (&text[..], false),
+        ] {
+            for step in [1, 3, 4096] {
+                let mut reader = MaybeReader::new(Trickle { data: input, step });
+                assert_eq!(reader.is_compressed().unwrap(), want_compressed);
+                let mut out = Vec::new();
+                reader.read_to_end(&mut out).unwrap();
+                assert!(out == text, "step {}", step);
+            }
+        }
+
+        // Input shorter than the identifier, and a truncated identifier
+        for input in [&b""[..], b"hi", &MAGIC_CHUNK[..6]] {
+            let mut out = Vec::new();
+            let mut reader = MaybeReader::new(input);
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, input);
+            assert!(!reader.is_compressed().unwrap());
+        }
+    }
+}
@@ -5,11 +5,25 @@
 
 //! Stream reader for S2 decompression
 
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
 
 use crate::constants::*;
 use crate::crc::crc;
-use crate::decode::decode;
+use crate::decode::{decode, decode_len, decode_snappy_strict, decode_with_dict, is_whole_block};
+use crate::dict::Dict;
+use crate::encode::Level;
+use crate::error::{Error, Result};
+use crate::index::{Index, S2_INDEX_TRAILER};
+
+#[cfg(feature = "concurrent")]
+use rayon::prelude::*;
+
+/// Callback set by [`Reader::on_block_decoded`]
+type BlockCallback = Box<dyn FnMut(&[u8], u32) + Send + Sync>;
+
+/// Callback set by [`Reader::on_skippable_chunk`]
+type SkippableCallback = Box<dyn FnMut(u8, &[u8]) + Send + Sync>;
 
 /// Reader decompresses data using the S2 stream format
 ///
@@ -18,6 +32,13 @@ use crate::decode::decode;
 /// - Framed compressed blocks with CRC checksums
 /// - Support for skippable frames and padding
 ///
+/// `ErrorKind::Interrupted` from the underlying reader is retried, as with
+/// `read_exact`; other errors are returned.
+///
+/// A stream cut between two chunks cannot be told apart from a complete
+/// one, unless it was written with an EOF marker and the reader is set to
+/// [`expect_eof_marker`](Reader::expect_eof_marker).
+///
 /// # Example
 ///
 /// ```
@@ -40,13 +61,35 @@ use crate::decode::decode;
 /// assert_eq!(decompressed, b"Hello, World!");
 /// ```
 pub struct Reader<R: Read> {
-    reader: R,
+    reader: BufReader<R>, // Unbuffered unless set by with_read_buffer
     buf: Vec<u8>,
     pos: usize,
     read_header: bool,
     eof: bool,
     max_block_size: usize,
     ignore_stream_id: bool,
+    skip_prefix: usize,          // Bytes discarded before the stream identifier
+    dict: Option<Dict>,          // Dictionary used to decode compressed chunks
+    lenient_crc: bool,           // Accept compressed chunks written without a CRC
+    snappy_strict: bool,         // Enforce the Snappy framing format
+    max_total: Option<u64>,      // Cap on the total decoded size of the stream
+    total_decoded: u64,          // Decoded bytes accepted so far
+    level: Option<Level>,        // Level recorded by Writer::with_level, if seen
+    index: Option<Index>,        // Index read from an index chunk, if seen
+    last_block_crc: Option<u32>, // CRC of the most recently decoded block
+    on_block: Option<BlockCallback>, // Called with each decoded block and its CRC
+    on_skippable: Option<SkippableCallback>, // Called with each skippable chunk not used
+    // Position of the next chunk, for error messages
+    compressed_offset: u64, // Bytes consumed from the underlying reader
+    chunk_index: u64,       // Chunks consumed, stream identifier included
+    // EOF marker (zero-length padding frame) handling
+    expect_eof_marker: bool, // Fail at EOF unless the marker was seen
+    seen_eof_marker: bool,   // Marker seen with no data chunk after it
+    // Parallel decoding
+    #[cfg(feature = "concurrent")]
+    concurrency: usize, // Chunks decoded per batch
+    #[cfg(feature = "concurrent")]
+    pending_error: Option<io::Error>, // Error held back until the buffer drains
     // Seeking support
     current_uncompressed_offset: i64, // Current position in uncompressed stream
 }
@@ -57,13 +100,32 @@ impl<R: Read> Reader<R> {
     /// Default max_block_size is 4MB (the S2 maximum)
     pub fn new(reader: R) -> Self {
         Reader {
-            reader,
+            reader: BufReader::with_capacity(0, reader),
             buf: Vec::new(),
             pos: 0,
             read_header: false,
             eof: false,
             max_block_size: MAX_BLOCK_SIZE,
             ignore_stream_id: false,
+            skip_prefix: 0,
+            dict: None,
+            lenient_crc: false,
+            snappy_strict: false,
+            max_total: None,
+            total_decoded: 0,
+            level: None,
+            index: None,
+            last_block_crc: None,
+            on_block: None,
+            on_skippable: None,
+            compressed_offset: 0,
+            chunk_index: 0,
+            expect_eof_marker: false,
+            seen_eof_marker: false,
+            #[cfg(feature = "concurrent")]
+            concurrency: 1,
+            #[cfg(feature = "concurrent")]
+            pending_error: None,
             current_uncompressed_offset: 0,
         }
     }
@@ -81,16 +143,27 @@ impl<R: Read> Reader<R> {
             max_block_size > 0 && max_block_size <= MAX_BLOCK_SIZE,
             "max_block_size must be > 0 and <= 4MB"
         );
-        Reader {
-            reader,
-            buf: Vec::new(),
-            pos: 0,
-            read_header: false,
-            eof: false,
-            max_block_size,
-            ignore_stream_id: false,
-            current_uncompressed_offset: 0,
+        let mut r = Self::new(reader);
+        r.max_block_size = max_block_size;
+        r
+    }
+
+    /// Create a new Reader with a maximum block size limit, reporting an
+    /// invalid limit instead of panicking
+    ///
+    /// Same as [`with_max_block_size`](Reader::with_max_block_size), for
+    /// limits that come from configuration.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidInput` if max_block_size is 0 or greater than 4MB
+    pub fn try_with_max_block_size(reader: R, max_block_size: usize) -> Result<Self> {
+        if max_block_size == 0 || max_block_size > MAX_BLOCK_SIZE {
+            return Err(Error::InvalidInput(format!(
+                "max_block_size {} must be > 0 and <= 4MB",
+                max_block_size
+            )));
         }
+        Ok(Self::with_max_block_size(reader, max_block_size))
     }
 
     /// Create a new Reader that skips the stream identifier check
@@ -98,16 +171,40 @@ impl<R: Read> Reader<R> {
     /// This can be useful when reading from a stream that has been
     /// forwarded to a specific point and doesn't start with the magic bytes.
     pub fn with_ignore_stream_id(reader: R) -> Self {
-        Reader {
-            reader,
-            buf: Vec::new(),
-            pos: 0,
-            read_header: true, // Skip reading header
-            eof: false,
-            max_block_size: MAX_BLOCK_SIZE,
-            ignore_stream_id: true,
-            current_uncompressed_offset: 0,
-        }
+        let mut r = Self::new(reader);
+        r.read_header = true; // Skip reading header
+        r.ignore_stream_id = true;
+        r
+    }
+
+    /// Create a new Reader that discards `prefix_len` bytes before the
+    /// stream identifier
+    ///
+    /// For streams stored after a fixed-size header (e.g. a 512-byte
+    /// archive header). Unlike [`with_ignore_stream_id`](Reader::with_ignore_stream_id),
+    /// the identifier is still verified once the prefix is skipped. The
+    /// prefix is skipped again after [`reset`](Reader::reset) or a seek back
+    /// to the start, and is included in the offsets reported in errors.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut file = vec![0u8; 512]; // header
+    /// Writer::new(&mut file).write_all(b"Hello, World!").unwrap();
+    ///
+    /// let mut out = Vec::new();
+    /// Reader::with_skip_prefix(&file[..], 512)
+    ///     .read_to_end(&mut out)
+    ///     .unwrap();
+    /// assert_eq!(out, b"Hello, World!");
+    /// ```
+    pub fn with_skip_prefix(reader: R, prefix_len: usize) -> Self {
+        let mut r = Self::new(reader);
+        r.skip_prefix = prefix_len;
+        r
     }
 
     /// Create a new Reader with a pre-allocated buffer size
@@ -125,18 +222,336 @@ impl<R: Read> Reader<R> {
             (1024..=MAX_BLOCK_SIZE).contains(&alloc_block_size),
             "alloc_block_size must be >= 1KB and <= 4MB"
         );
-        Reader {
-            reader,
-            buf: Vec::with_capacity(alloc_block_size),
-            pos: 0,
-            read_header: false,
-            eof: false,
-            max_block_size: MAX_BLOCK_SIZE,
-            ignore_stream_id: false,
-            current_uncompressed_offset: 0,
+        let mut r = Self::new(reader);
+        r.buf = Vec::with_capacity(alloc_block_size);
+        r
+    }
+
+    /// Create a new Reader that reads from `reader` in chunks of up to
+    /// `size` bytes
+    ///
+    /// By default each chunk header, checksum and body is read from
+    /// `reader` separately, which means several small reads per chunk and
+    /// is slow on unbuffered sources such as a `File` or a socket. With a
+    /// read buffer, the stream is fetched in larger reads and parsed from
+    /// memory; reads larger than the buffer still go straight to the
+    /// destination. 64KB is a good size. This is the same as passing
+    /// `BufReader::with_capacity(size, reader)`, except that `R` stays the
+    /// type of the underlying reader, as seen by [`get_ref`](Reader::get_ref)
+    /// and [`reset`](Reader::reset). Seeking discards the buffered data.
+    ///
+    /// The Reader may then have read past the end of the stream: data
+    /// after it (e.g. in a larger file) is not left in `reader`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// Writer::with_block_size(&mut compressed, 4096)
+    ///     .write_all(&[7u8; 100_000])
+    ///     .unwrap();
+    ///
+    /// let mut out = Vec::new();
+    /// Reader::with_read_buffer(&compressed[..], 64 * 1024)
+    ///     .read_to_end(&mut out)
+    ///     .unwrap();
+    /// assert_eq!(out.len(), 100_000);
+    /// ```
+    pub fn with_read_buffer(reader: R, size: usize) -> Self {
+        let mut r = Self::new(reader);
+        r.reader = BufReader::with_capacity(size, r.reader.into_inner());
+        r
+    }
+
+    /// Create a new Reader for a stream whose blocks were compressed with a
+    /// dictionary
+    ///
+    /// Every compressed chunk is decoded with [`decode_with_dict`], so `dict`
+    /// must be identical to the one given to
+    /// [`Writer::with_dict`](crate::Writer::with_dict). The dictionary is kept
+    /// across [`reset`](Reader::reset).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{make_dict, Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let dict_data = b"Common header text shared by every record in the stream.";
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let dict = make_dict(dict_data, None).unwrap();
+    ///     let mut writer = Writer::with_dict(&mut compressed, dict);
+    ///     writer.write_all(b"Common header text shared by every record!").unwrap();
+    /// }
+    ///
+    /// let dict = make_dict(dict_data, None).unwrap();
+    /// let mut reader = Reader::with_dict(&compressed[..], dict);
+    /// let mut decompressed = Vec::new();
+    /// reader.read_to_end(&mut decompressed).unwrap();
+    /// assert_eq!(decompressed, b"Common header text shared by every record!");
+    /// ```
+    pub fn with_dict(reader: R, dict: Dict) -> Self {
+        let mut r = Self::new(reader);
+        r.dict = Some(dict);
+        r
+    }
+
+    /// Create a new Reader that decodes chunks in parallel
+    ///
+    /// Up to `concurrency` data chunks are read ahead, then decoded and CRC
+    /// verified in parallel with Rayon, and their data served in order. The
+    /// output and errors are the same as for [`Reader::new`]: data from
+    /// chunks before a failing one is returned first, then the error. The
+    /// buffer holds up to `concurrency` decoded blocks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let data = vec![7u8; 1 << 20];
+    /// let mut compressed = Vec::new();
+    /// Writer::with_block_size(&mut compressed, 64 << 10)
+    ///     .write_all(&data)
+    ///     .unwrap();
+    ///
+    /// let mut out = Vec::new();
+    /// Reader::with_concurrency(&compressed[..], 4)
+    ///     .read_to_end(&mut out)
+    ///     .unwrap();
+    /// assert_eq!(out, data);
+    /// ```
+    #[cfg(feature = "concurrent")]
+    pub fn with_concurrency(reader: R, concurrency: usize) -> Self {
+        let mut r = Self::new(reader);
+        r.concurrency = concurrency.max(1);
+        r
+    }
+
+    /// Create a new Reader that accepts compressed chunks missing their CRC
+    ///
+    /// Some nonconforming producers wrote compressed chunks without the
+    /// 4-byte checksum. In this mode, a compressed chunk whose payload only
+    /// parses as a block when taken whole (including when it is shorter
+    /// than a checksum) is decoded as such, without CRC verification,
+    /// instead of failing with "chunk too small" or a decode error. Chunks
+    /// that do carry a CRC are still verified.
+    ///
+    /// This weakens corruption detection and is only meant for interop
+    /// with such producers; it is off by default.
+    pub fn with_lenient_crc(reader: R) -> Self {
+        let mut r = Self::new(reader);
+        r.lenient_crc = true;
+        r
+    }
+
+    /// Create a new Reader that only accepts conformant Snappy framed streams
+    ///
+    /// The default reader takes both S2 and Snappy streams and is lenient
+    /// about what it skips. In this mode, reading fails with
+    /// `ErrorKind::InvalidData` unless the stream follows the Snappy framing
+    /// format:
+    ///
+    /// - the first chunk is the Snappy stream identifier (`sNaPpY`), and any
+    ///   later identifier is the Snappy one too;
+    /// - no S2-only chunks (index, level or size metadata);
+    /// - blocks decode to at most 64KB and use no S2 repeat codes.
+    ///
+    /// Use it to check that output is readable by plain Snappy decoders.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// Writer::new(&mut compressed).write_all(b"Hello, World!").unwrap();
+    ///
+    /// // An S2 stream is not a Snappy stream
+    /// let mut out = Vec::new();
+    /// assert!(Reader::with_snappy_strict(&compressed[..])
+    ///     .read_to_end(&mut out)
+    ///     .is_err());
+    /// ```
+    pub fn with_snappy_strict(reader: R) -> Self {
+        let mut r = Self::new(reader);
+        r.snappy_strict = true;
+        r.max_block_size = MAX_SNAPPY_BLOCK_SIZE;
+        r
+    }
+
+    /// Require the stream to end with an EOF marker
+    ///
+    /// The stream format has no mandatory terminator: a stream cut between
+    /// two chunks reads back as a shorter, valid stream, and only a cut in
+    /// the middle of a chunk is reported. With this set, reaching the end of
+    /// the underlying reader before the marker written by
+    /// [`Writer::finish_with_eof_marker`](crate::Writer::finish_with_eof_marker)
+    /// fails with `ErrorKind::UnexpectedEof`, so truncation is detected
+    /// wherever it happens.
+    ///
+    /// The marker is a zero-length padding frame, which readers that do not
+    /// look for it simply skip. Data chunks after a marker (as in
+    /// concatenated streams) require another marker before EOF.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let mut writer = Writer::new(&mut compressed);
+    ///     writer.write_all(b"Hello, World!").unwrap();
+    ///     writer.finish_with_eof_marker().unwrap();
+    /// }
+    ///
+    /// let mut reader = Reader::new(&compressed[..]);
+    /// reader.expect_eof_marker();
+    /// let mut out = Vec::new();
+    /// reader.read_to_end(&mut out).unwrap();
+    /// assert_eq!(out, b"Hello, World!");
+    /// ```
+    pub fn expect_eof_marker(&mut self) {
+        self.expect_eof_marker = true;
+    }
+
+    /// Refuse to decode more than `max_total` bytes in total
+    ///
+    /// Where [`with_max_block_size`](Reader::with_max_block_size) bounds a
+    /// single block, this bounds the whole stream, capping the memory or
+    /// disk an untrusted stream can expand into. It applies to a reader
+    /// made by any constructor, e.g. with a dictionary or parallel
+    /// decoding. The check uses each chunk's declared size, so the chunk
+    /// that would cross the limit is rejected with an `InvalidData` error
+    /// before it is decoded; data from earlier chunks is still returned.
+    /// The count restarts on [`reset`](Reader::reset).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// Writer::new(&mut compressed).write_all(&[0u8; 100_000]).unwrap();
+    ///
+    /// let mut out = Vec::new();
+    /// let err = Reader::new(&compressed[..])
+    ///     .set_max_total(10_000)
+    ///     .read_to_end(&mut out)
+    ///     .unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    /// ```
+    pub fn set_max_total(&mut self, max_total: u64) -> &mut Self {
+        self.max_total = Some(max_total);
+        self
+    }
+
+    /// Call `f` with each block as it is decoded, and its CRC
+    ///
+    /// Every data chunk is passed once, in stream order, right after its
+    /// CRC has been verified, so callers can compute their own hashes or
+    /// record block boundaries without a second pass over the output. The
+    /// CRC is the one stored in the chunk (see
+    /// [`last_block_crc`](Reader::last_block_crc)). When decoding in
+    /// parallel, blocks are passed as each batch is decoded, ahead of
+    /// `read`. Chunks skipped with [`skip_block`](Reader::skip_block) are
+    /// not decoded and not passed. Replaces any previous callback.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut compressed = Vec::new();
+    /// Writer::with_block_size(&mut compressed, 64 * 1024)
+    ///     .write_all(&[7u8; 200_000])
+    ///     .unwrap();
+    ///
+    /// let sizes = Arc::new(Mutex::new(Vec::new()));
+    /// let mut reader = Reader::new(&compressed[..]);
+    /// let seen = sizes.clone();
+    /// reader.on_block_decoded(move |block, _crc| seen.lock().unwrap().push(block.len()));
+    /// reader.read_to_end(&mut Vec::new()).unwrap();
+    /// assert_eq!(*sizes.lock().unwrap(), [65536, 65536, 65536, 3392]);
+    /// ```
+    pub fn on_block_decoded<F>(&mut self, f: F)
+    where
+        F: FnMut(&[u8], u32) + Send + Sync + 'static,
+    {
+        self.on_block = Some(Box::new(f));
+    }
+
+    /// Call `f` with the type and payload of each skippable chunk (types
+    /// 0x80 to 0xfd) the reader doesn't use itself
+    ///
+    /// Skippable chunks carry application data alongside the stream, such
+    /// as the entry headers of [`ArchiveWriter`](crate::ArchiveWriter). They
+    /// are discarded unless a callback is set. Index and level chunks the
+    /// reader reads for itself are not passed on. `f` is called as the
+    /// chunk is read, so before the data of any chunk after it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{ArchiveWriter, Reader};
+    /// use std::io::Read;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut archive = ArchiveWriter::new(Vec::new());
+    /// archive.add_entry("a.txt", b"first").unwrap();
+    /// archive.add_entry("b.txt", b"second").unwrap();
+    /// let stream = archive.get_ref().clone();
+    ///
+    /// let types = Arc::new(Mutex::new(Vec::new()));
+    /// let mut reader = Reader::new(&stream[..]);
+    /// let seen = types.clone();
+    /// reader.on_skippable_chunk(move |chunk_type, _payload| seen.lock().unwrap().push(chunk_type));
+    /// reader.read_to_end(&mut Vec::new()).unwrap();
+    /// assert_eq!(*types.lock().unwrap(), [0x9c, 0x9c]);
+    /// ```
+    pub fn on_skippable_chunk<F>(&mut self, f: F)
+    where
+        F: FnMut(u8, &[u8]) + Send + Sync + 'static,
+    {
+        self.on_skippable = Some(Box::new(f));
+    }
+
+    /// Record a verified block
+    fn block_decoded(&mut self, data: &[u8], checksum: u32) {
+        self.last_block_crc = Some(checksum);
+        if let Some(f) = self.on_block.as_mut() {
+            f(data, checksum);
         }
     }
 
+    /// Account for a chunk decoding to `len` bytes, failing if it would
+    /// exceed the total limit
+    fn reserve_decoded(&mut self, len: usize) -> io::Result<()> {
+        let total = self.total_decoded + len as u64;
+        if let Some(max_total) = self.max_total {
+            if total > max_total {
+                return Err(self.chunk_error(format!(
+                    "total decompressed size ({}) exceeds limit ({})",
+                    total, max_total
+                )));
+            }
+        }
+        self.total_decoded = total;
+        Ok(())
+    }
+
     /// Read and verify the stream identifier
     fn read_stream_identifier(&mut self) -> io::Result<()> {
         // If ignore_stream_id is set, skip verification
@@ -144,10 +559,24 @@ impl<R: Read> Reader<R> {
             return Ok(());
         }
 
+        if self.skip_prefix > 0 {
+            let prefix = self.skip_prefix as u64;
+            let skipped = io::copy(&mut self.reader.by_ref().take(prefix), &mut io::sink())?;
+            if skipped < prefix {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "unexpected EOF inside stream prefix",
+                ));
+            }
+            self.compressed_offset += prefix;
+        }
+
         let mut magic = [0u8; MAGIC_CHUNK.len()];
         self.reader.read_exact(&mut magic)?;
 
-        if magic == *MAGIC_CHUNK || magic == *MAGIC_CHUNK_SNAPPY {
+        if (magic == *MAGIC_CHUNK && !self.snappy_strict) || magic == *MAGIC_CHUNK_SNAPPY {
+            self.compressed_offset += magic.len() as u64;
+            self.chunk_index += 1;
             Ok(())
         } else {
             Err(io::Error::new(
@@ -157,141 +586,282 @@ impl<R: Read> Reader<R> {
         }
     }
 
-    /// Read the next chunk from the stream
+    /// Read the next data chunk and make its decoded contents the buffer
+    ///
+    /// Only called once the buffer has been consumed, so at most one block
+    /// is held at a time.
     fn read_chunk(&mut self) -> io::Result<bool> {
-        // Read chunk type and length (4 bytes total)
-        let mut header = [0u8; 4];
-        match self.reader.read_exact(&mut header) {
-            Ok(()) => {}
-            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                self.eof = true;
-                return Ok(false);
+        debug_assert!(self.pos >= self.buf.len());
+        match self.read_raw_chunk()? {
+            Some(raw) => {
+                let (decoded, checksum) = raw.decode(self.dict.as_ref(), self.max_block_size)?;
+                self.block_decoded(&decoded, checksum);
+                self.buf = decoded;
+                self.pos = 0;
+                Ok(true)
             }
-            Err(e) => return Err(e),
+            None => Ok(false),
         }
+    }
 
-        let chunk_type = header[0];
-        let chunk_len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
-
-        match chunk_type {
-            CHUNK_TYPE_COMPRESSED_DATA => {
-                self.read_compressed_chunk(chunk_len)?;
-                Ok(true)
-            }
-            CHUNK_TYPE_UNCOMPRESSED_DATA => {
-                self.read_uncompressed_chunk(chunk_len)?;
-                Ok(true)
+    /// Read up to `concurrency` data chunks, then decode and verify them in
+    /// parallel into the buffer
+    ///
+    /// Like `read_chunk`, only called on an empty buffer; it then holds at
+    /// most `concurrency` blocks.
+    ///
+    /// Chunks decoded before the first failing one are kept in the buffer;
+    /// the error is held back until they have been read.
+    #[cfg(feature = "concurrent")]
+    fn read_chunks_parallel(&mut self) -> io::Result<bool> {
+        debug_assert!(self.pos >= self.buf.len() && self.buf.is_empty());
+        let mut raws = Vec::with_capacity(self.concurrency);
+        while raws.len() < self.concurrency {
+            match self.read_raw_chunk() {
+                Ok(Some(raw)) => raws.push(raw),
+                Ok(None) => break,
+                Err(e) if raws.is_empty() => return Err(e),
+                Err(e) => {
+                    self.pending_error = Some(e);
+                    break;
+                }
             }
-            CHUNK_TYPE_PADDING | CHUNK_TYPE_INDEX => {
-                // Skip this chunk
-                self.skip_chunk(chunk_len)?;
-                // Read next chunk
-                self.read_chunk()
+        }
+        if raws.is_empty() {
+            return Ok(false);
+        }
+
+        let dict = self.dict.as_ref();
+        let max_block_size = self.max_block_size;
+        let decoded: Vec<io::Result<(Vec<u8>, u32)>> = raws
+            .into_par_iter()
+            .map(|raw| raw.decode(dict, max_block_size))
+            .collect();
+
+        for result in decoded {
+            match result {
+                Ok((data, checksum)) => {
+                    self.block_decoded(&data, checksum);
+                    self.buf.extend_from_slice(&data);
+                }
+                Err(e) if self.buf.is_empty() => return Err(e),
+                Err(e) => {
+                    // An earlier chunk's error replaces any from reading ahead
+                    self.pending_error = Some(e);
+                    break;
+                }
             }
-            CHUNK_TYPE_STREAM_IDENTIFIER => {
-                // Skip stream identifier in the middle of the stream
-                self.skip_chunk(chunk_len)?;
-                self.read_chunk()
+        }
+        Ok(true)
+    }
+
+    /// Read the next data chunk without decoding it, skipping any other
+    /// chunks on the way
+    ///
+    /// Returns `None` at the end of the stream.
+    fn read_raw_chunk(&mut self) -> io::Result<Option<RawChunk>> {
+        loop {
+            // Read chunk type and length (4 bytes total)
+            let mut header = [0u8; 4];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    if self.expect_eof_marker && !self.seen_eof_marker {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!(
+                                "stream truncated: no EOF marker before end of input (chunk {} at compressed offset {})",
+                                self.chunk_index, self.compressed_offset
+                            ),
+                        ));
+                    }
+                    self.eof = true;
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
             }
-            0x80..=0xfd => {
-                // Skippable chunk range
-                self.skip_chunk(chunk_len)?;
-                self.read_chunk()
+
+            let chunk_type = header[0];
+            let chunk_len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+
+            match chunk_type {
+                CHUNK_TYPE_INDEX
+                | CHUNK_TYPE_LEVEL
+                | CHUNK_TYPE_SIZE
+                | CHUNK_TYPE_ARCHIVE_ENTRY
+                    if self.snappy_strict =>
+                {
+                    return Err(self.chunk_error(format!(
+                        "S2-only chunk type 0x{:02x} in Snappy stream",
+                        chunk_type
+                    )));
+                }
+                CHUNK_TYPE_STREAM_IDENTIFIER if self.snappy_strict => {
+                    let mut body = vec![0u8; chunk_len];
+                    self.reader.read_exact(&mut body)?;
+                    if body != MAGIC_BODY_SNAPPY {
+                        return Err(self.chunk_error("invalid stream identifier".to_string()));
+                    }
+                    self.advance_chunk(chunk_len);
+                }
+                CHUNK_TYPE_COMPRESSED_DATA => {
+                    let raw = self.read_compressed_chunk(chunk_len)?;
+                    self.advance_chunk(chunk_len);
+                    self.seen_eof_marker = false;
+                    return Ok(Some(raw));
+                }
+                CHUNK_TYPE_UNCOMPRESSED_DATA => {
+                    let raw = self.read_uncompressed_chunk(chunk_len)?;
+                    self.advance_chunk(chunk_len);
+                    self.seen_eof_marker = false;
+                    return Ok(Some(raw));
+                }
+                CHUNK_TYPE_PADDING if chunk_len == 0 => {
+                    // EOF marker
+                    self.seen_eof_marker = true;
+                    self.advance_chunk(chunk_len);
+                }
+                CHUNK_TYPE_INDEX => {
+                    // Kept if valid, so a leading index is available before
+                    // the first data chunk
+                    let mut chunk = vec![0u8; CHUNK_HEADER_SIZE + chunk_len];
+                    chunk[..CHUNK_HEADER_SIZE].copy_from_slice(&header);
+                    self.reader.read_exact(&mut chunk[CHUNK_HEADER_SIZE..])?;
+                    let mut index = Index::new();
+                    if index.load(&chunk).is_ok() {
+                        self.index = Some(index);
+                    }
+                    self.advance_chunk(chunk_len);
+                }
+                CHUNK_TYPE_PADDING => {
+                    // Skip this chunk
+                    self.skip_chunk(chunk_len)?;
+                    self.advance_chunk(chunk_len);
+                }
+                CHUNK_TYPE_STREAM_IDENTIFIER => {
+                    // Skip stream identifier in the middle of the stream
+                    self.skip_chunk(chunk_len)?;
+                    self.advance_chunk(chunk_len);
+                }
+                CHUNK_TYPE_LEVEL if chunk_len == LEVEL_MAGIC.len() + 1 => {
+                    // Level metadata; left alone unless it carries our magic
+                    let mut payload = [0u8; LEVEL_MAGIC.len() + 1];
+                    self.reader.read_exact(&mut payload)?;
+                    if payload[..LEVEL_MAGIC.len()] == *LEVEL_MAGIC {
+                        if let Some(level) = Level::from_id(payload[LEVEL_MAGIC.len()]) {
+                            self.level = Some(level);
+                        }
+                    }
+                    self.advance_chunk(chunk_len);
+                }
+                0x80..=0xfd => {
+                    // Skippable chunk range; handed to on_skippable_chunk
+                    if let Some(f) = self.on_skippable.as_mut() {
+                        let mut payload = vec![0u8; chunk_len];
+                        self.reader.read_exact(&mut payload)?;
+                        f(chunk_type, &payload);
+                    } else {
+                        self.skip_chunk(chunk_len)?;
+                    }
+                    self.advance_chunk(chunk_len);
+                }
+                _ => {
+                    return Err(
+                        self.chunk_error(format!("unknown chunk type: 0x{:02x}", chunk_type))
+                    )
+                }
             }
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("unknown chunk type: 0x{:02x}", chunk_type),
-            )),
         }
     }
 
-    /// Read a compressed data chunk
-    fn read_compressed_chunk(&mut self, chunk_len: usize) -> io::Result<()> {
-        if chunk_len < CHECKSUM_SIZE {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "chunk too small",
-            ));
-        }
+    /// Move the position past a fully consumed chunk
+    fn advance_chunk(&mut self, chunk_len: usize) {
+        self.compressed_offset += (CHUNK_HEADER_SIZE + chunk_len) as u64;
+        self.chunk_index += 1;
+    }
 
-        // Read checksum
-        let mut checksum_bytes = [0u8; 4];
-        self.reader.read_exact(&mut checksum_bytes)?;
-        let expected_crc = u32::from_le_bytes(checksum_bytes);
+    /// Build an InvalidData error locating the chunk being read
+    fn chunk_error(&self, msg: String) -> io::Error {
+        chunk_error_at(msg, self.chunk_index, self.compressed_offset)
+    }
 
-        // Read compressed data
-        let data_len = chunk_len - CHECKSUM_SIZE;
-        let mut compressed = vec![0u8; data_len];
-        self.reader.read_exact(&mut compressed)?;
+    /// Read a compressed data chunk
+    fn read_compressed_chunk(&mut self, chunk_len: usize) -> io::Result<RawChunk> {
+        if chunk_len < CHECKSUM_SIZE && !self.lenient_crc {
+            return Err(self.chunk_error("chunk too small".to_string()));
+        }
 
-        // Decompress
-        let decompressed = decode(&compressed).map_err(|e| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("decode error: {}", e))
-        })?;
+        let mut chunk = vec![0u8; chunk_len];
+        self.reader.read_exact(&mut chunk)?;
 
-        // Check against max_block_size limit
-        if decompressed.len() > self.max_block_size {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "decompressed block size ({}) exceeds limit ({})",
-                    decompressed.len(),
-                    self.max_block_size
-                ),
-            ));
+        // Split off the checksum, unless in lenient mode the chunk only
+        // makes sense without one
+        let crc_absent = self.lenient_crc
+            && !(chunk_len >= CHECKSUM_SIZE && is_whole_block(&chunk[CHECKSUM_SIZE..]))
+            && is_whole_block(&chunk);
+        if chunk_len < CHECKSUM_SIZE && !crc_absent {
+            return Err(self.chunk_error("chunk too small".to_string()));
         }
+        let (checksum, start) = if crc_absent {
+            (None, 0)
+        } else {
+            let checksum = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            (Some(checksum), CHECKSUM_SIZE)
+        };
 
-        // Verify CRC
-        let actual_crc = crc(&decompressed);
-        if actual_crc != expected_crc {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "CRC mismatch"));
+        // Enforce the total limit before decoding
+        if self.max_total.is_some() {
+            let (dlen, _) = decode_len(&chunk[start..])
+                .map_err(|e| self.chunk_error(format!("decode error: {}", e)))?;
+            self.reserve_decoded(dlen)?;
         }
 
-        // Add to buffer
-        self.buf.extend_from_slice(&decompressed);
-        Ok(())
+        Ok(RawChunk {
+            data: chunk,
+            start,
+            compressed: true,
+            snappy_strict: self.snappy_strict,
+            checksum,
+            chunk_index: self.chunk_index,
+            compressed_offset: self.compressed_offset,
+        })
     }
 
     /// Read an uncompressed data chunk
-    fn read_uncompressed_chunk(&mut self, chunk_len: usize) -> io::Result<()> {
+    fn read_uncompressed_chunk(&mut self, chunk_len: usize) -> io::Result<RawChunk> {
         if chunk_len < CHECKSUM_SIZE {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "chunk too small",
-            ));
+            return Err(self.chunk_error("chunk too small".to_string()));
         }
 
         // Read checksum
         let mut checksum_bytes = [0u8; 4];
         self.reader.read_exact(&mut checksum_bytes)?;
-        let expected_crc = u32::from_le_bytes(checksum_bytes);
+        let checksum = u32::from_le_bytes(checksum_bytes);
 
         // Read uncompressed data
         let data_len = chunk_len - CHECKSUM_SIZE;
 
         // Check against max_block_size limit
         if data_len > self.max_block_size {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "uncompressed block size ({}) exceeds limit ({})",
-                    data_len, self.max_block_size
-                ),
-            ));
+            return Err(self.chunk_error(format!(
+                "uncompressed block size ({}) exceeds limit ({})",
+                data_len, self.max_block_size
+            )));
         }
+        self.reserve_decoded(data_len)?;
 
         let mut data = vec![0u8; data_len];
         self.reader.read_exact(&mut data)?;
 
-        // Verify CRC
-        let actual_crc = crc(&data);
-        if actual_crc != expected_crc {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "CRC mismatch"));
-        }
-
-        // Add to buffer
-        self.buf.extend_from_slice(&data);
-        Ok(())
+        Ok(RawChunk {
+            data,
+            start: 0,
+            compressed: false,
+            snappy_strict: false,
+            checksum: Some(checksum),
+            chunk_index: self.chunk_index,
+            compressed_offset: self.compressed_offset,
+        })
     }
 
     /// Skip a chunk
@@ -301,82 +871,806 @@ impl<R: Read> Reader<R> {
         Ok(())
     }
 
-    /// Reset the reader to use a new underlying reader
-    pub fn reset(&mut self, reader: R) -> R {
+    /// Decode and CRC-check the rest of the stream without keeping its output
+    ///
+    /// Like [`verify_stream`], but with this reader's settings (dictionary,
+    /// size limits, EOF marker, ...). Data already buffered by earlier reads
+    /// is counted and discarded.
+    pub fn verify(&mut self) -> io::Result<VerifyReport> {
+        let start = Instant::now();
+        let mut report = VerifyReport {
+            decompressed_bytes: (self.buf.len() - self.pos.min(self.buf.len())) as u64,
+            ..VerifyReport::default()
+        };
         self.buf.clear();
         self.pos = 0;
-        self.read_header = false;
-        self.eof = false;
-        self.current_uncompressed_offset = 0;
-        std::mem::replace(&mut self.reader, reader)
-    }
 
-    /// Get a reference to the underlying reader
-    pub fn get_ref(&self) -> &R {
-        &self.reader
-    }
+        // An error held back by parallel decoding comes after the buffered
+        // data, which has just been counted
+        #[cfg(feature = "concurrent")]
+        if let Some(err) = self.pending_error.take() {
+            return Err(err);
+        }
 
-    /// Get a mutable reference to the underlying reader
-    pub fn get_mut(&mut self) -> &mut R {
-        &mut self.reader
+        if !self.read_header {
+            self.read_stream_identifier()?;
+            self.read_header = true;
+        }
+        while let Some(raw) = self.read_raw_chunk()? {
+            let (decoded, checksum) = raw.decode(self.dict.as_ref(), self.max_block_size)?;
+            self.block_decoded(&decoded, checksum);
+            report.decompressed_bytes += decoded.len() as u64;
+            report.data_chunks += 1;
+        }
+        self.current_uncompressed_offset += report.decompressed_bytes as i64;
+
+        report.compressed_bytes = self.compressed_offset;
+        report.elapsed = start.elapsed();
+        Ok(report)
     }
-}
 
-impl<R: Read> Read for Reader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        // Read stream header if not already done
+    /// Decode the next data chunk of the stream and return it whole
+    ///
+    /// Returns `None` at the end of the stream. Data left buffered by
+    /// earlier `read` calls is returned first, as a single block.
+    pub fn read_block(&mut self) -> io::Result<Option<Vec<u8>>> {
         if !self.read_header {
             self.read_stream_identifier()?;
             self.read_header = true;
         }
 
-        // If buffer is empty and not EOF, read next chunk
-        while self.pos >= self.buf.len() && !self.eof {
+        let block = if self.pos < self.buf.len() {
+            let block = self.buf.split_off(self.pos);
             self.buf.clear();
             self.pos = 0;
-            if !self.read_chunk()? {
-                break;
+            block
+        } else {
+            #[cfg(feature = "concurrent")]
+            if let Some(err) = self.pending_error.take() {
+                return Err(err);
             }
-        }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.read_raw_chunk()? {
+                Some(raw) => {
+                    let (block, checksum) = raw.decode(self.dict.as_ref(), self.max_block_size)?;
+                    self.block_decoded(&block, checksum);
+                    block
+                }
+                None => return Ok(None),
+            }
+        };
+        self.current_uncompressed_offset += block.len() as i64;
+        Ok(Some(block))
+    }
 
-        // Copy from buffer
-        let available = self.buf.len() - self.pos;
-        if available == 0 {
-            return Ok(0); // EOF
+    /// Skip the next data chunk without decoding it
+    ///
+    /// The chunk is read but neither decoded nor CRC-checked; its decoded
+    /// length is taken from the length header of the compressed block.
+    /// Returns that length, or `None` at the end of the stream. As with
+    /// [`read_block`](Reader::read_block), data left buffered by earlier
+    /// `read` calls counts as one block and is discarded first.
+    ///
+    /// The skipped block has no computed CRC, so
+    /// [`last_block_crc`](Reader::last_block_crc) is cleared.
+    pub fn skip_block(&mut self) -> io::Result<Option<usize>> {
+        if !self.read_header {
+            self.read_stream_identifier()?;
+            self.read_header = true;
         }
 
-        let to_copy = available.min(buf.len());
-        buf[..to_copy].copy_from_slice(&self.buf[self.pos..self.pos + to_copy]);
-        self.pos += to_copy;
-
-        // Track uncompressed offset
-        self.current_uncompressed_offset += to_copy as i64;
-
-        Ok(to_copy)
+        let len = if self.pos < self.buf.len() {
+            let len = self.buf.len() - self.pos;
+            self.buf.clear();
+            self.pos = 0;
+            len
+        } else {
+            #[cfg(feature = "concurrent")]
+            if let Some(err) = self.pending_error.take() {
+                return Err(err);
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.read_raw_chunk()? {
+                Some(raw) if raw.compressed => {
+                    let (dlen, _) = decode_len(&raw.data[raw.start..])
+                        .map_err(|e| raw.error(format!("decode error: {}", e)))?;
+                    if dlen > self.max_block_size {
+                        return Err(raw.error(format!(
+                            "decompressed block size ({}) exceeds limit ({})",
+                            dlen, self.max_block_size
+                        )));
+                    }
+                    self.last_block_crc = None;
+                    dlen
+                }
+                Some(raw) => {
+                    self.last_block_crc = None;
+                    raw.data.len()
+                }
+                None => return Ok(None),
+            }
+        };
+        self.current_uncompressed_offset += len as i64;
+        Ok(Some(len))
     }
-}
 
-/// Implementation of Seek for Reader with seekable underlying reader
-///
-/// Note: This provides basic seeking support. For efficient random access,
-/// use an Index to map uncompressed offsets to compressed positions.
-impl<R: Read + Seek> Seek for Reader<R> {
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        // Calculate target uncompressed position
-        let target_pos = match pos {
-            SeekFrom::Start(offset) => offset as i64,
-            SeekFrom::Current(offset) => self.current_uncompressed_offset + offset,
-            SeekFrom::End(_) => {
-                // For SeekFrom::End, we would need to know the total uncompressed size
-                // This requires either reading the entire stream or having an Index
-                return Err(io::Error::new(
-                    io::ErrorKind::Unsupported,
-                    "SeekFrom::End not supported without an Index. Use Index::find() to seek from end.",
-                ));
+    /// Turn the reader into an iterator over the decoded data chunks
+    ///
+    /// Each item is one whole block, as returned by
+    /// [`read_block`](Reader::read_block). Iteration stops after the first
+    /// error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Writer, Reader};
+    /// use std::io::Write;
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let mut writer = Writer::new(&mut compressed);
+    ///     writer.write_all(b"Hello, World!").unwrap();
+    /// }
+    ///
+    /// let blocks: Vec<Vec<u8>> = Reader::new(&compressed[..])
+    ///     .chunks()
+    ///     .collect::<std::io::Result<_>>()
+    ///     .unwrap();
+    /// assert_eq!(blocks.concat(), b"Hello, World!");
+    /// ```
+    pub fn chunks(mut self) -> impl Iterator<Item = io::Result<Vec<u8>>> {
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
             }
-        };
+            let next = self.read_block().transpose();
+            failed = matches!(next, Some(Err(_)));
+            next
+        })
+    }
 
-        if target_pos < 0 {
+    /// Compression level recorded in the stream by
+    /// [`Writer::with_level`](crate::Writer::with_level)
+    ///
+    /// `None` until the level chunk has been read (it directly follows the
+    /// stream identifier, so after the first read), and for streams
+    /// without one.
+    pub fn level(&self) -> Option<Level> {
+        self.level
+    }
+
+    /// Seek index found in the stream
+    ///
+    /// `None` until an index chunk has been read. An index written at the
+    /// start of the stream is read along with the stream identifier, so it
+    /// is available after the first read (or a `SeekFrom::End` seek, which
+    /// uses it); a trailing index only once the data has been read.
+    pub fn index(&self) -> Option<&Index> {
+        self.index.as_ref()
+    }
+
+    /// Decoded bytes left to read, as a lower bound and, if known, the
+    /// exact count
+    ///
+    /// The lower bound is the data already decoded and buffered, typically
+    /// the rest of the current block. The exact count is known once an
+    /// index has been read (see [`index`](Reader::index), so from the
+    /// first read for a stream with a leading index) or at the end of the
+    /// stream. `read_to_end` uses it to size its output, reserving at most
+    /// one block beyond what is buffered since the index isn't verified.
+    pub fn size_hint(&self) -> (u64, Option<u64>) {
+        let buffered = self.buffered_len() as u64;
+        if self.eof {
+            return (buffered, Some(buffered));
+        }
+        let exact = self
+            .index
+            .as_ref()
+            .filter(|index| index.total_uncompressed >= self.current_uncompressed_offset)
+            .map(|index| (index.total_uncompressed - self.current_uncompressed_offset) as u64)
+            .filter(|&left| left >= buffered);
+        (buffered, exact)
+    }
+
+    /// Decoded bytes buffered and not yet read
+    pub(crate) fn buffered_len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// CRC of the most recently decoded block, as computed by
+    /// [`crc`](crate::crc::crc)
+    ///
+    /// This is the checksum already computed to verify the block, so
+    /// tools cross-checking content (e.g. after recompressing) don't need
+    /// a second pass over the data. For a block stored with a CRC it is
+    /// the stored value. `None` before the first data chunk is decoded.
+    ///
+    /// When decoding in parallel (`with_concurrency`), blocks are decoded
+    /// ahead of `read` in batches, and this is the CRC of the last block of
+    /// the latest batch.
+    pub fn last_block_crc(&self) -> Option<u32> {
+        self.last_block_crc
+    }
+
+    /// Reset the reader to use a new underlying reader
+    pub fn reset(&mut self, reader: R) -> R {
+        self.buf.clear();
+        self.pos = 0;
+        self.read_header = false;
+        self.eof = false;
+        self.total_decoded = 0;
+        self.level = None;
+        self.index = None;
+        self.compressed_offset = 0;
+        self.chunk_index = 0;
+        self.seen_eof_marker = false;
+        self.last_block_crc = None;
+        #[cfg(feature = "concurrent")]
+        {
+            self.pending_error = None;
+        }
+        self.current_uncompressed_offset = 0;
+        let capacity = self.reader.capacity();
+        std::mem::replace(&mut self.reader, BufReader::with_capacity(capacity, reader)).into_inner()
+    }
+
+    /// Get a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying reader
+    ///
+    /// With a read buffer (see [`with_read_buffer`](Reader::with_read_buffer)),
+    /// reading from or seeking it directly leaves the Reader out of step
+    /// with it.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+}
+
+/// A data chunk read from the stream but not yet decoded or verified
+struct RawChunk {
+    /// Chunk payload; the block or data starts at `start`
+    data: Vec<u8>,
+    start: usize,
+    compressed: bool,
+    /// Reject blocks plain Snappy decoders cannot read
+    snappy_strict: bool,
+    /// Expected CRC of the decoded data, if the chunk has one
+    checksum: Option<u32>,
+    // Position of the chunk, for error messages
+    chunk_index: u64,
+    compressed_offset: u64,
+}
+
+impl RawChunk {
+    /// Length of the chunk's data once decoded, without decoding it
+    #[cfg(feature = "concurrent")]
+    fn decoded_len(&self) -> io::Result<usize> {
+        if !self.compressed {
+            return Ok(self.data.len());
+        }
+        decode_len(&self.data[self.start..])
+            .map(|(dlen, _)| dlen)
+            .map_err(|e| self.error(format!("decode error: {}", e)))
+    }
+
+    /// Decode the chunk and verify its CRC, returning the data and its CRC
+    ///
+    /// Needs nothing from the reader, so chunks can be decoded in parallel.
+    fn decode(mut self, dict: Option<&Dict>, max_block_size: usize) -> io::Result<(Vec<u8>, u32)> {
+        let decoded = if self.compressed {
+            let block = &self.data[self.start..];
+            let decoded = match dict {
+                Some(dict) => decode_with_dict(block, dict),
+                None if self.snappy_strict => decode_snappy_strict(block),
+                None => decode(block),
+            };
+            let decompressed = decoded.map_err(|e| self.error(format!("decode error: {}", e)))?;
+
+            // Check against max_block_size limit
+            if decompressed.len() > max_block_size {
+                return Err(self.error(format!(
+                    "decompressed block size ({}) exceeds limit ({})",
+                    decompressed.len(),
+                    max_block_size
+                )));
+            }
+            decompressed
+        } else {
+            std::mem::take(&mut self.data)
+        };
+
+        // Verify CRC
+        let actual = crc(&decoded);
+        if let Some(checksum) = self.checksum {
+            if actual != checksum {
+                return Err(self.error("CRC mismatch".to_string()));
+            }
+        }
+        Ok((decoded, actual))
+    }
+
+    fn error(&self, msg: String) -> io::Error {
+        chunk_error_at(msg, self.chunk_index, self.compressed_offset)
+    }
+}
+
+/// Build an InvalidData error locating a chunk
+fn chunk_error_at(msg: String, chunk_index: u64, compressed_offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} (chunk {} at compressed offset {})",
+            msg, chunk_index, compressed_offset
+        ),
+    )
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read stream header if not already done
+        if !self.read_header {
+            self.read_stream_identifier()?;
+            self.read_header = true;
+        }
+
+        // Report an error held back by parallel decoding once the data
+        // before it has been read
+        #[cfg(feature = "concurrent")]
+        if self.pos >= self.buf.len() {
+            if let Some(err) = self.pending_error.take() {
+                return Err(err);
+            }
+        }
+
+        // If buffer is empty and not EOF, read next chunk
+        while self.pos >= self.buf.len() && !self.eof {
+            self.buf.clear();
+            self.pos = 0;
+            #[cfg(feature = "concurrent")]
+            let more = if self.concurrency > 1 {
+                self.read_chunks_parallel()?
+            } else {
+                self.read_chunk()?
+            };
+            #[cfg(not(feature = "concurrent"))]
+            let more = self.read_chunk()?;
+            if !more {
+                break;
+            }
+        }
+
+        // Copy from buffer
+        let available = self.buf.len() - self.pos;
+        if available == 0 {
+            return Ok(0); // EOF
+        }
+
+        let to_copy = available.min(buf.len());
+        buf[..to_copy].copy_from_slice(&self.buf[self.pos..self.pos + to_copy]);
+        self.pos += to_copy;
+
+        // Track uncompressed offset
+        self.current_uncompressed_offset += to_copy as i64;
+
+        Ok(to_copy)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        loop {
+            // An empty read loads the next block once the buffer is drained
+            let _ = self.read(&mut [])?;
+            let (lower, exact) = self.size_hint();
+            if lower == 0 {
+                return Ok(buf.len() - start);
+            }
+            // The index may be forged, so don't reserve more than the next
+            // block could add
+            let cap = lower.saturating_add(self.max_block_size as u64);
+            buf.reserve(exact.unwrap_or(lower).min(cap) as usize);
+            buf.extend_from_slice(&self.buf[self.pos..]);
+            self.current_uncompressed_offset += (self.buf.len() - self.pos) as i64;
+            self.pos = self.buf.len();
+        }
+    }
+}
+
+/// Compute the total decoded length of an S2/Snappy stream without
+/// decompressing it
+///
+/// Walks the chunk headers of the stream. For each compressed chunk only the
+/// block's varint length header is read; the rest of the payload is skipped.
+/// Uncompressed chunks contribute their payload length, and padding, index
+/// and other skippable chunks are skipped entirely.
+///
+/// The payloads are still read from `reader` (just not decoded or CRC
+/// checked), so the cost is O(chunks) in CPU but O(bytes) in I/O. A stream
+/// written with an index can answer this in O(1) via
+/// [`Index::total_uncompressed`](crate::Index::total_uncompressed).
+///
+/// # Example
+///
+/// ```
+/// use minlz::{stream_decoded_len, Writer};
+/// use std::io::Write;
+///
+/// let mut compressed = Vec::new();
+/// {
+///     let mut writer = Writer::new(&mut compressed);
+///     writer.write_all(&[b'x'; 10_000]).unwrap();
+/// }
+///
+/// assert_eq!(stream_decoded_len(&compressed[..]).unwrap(), 10_000);
+/// ```
+pub fn stream_decoded_len<R: Read>(mut reader: R) -> io::Result<u64> {
+    let mut magic = [0u8; MAGIC_CHUNK.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != *MAGIC_CHUNK && magic != *MAGIC_CHUNK_SNAPPY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid stream identifier",
+        ));
+    }
+
+    let mut total: u64 = 0;
+    loop {
+        let mut header = [0u8; 4];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(total),
+            Err(e) => return Err(e),
+        }
+
+        let chunk_type = header[0];
+        let chunk_len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+
+        match chunk_type {
+            CHUNK_TYPE_COMPRESSED_DATA => {
+                if chunk_len < CHECKSUM_SIZE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "chunk too small",
+                    ));
+                }
+                skip_bytes(&mut reader, CHECKSUM_SIZE)?;
+
+                // The decoded length is the varint at the start of the block.
+                let data_len = chunk_len - CHECKSUM_SIZE;
+                let mut peek = [0u8; 10];
+                let n = data_len.min(peek.len());
+                reader.read_exact(&mut peek[..n])?;
+                let (dlen, _) = decode_len(&peek[..n]).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("decode error: {}", e))
+                })?;
+                total += dlen as u64;
+                skip_bytes(&mut reader, data_len - n)?;
+            }
+            CHUNK_TYPE_UNCOMPRESSED_DATA => {
+                if chunk_len < CHECKSUM_SIZE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "chunk too small",
+                    ));
+                }
+                total += (chunk_len - CHECKSUM_SIZE) as u64;
+                skip_bytes(&mut reader, chunk_len)?;
+            }
+            CHUNK_TYPE_PADDING | CHUNK_TYPE_INDEX | CHUNK_TYPE_STREAM_IDENTIFIER | 0x80..=0xfd => {
+                skip_bytes(&mut reader, chunk_len)?;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown chunk type: 0x{:02x}", chunk_type),
+                ))
+            }
+        }
+    }
+}
+
+/// Result of verifying a stream with [`verify_stream`] or [`Reader::verify`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VerifyReport {
+    /// Bytes the stream decodes to
+    pub decompressed_bytes: u64,
+    /// Stream bytes consumed, stream identifier included
+    pub compressed_bytes: u64,
+    /// Data chunks (compressed or uncompressed) decoded
+    pub data_chunks: u64,
+    /// Time spent reading, decoding and verifying
+    pub elapsed: Duration,
+}
+
+impl VerifyReport {
+    /// Decompressed bytes per second, or 0 if no time was measured
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.decompressed_bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Decode and CRC-check a whole stream without keeping its output
+///
+/// Every chunk is decoded and verified exactly as [`Reader`] would, but the
+/// decoded data is dropped as soon as it is checked, so memory use is
+/// bounded by one block. The first error is returned as is.
+///
+/// Use [`Reader::verify`] to verify with a dictionary or other reader
+/// settings.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{verify_stream, Writer};
+/// use std::io::Write;
+///
+/// let mut compressed = Vec::new();
+/// Writer::new(&mut compressed).write_all(&[b'x'; 10_000]).unwrap();
+///
+/// let report = verify_stream(&compressed[..]).unwrap();
+/// assert_eq!(report.decompressed_bytes, 10_000);
+/// assert_eq!(report.compressed_bytes, compressed.len() as u64);
+/// ```
+pub fn verify_stream<R: Read>(reader: R) -> io::Result<VerifyReport> {
+    Reader::new(reader).verify()
+}
+
+/// Decode a stream as far as it is intact
+///
+/// Returns the data of every chunk decoded before the first error, and
+/// that error (`None` if the whole stream decoded). Use it to salvage the
+/// start of a damaged archive; the data of the chunk that failed (a CRC
+/// mismatch, a corrupt block or a truncated chunk) is not included.
+/// [`Reader`] and [`verify_stream`] stay strict.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_stream_recoverable, Writer};
+/// use std::io::Write;
+///
+/// let mut compressed = Vec::new();
+/// Writer::with_block_size(&mut compressed, 4096)
+///     .write_all(&[b'x'; 10_000])
+///     .unwrap();
+///
+/// // Cut off in the middle of the last chunk
+/// let cut = &compressed[..compressed.len() - 2];
+/// let (data, err) = decode_stream_recoverable(cut);
+/// assert_eq!(data, [b'x'; 8192]);
+/// assert!(err.is_some());
+/// ```
+pub fn decode_stream_recoverable<R: Read>(reader: R) -> (Vec<u8>, Option<io::Error>) {
+    let mut reader = Reader::new(reader);
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; DEFAULT_BLOCK_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return (out, None),
+            Ok(n) => out.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return (out, Some(e)),
+        }
+    }
+}
+
+/// Discard exactly `n` bytes from `reader`
+fn skip_bytes<R: Read>(reader: &mut R, n: usize) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(n as u64), &mut io::sink())?;
+    if skipped < n as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "unexpected EOF inside chunk",
+        ));
+    }
+    Ok(())
+}
+
+/// Decode the bytes `[start, start + len)` of an indexed stream, decoding
+/// the blocks that cover them in parallel
+///
+/// `index` is the stream's [`Index`] (as loaded from the stream or kept
+/// from the writer); its offsets are positions in `reader`. Reading starts
+/// at the last index entry at or before `start`, only the chunks up to
+/// the end of the range are read, and they are decoded and CRC checked on
+/// a pool of `threads` threads (at least 1). The result is exactly `len`
+/// bytes long.
+///
+/// Fails with `ErrorKind::InvalidInput` if the range does not lie within
+/// the stream's total size as recorded in the index.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{decode_range_concurrent, Index, Writer};
+/// use std::io::{Cursor, Write};
+///
+/// let data: Vec<u8> = (0..3_000_000u32).map(|i| (i / 11) as u8).collect();
+/// let mut compressed = Vec::new();
+/// {
+///     let mut writer = Writer::with_index_and_block_size(&mut compressed, 256 * 1024);
+///     writer.write_all(&data).unwrap();
+/// }
+///
+/// // The index is the last chunk; its length precedes the trailer
+/// let at = compressed.len() - 10;
+/// let index_len = u32::from_le_bytes(compressed[at..at + 4].try_into().unwrap()) as usize;
+/// let mut index = Index::new();
+/// index.load(&compressed[compressed.len() - index_len..]).unwrap();
+/// let range = decode_range_concurrent(Cursor::new(&compressed), &index, 1_234_567, 1000, 4)
+///     .unwrap();
+/// assert_eq!(range, &data[1_234_567..1_235_567]);
+/// ```
+#[cfg(feature = "concurrent")]
+pub fn decode_range_concurrent<R: Read + Seek>(
+    mut reader: R,
+    index: &Index,
+    start: u64,
+    len: u64,
+    threads: usize,
+) -> io::Result<Vec<u8>> {
+    let end = start
+        .checked_add(len)
+        .filter(|&end| index.total_uncompressed >= 0 && end <= index.total_uncompressed as u64)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "range beyond end of stream"))?;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let (comp, uncomp) = index
+        .find(start as i64)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    reader.seek(SeekFrom::Start(comp as u64))?;
+
+    // Collect the chunks overlapping the range, with the offset of the
+    // first one
+    let mut r = Reader::with_ignore_stream_id(reader);
+    r.compressed_offset = comp as u64;
+    let mut raws = Vec::new();
+    let mut first = None;
+    let mut pos = uncomp as u64;
+    while pos < end {
+        let raw = r.read_raw_chunk()?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("stream ended at {} before end of range {}", pos, end),
+            )
+        })?;
+        let next = pos + raw.decoded_len()? as u64;
+        if next > start {
+            first.get_or_insert(pos);
+            raws.push(raw);
+        }
+        pos = next;
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .build()
+        .map_err(io::Error::other)?;
+    let blocks = pool.install(|| {
+        raws.into_par_iter()
+            .map(|raw| raw.decode(None, MAX_BLOCK_SIZE))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+
+    let mut out = Vec::with_capacity(len as usize);
+    let mut skip = (start - first.unwrap_or(start)) as usize;
+    for (block, _) in blocks {
+        let block = &block[skip..];
+        skip = 0;
+        let want = len as usize - out.len();
+        out.extend_from_slice(&block[..want.min(block.len())]);
+    }
+    Ok(out)
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Total uncompressed size recorded by
+    /// [`Writer::with_size_header`](crate::Writer::with_size_header)
+    ///
+    /// Looks for the size chunk at the end of the underlying stream,
+    /// stepping back over an EOF marker and an index, without decoding
+    /// anything. The underlying reader's position is restored afterwards.
+    /// Returns `None` if the stream has no size chunk there.
+    pub fn total_size_hint(&mut self) -> io::Result<Option<u64>> {
+        let pos = self.reader.stream_position()?;
+        let hint = self.find_size_chunk();
+        self.reader.seek(SeekFrom::Start(pos))?;
+        hint
+    }
+
+    fn find_size_chunk(&mut self) -> io::Result<Option<u64>> {
+        let mut end = self.reader.seek(SeekFrom::End(0))?;
+
+        // EOF marker
+        let mut marker = [0u8; CHUNK_HEADER_SIZE];
+        if end >= marker.len() as u64 {
+            self.read_exact_at(end - marker.len() as u64, &mut marker)?;
+            if marker == [CHUNK_TYPE_PADDING, 0, 0, 0] {
+                end -= marker.len() as u64;
+            }
+        }
+
+        // Index, which ends with its total size and a trailer
+        let mut tail = [0u8; 4 + S2_INDEX_TRAILER.len()];
+        if end >= tail.len() as u64 {
+            self.read_exact_at(end - tail.len() as u64, &mut tail)?;
+            if tail[4..] == *S2_INDEX_TRAILER {
+                let index_len = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]) as u64;
+                end = end.checked_sub(index_len).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "index larger than stream")
+                })?;
+            }
+        }
+
+        let mut chunk = [0u8; SIZE_CHUNK_LEN];
+        if end < chunk.len() as u64 {
+            return Ok(None);
+        }
+        self.read_exact_at(end - chunk.len() as u64, &mut chunk)?;
+        let len = ((SIZE_CHUNK_LEN - CHUNK_HEADER_SIZE) as u32).to_le_bytes();
+        if chunk[0] != CHUNK_TYPE_SIZE
+            || chunk[1..CHUNK_HEADER_SIZE] != len[..3]
+            || chunk[SIZE_CHUNK_LEN - SIZE_MAGIC.len()..] != *SIZE_MAGIC
+        {
+            return Ok(None);
+        }
+        let mut size = [0u8; 8];
+        size.copy_from_slice(&chunk[CHUNK_HEADER_SIZE..CHUNK_HEADER_SIZE + 8]);
+        Ok(Some(u64::from_le_bytes(size)))
+    }
+
+    fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(pos))?;
+        self.reader.read_exact(buf)
+    }
+}
+
+/// Implementation of Seek for Reader with seekable underlying reader
+///
+/// Note: This provides basic seeking support. For efficient random access,
+/// use an Index to map uncompressed offsets to compressed positions.
+impl<R: Read + Seek> Seek for Reader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // Calculate target uncompressed position
+        let target_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.current_uncompressed_offset + offset,
+            SeekFrom::End(offset) => {
+                // The total size is only known from an index; a leading one
+                // is read with the first chunk
+                if self.index.is_none() && !self.read_header {
+                    // An empty read loads the first data chunk and the
+                    // metadata chunks before it
+                    let _ = self.read(&mut [])?;
+                }
+                match self.index.as_ref() {
+                    Some(index) if index.total_uncompressed >= 0 => {
+                        index.total_uncompressed + offset
+                    }
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Unsupported,
+                            "SeekFrom::End not supported without an Index. Use Index::find() to seek from end.",
+                        ));
+                    }
+                }
+            }
+        };
+
+        if target_pos < 0 {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "cannot seek to negative position",
@@ -403,6 +1697,15 @@ impl<R: Read + Seek> Seek for Reader<R> {
             self.pos = 0;
             self.read_header = false;
             self.eof = false;
+            self.total_decoded = 0;
+            self.compressed_offset = 0;
+            self.chunk_index = 0;
+            self.seen_eof_marker = false;
+            self.last_block_crc = None;
+            #[cfg(feature = "concurrent")]
+            {
+                self.pending_error = None;
+            }
             self.current_uncompressed_offset = 0;
             return Ok(0);
         }
@@ -414,6 +1717,15 @@ impl<R: Read + Seek> Seek for Reader<R> {
             self.pos = 0;
             self.read_header = false;
             self.eof = false;
+            self.total_decoded = 0;
+            self.compressed_offset = 0;
+            self.chunk_index = 0;
+            self.seen_eof_marker = false;
+            self.last_block_crc = None;
+            #[cfg(feature = "concurrent")]
+            {
+                self.pending_error = None;
+            }
             self.current_uncompressed_offset = 0;
         }
 
@@ -465,6 +1777,37 @@ mod tests {
         assert_eq!(decompressed, data);
     }
 
+    #[test]
+    fn test_reader_with_skip_prefix() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+        let mut file: Vec<u8> = (0..100u32).map(|i| (i * 37) as u8).collect();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut file, 4096);
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut decompressed = Vec::new();
+        Reader::with_skip_prefix(&file[..], 100)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        // The identifier is still checked after the prefix.
+        for prefix in [99, 101, file.len() + 1] {
+            let mut reader = Reader::with_skip_prefix(&file[..], prefix);
+            assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        }
+
+        // Seeking back to the start skips the prefix again.
+        let mut reader = Reader::with_skip_prefix(io::Cursor::new(&file), 100);
+        let mut head = [0u8; 10_000];
+        reader.read_exact(&mut head).unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut again = Vec::new();
+        reader.read_to_end(&mut again).unwrap();
+        assert_eq!(again, data);
+    }
+
     #[test]
     fn test_reader_with_ignore_stream_id() {
         // Compress
@@ -550,15 +1893,15 @@ mod tests {
 
     #[test]
     fn test_reader_multiple_chunks() {
-        let data1 = b"First chunk of data";
-        let data2 = b"Second chunk of data";
+        let data1 = b"First chunk of data".repeat(40);
+        let data2 = b"Second chunk of data".repeat(40);
 
         // Compress with small block size to create multiple chunks
         let mut compressed = Vec::new();
         {
-            let mut writer = Writer::with_block_size(&mut compressed, 16);
-            writer.write_all(data1).unwrap();
-            writer.write_all(data2).unwrap();
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 512);
+            writer.write_all(&data1).unwrap();
+            writer.write_all(&data2).unwrap();
             writer.flush().unwrap();
         }
 
@@ -568,8 +1911,8 @@ mod tests {
         reader.read_to_end(&mut decompressed).unwrap();
 
         let mut expected = Vec::new();
-        expected.extend_from_slice(data1);
-        expected.extend_from_slice(data2);
+        expected.extend_from_slice(&data1);
+        expected.extend_from_slice(&data2);
 
         assert_eq!(decompressed, expected);
     }
@@ -758,31 +2101,144 @@ mod tests {
     }
 
     #[test]
-    fn test_reader_seek_negative() {
-        use std::io::Cursor;
-
-        let data = b"Test data";
+    fn test_reader_size_hint() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 241) as u8).collect();
         let mut compressed = Vec::new();
-        {
-            let mut writer = Writer::new(&mut compressed);
-            writer.write_all(data).unwrap();
-            writer.flush().unwrap();
-        }
-
-        let mut reader = Reader::new(Cursor::new(compressed));
+        let index = {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 64 * 1024);
+            writer.write_all(&data).unwrap();
+            writer.take_index().unwrap().unwrap()
+        };
 
-        // Seeking to negative position should error
-        let result = reader.seek(SeekFrom::Current(-10));
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let mut reader = Reader::new(&compressed[..]);
+        assert_eq!(reader.size_hint(), (0, None));
+        reader.read_exact(&mut [0u8; 10]).unwrap();
+        assert_eq!(reader.size_hint(), (65526, None));
+        let mut out = vec![1, 2, 3];
+        assert_eq!(reader.read_to_end(&mut out).unwrap(), data.len() - 10);
+        assert_eq!(out[3..], data[10..]);
+        assert_eq!(reader.size_hint(), (0, Some(0)));
+
+        // With a leading index the rest is known after the first read
+        let mut stream = MAGIC_CHUNK.to_vec();
+        index
+            .clone()
+            .append_to(
+                &mut stream,
+                index.total_uncompressed,
+                index.total_compressed,
+            )
+            .unwrap();
+        stream.extend_from_slice(&compressed[MAGIC_CHUNK.len()..]);
+        let mut reader = Reader::new(&stream[..]);
+        reader.read_exact(&mut [0u8; 10]).unwrap();
+        assert_eq!(reader.size_hint(), (65526, Some(data.len() as u64 - 10)));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data[10..]);
+        assert_eq!(out.capacity(), out.len());
     }
 
     #[test]
-    fn test_reader_seek_beyond_eof() {
+    fn test_reader_leading_index() {
         use std::io::Cursor;
 
-        let data = b"Short";
-        let mut compressed = Vec::new();
+        let data: Vec<u8> = (0..300_000u32)
+            .map(|i| (i % 241) as u8 ^ (i >> 12) as u8)
+            .collect();
+        let mut body = Vec::new();
+        let index = {
+            let mut writer = Writer::with_index_and_block_size(&mut body, 64 * 1024);
+            writer.write_all(&data).unwrap();
+            writer.take_index().unwrap().unwrap()
+        };
+
+        // Move the index in front of the data chunks
+        let mut stream = MAGIC_CHUNK.to_vec();
+        let mut leading = index.clone();
+        leading
+            .append_to(
+                &mut stream,
+                index.total_uncompressed,
+                index.total_compressed,
+            )
+            .unwrap();
+        stream.extend_from_slice(&body[MAGIC_CHUNK.len()..]);
+
+        let mut reader = Reader::new(Cursor::new(stream.clone()));
+        assert!(reader.index().is_none());
+        assert_eq!(
+            reader.seek(SeekFrom::End(-10)).unwrap(),
+            data.len() as u64 - 10
+        );
+        assert_eq!(
+            reader.index().unwrap().total_uncompressed,
+            data.len() as i64
+        );
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 10..]);
+
+        let mut reader = Reader::new(&stream[..]);
+        let mut out = Vec::new();
+        reader.read_exact(&mut [0u8; 1]).unwrap();
+        assert!(reader.index().is_some());
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[1..]);
+    }
+
+    #[test]
+    fn test_reader_read_to_end_forged_index() {
+        let data = vec![7u8; 1000];
+        let mut body = Vec::new();
+        let index = {
+            let mut writer = Writer::with_index(&mut body);
+            writer.write_all(&data).unwrap();
+            writer.take_index().unwrap().unwrap()
+        };
+
+        // A leading index claiming far more data than any buffer could hold
+        let mut stream = MAGIC_CHUNK.to_vec();
+        index
+            .clone()
+            .append_to(&mut stream, 1 << 61, index.total_compressed)
+            .unwrap();
+        stream.extend_from_slice(&body[MAGIC_CHUNK.len()..]);
+
+        let mut reader = Reader::new(&stream[..]);
+        reader.read_exact(&mut [0u8; 1]).unwrap();
+        assert_eq!(reader.size_hint().1, Some((1 << 61) - 1));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[1..]);
+    }
+
+    #[test]
+    fn test_reader_seek_negative() {
+        use std::io::Cursor;
+
+        let data = b"Test data";
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::new(&mut compressed);
+            writer.write_all(data).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let mut reader = Reader::new(Cursor::new(compressed));
+
+        // Seeking to negative position should error
+        let result = reader.seek(SeekFrom::Current(-10));
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_reader_seek_beyond_eof() {
+        use std::io::Cursor;
+
+        let data = b"Short";
+        let mut compressed = Vec::new();
         {
             let mut writer = Writer::new(&mut compressed);
             writer.write_all(data).unwrap();
@@ -805,7 +2261,7 @@ mod tests {
         let data = vec![b'A'; 10000];
         let mut compressed = Vec::new();
         {
-            let mut writer = Writer::with_block_size(&mut compressed, 1024);
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 1024);
             writer.write_all(&data).unwrap();
             writer.flush().unwrap();
         }
@@ -821,4 +2277,1017 @@ mod tests {
         reader.read_exact(&mut buf).unwrap();
         assert_eq!(&buf[..], &[b'A'; 100][..]);
     }
+
+    #[test]
+    fn test_reader_with_dict_roundtrip() {
+        use crate::make_dict;
+
+        let dict_data = b"The quick brown fox jumps over the lazy dog. Lorem ipsum dolor sit amet.";
+        let record = b"The quick brown fox jumps over the lazy dog. Lorem ipsum dolor sit amet!";
+        let data: Vec<u8> = record.repeat(3000);
+
+        let mut compressed = Vec::new();
+        {
+            let dict = make_dict(dict_data, None).unwrap();
+            let mut writer = Writer::with_dict(&mut compressed, dict);
+            writer.write_all(&data).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let dict = make_dict(dict_data, None).unwrap();
+        let mut reader = Reader::with_dict(&compressed[..], dict);
+        let mut decompressed = Vec::new();
+        reader.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+
+        // The dictionary survives a reset.
+        reader.reset(&compressed[..]);
+        let mut again = Vec::new();
+        reader.read_to_end(&mut again).unwrap();
+        assert_eq!(again, data);
+    }
+
+    #[test]
+    fn test_reader_error_reports_chunk_position() {
+        let data: Vec<u8> = (0..3 * 4096u32).map(|i| (i % 100) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, 4096);
+            writer.write_all(&data).unwrap();
+        }
+
+        // Locate the third data chunk (chunk 3, after the stream identifier).
+        let mut offset = MAGIC_CHUNK.len();
+        for _ in 0..2 {
+            let len = u32::from_le_bytes([
+                compressed[offset + 1],
+                compressed[offset + 2],
+                compressed[offset + 3],
+                0,
+            ]) as usize;
+            offset += CHUNK_HEADER_SIZE + len;
+        }
+        assert_eq!(compressed[offset], CHUNK_TYPE_COMPRESSED_DATA);
+
+        // Corrupt the block's length header.
+        compressed[offset + CHUNK_HEADER_SIZE + CHECKSUM_SIZE] ^= 0xff;
+
+        let mut decompressed = Vec::new();
+        let err = Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let msg = err.to_string();
+        assert!(
+            msg.contains(&format!("chunk 3 at compressed offset {}", offset)),
+            "{}",
+            msg
+        );
+        assert_eq!(decompressed, &data[..2 * 4096]);
+    }
+
+    #[test]
+    fn test_reader_max_total() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 100) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, 16 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        // At or above the decoded size: fine.
+        for max in [100_000, 1 << 20] {
+            let mut decompressed = Vec::new();
+            Reader::new(&compressed[..])
+                .set_max_total(max)
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+
+        // Below it: errors partway, after the blocks that fit.
+        let mut decompressed = Vec::new();
+        let err = Reader::new(&compressed[..])
+            .set_max_total(40_000)
+            .read_to_end(&mut decompressed)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("exceeds limit (40000)"), "{}", err);
+        assert_eq!(decompressed, &data[..2 * 16 * 1024]);
+
+        // Also on a reader with a dictionary
+        let dict = crate::make_dict(&data[..1000], None).unwrap();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_dict(&mut compressed, dict.clone());
+            writer.write_all(&data).unwrap();
+        }
+        let mut reader = Reader::with_dict(&compressed[..], dict);
+        reader.set_max_total(40_000);
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("exceeds limit (40000)"), "{}", err);
+    }
+
+    /// Yields at most 3 bytes per call, failing every other call with
+    /// `Interrupted`, as a signal-interrupted syscall would.
+    struct InterruptingReader<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl Read for InterruptingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "signal"));
+            }
+            let n = buf.len().min(self.data.len()).min(3);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_reader_retries_interrupted() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 100) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 4096);
+            writer.write_all(&data).unwrap();
+        }
+
+        let source = InterruptingReader {
+            data: &compressed,
+            interrupt: false,
+        };
+        let mut decompressed = Vec::new();
+        Reader::new(source).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+
+        let source = InterruptingReader {
+            data: &compressed,
+            interrupt: false,
+        };
+        assert_eq!(stream_decoded_len(source).unwrap(), data.len() as u64);
+    }
+
+    /// Counts the reads made on the wrapped reader
+    struct CountingReader<R> {
+        inner: R,
+        reads: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_reader_read_buffer() {
+        use std::io::Cursor;
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 4096);
+            writer.write_all(&data).unwrap();
+        }
+
+        // Several reads per chunk without a buffer, a few per buffer fill
+        // with one
+        let mut counts = Vec::new();
+        for size in [None, Some(1), Some(100), Some(64 * 1024)] {
+            let source = CountingReader {
+                inner: &compressed[..],
+                reads: 0,
+            };
+            let mut reader = match size {
+                Some(size) => Reader::with_read_buffer(source, size),
+                None => Reader::new(source),
+            };
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data, "buffer size {:?}", size);
+            counts.push(reader.get_ref().reads);
+        }
+        assert!(counts[0] > 2 * (data.len() / 4096), "{:?}", counts);
+        assert!(
+            counts[3] <= compressed.len() / (64 * 1024) + 2,
+            "{:?}",
+            counts
+        );
+
+        // Seeking discards the buffered data
+        let mut reader = Reader::with_read_buffer(Cursor::new(&compressed[..]), 64 * 1024);
+        let mut buf = [0u8; 100];
+        for pos in [200_000u64, 5_000, 299_900, 0] {
+            reader.seek(SeekFrom::Start(pos)).unwrap();
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, data[pos as usize..pos as usize + 100], "pos {}", pos);
+        }
+        assert_eq!(reader.seek(SeekFrom::Current(-50)).unwrap(), 50);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[50..150]);
+
+        // reset keeps the buffer size and hands back the old reader
+        let old = reader.reset(Cursor::new(&compressed[..]));
+        assert_eq!(old.get_ref().len(), compressed.len());
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(reader.reader.capacity(), 64 * 1024);
+    }
+
+    #[test]
+    fn test_reader_expect_eof_marker() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 1024);
+            writer.write_all(&data).unwrap();
+            writer.finish_with_eof_marker().unwrap();
+            assert!(writer.finish_with_eof_marker().is_err());
+        }
+        assert_eq!(
+            compressed[compressed.len() - 4..],
+            [CHUNK_TYPE_PADDING, 0, 0, 0]
+        );
+
+        let read = |stream: &[u8], expect: bool| {
+            let mut reader = Reader::new(stream);
+            if expect {
+                reader.expect_eof_marker();
+            }
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).map(|_| out)
+        };
+
+        // Complete stream, with or without the check.
+        assert_eq!(read(&compressed, true).unwrap(), data);
+        assert_eq!(read(&compressed, false).unwrap(), data);
+
+        // Cut just before the marker, or a whole chunk earlier: both look
+        // like valid streams unless the marker is required.
+        let before_marker = &compressed[..compressed.len() - 4];
+        assert_eq!(read(before_marker, false).unwrap(), data);
+        let err = read(before_marker, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut boundaries = Vec::new();
+        let mut pos = MAGIC_CHUNK.len();
+        while pos < before_marker.len() {
+            boundaries.push(pos);
+            let len = u32::from_le_bytes([
+                before_marker[pos + 1],
+                before_marker[pos + 2],
+                before_marker[pos + 3],
+                0,
+            ]) as usize;
+            pos += CHUNK_HEADER_SIZE + len;
+        }
+        let cut = &compressed[..*boundaries.last().unwrap()];
+        assert!(read(cut, false).unwrap().len() < data.len());
+        assert!(read(cut, true).is_err());
+
+        // Data appended after the marker needs a marker of its own.
+        let mut extended = compressed.clone();
+        extended.extend_from_slice(&compressed[cut.len()..before_marker.len()]);
+        assert!(read(&extended, true).is_err());
+        extended.extend_from_slice(&[CHUNK_TYPE_PADDING, 0, 0, 0]);
+        assert!(read(&extended, true).is_ok());
+    }
+
+    #[test]
+    fn test_reader_with_lenient_crc() {
+        use crate::encode::encode;
+
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 97) as u8).collect();
+        let block = encode(&data);
+        let tiny = encode(b"");
+        assert!(tiny.len() < CHECKSUM_SIZE);
+
+        // A regular chunk, a chunk missing its CRC, and one too short to
+        // ever have held a CRC.
+        let mut stream = MAGIC_CHUNK.to_vec();
+        stream.push(CHUNK_TYPE_COMPRESSED_DATA);
+        stream.extend_from_slice(&((CHECKSUM_SIZE + block.len()) as u32).to_le_bytes()[..3]);
+        stream.extend_from_slice(&crc(&data).to_le_bytes());
+        stream.extend_from_slice(&block);
+        let strict_len = stream.len();
+        for b in [&block, &tiny] {
+            stream.push(CHUNK_TYPE_COMPRESSED_DATA);
+            stream.extend_from_slice(&(b.len() as u32).to_le_bytes()[..3]);
+            stream.extend_from_slice(b);
+        }
+
+        let mut out = Vec::new();
+        assert!(Reader::new(&stream[..]).read_to_end(&mut out).is_err());
+
+        let mut out = Vec::new();
+        Reader::with_lenient_crc(&stream[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out.len(), 2 * data.len());
+        assert_eq!(&out[..data.len()], &data[..]);
+        assert_eq!(&out[data.len()..], &data[..]);
+
+        // Chunks that have a CRC are still verified.
+        let mut corrupt = stream[..strict_len].to_vec();
+        corrupt[MAGIC_CHUNK.len() + CHUNK_HEADER_SIZE] ^= 1;
+        let err = Reader::with_lenient_crc(&corrupt[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert!(err.to_string().contains("CRC mismatch"));
+    }
+
+    #[test]
+    fn test_reader_try_with_max_block_size() {
+        for size in [0, MAX_BLOCK_SIZE + 1] {
+            let err = Reader::try_with_max_block_size(&[][..], size)
+                .err()
+                .unwrap();
+            assert!(matches!(err, Error::InvalidInput(_)), "{}", size);
+            assert!(err.to_string().contains(&size.to_string()));
+        }
+
+        let mut compressed = Vec::new();
+        Writer::with_block_size(&mut compressed, 64 * 1024)
+            .write_all(&[5u8; 100_000])
+            .unwrap();
+        let mut out = Vec::new();
+        Reader::try_with_max_block_size(&compressed[..], 64 * 1024)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, [5u8; 100_000]);
+    }
+
+    #[test]
+    fn test_reader_with_snappy_strict() {
+        use crate::encode::{encode, encode_snappy};
+
+        fn push_chunk(stream: &mut Vec<u8>, chunk_type: u8, body: &[u8]) {
+            stream.push(chunk_type);
+            stream.extend_from_slice(&(body.len() as u32).to_le_bytes()[..3]);
+            stream.extend_from_slice(body);
+        }
+        fn push_block(stream: &mut Vec<u8>, data: &[u8], block: &[u8]) {
+            push_chunk(
+                stream,
+                CHUNK_TYPE_COMPRESSED_DATA,
+                &[&crc(data).to_le_bytes()[..], block].concat(),
+            );
+        }
+        fn read_strict(stream: &[u8]) -> io::Result<Vec<u8>> {
+            let mut out = Vec::new();
+            Reader::with_snappy_strict(stream).read_to_end(&mut out)?;
+            Ok(out)
+        }
+
+        let data = b"snappy stream, snappy stream, snappy stream".repeat(100);
+        let mut snappy = MAGIC_CHUNK_SNAPPY.to_vec();
+        push_block(&mut snappy, &data, &encode_snappy(&data));
+        push_chunk(&mut snappy, CHUNK_TYPE_PADDING, &[0; 8]);
+        snappy.extend_from_slice(MAGIC_CHUNK_SNAPPY);
+        push_chunk(
+            &mut snappy,
+            CHUNK_TYPE_UNCOMPRESSED_DATA,
+            &[&crc(b"tail").to_le_bytes()[..], b"tail"].concat(),
+        );
+        let mut want = data.clone();
+        want.extend_from_slice(b"tail");
+        assert_eq!(read_strict(&snappy).unwrap(), want);
+
+        // Streams written by Writer are S2 streams
+        let mut s2 = Vec::new();
+        Writer::new(&mut s2).write_all(&data).unwrap();
+        assert!(Reader::new(&s2[..]).read_to_end(&mut Vec::new()).is_ok());
+        let err = read_strict(&s2).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid stream identifier"),
+            "{}",
+            err
+        );
+
+        // A Snappy identifier does not make S2 content acceptable
+        let repeats = encode(&data);
+        let mut stream = MAGIC_CHUNK_SNAPPY.to_vec();
+        push_block(&mut stream, &data, &repeats);
+        assert!(Reader::new(&stream[..])
+            .read_to_end(&mut Vec::new())
+            .is_ok());
+        assert!(read_strict(&stream).is_err());
+
+        let mut stream = MAGIC_CHUNK_SNAPPY.to_vec();
+        stream.extend_from_slice(MAGIC_CHUNK);
+        assert!(read_strict(&stream).is_err());
+
+        for chunk_type in [CHUNK_TYPE_INDEX, CHUNK_TYPE_LEVEL, CHUNK_TYPE_SIZE] {
+            let mut stream = MAGIC_CHUNK_SNAPPY.to_vec();
+            push_chunk(&mut stream, chunk_type, b"MzLvl\x01");
+            let err = read_strict(&stream).unwrap_err();
+            assert!(err.to_string().contains("S2-only chunk type"), "{}", err);
+        }
+
+        // Blocks are limited to 64KB
+        let big = vec![7u8; MAX_SNAPPY_BLOCK_SIZE + 1];
+        let mut stream = MAGIC_CHUNK_SNAPPY.to_vec();
+        push_block(&mut stream, &big, &encode_snappy(&big));
+        assert!(Reader::new(&stream[..])
+            .read_to_end(&mut Vec::new())
+            .is_ok());
+        assert!(read_strict(&stream).is_err());
+        let mut stream = MAGIC_CHUNK_SNAPPY.to_vec();
+        push_block(&mut stream, &big[1..], &encode_snappy(&big[1..]));
+        assert_eq!(read_strict(&stream).unwrap(), &big[1..]);
+    }
+
+    #[cfg(feature = "concurrent")]
+    #[test]
+    fn test_decode_range_concurrent() {
+        use std::io::Cursor;
+
+        // Compressible and incompressible stretches, so both chunk types
+        // are written
+        let mut seed = 5u32;
+        let data: Vec<u8> = (0..6_000_000u32)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if (i / 300_000) % 3 == 0 {
+                    (seed >> 16) as u8
+                } else {
+                    ((i / 13) % 251) as u8
+                }
+            })
+            .collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 256 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+        let at = compressed.len() - 10;
+        let index_len = u32::from_le_bytes(compressed[at..at + 4].try_into().unwrap()) as usize;
+        let mut index = Index::new();
+        index
+            .load(&compressed[compressed.len() - index_len..])
+            .unwrap();
+
+        let n = data.len();
+        for (start, len) in [
+            (3_141_592, 1_000_000),
+            (2_097_152, 262_144),
+            (1_500_000, 1),
+            (0, 100),
+            (0, n),
+            (n - 7, 7),
+            (n, 0),
+        ] {
+            for threads in [1, 4] {
+                let range = decode_range_concurrent(
+                    Cursor::new(&compressed),
+                    &index,
+                    start as u64,
+                    len as u64,
+                    threads,
+                )
+                .unwrap();
+                assert!(range == data[start..start + len], "{} {}", start, len);
+            }
+        }
+
+        for (start, len) in [(n as u64, 1), (n as u64 - 10, 11), (1, u64::MAX)] {
+            let err = decode_range_concurrent(Cursor::new(&compressed), &index, start, len, 2)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        // Corruption inside the range is reported
+        let mut corrupt = compressed.clone();
+        let (comp, uncomp) = index.find(3_141_592).unwrap();
+        corrupt[comp as usize + CHUNK_HEADER_SIZE + 100] ^= 0xff;
+        let err = decode_range_concurrent(Cursor::new(&corrupt), &index, uncomp as u64, 10, 2)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "concurrent")]
+    #[test]
+    fn test_reader_with_concurrency() {
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i * 31 % 253) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 2048);
+            writer.write_all(&data).unwrap();
+        }
+
+        let read = |stream: &[u8], concurrency: usize| {
+            let mut reader = Reader::with_concurrency(stream, concurrency);
+            let mut out = Vec::new();
+            let result = reader.read_to_end(&mut out).map_err(|e| e.to_string());
+            (out, result)
+        };
+
+        for concurrency in [1, 2, 4, 7, 64] {
+            let (out, result) = read(&compressed, concurrency);
+            assert!(result.is_ok());
+            assert_eq!(out, data);
+        }
+
+        // Corrupt the CRC of each chunk in turn: the parallel reader must
+        // return the same data and the same error as the serial one.
+        let mut pos = MAGIC_CHUNK.len();
+        while pos < compressed.len() {
+            let len = u32::from_le_bytes([
+                compressed[pos + 1],
+                compressed[pos + 2],
+                compressed[pos + 3],
+                0,
+            ]) as usize;
+            let mut corrupt = compressed.clone();
+            corrupt[pos + CHUNK_HEADER_SIZE] ^= 0xff;
+
+            let (want_out, want_err) = read(&corrupt, 1);
+            assert!(want_err.as_ref().unwrap_err().contains("CRC mismatch"));
+            for concurrency in [2, 4, 7] {
+                let (out, err) = read(&corrupt, concurrency);
+                assert_eq!(out, want_out);
+                assert_eq!(err, want_err);
+            }
+            pos += CHUNK_HEADER_SIZE + len;
+        }
+
+        // Errors found while reading ahead are also held back.
+        let (out, err) = {
+            let mut reader = Reader::with_concurrency(&compressed[..], 4);
+            reader.set_max_total(10_000);
+            let mut out = Vec::new();
+            let err = reader.read_to_end(&mut out).unwrap_err();
+            (out, err)
+        };
+        assert_eq!(out, &data[..8192]);
+        assert!(err.to_string().contains("exceeds limit"));
+    }
+
+    #[test]
+    fn test_reader_on_skippable_chunk() {
+        use std::sync::{Arc, Mutex};
+
+        let mut stream = Vec::new();
+        {
+            let mut writer = Writer::with_index(&mut stream);
+            writer.write_all(b"before").unwrap();
+            writer.write_skippable_chunk(0x90, b"app data").unwrap();
+            writer.write_all(b" after").unwrap();
+        }
+
+        // Without a callback the chunk is skipped
+        let mut out = Vec::new();
+        Reader::new(&stream[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"before after");
+
+        // The callback gets it, but not the index the reader reads itself,
+        // and before the data that follows it
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = Reader::new(&stream[..]);
+        let chunks = seen.clone();
+        reader.on_skippable_chunk(move |chunk_type, payload| {
+            chunks.lock().unwrap().push((chunk_type, payload.to_vec()))
+        });
+        let mut buf = [0u8; 6];
+        reader.read_exact(&mut buf).unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(*seen.lock().unwrap(), [(0x90, b"app data".to_vec())]);
+        assert_eq!(&buf, b" after");
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "concurrent")]
+    #[test]
+    fn test_reader_verify_concurrent_error() {
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i * 31 % 253) as u8).collect();
+        let mut compressed = Vec::new();
+        Writer::with_block_size_unclamped(&mut compressed, 2048)
+            .write_all(&data)
+            .unwrap();
+
+        // Corrupt the CRC of the third data chunk
+        let mut pos = MAGIC_CHUNK.len();
+        for _ in 0..2 {
+            pos += CHUNK_HEADER_SIZE
+                + u32::from_le_bytes([
+                    compressed[pos + 1],
+                    compressed[pos + 2],
+                    compressed[pos + 3],
+                    0,
+                ]) as usize;
+        }
+        compressed[pos + CHUNK_HEADER_SIZE] ^= 0xff;
+
+        // The first read decodes the first four chunks in parallel and
+        // holds back the error; verify must still report it
+        let mut reader = Reader::with_concurrency(&compressed[..], 4);
+        reader.read_exact(&mut [0u8; 1]).unwrap();
+        let err = reader.verify().unwrap_err();
+        assert!(err.to_string().contains("CRC mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn test_decode_stream_recoverable() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i / 5 % 241) as u8).collect();
+        let mut compressed = Vec::new();
+        Writer::with_block_size(&mut compressed, 64 * 1024)
+            .write_all(&data)
+            .unwrap();
+
+        let (out, err) = decode_stream_recoverable(&compressed[..]);
+        assert_eq!(out, data);
+        assert!(err.is_none());
+
+        // Corrupt the CRC of the third data chunk.
+        let mut pos = MAGIC_CHUNK.len();
+        for _ in 0..2 {
+            pos += CHUNK_HEADER_SIZE
+                + u32::from_le_bytes([
+                    compressed[pos + 1],
+                    compressed[pos + 2],
+                    compressed[pos + 3],
+                    0,
+                ]) as usize;
+        }
+        compressed[pos + CHUNK_HEADER_SIZE] ^= 1;
+        let (out, err) = decode_stream_recoverable(&compressed[..]);
+        assert_eq!(out, data[..128 * 1024]);
+        assert_eq!(err.unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // Truncated in the third chunk
+        let (out, err) = decode_stream_recoverable(&compressed[..pos + 100]);
+        assert_eq!(out, data[..128 * 1024]);
+        assert!(err.is_some());
+
+        // Not a stream at all
+        let (out, err) = decode_stream_recoverable(&b"plain text"[..]);
+        assert!(out.is_empty());
+        assert!(err.is_some());
+    }
+
+    #[test]
+    fn test_verify_stream() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 1024);
+            writer.enable_index();
+            writer.write_all(&data).unwrap();
+        }
+
+        let report = verify_stream(&compressed[..]).unwrap();
+        assert_eq!(report.decompressed_bytes, data.len() as u64);
+        assert_eq!(report.compressed_bytes, compressed.len() as u64);
+        assert_eq!(report.data_chunks, 10);
+
+        // Counts carry on from data already read.
+        let mut reader = Reader::new(&compressed[..]);
+        let mut head = [0u8; 1500];
+        reader.read_exact(&mut head).unwrap();
+        let report = reader.verify().unwrap();
+        assert_eq!(report.decompressed_bytes, data.len() as u64 - 1500);
+
+        // Corrupt the CRC of the third data chunk.
+        let mut pos = MAGIC_CHUNK.len();
+        for _ in 0..2 {
+            pos += CHUNK_HEADER_SIZE
+                + u32::from_le_bytes([
+                    compressed[pos + 1],
+                    compressed[pos + 2],
+                    compressed[pos + 3],
+                    0,
+                ]) as usize;
+        }
+        compressed[pos + CHUNK_HEADER_SIZE] ^= 1;
+        let err = verify_stream(&compressed[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("CRC mismatch"));
+    }
+
+    #[test]
+    fn test_reader_chunks() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 241) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut want = Vec::new();
+        Reader::new(&compressed[..]).read_to_end(&mut want).unwrap();
+
+        let chunks: Vec<Vec<u8>> = Reader::new(&compressed[..])
+            .chunks()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 10);
+        assert!(chunks[..9].iter().all(|c| c.len() == 1024));
+        assert_eq!(chunks.concat(), want);
+
+        // Data already buffered by read comes back first.
+        let mut reader = Reader::new(&compressed[..]);
+        let mut head = [0u8; 100];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(reader.read_block().unwrap().unwrap(), &data[100..1024]);
+        assert_eq!(reader.read_block().unwrap().unwrap(), &data[1024..2048]);
+
+        // Iteration stops at the first error.
+        let last = compressed.len() - 1;
+        compressed[last] ^= 0xff;
+        let results: Vec<_> = Reader::new(&compressed[..]).chunks().collect();
+        assert_eq!(results.len(), 10);
+        assert!(results[..9].iter().all(|r| r.is_ok()));
+        assert!(results[9].is_err());
+    }
+
+    #[test]
+    fn test_reader_buffers_one_block() {
+        use crate::Writer;
+        use std::io::Write;
+
+        // Alternate compressible and incompressible blocks, so compressed
+        // and uncompressed chunks interleave
+        let block_size = 16 * 1024;
+        let mut seed = 3u32;
+        let data: Vec<u8> = (0..10 * block_size as u32)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if (i / block_size as u32) % 2 == 0 {
+                    (i % 7) as u8
+                } else {
+                    (seed >> 16) as u8
+                }
+            })
+            .collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, block_size);
+            writer.write_all(&data).unwrap();
+        }
+        let mut chunk_types = Vec::new();
+        let mut at = MAGIC_CHUNK.len();
+        while at < compressed.len() {
+            chunk_types.push(compressed[at]);
+            at += CHUNK_HEADER_SIZE
+                + u32::from_le_bytes([
+                    compressed[at + 1],
+                    compressed[at + 2],
+                    compressed[at + 3],
+                    0,
+                ]) as usize;
+        }
+        assert_eq!(
+            chunk_types[..4],
+            [
+                CHUNK_TYPE_COMPRESSED_DATA,
+                CHUNK_TYPE_UNCOMPRESSED_DATA,
+                CHUNK_TYPE_COMPRESSED_DATA,
+                CHUNK_TYPE_UNCOMPRESSED_DATA
+            ]
+        );
+
+        // Read in odd-sized pieces that straddle block boundaries
+        let mut reader = Reader::new(&compressed[..]);
+        let mut decompressed = Vec::new();
+        let mut piece = [0u8; 3001];
+        loop {
+            let n = reader.read(&mut piece).unwrap();
+            if n == 0 {
+                break;
+            }
+            decompressed.extend_from_slice(&piece[..n]);
+            assert!(reader.buf.len() <= block_size, "{}", reader.buf.len());
+            assert!(reader.buf.capacity() <= block_size + 64);
+        }
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn test_reader_skip_block() {
+        use crate::Writer;
+        use std::io::Write;
+
+        // Compressible and incompressible blocks of 64KB
+        let mut data: Vec<u8> = (0..192 * 1024u32).map(|i| ((i / 9) % 97) as u8).collect();
+        let mut seed = 7u32;
+        data.extend((0..64 * 1024).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        }));
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, 64 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut reader = Reader::new(&compressed[..]);
+        assert_eq!(reader.skip_block().unwrap(), Some(64 * 1024));
+        assert_eq!(
+            reader.read_block().unwrap().unwrap(),
+            &data[64 * 1024..128 * 1024]
+        );
+        assert!(reader.last_block_crc().is_some());
+        assert_eq!(reader.skip_block().unwrap(), Some(64 * 1024));
+        assert_eq!(reader.last_block_crc(), None);
+        assert_eq!(reader.current_uncompressed_offset, 192 * 1024);
+
+        // Skips the uncompressed block, then ends
+        assert_eq!(reader.skip_block().unwrap(), Some(64 * 1024));
+        assert_eq!(reader.skip_block().unwrap(), None);
+        assert_eq!(reader.read_block().unwrap(), None);
+
+        // Leftover data from a partial read is skipped first
+        let mut reader = Reader::new(&compressed[..]);
+        let mut head = [0u8; 1000];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(reader.skip_block().unwrap(), Some(64 * 1024 - 1000));
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[64 * 1024..]);
+    }
+
+    #[test]
+    fn test_reader_last_block_crc() {
+        use crate::crc::crc;
+
+        let data: Vec<u8> = (0..5_000u32).map(|i| (i % 89) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 2048);
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut reader = Reader::new(&compressed[..]);
+        assert_eq!(reader.last_block_crc(), None);
+        for block in data.chunks(2048) {
+            assert_eq!(reader.read_block().unwrap().unwrap(), block);
+            assert_eq!(reader.last_block_crc(), Some(crc(block)));
+        }
+
+        // Reading through `read` tracks the block just decoded.
+        let mut reader = Reader::new(&compressed[..]);
+        let mut head = [0u8; 3000];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(reader.last_block_crc(), Some(crc(&data[2048..4096])));
+
+        reader.reset(&compressed[..]);
+        assert_eq!(reader.last_block_crc(), None);
+    }
+
+    #[test]
+    fn test_reader_on_block_decoded() {
+        use crate::crc::crc;
+        use std::sync::{Arc, Mutex};
+
+        // Compressible and incompressible blocks
+        let mut seed = 3u32;
+        let data: Vec<u8> = (0..10_000u32)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if (i / 2048) % 2 == 0 {
+                    (i % 89) as u8
+                } else {
+                    (seed >> 16) as u8
+                }
+            })
+            .collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 2048);
+            writer.write_all(&data).unwrap();
+        }
+        let want: Vec<(Vec<u8>, u32)> = data.chunks(2048).map(|b| (b.to_vec(), crc(b))).collect();
+
+        let record = |reader: &mut Reader<&[u8]>| {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let log = seen.clone();
+            reader.on_block_decoded(move |block, checksum| {
+                log.lock().unwrap().push((block.to_vec(), checksum))
+            });
+            seen
+        };
+
+        let mut reader = Reader::new(&compressed[..]);
+        let seen = record(&mut reader);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(*seen.lock().unwrap(), want);
+
+        let mut reader = Reader::new(&compressed[..]);
+        let seen = record(&mut reader);
+        while reader.read_block().unwrap().is_some() {}
+        assert_eq!(*seen.lock().unwrap(), want);
+
+        let mut reader = Reader::new(&compressed[..]);
+        let seen = record(&mut reader);
+        reader.verify().unwrap();
+        assert_eq!(*seen.lock().unwrap(), want);
+
+        // Skipped blocks are not decoded
+        let mut reader = Reader::new(&compressed[..]);
+        let seen = record(&mut reader);
+        reader.skip_block().unwrap();
+        reader.read_block().unwrap();
+        assert_eq!(*seen.lock().unwrap(), want[1..2]);
+
+        #[cfg(feature = "concurrent")]
+        {
+            let mut reader = Reader::with_concurrency(&compressed[..], 3);
+            let seen = record(&mut reader);
+            reader.read_to_end(&mut Vec::new()).unwrap();
+            assert_eq!(*seen.lock().unwrap(), want);
+        }
+    }
+
+    #[test]
+    fn test_reader_total_size_hint() {
+        use std::io::Cursor;
+
+        let data: Vec<u8> = (0..30_000u32).map(|i| (i % 61) as u8).collect();
+        for (len, index, eof_marker) in [
+            (0, false, false),
+            (1, false, false),
+            (30_000, false, false),
+            (30_000, true, false),
+            (30_000, false, true),
+            (30_000, true, true),
+        ] {
+            let mut compressed = Vec::new();
+            {
+                let mut writer = Writer::with_size_header(&mut compressed);
+                if index {
+                    writer.enable_index();
+                }
+                writer.write_all(&data[..len]).unwrap();
+                if eof_marker {
+                    writer.finish_with_eof_marker().unwrap();
+                }
+            }
+
+            let mut reader = Reader::new(Cursor::new(&compressed));
+            let mut head = vec![0u8; len.min(100)];
+            reader.read_exact(&mut head).unwrap();
+            assert_eq!(reader.total_size_hint().unwrap(), Some(len as u64));
+
+            // The read position is unaffected.
+            let mut out = head;
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, &data[..len]);
+        }
+
+        // Streams without the chunk
+        let mut plain = Vec::new();
+        Writer::with_index(&mut plain).write_all(&data).unwrap();
+        for stream in [&plain[..], &[][..], MAGIC_CHUNK] {
+            let mut reader = Reader::new(Cursor::new(stream));
+            assert_eq!(reader.total_size_hint().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_stream_decoded_len() {
+        // Mixed compressible / incompressible data so both chunk types occur.
+        let mut data = vec![b'A'; 50_000];
+        data.extend((0..50_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8));
+        data.extend_from_slice(&[b'B'; 12_345]);
+
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 8 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut decompressed = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        let len = stream_decoded_len(&compressed[..]).unwrap();
+        assert_eq!(len, decompressed.len() as u64);
+        assert_eq!(len, data.len() as u64);
+    }
+
+    #[test]
+    fn test_stream_decoded_len_truncated() {
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::new(&mut compressed);
+            writer.write_all(&[b'A'; 10_000]).unwrap();
+        }
+        compressed.truncate(compressed.len() - 1);
+
+        let err = stream_decoded_len(&compressed[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
 }
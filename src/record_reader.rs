@@ -0,0 +1,168 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Stream reader that hands out decompressed data in fixed-size records
+
+use std::io::{self, Read};
+
+use crate::reader::Reader;
+
+/// RecordReader decompresses a stream and returns it in records of a fixed
+/// size
+///
+/// Records are cut from the decompressed data, independently of the
+/// stream's block boundaries. A stream whose length is not a multiple of
+/// the record size ends in a partial record: instead of returning it as a
+/// short record, [`next_record`](RecordReader::next_record) fails with
+/// `ErrorKind::UnexpectedEof`, and the bytes are then available from
+/// [`partial_record`](RecordReader::partial_record).
+///
+/// # Example
+///
+/// ```
+/// use minlz::{RecordReader, Writer};
+/// use std::io::Write;
+///
+/// let mut compressed = Vec::new();
+/// Writer::new(&mut compressed).write_all(b"aaaabbbbcccc").unwrap();
+///
+/// let mut reader = RecordReader::new(&compressed[..], 4);
+/// while let Some(record) = reader.next_record().unwrap() {
+///     assert_eq!(record.len(), 4);
+/// }
+/// ```
+pub struct RecordReader<R: Read> {
+    reader: Reader<R>,
+    record_size: usize,
+    partial: Vec<u8>,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Create a new RecordReader decompressing `reader` with default
+    /// [`Reader`] settings
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record_size` is 0.
+    pub fn new(reader: R, record_size: usize) -> Self {
+        Self::with_reader(Reader::new(reader), record_size)
+    }
+
+    /// Create a new RecordReader over an already configured [`Reader`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record_size` is 0.
+    pub fn with_reader(reader: Reader<R>, record_size: usize) -> Self {
+        assert!(record_size > 0, "record size must be at least 1");
+        RecordReader {
+            reader,
+            record_size,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Size of the records returned by `next_record`
+    pub fn record_size(&self) -> usize {
+        self.record_size
+    }
+
+    /// Read the next record
+    ///
+    /// Returns `None` at the end of the stream. If the stream ends inside
+    /// a record, returns an `UnexpectedEof` error; the bytes read for that
+    /// record are kept in [`partial_record`](RecordReader::partial_record).
+    pub fn next_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut record = vec![0u8; self.record_size];
+        let mut n = 0;
+        while n < record.len() {
+            match self.reader.read(&mut record[n..]) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if n == record.len() {
+            return Ok(Some(record));
+        }
+        if n == 0 {
+            return Ok(None);
+        }
+        record.truncate(n);
+        self.partial = record;
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "stream ends inside a record ({} of {} bytes)",
+                n, self.record_size
+            ),
+        ))
+    }
+
+    /// Bytes of the final, incomplete record
+    ///
+    /// Empty unless [`next_record`](RecordReader::next_record) has reported
+    /// the stream ending inside a record.
+    pub fn partial_record(&self) -> &[u8] {
+        &self.partial
+    }
+
+    /// Get a reference to the underlying [`Reader`]
+    pub fn get_ref(&self) -> &Reader<R> {
+        &self.reader
+    }
+
+    /// Unwrap the underlying [`Reader`]
+    pub fn into_inner(self) -> Reader<R> {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Writer;
+    use std::io::Write;
+
+    #[test]
+    fn test_record_reader() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 233) as u8).collect();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, 64 * 1024);
+            writer.write_all(&data).unwrap();
+        }
+
+        // 100 does not divide the block size, so records straddle blocks
+        let mut reader = RecordReader::new(&compressed[..], 100);
+        let mut records = 0;
+        while let Some(record) = reader.next_record().unwrap() {
+            assert_eq!(record, &data[records * 100..(records + 1) * 100]);
+            records += 1;
+        }
+        assert_eq!(records, 2000);
+        assert!(reader.partial_record().is_empty());
+        assert!(reader.next_record().unwrap().is_none());
+
+        // 300 leaves a partial record of 200 bytes
+        let mut reader = RecordReader::new(&compressed[..], 300);
+        let mut records = 0;
+        let err = loop {
+            match reader.next_record() {
+                Ok(Some(record)) => {
+                    assert_eq!(record.len(), 300);
+                    records += 1;
+                }
+                Ok(None) => panic!("partial record not reported"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(records, 666);
+        assert_eq!(reader.partial_record(), &data[199_800..]);
+    }
+}
@@ -1,7 +1,7 @@
 // Copyright 2024 Karpeles Lab Inc.
 // Tests for Snappy encoding compatibility
 
-use crate::{decode, encode_snappy};
+use crate::{analyze_block, decode, encode_snappy};
 
 #[test]
 fn test_encode_snappy_roundtrip() {
@@ -84,3 +84,96 @@ fn test_encode_snappy_mixed() {
     let decoded = decode(&encoded).expect("decode failed");
     assert_eq!(decoded, data);
 }
+
+/// Bytes produced by S2 repeat codes in a block; Snappy decoders reject
+/// them.
+fn repeat_bytes(block: &[u8]) -> u64 {
+    analyze_block(block).unwrap().repeat_bytes
+}
+
+#[test]
+fn test_encode_snappy_never_emits_repeats() {
+    // One size per table configuration, with long matches that S2 would
+    // encode as copy + repeat.
+    for len in [300, 3000, 12_000, 100_000] {
+        let data: Vec<u8> = (0..len)
+            .map(|i| if i % 500 < 200 { b'x' } else { (i % 13) as u8 })
+            .collect();
+        let encoded = encode_snappy(&data);
+        assert_eq!(repeat_bytes(&encoded), 0, "len {}", len);
+        assert_eq!(decode(&encoded).unwrap(), data);
+        assert!(encoded.len() < data.len() / 4);
+        // The same input does use repeats in S2 mode.
+        assert!(repeat_bytes(&crate::encode(&data)) > 0, "len {}", len);
+    }
+}
+
+#[test]
+fn test_encode_snappy_matches_go() {
+    // testdata/*.rawsnappy was produced by Go's snappy.Encode.
+    let src = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+    let want = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt.rawsnappy").unwrap();
+    assert_eq!(encode_snappy(&src), want);
+}
+
+#[test]
+fn test_encode_snappy_independent_fragments() {
+    // Every 64KB fragment is compressed on its own, so a repeated
+    // fragment encodes exactly as it does alone.
+    let mut state = 7u32;
+    let fragment: Vec<u8> = (0..65536)
+        .map(|i| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            if i % 3 == 0 {
+                (state >> 24) as u8
+            } else {
+                b'a' + (i % 17) as u8
+            }
+        })
+        .collect();
+    let single = encode_snappy(&fragment);
+    let double = encode_snappy(&[fragment.clone(), fragment.clone()].concat());
+    // Length headers: varint(65536) and varint(131072), both 3 bytes.
+    assert_eq!(&single[..3], &[0x80, 0x80, 0x04]);
+    assert_eq!(&double[..3], &[0x80, 0x80, 0x08]);
+    assert_eq!(double[3..], [&single[3..], &single[3..]].concat());
+    assert_eq!(decode(&double).unwrap().len(), 2 * fragment.len());
+}
+
+#[test]
+fn test_encode_snappy_better() {
+    use crate::{decode_snappy, encode_snappy_better};
+
+    let twain = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+    let fast = encode_snappy(&twain);
+    let better = encode_snappy_better(&twain);
+    assert_eq!(decode_snappy(&better).unwrap(), twain);
+    assert_eq!(repeat_bytes(&better), 0);
+    assert!(
+        better.len() < fast.len(),
+        "better {} >= fast {}",
+        better.len(),
+        fast.len()
+    );
+
+    // Sizes around the literal cutoff and the fragment size, runs that S2
+    // would encode as repeats, and incompressible data
+    let mut state = 1u32;
+    let random: Vec<u8> = (0..200_000)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 24) as u8
+        })
+        .collect();
+    for len in [0, 1, 31, 32, 33, 300, 65535, 65536, 65537, 200_000] {
+        let runs: Vec<u8> = (0..len)
+            .map(|i| if i % 500 < 200 { b'x' } else { (i % 13) as u8 })
+            .collect();
+        for data in [runs, random[..len].to_vec()] {
+            let encoded = encode_snappy_better(&data);
+            assert_eq!(repeat_bytes(&encoded), 0, "len {}", len);
+            assert_eq!(decode_snappy(&encoded).unwrap(), data, "len {}", len);
+            assert!(encoded.len() <= crate::max_encoded_len(len).unwrap());
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Stream writer that rotates output across multiple S2 streams
+
+use std::io::{self, Write};
+
+use crate::constants::*;
+use crate::writer::Writer;
+
+/// SplitWriter compresses data into a sequence of independent S2 streams
+///
+/// Output goes to writers obtained from a factory closure. Whenever the
+/// current output reaches `max_file_size` compressed bytes, its stream is
+/// finalized (index and trailer written, underlying writer flushed) and the
+/// next write opens a new one. Each output is a complete stream with its own
+/// magic header that decodes independently of the others.
+///
+/// Rotation only happens at block boundaries, so no block is split across
+/// outputs; an output can therefore exceed `max_file_size` by up to one
+/// compressed block (plus its index, if enabled). A new output is only
+/// requested when there is data to write into it. Finalized outputs are
+/// dropped, which closes them if they are files.
+///
+/// # Example
+///
+/// ```no_run
+/// use minlz::SplitWriter;
+/// use std::fs::File;
+/// use std::io::Write;
+///
+/// let mut n = 0;
+/// let mut writer = SplitWriter::new(
+///     || {
+///         n += 1;
+///         File::create(format!("app.log.{}.s2", n))
+///     },
+///     64 << 20, // rotate at 64MB
+/// );
+/// writer.enable_index();
+/// writer.write_all(b"log line\n").unwrap();
+/// writer.finish().unwrap();
+/// ```
+pub struct SplitWriter<W: Write, F: FnMut() -> io::Result<W>> {
+    factory: F,
+    current: Option<Writer<W>>,
+    max_file_size: u64,
+    block_size: usize,
+    index: bool,
+    opened: usize,
+}
+
+impl<W: Write, F: FnMut() -> io::Result<W>> SplitWriter<W, F> {
+    /// Create a new SplitWriter with the default block size (1MB)
+    ///
+    /// `factory` is called each time a new output is needed.
+    pub fn new(factory: F, max_file_size: u64) -> Self {
+        Self::with_block_size(factory, max_file_size, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a new SplitWriter with a specific block size
+    ///
+    /// Block size is clamped to 4KB..=4MB, as for [`Writer::with_block_size`].
+    ///
+    /// # Panics
+    /// Panics if max_file_size is 0
+    pub fn with_block_size(factory: F, max_file_size: u64, block_size: usize) -> Self {
+        assert!(max_file_size > 0, "max_file_size must be > 0");
+        SplitWriter {
+            factory,
+            current: None,
+            max_file_size,
+            block_size: block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE),
+            index: false,
+            opened: 0,
+        }
+    }
+
+    /// Append a seek index to every output stream
+    ///
+    /// Affects outputs opened after this call.
+    pub fn enable_index(&mut self) {
+        self.index = true;
+    }
+
+    /// Number of outputs opened so far (including the current one)
+    pub fn files_opened(&self) -> usize {
+        self.opened
+    }
+
+    /// Finalize the current output, reporting any error
+    ///
+    /// Dropping the SplitWriter does the same but ignores errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.rotate()
+    }
+
+    /// Get the writer for the current output, opening one if needed
+    fn current(&mut self) -> io::Result<&mut Writer<W>> {
+        if self.current.is_none() {
+            let inner = (self.factory)()?;
+            let mut writer = Writer::with_block_size(inner, self.block_size);
+            if self.index {
+                writer.enable_index();
+            }
+            self.current = Some(writer);
+            self.opened += 1;
+        }
+        Ok(self.current.as_mut().expect("current output just opened"))
+    }
+
+    /// Finalize the current output, if any
+    fn rotate(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(mut writer) => writer.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write, F: FnMut() -> io::Result<W>> Write for SplitWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        while written < buf.len() {
+            let max_file_size = self.max_file_size;
+            let writer = self.current()?;
+
+            // Never hand the writer more than fits in its current block, so
+            // a full block is flushed (and the size checked) before the next.
+            let space = writer.block_size() - writer.buffered();
+            let to_write = (buf.len() - written).min(space);
+            writer.write_all(&buf[written..written + to_write])?;
+            written += to_write;
+
+            if writer.buffered() == writer.block_size() {
+                writer.flush_block()?;
+                if writer.compressed_written() >= max_file_size {
+                    self.rotate()?;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Flushes the current output as a (possibly short) block; this may
+    /// trigger a rotation if it brings the output to `max_file_size`.
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.current.as_mut() {
+            writer.flush()?;
+            if writer.compressed_written() >= self.max_file_size {
+                self.rotate()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, F: FnMut() -> io::Result<W>> Drop for SplitWriter<W, F> {
+    fn drop(&mut self) {
+        let _ = self.rotate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reader;
+    use std::cell::RefCell;
+    use std::io::Read;
+    use std::rc::Rc;
+
+    /// A `Write` sink into one slot of a shared list of files, readable
+    /// after the SplitWriter drops it.
+    struct SharedSink {
+        files: Rc<RefCell<Vec<Vec<u8>>>>,
+        idx: usize,
+    }
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.files.borrow_mut()[self.idx].extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_split_writer_three_files() {
+        // Incompressible data: each 4KB block is stored as ~4KB, so three
+        // blocks push a file past 10KB.
+        let mut x: u32 = 0x12345678;
+        let data: Vec<u8> = (0..9 * 4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+
+        let files: Rc<RefCell<Vec<Vec<u8>>>> = Rc::default();
+        {
+            let files = files.clone();
+            let mut writer = SplitWriter::with_block_size(
+                move || {
+                    files.borrow_mut().push(Vec::new());
+                    let idx = files.borrow().len() - 1;
+                    Ok(SharedSink {
+                        files: files.clone(),
+                        idx,
+                    })
+                },
+                10_000,
+                4096,
+            );
+            writer.enable_index();
+            writer.write_all(&data).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let files = files.borrow();
+        assert_eq!(files.len(), 3);
+
+        let mut joined = Vec::new();
+        for file in files.iter() {
+            assert_eq!(&file[..MAGIC_CHUNK.len()], MAGIC_CHUNK);
+
+            let mut part = Vec::new();
+            Reader::new(&file[..]).read_to_end(&mut part).unwrap();
+            assert_eq!(part.len(), 3 * 4096);
+            joined.extend_from_slice(&part);
+        }
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn test_split_writer_no_empty_trailing_file() {
+        let data = vec![b'A'; 5 * 4096];
+        let mut writer = SplitWriter::with_block_size(|| Ok(Vec::new()), 1, 4096);
+        writer.write_all(&data).unwrap();
+
+        // Every block reaches the 1-byte limit, so each one rotates; the
+        // output after the last block is never opened.
+        assert_eq!(writer.files_opened(), 5);
+        writer.finish().unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Based on the S2 compression format by Klaus Post
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Stream writer that sends one compressed stream to two outputs
+
+use std::io::{self, Write};
+
+use crate::constants::*;
+use crate::writer::Writer;
+
+/// Write sink that duplicates everything into two writers
+struct Tee<A: Write, B: Write> {
+    a: A,
+    b: B,
+}
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Both outputs must get the same bytes, so never report a short write
+        self.a.write_all(buf)?;
+        self.b.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.a.flush()?;
+        self.b.flush()
+    }
+}
+
+/// TeeWriter compresses data once and writes the stream to two outputs
+///
+/// For sending the same data to a primary and a backup destination: each
+/// block is encoded once and the framed bytes are written to both outputs,
+/// which therefore hold byte-identical streams. A write error from either
+/// output is returned, and the outputs may then differ.
+///
+/// # Example
+///
+/// ```
+/// use minlz::TeeWriter;
+/// use std::io::Write;
+///
+/// let mut primary = Vec::new();
+/// let mut backup = Vec::new();
+/// {
+///     let mut writer = TeeWriter::new(&mut primary, &mut backup);
+///     writer.write_all(b"Hello, World!").unwrap();
+///     writer.finish().unwrap();
+/// }
+/// assert_eq!(primary, backup);
+/// ```
+pub struct TeeWriter<A: Write, B: Write> {
+    writer: Writer<Tee<A, B>>,
+}
+
+impl<A: Write, B: Write> TeeWriter<A, B> {
+    /// Create a new TeeWriter with the default block size (1MB)
+    pub fn new(a: A, b: B) -> Self {
+        Self::with_block_size(a, b, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a new TeeWriter with a specific block size
+    ///
+    /// Block size is clamped to 4KB..=4MB, as for [`Writer::with_block_size`].
+    pub fn with_block_size(a: A, b: B, block_size: usize) -> Self {
+        TeeWriter {
+            writer: Writer::with_block_size(Tee { a, b }, block_size),
+        }
+    }
+
+    /// Append a seek index to both streams
+    pub fn enable_index(&mut self) {
+        self.writer.enable_index();
+    }
+
+    /// Finish both streams, reporting any error
+    ///
+    /// Dropping the TeeWriter does the same but ignores errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.close()
+    }
+
+    /// Get references to the two underlying writers
+    pub fn get_ref(&self) -> (&A, &B) {
+        let tee = self.writer.get_ref();
+        (&tee.a, &tee.b)
+    }
+}
+
+impl<A: Write, B: Write> Write for TeeWriter<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reader;
+    use std::io::Read;
+
+    #[test]
+    fn test_tee_writer() {
+        let data: Vec<u8> = (0..500_000u32)
+            .map(|i| ((i / 9) % 163) as u8 ^ (i >> 14) as u8)
+            .collect();
+
+        let mut primary = Vec::new();
+        let mut backup = Vec::new();
+        {
+            let mut writer = TeeWriter::with_block_size(&mut primary, &mut backup, 64 * 1024);
+            writer.enable_index();
+            writer.write_all(&data[..1000]).unwrap();
+            writer.flush().unwrap();
+            let (a, b) = writer.get_ref();
+            assert!(!a.is_empty());
+            assert_eq!(a, b);
+            writer.write_all(&data[1000..]).unwrap();
+            writer.finish().unwrap();
+        }
+        assert_eq!(primary, backup);
+
+        let mut single = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut single, 64 * 1024);
+            writer.enable_index();
+            writer.write_all(&data[..1000]).unwrap();
+            writer.flush().unwrap();
+            writer.write_all(&data[1000..]).unwrap();
+        }
+        assert_eq!(primary, single);
+
+        for stream in [&primary, &backup] {
+            let mut out = Vec::new();
+            Reader::new(&stream[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+    }
+}
@@ -211,6 +211,201 @@ fn test_large_offsets() {
     roundtrip(&data).unwrap();
 }
 
+#[test]
+fn test_encode_guaranteed() {
+    use crate::{decode_guaranteed, encode_guaranteed, Error};
+
+    let mut seed = 7u32;
+    for len in [0, 1, 31, 32, 100, 4096, 200_000] {
+        let random: Vec<u8> = (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let out = encode_guaranteed(&random);
+        assert!(out.len() <= random.len() + 1, "len {}", len);
+        assert_eq!(decode_guaranteed(&out).unwrap(), random);
+    }
+
+    let text = b"compressible text, compressible text, compressible text".repeat(100);
+    let out = encode_guaranteed(&text);
+    assert_eq!(out[0], 0);
+    assert_eq!(out[1..], encode(&text));
+    assert_eq!(decode_guaranteed(&out).unwrap(), text);
+
+    assert_eq!(decode_guaranteed(&[]), Err(Error::Corrupt));
+    assert_eq!(decode_guaranteed(&[2, 0]), Err(Error::Corrupt));
+    assert_eq!(decode_guaranteed(&[0, 0x05, 0x10]), Err(Error::Corrupt));
+}
+
+#[test]
+fn test_analyze_block() {
+    use crate::{analyze_block, encode_snappy, Error};
+
+    // A run: one literal, one copy at offset 1, then long repeats
+    let data = vec![b'a'; 1 << 20];
+    for compressed in [encode(&data), encode_better(&data), encode_best(&data)] {
+        let a = analyze_block(&compressed).unwrap();
+        assert_eq!(a.decoded_len, data.len());
+        assert_eq!(
+            a.literal_bytes + a.copy_bytes + a.repeat_bytes,
+            data.len() as u64
+        );
+        assert!(a.repeat_bytes > data.len() as u64 * 99 / 100);
+        assert!(a.repeat_lengths[17..].iter().sum::<u64>() > 0);
+        assert_eq!(a.copy_offsets[2..].iter().sum::<u64>(), 0);
+    }
+
+    // Snappy blocks have no repeats; a far match needs a COPY4 offset
+    let mut data = b"unique_pattern_12345".to_vec();
+    data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+    data.extend_from_slice(b"unique_pattern_12345");
+    let a = analyze_block(&encode_snappy(&data)).unwrap();
+    assert!(a.repeat_lengths.is_empty());
+    assert_eq!(a.literal_bytes + a.copy_bytes, data.len() as u64);
+    let a = analyze_block(&encode(&data)).unwrap();
+    assert_eq!(a.copy_offsets.len(), 18);
+    assert_eq!(a.copy_offsets[17], 1);
+
+    // Same validation as decode
+    for bad in [
+        &b"\x05\x10hell"[..],
+        b"\x05\x00a\x01\x02",
+        b"\x05\x01\x00",
+        b"\x05\x00a\x09\x01",
+    ] {
+        assert_eq!(analyze_block(bad), Err(Error::Corrupt), "{:?}", bad);
+    }
+}
+
+#[test]
+fn test_decode_max_offset() {
+    use crate::decode_max_offset;
+
+    // The second pattern can only be copied from 70020 bytes back
+    let mut data = Vec::new();
+    data.extend(b"unique_pattern_12345");
+    data.extend(vec![b'x'; 70000]);
+    data.extend(b"unique_pattern_12345");
+
+    for compressed in [encode(&data), encode_better(&data), encode_best(&data)] {
+        assert_eq!(
+            decode_max_offset(&compressed, 65535),
+            Err(crate::Error::Unsupported)
+        );
+        assert_eq!(
+            decode_max_offset(&compressed, u32::MAX as usize).unwrap(),
+            data
+        );
+    }
+
+    // Short copies pass a small cap; a cap of 0 allows only literals
+    let near = b"abcabcabcabcabcabcabcabc".repeat(4);
+    let compressed = encode(&near);
+    assert_eq!(decode_max_offset(&compressed, 3).unwrap(), near);
+    assert_eq!(
+        decode_max_offset(&compressed, 0),
+        Err(crate::Error::Unsupported)
+    );
+    let literal = encode(b"xyz");
+    assert_eq!(decode_max_offset(&literal, 0).unwrap(), b"xyz");
+}
+
+/// Sizes around the smallest block the encoders look for matches in, where
+/// `src.len() - INPUT_MARGIN` is computed
+#[test]
+fn test_encode_small_sizes() {
+    use crate::constants::{INPUT_MARGIN, MIN_NON_LITERAL_BLOCK_SIZE};
+    use crate::{decode_with_dict, encode_snappy, encode_with_dict, make_dict};
+
+    let dict = make_dict(b"abcdefghabcdefghabcdefgh", None).unwrap();
+    for len in 0..=MIN_NON_LITERAL_BLOCK_SIZE + INPUT_MARGIN + 16 {
+        let noise: Vec<u8> = (0..len).map(|i| (i * 149 % 256) as u8).collect();
+        let runs = b"abcdefgh".repeat(len / 8 + 1)[..len].to_vec();
+        for src in [vec![0u8; len], noise, runs] {
+            for enc in [
+                encode(&src),
+                encode_better(&src),
+                encode_best(&src),
+                encode_snappy(&src),
+            ] {
+                assert_eq!(decode(&enc).unwrap(), src, "len {}", len);
+            }
+            let enc = encode_with_dict(&src, &dict);
+            assert_eq!(decode_with_dict(&enc, &dict).unwrap(), src, "len {}", len);
+        }
+    }
+}
+
+/// The smallest blocks the encoders search, where the first 8-byte load
+/// and the tail of the match extension sit closest to the end
+#[test]
+fn test_encode_min_block_boundary() {
+    use crate::constants::MIN_NON_LITERAL_BLOCK_SIZE;
+    use crate::encode::test_helpers::test_encode_block_with_table;
+    use crate::varint::encode_varint;
+
+    for len in [MIN_NON_LITERAL_BLOCK_SIZE, MIN_NON_LITERAL_BLOCK_SIZE + 1] {
+        let distinct: Vec<u8> = (0..len as u8).collect();
+        let same = vec![b'z'; len];
+        for src in [distinct, same] {
+            for enc in [encode(&src), encode_better(&src), encode_best(&src)] {
+                assert_eq!(decode(&enc).unwrap(), src, "len {}", len);
+            }
+
+            let mut dst = vec![0u8; max_encoded_len(len).unwrap()];
+            let n = encode_varint(&mut dst, len as u64);
+            let body = test_encode_block_with_table(&mut dst[n..], &src, &mut [0; 256]);
+            if src[0] == src[1] {
+                assert!(body > 0, "len {}", len);
+                dst.truncate(n + body);
+                assert_eq!(decode(&dst).unwrap(), src, "len {}", len);
+            } else {
+                assert_eq!(body, 0, "len {}", len);
+            }
+        }
+    }
+}
+
+/// Sizes around the literal cutoff and each table-size switch of the
+/// encoders (see `MIN_NON_LITERAL_BLOCK_SIZE`)
+#[test]
+fn test_encode_level_size_thresholds() {
+    use crate::constants::MIN_NON_LITERAL_BLOCK_SIZE;
+    use crate::{decode_with_dict, encode_with_dict, make_dict};
+
+    let dict = make_dict(&b"threshold ".repeat(8), None).unwrap();
+    // Compressible from the first bytes, so only the cutoff stops matching
+    let text = b"size tiers ".repeat(7000);
+    for at in [MIN_NON_LITERAL_BLOCK_SIZE, 512, 4096, 16384, 65536] {
+        for len in at - 1..=at + 1 {
+            let src = &text[..len];
+            let encoded = [
+                encode(src),
+                encode_better(src),
+                encode_best(src),
+                encode_with_dict(src, &dict),
+            ];
+            for (level, enc) in encoded.iter().enumerate() {
+                if level == 3 {
+                    assert_eq!(decode_with_dict(enc, &dict).unwrap(), src, "len {}", len);
+                } else {
+                    assert_eq!(decode(enc).unwrap(), src, "level {} len {}", level, len);
+                }
+                if len < MIN_NON_LITERAL_BLOCK_SIZE {
+                    // Varint length, literal tag, then the input
+                    let mut literal = vec![len as u8, ((len - 1) << 2) as u8];
+                    literal.extend_from_slice(src);
+                    assert_eq!(*enc, literal, "level {} len {}", level, len);
+                } else {
+                    assert!(enc.len() < len, "level {} len {}", level, len);
+                }
+            }
+        }
+    }
+}
+
 #[test]
 fn test_boundary_conditions() {
     // Test various boundary conditions
@@ -233,6 +428,117 @@ fn test_repeating_patterns() {
     }
 }
 
+/// Every length form of the repeat-offset copy, and a repeat of a COPY4
+/// offset beyond 64KB
+#[test]
+fn test_decode_repeat_length_forms() {
+    let block = |len: usize, tokens: &[u8]| {
+        let mut b = Vec::new();
+        let mut n = len;
+        while n >= 0x80 {
+            b.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        b.push(n as u8);
+        b.extend_from_slice(tokens);
+        b
+    };
+
+    // "abcd", a copy at offset 4, then a repeat of `len` bytes
+    for (len, repeat) in [
+        (4, vec![0x01, 0x00]),
+        (8, vec![0x11, 0x00]),
+        (8, vec![0x15, 0x00, 0x00]),
+        (263, vec![0x15, 0x00, 0xff]),
+        (260, vec![0x19, 0x00, 0x00, 0x00]),
+        (65795, vec![0x19, 0x00, 0xff, 0xff]),
+        (65540, vec![0x1d, 0x00, 0x00, 0x00, 0x00]),
+        (200_000, {
+            let n = (200_000u32 - 65540).to_le_bytes();
+            vec![0x1d, 0x00, n[0], n[1], n[2]]
+        }),
+    ] {
+        let mut tokens = vec![0x0c, b'a', b'b', b'c', b'd', 0x01, 0x04];
+        tokens.extend_from_slice(&repeat);
+        let want: Vec<u8> = b"abcd".iter().cycle().take(8 + len).copied().collect();
+        assert_eq!(
+            decode(&block(want.len(), &tokens)).unwrap(),
+            want,
+            "{}",
+            len
+        );
+    }
+
+    // "pq", 70000 dots, a COPY4 of "pq" and the dot after it (offset
+    // 70002), then a repeat of that offset
+    let mut tokens = vec![0x04, b'p', b'q', 0xf8]; // literal, 3-byte length
+    tokens.extend_from_slice(&(70000u32 - 1).to_le_bytes()[..3]);
+    tokens.extend(std::iter::repeat(b'.').take(70000));
+    tokens.push(0x0b); // COPY4, length 3
+    tokens.extend_from_slice(&70002u32.to_le_bytes());
+    tokens.extend_from_slice(&[0x05, 0x00]); // repeat, length 5
+    let mut want = b"pq".to_vec();
+    want.extend(std::iter::repeat(b'.').take(70000));
+    want.extend_from_slice(b"pq.");
+    let start = want.len() - 70002;
+    want.extend_from_within(start..start + 5);
+    assert_eq!(decode(&block(want.len(), &tokens)).unwrap(), want);
+}
+
+/// emit_repeat output for every length form, including the split of
+/// over-long repeats, decodes back to the repeated bytes
+#[test]
+fn test_emit_repeat_roundtrip() {
+    use crate::encode::test_helpers::{test_emit_copy, test_emit_literal, test_emit_repeat};
+    use crate::varint::encode_varint;
+
+    let mut lengths: Vec<usize> = (4..=20).collect();
+    lengths.extend([200, 262, 263, 264, 265, 300]);
+    lengths.extend(65790..=65800);
+    lengths.extend([65536, 65540, 200_000]);
+    if cfg!(target_pointer_width = "64") {
+        lengths.push((1 << 24) + (1 << 16) + 100);
+    }
+
+    let mut forms = [false; 8];
+    let mut with_offset = false;
+    for offset in [4, 1000, 3000] {
+        // `offset` literal bytes, a copy of 4 at `offset`, then the repeat
+        let lit: Vec<u8> = (0..offset).map(|i| (i * 7 + i / 251) as u8).collect();
+        for &length in &lengths {
+            let mut tokens = vec![0u8; offset + 64];
+            let mut d = test_emit_literal(&mut tokens, &lit);
+            d += test_emit_copy(&mut tokens[d..], offset, 4);
+            let r = d;
+            d += test_emit_repeat(&mut tokens[d..], offset, length);
+            tokens.truncate(d);
+            if tokens[r + 1] == 0 {
+                forms[(tokens[r] >> 2 & 7) as usize] = true;
+            } else {
+                // Short form carrying the offset
+                with_offset = true;
+            }
+
+            let mut want = lit.clone();
+            for i in 0..4 + length {
+                want.push(want[i]);
+            }
+            let mut block = vec![0u8; 10];
+            let n = encode_varint(&mut block, want.len() as u64);
+            block.truncate(n);
+            block.extend_from_slice(&tokens);
+            assert!(
+                decode(&block).unwrap() == want,
+                "offset={}, length={}",
+                offset,
+                length
+            );
+        }
+    }
+    assert_eq!(forms, [true; 8]);
+    assert!(with_offset);
+}
+
 // Critical tests ported from Go implementation
 
 #[test]
@@ -715,6 +1021,24 @@ fn test_emit_copy() {
             80000,
             vec![0xff, 0x00, 0x20, 0x03, 0x00, 0x1d, 0x00, 0x3c, 0x38, 0x00],
         ),
+        // offset=2047/2049 cases: tagCopy1 only holds 11 offset bits, so
+        // 2047 is the largest offset it can encode and 2049 must use tagCopy2
+        (2047, 4, vec![0xe1, 0xff]),
+        (2047, 5, vec![0xe5, 0xff]),
+        (2047, 6, vec![0xe9, 0xff]),
+        (2047, 7, vec![0xed, 0xff]),
+        (2047, 8, vec![0xf1, 0xff]),
+        (2047, 9, vec![0xf5, 0xff]),
+        (2047, 10, vec![0xf9, 0xff]),
+        (2047, 11, vec![0xfd, 0xff]),
+        (2049, 4, vec![0x0e, 0x01, 0x08]),
+        (2049, 5, vec![0x12, 0x01, 0x08]),
+        (2049, 6, vec![0x16, 0x01, 0x08]),
+        (2049, 7, vec![0x1a, 0x01, 0x08]),
+        (2049, 8, vec![0x1e, 0x01, 0x08]),
+        (2049, 9, vec![0x22, 0x01, 0x08]),
+        (2049, 10, vec![0x26, 0x01, 0x08]),
+        (2049, 11, vec![0x2a, 0x01, 0x08]),
     ];
 
     let mut dst = vec![0u8; 100];
@@ -736,6 +1060,80 @@ fn test_emit_copy() {
     }
 }
 
+/// The size estimates used for scoring must match what is emitted, including
+/// COPY4 and repeat splits
+#[test]
+fn test_emit_copy_size_matches_emit() {
+    use crate::encode::test_helpers::{
+        test_emit_copy, test_emit_copy_size, test_emit_repeat, test_emit_repeat_size,
+    };
+
+    let big = (1 << 24) + (1 << 16);
+    let mut lengths: Vec<usize> = (4..=70).collect();
+    lengths.extend([128, 260, 261, 262, 263, 264, 267, 268]);
+    lengths.extend(65535..=65540);
+    lengths.extend(65795..=65800);
+    lengths.extend(big - 8..=big + 8);
+    lengths.extend([2 * big, 3 * big + 5]);
+
+    let mut dst = vec![0u8; 64];
+    for offset in [1, 2, 2047, 2048, 65535, 65536, 1 << 20] {
+        for &length in &lengths {
+            let n = test_emit_copy(&mut dst, offset, length);
+            assert_eq!(
+                test_emit_copy_size(offset, length),
+                n,
+                "copy offset={}, length={}",
+                offset,
+                length
+            );
+            let n = test_emit_repeat(&mut dst, offset, length);
+            assert_eq!(
+                test_emit_repeat_size(offset, length),
+                n,
+                "repeat offset={}, length={}",
+                offset,
+                length
+            );
+        }
+    }
+}
+
+/// Every repeat length up to past the 4-byte form, at offsets either side of
+/// the 2-byte offset form's limit
+#[test]
+fn test_emit_repeat_size_exhaustive() {
+    use crate::encode::test_helpers::{test_emit_repeat, test_emit_repeat_size};
+
+    let mut dst = [0u8; 16];
+    for offset in [8, 2047, 2048, 65535] {
+        for length in 4..70000 {
+            let n = test_emit_repeat(&mut dst, offset, length);
+            assert_eq!(
+                test_emit_repeat_size(offset, length),
+                n,
+                "offset={}, length={}",
+                offset,
+                length
+            );
+        }
+    }
+}
+
+/// Runs longer than a single 5-byte repeat can express must be split
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_encode_long_run_roundtrip() {
+    let src = vec![0u8; 40 << 20];
+    for (name, enc) in [
+        ("fast", encode(&src)),
+        ("better", encode_better(&src)),
+        ("best", encode_best(&src)),
+    ] {
+        assert!(decode(&enc).unwrap() == src, "{}", name);
+    }
+}
+
 #[test]
 fn test_match_len() {
     use crate::encode::test_helpers::test_match_len;
@@ -1377,6 +1775,120 @@ fn test_writer_reset_without_flush() {
     );
 }
 
+#[test]
+fn test_decode_empty_input() {
+    use crate::{
+        decode_block, decode_into, decode_len, decode_snappy, decode_snappy_strict,
+        decode_with_dict, make_dict, Error,
+    };
+
+    let dict = make_dict(b"some dictionary content for the test", None).unwrap();
+
+    // No length header at all
+    assert_eq!(decode_len(&[]), Err(Error::Corrupt));
+    assert_eq!(decode(&[]), Err(Error::Corrupt));
+    assert_eq!(decode_block(&[]), Err(Error::Corrupt));
+    assert_eq!(decode_snappy(&[]), Err(Error::Corrupt));
+    assert_eq!(decode_snappy_strict(&[]), Err(Error::Corrupt));
+    assert_eq!(decode_with_dict(&[], &dict), Err(Error::Corrupt));
+    assert_eq!(decode_into(&mut [0u8; 16], &[]), Err(Error::Corrupt));
+    assert_eq!(decode_into(&mut [], &[]), Err(Error::Corrupt));
+
+    // A zero length header is an empty block
+    assert_eq!(decode_len(&[0x00]), Ok((0, 1)));
+    assert_eq!(decode(&[0x00]), Ok(Vec::new()));
+    assert_eq!(decode_block(&[0x00]), Ok((Vec::new(), 1)));
+    assert_eq!(decode_snappy(&[0x00]), Ok(Vec::new()));
+    assert_eq!(decode_snappy_strict(&[0x00]), Ok(Vec::new()));
+    assert_eq!(decode_with_dict(&[0x00], &dict), Ok(Vec::new()));
+    assert_eq!(decode_into(&mut [], &[0x00]), Ok(0));
+    assert_eq!(encode(&[]), [0x00]);
+
+    // A length header cut short
+    assert_eq!(decode_len(&[0x80]), Err(Error::Corrupt));
+    assert_eq!(decode(&[0x80]), Err(Error::Corrupt));
+}
+
+/// decode_into writes only the decoded length, with overlapping copies
+/// resolved inside the given region
+#[test]
+fn test_decode_into_region() {
+    use crate::{decode_into, decode_len, encode_snappy, Error};
+
+    let mut data = Vec::new();
+    for period in 1..=5 {
+        data.extend((0..1000).map(|i| b"vwxyz"[i % period]));
+    }
+    let text = b"far away text, copied again later; ".repeat(20);
+    data.extend_from_slice(&text);
+    data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+    data.extend_from_slice(&text);
+
+    for compressed in [
+        encode(&data),
+        encode_better(&data),
+        encode_best(&data),
+        encode_snappy(&data),
+    ] {
+        let (len, _) = decode_len(&compressed).unwrap();
+        assert_eq!(len, data.len());
+
+        let mut region = vec![0xaa; len + 64];
+        let n = decode_into(&mut region[32..32 + len], &compressed).unwrap();
+        assert_eq!(n, len);
+        assert!(region[32..32 + len] == data[..]);
+        assert!(region[..32].iter().all(|&b| b == 0xaa));
+        assert!(region[32 + len..].iter().all(|&b| b == 0xaa));
+
+        assert_eq!(
+            decode_into(&mut region[..len - 1], &compressed),
+            Err(Error::BufferTooSmall)
+        );
+    }
+}
+
+/// The declared length is a contract: tokens producing fewer or more bytes
+/// than it are rejected, whichever token type is at fault.
+#[test]
+fn test_decode_len_mismatch() {
+    use crate::{decode_into, decode_with_dict, make_dict, Error};
+
+    let dict = make_dict(b"dictionary contents used by the mismatch test", None).unwrap();
+    let cases: [(&str, &[u8]); 8] = [
+        // Under-production: tokens end before the declared length
+        ("literal short", b"\x0a\x10hello"),
+        ("copy1 short", b"\x0a\x00a\x01\x01"), // 1 + 4 bytes of 10
+        ("copy2 short", b"\x0a\x00a\x0a\x01\x00"), // 1 + 3 bytes of 10
+        ("copy4 short", b"\x0a\x00a\x0b\x01\x00\x00\x00"), // 1 + 3 bytes of 10
+        // Over-production: a token runs past the declared length
+        ("literal long", b"\x05\x24hellohello"),
+        ("copy1 long", b"\x05\x00a\x09\x01"), // 1 + 6 bytes of 5
+        ("copy2 long", b"\x05\x00a\x1e\x01\x00"), // 1 + 8 bytes of 5
+        ("copy4 long", b"\x05\x00a\x1f\x01\x00\x00\x00"), // 1 + 8 bytes of 5
+    ];
+
+    for (name, src) in cases {
+        assert_eq!(decode(src), Err(Error::Corrupt), "{}", name);
+        assert_eq!(
+            decode_with_dict(src, &dict),
+            Err(Error::Corrupt),
+            "{}",
+            name
+        );
+        let mut dst = [0u8; 64];
+        assert_eq!(decode_into(&mut dst, src), Err(Error::Corrupt), "{}", name);
+    }
+
+    // The same tokens with the right declared length decode fine
+    assert_eq!(decode(b"\x05\x10hello").unwrap(), b"hello");
+    assert_eq!(decode(b"\x05\x00a\x01\x01").unwrap(), b"aaaaa");
+    assert_eq!(decode(b"\x04\x00a\x0a\x01\x00").unwrap(), b"aaaa");
+    assert_eq!(
+        decode(b"\x09\x00a\x1f\x01\x00\x00\x00").unwrap(),
+        b"aaaaaaaaa"
+    );
+}
+
 #[test]
 fn test_decode_edge_cases() {
     use crate::decode::decode;
@@ -1699,3 +2211,961 @@ fn test_decode_oom_input_returns_too_large() {
         }
     }
 }
+
+/// The first copy of a block must never use the repeat encoding: the
+/// decoder starts with no previous offset and rejects it as corrupt.
+///
+/// The asm-port and best encoders start with `repeat = 1`, so a run of
+/// identical bytes makes the very first match a repeat-offset candidate.
+/// The better encoders start with `repeat = 0`. With a dictionary the
+/// decoder is seeded with the dictionary's repeat offset, so a leading
+/// repeat is valid there and must round-trip.
+#[test]
+fn test_first_copy_not_repeat() {
+    use crate::{decode_with_dict, encode_with_dict, make_dict_manual};
+
+    let mut cases: Vec<Vec<u8>> = Vec::new();
+    // Offset-1 run right at the start (matches the initial repeat of 1).
+    for len in [40, 100, 600, 5000, 20_000, 100_000] {
+        let mut v = vec![b'x'];
+        v.extend(std::iter::repeat(b'a').take(len));
+        cases.push(v);
+    }
+    // Offset-1 run after a short literal.
+    for len in [40, 700, 70_000] {
+        let mut v: Vec<u8> = (0..13).collect();
+        v.extend(std::iter::repeat(b'z').take(len));
+        cases.push(v);
+    }
+
+    for src in &cases {
+        for (name, block) in [
+            ("encode", encode(src)),
+            ("encode_better", encode_better(src)),
+            ("encode_best", encode_best(src)),
+        ] {
+            // A leading repeat has no offset to reuse, so analyze_block
+            // rejects it as corrupt
+            let analysis = crate::analyze_block(&block).unwrap_or_else(|e| {
+                panic!(
+                    "{} emitted a leading repeat for {} bytes: {}",
+                    name,
+                    src.len(),
+                    e
+                )
+            });
+            assert!(analysis.copy_bytes > 0, "{} emitted no copy", name);
+            assert_eq!(decode(&block).unwrap(), *src, "{} round-trip", name);
+        }
+    }
+
+    // Dictionary seeded so its repeat offset lines up with the first match.
+    let dict_data = b"0123456789abcdef0123456789ABCDEF--header--";
+    let dict = make_dict_manual(dict_data, 16).unwrap();
+    let mut src = dict_data[16..].to_vec();
+    src.extend_from_slice(&dict_data[16..]);
+    src.extend_from_slice(b" trailing bytes that do not repeat");
+    let block = encode_with_dict(&src, &dict);
+    assert_eq!(decode_with_dict(&block, &dict).unwrap(), src);
+}
+
+#[test]
+fn test_encode_with_dict_references_dict() {
+    use crate::{decode_with_dict, encode_with_dict, make_dict, MAX_DICT_SRC_OFFSET};
+
+    let dict_data = b"{\"level\":\"info\",\"service\":\"api\",\"message\":\"request handled\"}";
+    let dict = make_dict(dict_data, None).unwrap();
+
+    // A single record only compresses by copying from the dictionary.
+    let record =
+        b"{\"level\":\"info\",\"service\":\"api\",\"message\":\"request handled\",\"id\":1}";
+    let block = encode_with_dict(record, &dict);
+    assert!(block.len() < encode(record).len());
+    assert_eq!(decode_with_dict(&block, &dict).unwrap(), record);
+    assert!(decode(&block).is_err());
+
+    // Dictionary text past MAX_DICT_SRC_OFFSET must be copied from the
+    // block itself, as the decoder refuses dictionary copies there.
+    let mut x: u32 = 0x12345678;
+    let mut src: Vec<u8> = (0..MAX_DICT_SRC_OFFSET - 20)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+    for _ in 0..3 {
+        src.extend_from_slice(dict_data);
+    }
+    let block = encode_with_dict(&src, &dict);
+    assert_eq!(decode_with_dict(&block, &dict).unwrap(), src);
+}
+
+#[test]
+fn test_decode_with_dict_rejects_out_of_range_offset() {
+    use crate::{decode_with_dict, make_dict};
+
+    let dict = make_dict(&[b'd'; 32], None).unwrap();
+    // Literal "ab", then a copy reaching before the start of the dictionary.
+    let block = [10u8, 0x04, b'a', b'b', (7 << 2) | 2, 100, 0];
+    assert!(decode_with_dict(&block, &dict).is_err());
+}
+
+#[test]
+fn test_encode_best_with_options_effort_levels() {
+    use crate::{encode_best_with_options, BestOptions};
+
+    let twain = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+    let mut mixed: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+    let mut x: u32 = 0x9e3779b9;
+    mixed.extend((0..20_000).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        x as u8
+    }));
+    mixed.extend_from_slice(&twain);
+
+    let low = BestOptions {
+        max_skip: 256,
+        extra_candidate_positions: false,
+        ..BestOptions::default()
+    };
+    let high = BestOptions {
+        max_skip: 1,
+        extra_candidate_positions: true,
+        ..BestOptions::default()
+    };
+
+    for src in [&twain, &mixed] {
+        assert_eq!(
+            encode_best_with_options(src, &BestOptions::default()),
+            encode_best(src)
+        );
+
+        let fast = encode_best_with_options(src, &low);
+        let thorough = encode_best_with_options(src, &high);
+        assert_eq!(decode(&fast).unwrap(), *src);
+        assert_eq!(decode(&thorough).unwrap(), *src);
+        assert!(
+            thorough.len() <= fast.len(),
+            "high effort {} > low effort {}",
+            thorough.len(),
+            fast.len()
+        );
+    }
+}
+
+#[test]
+fn test_encode_better_mid_index_stride() {
+    use crate::{encode_better_with_options, BetterOptions};
+
+    // Words in pseudo-random order: large blocks with many short matches
+    let twain = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+    let words: Vec<&[u8]> = twain.split_inclusive(|&b| b == b' ').collect();
+    let mut x: u32 = 1;
+    let mut shuffled = Vec::new();
+    while shuffled.len() < 1 << 20 {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        shuffled.extend_from_slice(words[x as usize % words.len()]);
+    }
+
+    for src in [&twain, &shuffled] {
+        let mut sizes = Vec::new();
+        for stride in [0, 1, 2, 3, 8] {
+            let opts = BetterOptions {
+                mid_index_stride: stride,
+            };
+            let compressed = encode_better_with_options(src, &opts);
+            assert_eq!(decode(&compressed).unwrap(), *src);
+            if stride == 2 {
+                assert_eq!(compressed, encode_better(src));
+            }
+            sizes.push(compressed.len());
+        }
+        // 0 is treated as 1
+        assert_eq!(sizes[0], sizes[1]);
+        assert!(
+            sizes[1] < sizes[4],
+            "stride 1 {} >= stride 8 {}",
+            sizes[1],
+            sizes[4]
+        );
+    }
+}
+
+#[test]
+fn test_encode_best_no_literal_bonus() {
+    use crate::{encode_best_with_options, BestOptions};
+
+    let twain = std::fs::read("testdata/Mark.Twain-Tom.Sawyer.txt").unwrap();
+    let base = encode_best(&twain);
+    let mut sizes = Vec::new();
+    for bonus in [0, 1, 2, 4] {
+        let opts = BestOptions {
+            no_literal_bonus: bonus,
+            ..BestOptions::default()
+        };
+        let compressed = encode_best_with_options(&twain, &opts);
+        assert_eq!(decode(&compressed).unwrap(), twain);
+        if bonus == 1 {
+            assert_eq!(compressed, base);
+        } else {
+            assert_ne!(compressed, base, "bonus {}", bonus);
+        }
+        sizes.push(compressed.len());
+    }
+    // Different matches give a different size, if only by a few bytes
+    assert_ne!(sizes[0], sizes[1]);
+    assert_ne!(sizes[3], sizes[1]);
+}
+
+#[test]
+fn test_decode_block_reports_consumed() {
+    use crate::decode_block;
+
+    let mut inputs: Vec<Vec<u8>> = vec![Vec::new(), b"short".to_vec()];
+    inputs.push((0..100_000u32).map(|i| (i % 251) as u8).collect());
+    inputs.push(b"abcabcabc".repeat(5000));
+
+    for src in &inputs {
+        for block in [encode(src), encode_better(src), encode_best(src)] {
+            let mut buf = block.clone();
+            buf.extend_from_slice(b"trailing bytes \x00\x01\x02\xff");
+
+            let (decoded, consumed) = decode_block(&buf).unwrap();
+            assert_eq!(decoded, *src);
+            assert_eq!(consumed, block.len());
+
+            // decode itself still rejects the trailing bytes.
+            assert!(src.is_empty() || decode(&buf).is_err());
+        }
+    }
+
+    // Back-to-back blocks.
+    let mut buf = encode(b"first first first first first");
+    buf.extend_from_slice(&encode(b"second"));
+    let (first, n) = decode_block(&buf).unwrap();
+    assert_eq!(first, b"first first first first first");
+    assert_eq!(
+        decode_block(&buf[n..]).unwrap(),
+        (b"second".to_vec(), buf.len() - n)
+    );
+}
+
+#[test]
+fn test_decode_block_zero_padding() {
+    use crate::decode_block;
+
+    // Blocks stored in fixed-size slots padded with zeros
+    let src = b"padded block, padded block, padded block".repeat(20);
+    for block in [encode(&src), encode_better(&src), encode_best(&src)] {
+        let mut slot = block.clone();
+        slot.resize(block.len() + 100, 0);
+
+        assert_eq!(decode_block(&slot).unwrap(), (src.clone(), block.len()));
+        assert_eq!(decode(&slot), Err(crate::Error::Corrupt));
+    }
+
+    // An empty block followed by padding.
+    let slot = [0u8; 100];
+    assert_eq!(decode_block(&slot).unwrap(), (Vec::new(), 1));
+}
+
+#[test]
+fn test_decode_prefix() {
+    use crate::decode_prefix;
+
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt");
+    let runs = b"x".repeat(100_000);
+    for src in [&text[..], &runs[..], b"tiny"] {
+        for block in [encode(src), encode_better(src), encode_best(src)] {
+            let full = decode(&block).unwrap();
+            for n in [0, 1, 7, 100, 4097, src.len() - 1, src.len(), src.len() + 10] {
+                let n_clamped = n.min(src.len());
+                assert_eq!(
+                    decode_prefix(&block, n).unwrap(),
+                    &full[..n_clamped],
+                    "n {}",
+                    n
+                );
+            }
+        }
+    }
+
+    // Only the tokens up to the prefix are read: corruption after them
+    // goes unnoticed, corruption before them does not.
+    let block = encode(&text[..]);
+    let mut cut = block[..block.len() / 2].to_vec();
+    assert_eq!(decode_prefix(&cut, 100).unwrap(), &text[..100]);
+    assert_eq!(decode(&cut), Err(crate::Error::Corrupt));
+    cut.truncate(20);
+    assert_eq!(decode_prefix(&cut, 100), Err(crate::Error::Corrupt));
+
+    // A copy before any output is rejected even when it ends the prefix
+    assert_eq!(
+        decode_prefix(b"\x06\x01\x01", 1),
+        Err(crate::Error::Corrupt)
+    );
+    assert_eq!(decode_prefix(&[], 1), Err(crate::Error::Corrupt));
+}
+
+#[test]
+fn test_decode_block_rejects_overrun() {
+    use crate::decode_block;
+
+    // Declared length 3, but the literal carries 5 bytes.
+    assert_eq!(decode_block(b"\x03\x10hello"), Err(crate::Error::Corrupt));
+    // Declared length 10, but the tokens stop after 5.
+    assert_eq!(decode_block(b"\x0a\x10hello"), Err(crate::Error::Corrupt));
+    // Copy reaching past the declared length.
+    assert_eq!(
+        decode_block(b"\x06\x00a\x09\x01"),
+        Err(crate::Error::Corrupt)
+    );
+}
+
+#[test]
+fn test_encode_block_with_table() {
+    use crate::encode::test_helpers::test_encode_block_with_table;
+    use crate::varint::encode_varint;
+
+    let data: Vec<u8> = (0..100_000u32)
+        .map(|i| ((i / 13) % 89) as u8 ^ ((i * 7) >> 11) as u8)
+        .collect();
+    let encode_with_table = |src: &[u8], table: &mut [u32]| {
+        let mut dst = vec![0u8; max_encoded_len(src.len()).unwrap()];
+        let mut n = encode_varint(&mut dst, src.len() as u64);
+        let body = test_encode_block_with_table(&mut dst[n..], src, table);
+        assert!(body > 0);
+        n += body;
+        dst.truncate(n);
+        dst
+    };
+
+    // A zeroed table of the width encode picks gives encode's output
+    for (len, bits) in [(100, 8), (2000, 10), (8000, 12), (100_000, 14)] {
+        let src = &data[..len];
+        let mut table = vec![0u32; 1 << bits];
+        assert_eq!(
+            encode_with_table(src, &mut table),
+            encode(src),
+            "len {}",
+            len
+        );
+        assert!(table.iter().any(|&e| e != 0));
+    }
+
+    // Any table contents still give a valid encoding
+    let mut seed = 5u32;
+    for bits in [8, 10, 12, 14] {
+        for fill in 0..4 {
+            let mut table: Vec<u32> = (0..1 << bits)
+                .map(|i: u32| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    match fill {
+                        0 => seed >> 15,
+                        1 => u32::MAX - i,
+                        2 => i % 50_000,
+                        _ => (seed >> 16) % 100,
+                    }
+                })
+                .collect();
+            let encoded = encode_with_table(&data, &mut table);
+            assert_eq!(
+                decode(&encoded).unwrap(),
+                data,
+                "bits {} fill {}",
+                bits,
+                fill
+            );
+        }
+    }
+
+    let mut dst = vec![0u8; max_encoded_len(data.len()).unwrap()];
+    assert_eq!(
+        test_encode_block_with_table(&mut dst, &data[..10], &mut [0; 256]),
+        0
+    );
+}
+
+#[test]
+#[should_panic(expected = "unsupported hash table length")]
+fn test_encode_block_with_table_bad_length() {
+    use crate::encode::test_helpers::test_encode_block_with_table;
+
+    let src = vec![7u8; 1000];
+    let mut dst = vec![0u8; max_encoded_len(src.len()).unwrap()];
+    test_encode_block_with_table(&mut dst, &src, &mut [0; 1000]);
+}
+
+/// Inputs just past the point where the encoders start matching, and around
+/// each table-size switch, with matches ending at the last byte. Candidates
+/// found this close to `s_limit` must still be loaded within bounds.
+#[test]
+fn test_encode_near_input_margin() {
+    use crate::constants::{INPUT_MARGIN, MIN_NON_LITERAL_BLOCK_SIZE};
+    use crate::encode_snappy;
+
+    let mut lens: Vec<usize> =
+        (MIN_NON_LITERAL_BLOCK_SIZE..=MIN_NON_LITERAL_BLOCK_SIZE + 4 * INPUT_MARGIN).collect();
+    for boundary in [512, 4096, 16384] {
+        lens.extend(boundary - 2 * INPUT_MARGIN..=boundary + 2 * INPUT_MARGIN);
+    }
+
+    let mut rng = 0x1234_5678u32;
+    for len in lens {
+        let noise: Vec<u8> = (0..len)
+            .map(|_| {
+                rng ^= rng << 13;
+                rng ^= rng >> 17;
+                rng ^= rng << 5;
+                rng as u8
+            })
+            .collect();
+
+        let mut cases = vec![vec![0u8; len], (0..len).map(|i| (i % 5) as u8).collect()];
+        // Noise whose tail repeats earlier bytes, so the last match found
+        // ends exactly at the end of the input.
+        for tail in [4, INPUT_MARGIN, INPUT_MARGIN + 1, 2 * INPUT_MARGIN] {
+            let mut data = noise.clone();
+            data[len - tail..].copy_from_slice(&noise[..tail]);
+            cases.push(data);
+            let mut data = noise.clone();
+            let from = len / 2 - tail;
+            data.copy_within(from..from + tail, len - tail);
+            cases.push(data);
+        }
+
+        for (i, data) in cases.iter().enumerate() {
+            roundtrip(data).unwrap_or_else(|e| panic!("len {} case {}: {}", len, i, e));
+            let snappy = encode_snappy(data);
+            assert_eq!(
+                &decode(&snappy).unwrap(),
+                data,
+                "snappy len {} case {}",
+                len,
+                i
+            );
+        }
+    }
+}
+
+#[test]
+fn test_encode_with_options_table_bits() {
+    use crate::{encode_with_options, EncodeOptions};
+
+    let words: [&[u8]; 6] = [
+        b"alpha ",
+        b"beta ",
+        b"gamma ",
+        b"delta ",
+        b"epsilon ",
+        b"zeta ",
+    ];
+    let mut rng = 0x9e37_79b9u32;
+    let mut data = Vec::new();
+    while data.len() < 100 * 1024 {
+        rng ^= rng << 13;
+        rng ^= rng >> 17;
+        rng ^= rng << 5;
+        data.extend_from_slice(words[rng as usize % words.len()]);
+        data.push(rng as u8);
+    }
+    data.truncate(100 * 1024);
+
+    let forced = EncodeOptions {
+        table_bits: Some(14),
+        ..Default::default()
+    };
+    assert_eq!(
+        encode_with_options(&data, &EncodeOptions::default()),
+        encode(&data)
+    );
+    // 100KB already uses the 14-bit table.
+    assert_eq!(encode_with_options(&data, &forced), encode(&data));
+
+    // Cut into blocks of different sizes, the same bytes go through the
+    // same encoder: a 6KB block encodes like the start of a 32KB block.
+    // Left to pick the width from the size, the two diverge early.
+    let body = |block: &[u8], opts: &EncodeOptions| {
+        let encoded = encode_with_options(block, opts);
+        let (_, n) = crate::varint::decode_varint(&encoded).unwrap();
+        encoded[n..].to_vec()
+    };
+    let common = |a: &[u8], b: &[u8]| a.iter().zip(b).take_while(|(x, y)| x == y).count();
+
+    let small = body(&data[..6 * 1024], &forced);
+    let large = body(&data[..32 * 1024], &forced);
+    assert!(common(&small, &large) > small.len() * 9 / 10);
+
+    let small = body(&data[..6 * 1024], &EncodeOptions::default());
+    let large = body(&data[..32 * 1024], &EncodeOptions::default());
+    assert!(common(&small, &large) < small.len() / 10);
+
+    for bits in [0, 8, 9, 10, 12, 14, 20] {
+        let opts = EncodeOptions {
+            table_bits: Some(bits),
+            ..Default::default()
+        };
+        for len in [32, 1000, 5000, 20_000, data.len()] {
+            let encoded = encode_with_options(&data[..len], &opts);
+            assert_eq!(
+                decode(&encoded).unwrap(),
+                &data[..len],
+                "bits {} len {}",
+                bits,
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn test_encode_with_options_hash() {
+    use crate::{encode_with_options, EncodeOptions, HashKind};
+
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt");
+    let mut structured = Vec::new();
+    for i in 0..4096u32 {
+        structured.extend_from_slice(&(i * 16).to_le_bytes());
+        structured.extend_from_slice(&[1, 0, 0, 0]);
+        structured.extend_from_slice(&(i * 7 % 1000).to_le_bytes());
+        structured.extend_from_slice(&1.0f32.to_le_bytes());
+    }
+
+    for data in [&text[..], &structured[..]] {
+        for hash in [HashKind::Hash4, HashKind::Hash6] {
+            for table_bits in [None, Some(8), Some(10), Some(12), Some(14)] {
+                let opts = EncodeOptions {
+                    table_bits,
+                    hash: Some(hash),
+                    ..Default::default()
+                };
+                for len in [20, 300, 3000, 10_000, data.len()] {
+                    let encoded = encode_with_options(&data[..len], &opts);
+                    assert_eq!(
+                        decode(&encoded).unwrap(),
+                        &data[..len],
+                        "{:?} bits {:?} len {}",
+                        hash,
+                        table_bits,
+                        len
+                    );
+                }
+            }
+        }
+    }
+
+    // Forcing the hash a width already uses changes nothing
+    let hash4 = EncodeOptions {
+        hash: Some(HashKind::Hash4),
+        ..Default::default()
+    };
+    let hash6 = EncodeOptions {
+        hash: Some(HashKind::Hash6),
+        ..Default::default()
+    };
+    assert_eq!(
+        encode_with_options(&text[..3000], &hash4),
+        encode(&text[..3000])
+    );
+    assert_eq!(
+        encode_with_options(&structured, &hash6),
+        encode(&structured)
+    );
+    assert_ne!(encode_with_options(&text[..], &hash4), encode(&text[..]));
+}
+
+/// On 32-bit targets, large declared lengths must be rejected before any
+/// infallible allocation: either by `decode_len`, by the
+/// `MAX_DECODE_DST_SIZE` cap, or by the fallible reservation.
+#[cfg(target_pointer_width = "32")]
+#[test]
+fn test_decode_32bit_large_declared_len() {
+    use crate::{decode, decode_block, Error, MAX_DECODE_DST_SIZE};
+
+    for dlen in [
+        0xffff_ffffu64,
+        0x8000_0000,
+        0x7fff_ffff,
+        MAX_DECODE_DST_SIZE as u64 + 1,
+    ] {
+        let mut src = Vec::new();
+        let mut v = dlen;
+        while v >= 0x80 {
+            src.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        src.push(v as u8);
+        src.extend_from_slice(&[0x00, b'x']);
+
+        assert!(
+            matches!(decode(&src), Err(Error::TooLarge)),
+            "decode dlen {:#x}",
+            dlen
+        );
+        // decode_block checks the tokens before allocating.
+        assert!(
+            matches!(decode_block(&src), Err(Error::TooLarge | Error::Corrupt)),
+            "decode_block dlen {:#x}",
+            dlen
+        );
+    }
+}
+
+#[test]
+fn test_decode_snappy_strict() {
+    use crate::{decode_snappy_strict, encode_snappy, Error};
+
+    // Runs at a fixed distance make the S2 encoder emit repeat copies.
+    let data: Vec<u8> = (0..20_000u32)
+        .flat_map(|i| {
+            let mut rec = format!("record {:05} ", i % 97).into_bytes();
+            rec.push((i * 13) as u8);
+            rec
+        })
+        .collect();
+
+    let s2 = encode(&data);
+    assert_eq!(decode(&s2).unwrap(), data);
+    assert!(matches!(decode_snappy_strict(&s2), Err(Error::Unsupported)));
+
+    let snappy = encode_snappy(&data);
+    assert_eq!(decode_snappy_strict(&snappy).unwrap(), data);
+
+    // A repeat on its own: literal "abcd", copy offset 4, then a repeat.
+    let block = [8, 0x0c, b'a', b'b', b'c', b'd', 0x01, 0x04];
+    assert_eq!(decode(&block).unwrap(), b"abcdabcd");
+    assert_eq!(decode_snappy_strict(&block).unwrap(), b"abcdabcd");
+    let block = [12, 0x0c, b'a', b'b', b'c', b'd', 0x01, 0x04, 0x01, 0x00];
+    assert_eq!(decode(&block).unwrap(), b"abcdabcdabcd");
+    assert!(matches!(
+        decode_snappy_strict(&block),
+        Err(Error::Unsupported)
+    ));
+
+    // Corrupt input is still reported as such.
+    assert!(matches!(
+        decode_snappy_strict(&[8, 0x0c, b'a']),
+        Err(Error::Corrupt)
+    ));
+}
+
+// The format is little-endian throughout, so the encoded bytes of a given
+// input are the same on every target. Comparing against fixed bytes catches
+// any native-endian load or store that would only show up on big-endian
+// hosts.
+#[test]
+fn test_encode_golden_bytes() {
+    let repeated = b"abcdefgh_abcdefXY_abcdefgh_abcdefXY_bcdefgh_abcdefgh".repeat(3);
+    let cases: Vec<(&[u8], [Vec<u8>; 3])> = vec![
+        (
+            b"abcdabcdabcdabcdabcdabcdabcdabcd",
+            [
+                vec![0x20, 0x0c, 0x61, 0x62, 0x63, 0x64, 0x6e, 0x04, 0x00],
+                vec![0x20, 0x0c, 0x61, 0x62, 0x63, 0x64, 0x6e, 0x04, 0x00],
+                vec![0x20, 0x0c, 0x61, 0x62, 0x63, 0x64, 0x6e, 0x04, 0x00],
+            ],
+        ),
+        (
+            &[0u8; 100],
+            [
+                vec![0x64, 0x00, 0x00, 0x11, 0x01, 0x15, 0x00, 0x53],
+                vec![0x64, 0x00, 0x00, 0x11, 0x01, 0x15, 0x00, 0x53],
+                vec![0x64, 0x00, 0x00, 0x11, 0x01, 0x15, 0x00, 0x53],
+            ],
+        ),
+        (
+            b"one two three four five one two three four six one two three four five",
+            [
+                vec![
+                    0x46, 0x5c, 0x6f, 0x6e, 0x65, 0x20, 0x74, 0x77, 0x6f, 0x20, 0x74, 0x68, 0x72,
+                    0x65, 0x65, 0x20, 0x66, 0x6f, 0x75, 0x72, 0x20, 0x66, 0x69, 0x76, 0x65, 0x20,
+                    0x4a, 0x18, 0x00, 0x08, 0x73, 0x69, 0x78, 0x4e, 0x17, 0x00, 0x0c, 0x66, 0x69,
+                    0x76, 0x65,
+                ],
+                vec![
+                    0x46, 0x5c, 0x6f, 0x6e, 0x65, 0x20, 0x74, 0x77, 0x6f, 0x20, 0x74, 0x68, 0x72,
+                    0x65, 0x65, 0x20, 0x66, 0x6f, 0x75, 0x72, 0x20, 0x66, 0x69, 0x76, 0x65, 0x20,
+                    0x4a, 0x18, 0x00, 0x08, 0x73, 0x69, 0x78, 0x4e, 0x17, 0x00, 0x0c, 0x66, 0x69,
+                    0x76, 0x65,
+                ],
+                vec![
+                    0x46, 0x5c, 0x6f, 0x6e, 0x65, 0x20, 0x74, 0x77, 0x6f, 0x20, 0x74, 0x68, 0x72,
+                    0x65, 0x65, 0x20, 0x66, 0x6f, 0x75, 0x72, 0x20, 0x66, 0x69, 0x76, 0x65, 0x20,
+                    0x4a, 0x18, 0x00, 0x0c, 0x73, 0x69, 0x78, 0x20, 0x5a, 0x2f, 0x00,
+                ],
+            ],
+        ),
+        (
+            &repeated,
+            [
+                vec![
+                    0x9c, 0x01, 0x44, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x5f, 0x61,
+                    0x62, 0x63, 0x64, 0x65, 0x66, 0x58, 0x59, 0x5f, 0x46, 0x12, 0x00, 0x36, 0x11,
+                    0x00, 0x08, 0x67, 0x68, 0x61, 0x15, 0x00, 0x06, 0x11, 0x34, 0x15, 0x00, 0x49,
+                ],
+                vec![
+                    0x9c, 0x01, 0x20, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x5f, 0x09,
+                    0x09, 0x04, 0x58, 0x59, 0x0d, 0x00, 0x2e, 0x12, 0x00, 0x36, 0x11, 0x00, 0x04,
+                    0x67, 0x68, 0x46, 0x22, 0x00, 0x46, 0x12, 0x00, 0x36, 0x11, 0x00, 0xd6, 0x34,
+                    0x00,
+                ],
+                vec![
+                    0x9c, 0x01, 0x20, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x5f, 0x09,
+                    0x09, 0x08, 0x58, 0x59, 0x5f, 0x46, 0x12, 0x00, 0x36, 0x11, 0x00, 0x04, 0x67,
+                    0x68, 0x11, 0x34, 0x15, 0x00, 0x58,
+                ],
+            ],
+        ),
+    ];
+
+    for (n, (input, [standard, better, best])) in cases.iter().enumerate() {
+        assert_eq!(&encode(input), standard, "case {} standard", n);
+        assert_eq!(&encode_better(input), better, "case {} better", n);
+        assert_eq!(&encode_best(input), best, "case {} best", n);
+        for golden in [standard, better, best] {
+            assert_eq!(decode(golden).unwrap(), *input, "case {}", n);
+        }
+    }
+}
+
+// Stream framing stores the chunk length and CRC little-endian too
+#[test]
+fn test_stream_golden_bytes() {
+    use crate::{Reader, Writer};
+    use std::io::{Read, Write};
+
+    let input = b"abcdabcdabcdabcdabcdabcdabcdabcd";
+    let expected = [
+        0xff, 0x06, 0x00, 0x00, 0x53, 0x32, 0x73, 0x54, 0x77, 0x4f, // stream identifier
+        0x00, 0x0d, 0x00, 0x00, // compressed chunk, 13 bytes
+        0xd3, 0x85, 0xab, 0xe2, // masked CRC
+        0x20, 0x0c, 0x61, 0x62, 0x63, 0x64, 0x6e, 0x04, 0x00,
+    ];
+
+    let mut compressed = Vec::new();
+    {
+        let mut writer = Writer::new(&mut compressed);
+        writer.write_all(input).unwrap();
+    }
+    assert_eq!(compressed, expected);
+
+    let mut decompressed = Vec::new();
+    Reader::new(&expected[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, input);
+}
+
+/// Incompressible blocks past 64KB and 16MB are emitted as one literal with
+/// a 4- and 5-byte header (tags 62 and 63). Needs about 100MB, so only run
+/// on 64-bit targets.
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_huge_literal_block() {
+    let mut seed = 1u64;
+    let data: Vec<u8> = (0..20 << 20)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        })
+        .collect();
+
+    for (len, tag, header_len) in [(1 << 20, 62u8, 4), (20 << 20, 63, 5)] {
+        let src = &data[..len];
+        let encoded = encode(src);
+        let (dlen, n) = crate::decode_len(&encoded).unwrap();
+        assert_eq!(dlen, len);
+
+        // A single literal covering the whole block
+        assert_eq!(encoded[n], tag << 2, "len {}", len);
+        let mut lit_len = [0u8; 4];
+        lit_len[..header_len - 1].copy_from_slice(&encoded[n + 1..n + header_len]);
+        assert_eq!(u32::from_le_bytes(lit_len) as usize + 1, len);
+        assert_eq!(encoded.len(), n + header_len + len);
+
+        assert_eq!(decode(&encoded).unwrap(), src);
+    }
+}
+
+/// Identical input must give byte-identical output across runs and
+/// whatever a reused encoder saw before, for reproducible builds
+#[test]
+fn test_encode_deterministic() {
+    use crate::minlz::{compress_level, Level as MinLzLevel};
+    use crate::{encode_snappy, Encoder, Level, Writer};
+    use std::io::Write;
+
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt");
+    let mut seed = 11u32;
+    let mixed: Vec<u8> = (0..300_000u32)
+        .map(|i| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            if (i / 20_000) % 2 == 0 {
+                (seed >> 16) as u8
+            } else {
+                text[i as usize % text.len()]
+            }
+        })
+        .collect();
+    let inputs: [&[u8]; 4] = [&text[..100], &text[..], &mixed[..5000], &mixed];
+
+    let mut encoder = Encoder::new();
+    for src in inputs {
+        let expected = [
+            encode(src),
+            encode_better(src),
+            encode_best(src),
+            encode_snappy(src),
+        ];
+        assert_eq!(encode(src), expected[0]);
+        assert_eq!(encode_better(src), expected[1]);
+        assert_eq!(encode_best(src), expected[2]);
+        assert_eq!(encode_snappy(src), expected[3]);
+
+        // Tables left dirty by the previous input do not leak into the output
+        assert_eq!(encoder.encode(src), expected[0]);
+        assert_eq!(encoder.encode_better(src), expected[1]);
+        assert_eq!(encoder.encode_best(src), expected[2]);
+        assert_eq!(encoder.encode_snappy(src), expected[3]);
+
+        for level in [
+            MinLzLevel::Fastest,
+            MinLzLevel::Balanced,
+            MinLzLevel::Smallest,
+        ] {
+            assert_eq!(
+                compress_level(src, level).unwrap(),
+                compress_level(src, level).unwrap()
+            );
+        }
+
+        for level in [Level::Fast, Level::Better, Level::Best] {
+            let stream = || {
+                let mut out = Vec::new();
+                let mut writer = Writer::with_level(&mut out, level);
+                writer.write_all(src).unwrap();
+                writer.flush().unwrap();
+                drop(writer);
+                out
+            };
+            assert_eq!(stream(), stream());
+        }
+    }
+}
+
+#[test]
+fn test_decode_resume() {
+    use crate::{decode_resume, BlockDecodeState};
+
+    let text = include_bytes!("../testdata/Mark.Twain-Tom.Sawyer.txt");
+    let runs = b"x".repeat(100_000);
+    // Long literals need multi-byte length tags
+    let noise: Vec<u8> = (0..70_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    for src in [&text[..], &runs[..], &noise[..], b"tiny", b""] {
+        for block in [encode(src), encode_better(src), encode_best(src)] {
+            let expected = decode(&block).unwrap();
+
+            // One byte at a time splits every header and token
+            let mut state = BlockDecodeState::new();
+            let mut out = Vec::new();
+            for b in block.chunks(1) {
+                assert!(!state.is_complete());
+                assert_eq!(decode_resume(&mut state, &mut out, b).unwrap(), 1);
+            }
+            assert!(state.is_complete());
+            assert_eq!(state.decoded_len(), Some(src.len()));
+            assert_eq!(state.produced(), src.len());
+            assert_eq!(state.consumed(), block.len());
+            assert_eq!(out, expected);
+
+            // Uneven pieces, with trailing data left unused
+            let mut padded = block.clone();
+            padded.extend_from_slice(b"next");
+            let mut state = BlockDecodeState::new();
+            let mut out = Vec::new();
+            let mut used = 0;
+            for piece in padded.chunks(77) {
+                used += decode_resume(&mut state, &mut out, piece).unwrap();
+            }
+            assert_eq!(used, block.len());
+            assert_eq!(out, expected);
+        }
+    }
+
+    // Errors match one-shot decoding
+    let block = encode(&text[..]);
+    let mut state = BlockDecodeState::new();
+    let mut out = Vec::new();
+    decode_resume(&mut state, &mut out, &block[..100]).unwrap();
+    assert!(state.produced() > 0 && !state.is_complete());
+    out.push(0);
+    assert!(matches!(
+        decode_resume(&mut state, &mut out, &block[100..]),
+        Err(crate::Error::InvalidInput(_))
+    ));
+
+    let mut state = BlockDecodeState::new();
+    // A copy before any output
+    assert_eq!(
+        decode_resume(&mut state, &mut Vec::new(), &[4, 0x01, 1]),
+        Err(crate::Error::Corrupt)
+    );
+    let mut state = BlockDecodeState::new();
+    // A literal longer than the block
+    assert_eq!(
+        decode_resume(&mut state, &mut Vec::new(), &[2, 2 << 2]),
+        Err(crate::Error::Corrupt)
+    );
+}
+
+#[test]
+fn test_encode_force() {
+    use crate::{encode_force, encode_with_options, EncodeOptions};
+
+    // Random data: no matches, so forcing only changes which path emits
+    // the literal
+    let mut x = 0x9e37_79b9u32;
+    let random: Vec<u8> = (0..100_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+
+    // Short repeats in random data, saving less than 1/32
+    let mut marginal = random[..8192].to_vec();
+    for i in (128..8192).step_by(128) {
+        marginal.copy_within(i - 16..i - 8, i);
+    }
+
+    for src in [&random[..], &marginal[..], &random[..31], &random[..32]] {
+        let forced = encode_force(src);
+        assert_eq!(decode(&forced).unwrap(), src);
+        assert!(forced.len() <= max_encoded_len(src.len()).unwrap());
+    }
+
+    // encode stores these as a literal; the forced tokens are smaller, but
+    // not by enough to pass the check
+    let plain = encode(&marginal);
+    let forced = encode_force(&marginal);
+    assert_eq!(plain.len(), marginal.len() + 5);
+    assert!(forced.len() < plain.len());
+    assert!(forced.len() > marginal.len() - marginal.len() / 32);
+
+    // Without force the options give encode's output
+    let opts = EncodeOptions::default();
+    assert!(!opts.force);
+    assert_eq!(encode_with_options(&marginal, &opts), plain);
+}
@@ -5,11 +5,14 @@
 
 //! Stream writer for S2 compression
 
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
 
 use crate::constants::*;
 use crate::crc::crc;
-use crate::encode::encode;
+use crate::dict::Dict;
+use crate::encode::Level;
 use crate::index::Index;
 
 /// Writer compresses data using the S2 stream format
@@ -43,6 +46,12 @@ pub struct Writer<W: Write> {
     total_written: u64,      // Total bytes written to underlying writer (for padding calculation)
     index: Option<Index>,    // Optional index for seeking support
     uncompressed_total: u64, // Total uncompressed bytes written
+    dict: Option<Dict>,      // Optional dictionary every block is compressed with
+    level: Level,            // Encoder used for each block
+    write_level: bool,       // Record the level in a chunk after the stream identifier
+    write_size: bool,        // Record the uncompressed size in a chunk at the end
+    no_header: bool,         // Leave out the stream identifier
+    closed: bool,            // Set once the index/padding trailer has been written
 }
 
 impl<W: Write> Writer<W> {
@@ -53,9 +62,28 @@ impl<W: Write> Writer<W> {
 
     /// Create a new Writer with a specific block size
     ///
-    /// Block size must be between 4KB and 4MB
+    /// Block size is clamped to 4KB..=4MB, so smaller requests silently get
+    /// 4KB blocks. Use [`with_block_size_unclamped`](Writer::with_block_size_unclamped)
+    /// for smaller blocks.
     pub fn with_block_size(writer: W, block_size: usize) -> Self {
-        let block_size = block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+        Self::with_block_size_unclamped(writer, block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE))
+    }
+
+    /// Create a new Writer with a block size below the usual 4KB minimum
+    ///
+    /// Intended for tests that need many chunks from little data, and for
+    /// latency-sensitive streams where each flush should carry little data.
+    /// Small blocks cost ratio: every block restarts match finding with no
+    /// history and adds 8 bytes of chunk header and CRC, so expect noticeably
+    /// larger output below 4KB.
+    ///
+    /// # Panics
+    /// Panics if block_size is less than 512 bytes or greater than 4MB
+    pub fn with_block_size_unclamped(writer: W, block_size: usize) -> Self {
+        assert!(
+            (MIN_UNCLAMPED_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size),
+            "block_size must be >= 512 and <= 4MB"
+        );
 
         Writer {
             writer,
@@ -66,6 +94,12 @@ impl<W: Write> Writer<W> {
             total_written: 0,
             index: None,
             uncompressed_total: 0,
+            dict: None,
+            closed: false,
+            level: Level::Fast,
+            write_level: false,
+            write_size: false,
+            no_header: false,
         }
     }
 
@@ -105,6 +139,12 @@ impl<W: Write> Writer<W> {
             total_written: 0,
             index: Some(index),
             uncompressed_total: 0,
+            dict: None,
+            closed: false,
+            level: Level::Fast,
+            write_level: false,
+            write_size: false,
+            no_header: false,
         }
     }
 
@@ -144,9 +184,142 @@ impl<W: Write> Writer<W> {
             total_written: 0,
             index: None,
             uncompressed_total: 0,
+            dict: None,
+            closed: false,
+            level: Level::Fast,
+            write_level: false,
+            write_size: false,
+            no_header: false,
         }
     }
 
+    /// Create a new Writer that compresses every block with a dictionary
+    ///
+    /// Each block is encoded with
+    /// [`encode_with_dict`](crate::encode_with_dict), so the stream can only
+    /// be read by a [`Reader::with_dict`](crate::Reader::with_dict) given the
+    /// identical dictionary.
+    ///
+    /// Blocks stay independent: each one starts from the dictionary's
+    /// repeat offset, as the decoder seeds every block with it, so the
+    /// last offset of one block is not carried into the next.
+    pub fn with_dict(writer: W, dict: Dict) -> Self {
+        let mut w = Self::new(writer);
+        w.dict = Some(dict);
+        w
+    }
+
+    /// Create a new Writer that encodes every block at `level` and records
+    /// the level in the stream
+    ///
+    /// The level is stored in a skippable chunk right after the stream
+    /// identifier, where [`Reader::level`](crate::Reader::level) picks it
+    /// up. It is informational only: decoding does not depend on it, and
+    /// readers that don't know the chunk skip it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Level, Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let mut writer = Writer::with_level(&mut compressed, Level::Better);
+    ///     writer.write_all(b"Hello, World!").unwrap();
+    /// }
+    ///
+    /// let mut reader = Reader::new(&compressed[..]);
+    /// let mut out = Vec::new();
+    /// reader.read_to_end(&mut out).unwrap();
+    /// assert_eq!(reader.level(), Some(Level::Better));
+    /// ```
+    pub fn with_level(writer: W, level: Level) -> Self {
+        let mut w = Self::new(writer);
+        w.level = level;
+        w.write_level = true;
+        w
+    }
+
+    /// Create a new Writer that records the total uncompressed size in the
+    /// stream
+    ///
+    /// The size is only known once the stream is finished, so it is written
+    /// then, in a skippable chunk just before the index (if any) and the
+    /// EOF marker. [`Reader::total_size_hint`](crate::Reader::total_size_hint)
+    /// finds it from the end of a seekable stream without decoding
+    /// anything; readers that don't know the chunk skip it. Unlike an
+    /// index this is a single number. It can't be found past a
+    /// [`with_padding`](Writer::with_padding) trailer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Cursor, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let mut writer = Writer::with_size_header(&mut compressed);
+    ///     writer.write_all(&[7u8; 5000]).unwrap();
+    /// }
+    ///
+    /// let mut reader = Reader::new(Cursor::new(compressed));
+    /// assert_eq!(reader.total_size_hint().unwrap(), Some(5000));
+    /// ```
+    pub fn with_size_header(writer: W) -> Self {
+        let mut w = Self::new(writer);
+        w.write_size = true;
+        w
+    }
+
+    /// Create a new Writer that leaves out the stream identifier
+    ///
+    /// The output is a sequence of bare chunks, for containers that store
+    /// the stream at a known position and identify it themselves. Read it
+    /// back with [`Reader::with_ignore_stream_id`](crate::Reader::with_ignore_stream_id);
+    /// a plain [`Reader`](crate::Reader) rejects it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::{Reader, Writer};
+    /// use std::io::{Read, Write};
+    ///
+    /// let mut compressed = Vec::new();
+    /// {
+    ///     let mut writer = Writer::with_no_header(&mut compressed);
+    ///     writer.write_all(b"Hello, World!").unwrap();
+    /// }
+    ///
+    /// let mut out = Vec::new();
+    /// Reader::with_ignore_stream_id(&compressed[..])
+    ///     .read_to_end(&mut out)
+    ///     .unwrap();
+    /// assert_eq!(out, b"Hello, World!");
+    /// ```
+    pub fn with_no_header(writer: W) -> Self {
+        let mut w = Self::new(writer);
+        w.no_header = true;
+        w
+    }
+
+    /// Compress every block with a dictionary
+    ///
+    /// Same as [`with_dict`](Writer::with_dict), for writers built with
+    /// another constructor (e.g. with an index, padding or block size).
+    ///
+    /// # Panics
+    /// Panics if a block has already been written, since the stream would
+    /// then mix blocks with and without the dictionary
+    pub fn set_dict(&mut self, dict: Dict) {
+        assert!(
+            !self.wrote_header,
+            "set_dict must be called before any block is written"
+        );
+        self.dict = Some(dict);
+    }
+
     /// Enable index tracking on this writer
     ///
     /// This can be called after construction to enable index support.
@@ -159,18 +332,47 @@ impl<W: Write> Writer<W> {
         }
     }
 
+    /// Pad the finished stream to a multiple of `padding` bytes
+    ///
+    /// Same as [`with_padding`](Writer::with_padding), for writers built
+    /// with another constructor. Combined with an index, the padding goes
+    /// after the index, so nothing has to be buffered to add both.
+    ///
+    /// # Panics
+    /// Panics if padding is not > 1 and <= 4MB
+    pub fn set_padding(&mut self, padding: usize) {
+        assert!(
+            padding > 1 && padding <= MAX_BLOCK_SIZE,
+            "padding must be > 1 and <= 4MB"
+        );
+        self.padding = padding;
+    }
+
     /// Write the stream identifier if not already written
     fn write_header(&mut self) -> io::Result<()> {
         if !self.wrote_header {
-            self.writer.write_all(MAGIC_CHUNK)?;
-            self.total_written += MAGIC_CHUNK.len() as u64;
+            if !self.no_header {
+                self.writer.write_all(MAGIC_CHUNK)?;
+                self.total_written += MAGIC_CHUNK.len() as u64;
+            }
             self.wrote_header = true;
+
+            if self.write_level {
+                let len = LEVEL_MAGIC.len() + 1;
+                let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + len);
+                chunk.push(CHUNK_TYPE_LEVEL);
+                chunk.extend_from_slice(&(len as u32).to_le_bytes()[..3]);
+                chunk.extend_from_slice(LEVEL_MAGIC);
+                chunk.push(self.level.id());
+                self.writer.write_all(&chunk)?;
+                self.total_written += chunk.len() as u64;
+            }
         }
         Ok(())
     }
 
     /// Flush any buffered data as a compressed block
-    fn flush_block(&mut self) -> io::Result<()> {
+    pub(crate) fn flush_block(&mut self) -> io::Result<()> {
         if self.buf.is_empty() {
             return Ok(());
         }
@@ -193,7 +395,10 @@ impl<W: Write> Writer<W> {
         self.uncompressed_total += uncompressed_size;
 
         // Compress the block
-        let compressed = encode(&self.buf);
+        let compressed = match self.dict {
+            Some(ref dict) => self.level.encode_with_dict(&self.buf, dict),
+            None => self.level.encode(&self.buf),
+        };
 
         // Calculate CRC of uncompressed data
         let checksum = crc(&self.buf);
@@ -278,6 +483,7 @@ impl<W: Write> Writer<W> {
     /// Reset the writer to use a new underlying writer
     pub fn reset(&mut self, writer: W) -> W {
         self.buf.clear();
+        self.closed = false;
         self.wrote_header = false;
         self.total_written = 0;
         self.uncompressed_total = 0;
@@ -381,6 +587,23 @@ impl<W: Write> Writer<W> {
         Ok(())
     }
 
+    /// Write the size chunk if enabled (called on close/drop, after flushing)
+    fn apply_size(&mut self) -> io::Result<()> {
+        if self.write_size {
+            self.write_header()?;
+            let mut chunk = Vec::with_capacity(SIZE_CHUNK_LEN);
+            chunk.push(CHUNK_TYPE_SIZE);
+            chunk.extend_from_slice(
+                &((SIZE_CHUNK_LEN - CHUNK_HEADER_SIZE) as u32).to_le_bytes()[..3],
+            );
+            chunk.extend_from_slice(&self.uncompressed_total.to_le_bytes());
+            chunk.extend_from_slice(SIZE_MAGIC);
+            self.writer.write_all(&chunk)?;
+            self.total_written += chunk.len() as u64;
+        }
+        Ok(())
+    }
+
     /// Apply padding if needed (called on close/drop)
     fn apply_padding(&mut self) -> io::Result<()> {
         if self.padding > 1 {
@@ -394,6 +617,272 @@ impl<W: Write> Writer<W> {
         Ok(())
     }
 
+    /// Flush, then write the index and padding trailers, reporting errors
+    ///
+    /// This is what [`Drop`] does on a best-effort basis; once called, the
+    /// drop is a no-op.
+    pub(crate) fn close(&mut self) -> io::Result<()> {
+        self.finish(false)
+    }
+
+    /// Finish the stream with an EOF marker
+    ///
+    /// Like dropping the writer, this flushes the last block and writes the
+    /// index, but then also writes a zero-length padding frame (before any
+    /// [`with_padding`](Writer::with_padding) trailer). A
+    /// [`Reader`](crate::Reader) with
+    /// [`expect_eof_marker`](crate::Reader::expect_eof_marker) set uses it
+    /// to tell a complete stream from one truncated between chunks; other
+    /// readers skip it. Errors are reported, and the later drop is a no-op.
+    ///
+    /// # Errors
+    /// Returns `ErrorKind::InvalidInput` if the stream was already finished.
+    pub fn finish_with_eof_marker(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stream already finished",
+            ));
+        }
+        self.finish(true)
+    }
+
+    /// Make everything written so far durable as a valid stream
+    ///
+    /// Flushes the buffered data as a block, writes the current index (and
+    /// size chunk) as for a finished stream, then flushes the underlying
+    /// writer. If the process dies afterwards, the output up to this point
+    /// decodes to all data written before the checkpoint, and an index
+    /// written here covers all of it. Data written after the checkpoint is
+    /// only kept once the next checkpoint or the end of the stream is
+    /// written; a partial trailing chunk left by a crash has to be cut off
+    /// at the checkpoint's length before the output is used.
+    ///
+    /// The writer can keep going after a checkpoint: the index and size
+    /// chunks are skippable, and the ones written at the end cover the
+    /// whole stream. To continue in a new process, open the output for
+    /// appending and write to a new [`Writer`]; the streams concatenate,
+    /// though the new writer's index only covers what it writes.
+    ///
+    /// Each checkpoint ends a block early, so checkpointing often costs
+    /// some compression. Padding and the EOF marker are only written when
+    /// the stream is finished.
+    ///
+    /// # Errors
+    /// Returns `ErrorKind::InvalidInput` if the stream was already finished.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stream already finished",
+            ));
+        }
+        self.flush_block()?;
+        self.write_header()?;
+        self.apply_size()?;
+        self.apply_index()?;
+        self.writer.flush()
+    }
+
+    /// Flush the buffered data as a block, then pad the output to a multiple
+    /// of `alignment` bytes
+    ///
+    /// For storage that wants each flushed region to end on a sector
+    /// boundary. The padding is a skippable frame, as for
+    /// [`with_padding`](Writer::with_padding), but written now rather than
+    /// at the end of the stream. It is at least 5 bytes, since an empty
+    /// padding frame reads as the EOF marker (see
+    /// [`finish_with_eof_marker`](Writer::finish_with_eof_marker)), so up to
+    /// `alignment + 4` bytes may be added. The underlying writer is flushed
+    /// too. Returns the total number of bytes written, a multiple of
+    /// `alignment`. Nothing is written while the stream is still empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::Writer;
+    /// use std::io::Write;
+    ///
+    /// let mut writer = Writer::new(Vec::new());
+    /// writer.write_all(b"record one").unwrap();
+    /// let end = writer.flush_aligned(4096).unwrap();
+    /// assert_eq!(end, 4096);
+    /// assert_eq!(writer.get_ref().len(), 4096);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `ErrorKind::InvalidInput` if `alignment` is not > 1 and
+    /// <= 4MB, or if the stream was already finished.
+    pub fn flush_aligned(&mut self, alignment: usize) -> io::Result<u64> {
+        if alignment <= 1 || alignment > MAX_BLOCK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("alignment ({}) must be > 1 and <= 4MB", alignment),
+            ));
+        }
+        if self.closed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stream already finished",
+            ));
+        }
+        self.flush_block()?;
+        let mut padding_needed = Self::calc_skippable_frame(self.total_written, alignment as u64);
+        if padding_needed == CHUNK_HEADER_SIZE {
+            // Empty, it would mark a stream cut off here as complete
+            padding_needed += alignment;
+        }
+        self.write_skippable_frame(padding_needed)?;
+        self.writer.flush()?;
+        Ok(self.total_written)
+    }
+
+    /// Write the trailers once, optionally with an EOF marker
+    fn finish(&mut self, eof_marker: bool) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.flush_block()?;
+        self.apply_size()?;
+        self.apply_index()?;
+        if eof_marker {
+            self.write_header()?;
+            self.writer.write_all(&[CHUNK_TYPE_PADDING, 0, 0, 0])?;
+            self.total_written += CHUNK_HEADER_SIZE as u64;
+        }
+        self.apply_padding()?;
+        self.writer.flush()
+    }
+
+    /// Buffer as much of `buf` as fits in the current block
+    ///
+    /// Unlike [`Write::write`], which keeps compressing and writing blocks
+    /// until all of `buf` is consumed, this does a bounded amount of work
+    /// per call: if the current block is already full it is compressed and
+    /// written first (the only write to the underlying writer), then up to
+    /// one block's worth of `buf` is buffered. Returns the number of bytes
+    /// consumed, which is less than `buf.len()` once the block fills up.
+    ///
+    /// A block filled by this call is written by the next call (or by
+    /// [`flush`](Write::flush)), so an event loop can decide when to pay
+    /// for it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::Writer;
+    ///
+    /// let data = vec![b'x'; 100_000];
+    /// let mut compressed = Vec::new();
+    /// let mut writer = Writer::with_block_size(&mut compressed, 64 * 1024);
+    /// let mut rest = &data[..];
+    /// while !rest.is_empty() {
+    ///     let n = writer.write_some(rest).unwrap();
+    ///     rest = &rest[n..];
+    /// }
+    /// ```
+    pub fn write_some(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() == self.block_size {
+            self.flush_block()?;
+        }
+        let n = buf.len().min(self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Take the index built so far instead of writing it into the stream
+    ///
+    /// Flushes the buffered data as a block, then returns the index with its
+    /// totals set, for storing separately (e.g. in a database) and loading
+    /// with [`Index::load`] or using directly. Index tracking stops here,
+    /// so the finished stream carries no index. Returns `None` if the
+    /// writer wasn't tracking an index; call it once all data is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::Writer;
+    /// use std::io::Write;
+    ///
+    /// let mut compressed = Vec::new();
+    /// let mut writer = Writer::with_index(&mut compressed);
+    /// writer.write_all(&vec![7u8; 3 << 20]).unwrap();
+    /// let index = writer.take_index().unwrap().unwrap();
+    /// assert_eq!(index.total_uncompressed, 3 << 20);
+    /// ```
+    pub fn take_index(&mut self) -> io::Result<Option<Index>> {
+        self.flush_block()?;
+        Ok(self.index.take().map(|mut index| {
+            index.total_uncompressed = self.uncompressed_total as i64;
+            index.total_compressed = self.total_written as i64;
+            index
+        }))
+    }
+
+    /// Compression ratio of the stream so far: bytes written to the
+    /// underlying writer divided by the uncompressed bytes they hold
+    ///
+    /// Can be polled mid-stream, e.g. for progress reporting. Only flushed
+    /// blocks are counted, with the stream identifier and any metadata
+    /// chunks written so far, so early values run a little high. Returns
+    /// `None` until the first block has been flushed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minlz::Writer;
+    /// use std::io::Write;
+    ///
+    /// let mut writer = Writer::with_block_size(Vec::new(), 64 * 1024);
+    /// writer.write_all(&[b'a'; 1000]).unwrap();
+    /// assert_eq!(writer.current_ratio(), None); // still buffered
+    /// writer.write_all(&[b'a'; 100_000]).unwrap();
+    /// assert!(writer.current_ratio().unwrap() < 0.01);
+    /// ```
+    pub fn current_ratio(&self) -> Option<f64> {
+        if self.uncompressed_total == 0 {
+            return None;
+        }
+        Some(self.total_written as f64 / self.uncompressed_total as f64)
+    }
+
+    /// Number of uncompressed bytes buffered for the next block
+    pub(crate) fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Flush the buffered data, then write a skippable chunk of type
+    /// `chunk_type` holding `body`
+    pub(crate) fn write_skippable_chunk(&mut self, chunk_type: u8, body: &[u8]) -> io::Result<()> {
+        debug_assert!((0x80..=0xfd).contains(&chunk_type));
+        if body.len() > MAX_CHUNK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("chunk body too large ({} bytes)", body.len()),
+            ));
+        }
+        self.flush_block()?;
+        self.write_header()?;
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + body.len());
+        chunk.push(chunk_type);
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes()[..3]);
+        chunk.extend_from_slice(body);
+        self.writer.write_all(&chunk)?;
+        self.total_written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Block size used to split the input
+    pub(crate) fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Total bytes written to the underlying writer so far
+    pub(crate) fn compressed_written(&self) -> u64 {
+        self.total_written
+    }
+
     /// Get a reference to the underlying writer
     pub fn get_ref(&self) -> &W {
         &self.writer
@@ -405,6 +894,97 @@ impl<W: Write> Writer<W> {
     }
 }
 
+/// Compress everything from `src` into `dst` as an S2 stream
+///
+/// Reads `src` to the end one block (1MB) at a time, so memory stays
+/// bounded regardless of input size, encoding each block at `level`. The
+/// stream is finished and `dst` flushed before returning; at
+/// [`Level::Fast`] the output is identical to a [`Writer::new`] stream.
+/// Errors from either side are returned as-is, and `dst` then holds a
+/// partial stream.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{encode_reader, Level, Reader};
+/// use std::io::Read;
+///
+/// let data = vec![b'a'; 3 << 20];
+/// let mut compressed = Vec::new();
+/// encode_reader(&mut &data[..], &mut compressed, Level::Better).unwrap();
+///
+/// let mut out = Vec::new();
+/// Reader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+/// assert_eq!(out, data);
+/// ```
+pub fn encode_reader<R: Read, W: Write>(src: &mut R, dst: &mut W, level: Level) -> io::Result<()> {
+    let mut writer = Writer::new(dst);
+    writer.level = level;
+    io::copy(src, &mut writer)?;
+    writer.close()
+}
+
+/// Compress `src` in memory as an S2 stream of `block_size` blocks
+///
+/// [`encode`](crate::encode) writes a single block, whose length header
+/// limits it to inputs under 4GB. This splits `src` into blocks instead,
+/// so there is no limit on the input size, and frames them as a stream
+/// that [`Reader`](crate::Reader) decodes. The block size is clamped to
+/// 4KB..=4MB as with [`Writer::with_block_size`], and the output is
+/// identical to that writer's, except that empty input still gives a
+/// stream identifier.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{encode_chunked, Reader};
+/// use std::io::Read;
+///
+/// let data = b"split into blocks ".repeat(10_000);
+/// let compressed = encode_chunked(&data, 64 * 1024);
+///
+/// let mut out = Vec::new();
+/// Reader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+/// assert_eq!(out, data);
+/// ```
+pub fn encode_chunked(src: &[u8], block_size: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut writer = Writer::with_block_size(&mut out, block_size);
+    // The header makes empty input a valid, empty stream
+    writer
+        .write_header()
+        .and_then(|()| writer.write_all(src))
+        .and_then(|()| writer.close())
+        .expect("writing to a Vec cannot fail");
+    drop(writer);
+    out
+}
+
+/// Pick a block size suited to an input of `input_len` bytes
+///
+/// Known sizes are rounded up to a power of two and clamped to 4KB..=4MB,
+/// so small inputs don't allocate a block buffer far larger than the data
+/// while large ones get the biggest blocks. For unknown sizes (streaming
+/// input) this returns the [`Writer::new`] default of 1MB.
+///
+/// # Example
+///
+/// ```
+/// use minlz::recommended_block_size;
+///
+/// assert_eq!(recommended_block_size(Some(10_000)), 16 << 10);
+/// assert_eq!(recommended_block_size(Some(1 << 30)), 4 << 20);
+/// assert_eq!(recommended_block_size(None), 1 << 20);
+/// ```
+pub fn recommended_block_size(input_len: Option<u64>) -> usize {
+    match input_len {
+        Some(len) => (len.min(MAX_BLOCK_SIZE as u64) as usize)
+            .next_power_of_two()
+            .clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE),
+        None => DEFAULT_BLOCK_SIZE,
+    }
+}
+
 impl<W: Write> Write for Writer<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let mut written = 0;
@@ -436,12 +1016,138 @@ impl<W: Write> Write for Writer<W> {
 
 impl<W: Write> Drop for Writer<W> {
     fn drop(&mut self) {
-        // Flush any remaining data
-        let _ = self.flush();
-        // Apply index if configured (must be before padding)
-        let _ = self.apply_index();
-        // Apply padding if configured
-        let _ = self.apply_padding();
+        // Flush any remaining data, then the trailers
+        let _ = self.close();
+    }
+}
+
+/// Pool of block buffers shared by short-lived [`Writer`]s
+///
+/// A server compressing many small responses would otherwise allocate a
+/// fresh block buffer for every `Writer` it creates. Writers taken from
+/// the pool start with a recycled buffer and hand it back when they are
+/// finished or dropped. The pool can be shared between threads; it keeps
+/// as many buffers as were ever in use at the same time.
+///
+/// # Example
+///
+/// ```
+/// use minlz::{Reader, WriterPool};
+/// use std::io::{Read, Write};
+///
+/// let pool = WriterPool::new();
+/// for response in [&b"first"[..], b"second"] {
+///     let mut compressed = Vec::new();
+///     let mut writer = pool.get(&mut compressed);
+///     writer.write_all(response).unwrap();
+///     writer.finish().unwrap();
+///
+///     let mut out = Vec::new();
+///     Reader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+///     assert_eq!(out, response);
+/// }
+/// ```
+pub struct WriterPool {
+    block_size: usize,
+    bufs: Mutex<Vec<Vec<u8>>>,
+}
+
+impl WriterPool {
+    /// Create a pool of writers with the default block size (1MB)
+    pub fn new() -> Self {
+        Self::with_block_size(DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create a pool of writers with a specific block size
+    ///
+    /// Block size is clamped to 4KB..=4MB, as with
+    /// [`Writer::with_block_size`].
+    pub fn with_block_size(block_size: usize) -> Self {
+        WriterPool {
+            block_size: block_size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE),
+            bufs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Create a writer on `writer` that reuses a pooled buffer
+    pub fn get<W: Write>(&self, writer: W) -> PooledWriter<'_, W> {
+        let mut w = Writer::with_block_size(writer, self.block_size);
+        if let Some(buf) = self.bufs.lock().unwrap_or_else(|e| e.into_inner()).pop() {
+            w.buf = buf;
+        }
+        PooledWriter {
+            writer: w,
+            pool: self,
+        }
+    }
+
+    /// Number of buffers waiting to be reused
+    pub fn idle(&self) -> usize {
+        self.bufs.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn put(&self, mut buf: Vec<u8>) {
+        buf.clear();
+        self.bufs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(buf);
+    }
+}
+
+impl Default for WriterPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`Writer`] taken from a [`WriterPool`]
+///
+/// Dereferences to the `Writer`. Its buffer goes back to the pool when it
+/// is finished or dropped.
+pub struct PooledWriter<'a, W: Write> {
+    writer: Writer<W>,
+    pool: &'a WriterPool,
+}
+
+impl<W: Write> PooledWriter<'_, W> {
+    /// Finish the stream, reporting errors, and return the buffer to the
+    /// pool
+    ///
+    /// Dropping the writer does the same on a best-effort basis.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.close()
+    }
+}
+
+impl<W: Write> Deref for PooledWriter<'_, W> {
+    type Target = Writer<W>;
+
+    fn deref(&self) -> &Writer<W> {
+        &self.writer
+    }
+}
+
+impl<W: Write> DerefMut for PooledWriter<'_, W> {
+    fn deref_mut(&mut self) -> &mut Writer<W> {
+        &mut self.writer
+    }
+}
+
+impl<W: Write> Write for PooledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for PooledWriter<'_, W> {
+    fn drop(&mut self) {
+        let _ = self.writer.close();
+        self.pool.put(std::mem::take(&mut self.writer.buf));
     }
 }
 
@@ -556,6 +1262,79 @@ mod tests {
         assert_eq!(decompressed, data);
     }
 
+    #[test]
+    fn test_writer_checkpoint() {
+        use crate::{Index, Reader};
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..3_000_000u32)
+            .map(|i| ((i / 11) % 173) as u8 ^ (i >> 18) as u8)
+            .collect();
+        let (first, second) = data.split_at(1_700_000);
+        let load_index = |stream: &[u8]| {
+            let trailer = stream.len() - 10;
+            let len = u32::from_le_bytes(stream[trailer..trailer + 4].try_into().unwrap());
+            let mut index = Index::new();
+            index.load(&stream[stream.len() - len as usize..]).unwrap();
+            index
+        };
+
+        let mut compressed = Vec::new();
+        let checkpoint_len;
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 256 * 1024);
+            writer.write_all(first).unwrap();
+            writer.checkpoint().unwrap();
+            checkpoint_len = writer.compressed_written() as usize;
+            writer.write_all(second).unwrap();
+        }
+
+        // The output cut at the checkpoint is a complete, indexed stream
+        let head = &compressed[..checkpoint_len];
+        let mut decompressed = Vec::new();
+        Reader::new(head).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, first);
+        assert_eq!(load_index(head).total_uncompressed, first.len() as i64);
+
+        // Continuing after it gives the whole content and a full index
+        let mut decompressed = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+        let index = load_index(&compressed);
+        assert_eq!(index.total_uncompressed, data.len() as i64);
+        let (comp, uncomp) = index.find(2_500_000).unwrap();
+        let mut reader = Reader::with_ignore_stream_id(&compressed[comp as usize..]);
+        let mut skip = vec![0u8; 2_500_000 - uncomp as usize];
+        reader.read_exact(&mut skip).unwrap();
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], data[2_500_000]);
+
+        // Appending with a new writer after a checkpoint
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::new(&mut compressed);
+            writer.write_all(first).unwrap();
+            writer.checkpoint().unwrap();
+            std::mem::forget(writer); // Crash right after the checkpoint
+        }
+        Writer::new(&mut compressed).write_all(second).unwrap();
+        let mut decompressed = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        let mut writer = Writer::new(Vec::new());
+        writer.finish_with_eof_marker().unwrap();
+        assert_eq!(
+            writer.checkpoint().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
     #[test]
     fn test_writer_with_index_basic() {
         // Start with tiny data to debug
@@ -667,4 +1446,536 @@ mod tests {
         reader.read_to_end(&mut decompressed).unwrap();
         assert_eq!(decompressed.len(), data.len());
     }
+
+    /// Count the data chunks in a stream written by Writer
+    fn count_data_chunks(stream: &[u8]) -> usize {
+        let mut pos = MAGIC_CHUNK.len();
+        let mut chunks = 0;
+        while pos < stream.len() {
+            let len = u32::from_le_bytes([stream[pos + 1], stream[pos + 2], stream[pos + 3], 0]);
+            if stream[pos] <= CHUNK_TYPE_UNCOMPRESSED_DATA {
+                chunks += 1;
+            }
+            pos += CHUNK_HEADER_SIZE + len as usize;
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_writer_with_block_size_unclamped() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 100) as u8).collect();
+
+        let mut small = Vec::new();
+        Writer::with_block_size_unclamped(&mut small, 1024)
+            .write_all(&data)
+            .unwrap();
+        assert_eq!(count_data_chunks(&small), 10);
+
+        // The clamping constructor turns 1KB into 4KB blocks.
+        let mut clamped = Vec::new();
+        Writer::with_block_size(&mut clamped, 1024)
+            .write_all(&data)
+            .unwrap();
+        assert_eq!(count_data_chunks(&clamped), 3);
+
+        use crate::Reader;
+        use std::io::Read;
+        let mut decompressed = Vec::new();
+        Reader::new(&small[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    #[should_panic(expected = "block_size must be >= 512 and <= 4MB")]
+    fn test_writer_with_block_size_unclamped_too_small() {
+        let _ = Writer::with_block_size_unclamped(Vec::new(), 256);
+    }
+
+    #[test]
+    fn test_writer_write_some() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 100) as u8).collect();
+        let block_size = 4096;
+
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut compressed, block_size);
+            let mut rest = &data[..];
+            let mut blocks_written = 0;
+            while !rest.is_empty() {
+                let was_full = writer.buffered() == block_size;
+                let before = writer.compressed_written();
+                // Odd-sized slices so calls straddle block boundaries.
+                let n = writer.write_some(&rest[..rest.len().min(3000)]).unwrap();
+                assert!(n > 0 && n <= block_size);
+                rest = &rest[n..];
+
+                // At most one block reaches the underlying writer per call.
+                if writer.compressed_written() != before {
+                    assert!(was_full);
+                    blocks_written += 1;
+                }
+            }
+            assert_eq!(blocks_written, data.len() / block_size);
+        }
+
+        use crate::Reader;
+        use std::io::Read;
+        let mut decompressed = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn test_recommended_block_size() {
+        for (len, want) in [
+            (Some(0), MIN_BLOCK_SIZE),
+            (Some(1), MIN_BLOCK_SIZE),
+            (Some(4096), 4096),
+            (Some(4097), 8192),
+            (Some(10_000), 16 << 10),
+            (Some(1 << 20), 1 << 20),
+            (Some((1 << 20) + 1), 2 << 20),
+            (Some(MAX_BLOCK_SIZE as u64), MAX_BLOCK_SIZE),
+            (Some(u64::MAX), MAX_BLOCK_SIZE),
+            (None, DEFAULT_BLOCK_SIZE),
+        ] {
+            assert_eq!(recommended_block_size(len), want, "input_len {:?}", len);
+        }
+    }
+
+    #[test]
+    fn test_encode_reader() {
+        let data: Vec<u8> = (0..5_000_000u32)
+            .map(|i| ((i / 7) % 251) as u8 ^ (i % 3) as u8)
+            .collect();
+
+        let mut expected = Vec::new();
+        Writer::new(&mut expected).write_all(&data).unwrap();
+
+        let mut fast = Vec::new();
+        encode_reader(&mut &data[..], &mut fast, Level::Fast).unwrap();
+        assert_eq!(fast, expected);
+
+        use crate::Reader;
+        use std::io::Read;
+        for level in [Level::Better, Level::Best] {
+            let mut compressed = Vec::new();
+            encode_reader(&mut &data[..], &mut compressed, level).unwrap();
+            assert!(compressed.len() <= fast.len());
+
+            let mut decompressed = Vec::new();
+            Reader::new(&compressed[..])
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn test_writer_set_dict() {
+        use crate::dict::make_dict;
+        use crate::Reader;
+        use std::io::Read;
+
+        let dict_data = b"A sample record with common fields: id=, name=, status=active";
+        let data: Vec<u8> = (0..500u32)
+            .flat_map(|i| format!("id={} name=user{} status=active\n", i, i).into_bytes())
+            .collect();
+
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 4096);
+            writer.set_dict(make_dict(dict_data, None).unwrap());
+            writer.write_all(&data).unwrap();
+        }
+
+        let mut decompressed = Vec::new();
+        Reader::with_dict(&compressed[..], make_dict(dict_data, None).unwrap())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn test_writer_dict_blocks_independent() {
+        use crate::dict::make_dict;
+        use crate::{decode_with_dict, stream_checksum};
+
+        let dict_data = b"record id=00000 name=someone status=active flags=0x00\n".repeat(4);
+        let data: Vec<u8> = (0..1000u32)
+            .flat_map(|i| {
+                format!(
+                    "record id={:05} name=someone status=active flags=0x0{}\n",
+                    i,
+                    i % 3
+                )
+                .into_bytes()
+            })
+            .collect();
+
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_block_size_unclamped(&mut compressed, 1024);
+            writer.set_dict(make_dict(&dict_data, Some(b"record id=")).unwrap());
+            writer.write_all(&data).unwrap();
+        }
+
+        // Every block decodes on its own, starting from the dictionary's
+        // repeat offset
+        let dict = make_dict(&dict_data, Some(b"record id=")).unwrap();
+        let mut pos = MAGIC_CHUNK.len();
+        let mut out = Vec::new();
+        while pos < compressed.len() {
+            let len = u32::from_le_bytes([
+                compressed[pos + 1],
+                compressed[pos + 2],
+                compressed[pos + 3],
+                0,
+            ]) as usize;
+            let chunk = &compressed[pos + CHUNK_HEADER_SIZE..pos + CHUNK_HEADER_SIZE + len];
+            assert_eq!(compressed[pos], CHUNK_TYPE_COMPRESSED_DATA);
+            let block = decode_with_dict(&chunk[4..], &dict).unwrap();
+            assert_eq!(
+                u32::from_le_bytes(chunk[..4].try_into().unwrap()),
+                stream_checksum(&block)
+            );
+            out.extend_from_slice(&block);
+            pos += CHUNK_HEADER_SIZE + len;
+        }
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    #[should_panic(expected = "before any block is written")]
+    fn test_writer_set_dict_after_write() {
+        let mut writer = Writer::with_block_size(Vec::new(), 4096);
+        writer.write_all(&[0u8; 8192]).unwrap();
+        writer.flush().unwrap();
+        writer.set_dict(crate::dict::make_dict(&[1u8; 64], None).unwrap());
+    }
+
+    #[test]
+    fn test_writer_with_level() {
+        use crate::{stream_decoded_len, Reader};
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 97) as u8).collect();
+        for level in [Level::Fast, Level::Better, Level::Best] {
+            let mut compressed = Vec::new();
+            Writer::with_level(&mut compressed, level)
+                .write_all(&data)
+                .unwrap();
+
+            let mut reader = Reader::new(&compressed[..]);
+            assert_eq!(reader.level(), None);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+            assert_eq!(reader.level(), Some(level));
+
+            // The level chunk is an ordinary skippable chunk; without it the
+            // stream is exactly what a plain writer at that level produces.
+            let start = MAGIC_CHUNK.len();
+            let chunk_len = CHUNK_HEADER_SIZE + LEVEL_MAGIC.len() + 1;
+            assert!((0x80..=0xfd).contains(&compressed[start]));
+            assert_eq!(stream_decoded_len(&compressed[..]).unwrap(), 20_000);
+
+            let mut plain = Vec::new();
+            encode_reader(&mut &data[..], &mut plain, level).unwrap();
+            let mut stripped = compressed[..start].to_vec();
+            stripped.extend_from_slice(&compressed[start + chunk_len..]);
+            assert_eq!(stripped, plain);
+
+            let mut out = Vec::new();
+            let mut reader = Reader::new(&plain[..]);
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(reader.level(), None);
+        }
+    }
+
+    #[test]
+    fn test_writer_pool() {
+        use crate::Reader;
+        use std::io::Read;
+
+        let pool = WriterPool::with_block_size(8192);
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 113) as u8).collect();
+
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        {
+            let mut a = pool.get(&mut first);
+            let mut b = pool.get(&mut second);
+            a.write_all(&data).unwrap();
+            b.write_all(&data[..100]).unwrap();
+            assert_eq!(a.block_size(), 8192);
+            a.finish().unwrap();
+            // b is finished by drop.
+        }
+        assert_eq!(pool.idle(), 2);
+
+        for (compressed, want) in [(&first, &data[..]), (&second, &data[..100])] {
+            let mut out = Vec::new();
+            Reader::new(&compressed[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, want);
+        }
+
+        // The next writer reuses a buffer and produces the same stream as
+        // a fresh one.
+        let mut again = Vec::new();
+        {
+            let mut w = pool.get(&mut again);
+            assert_eq!(pool.idle(), 1);
+            assert!(w.buf.capacity() > 0);
+            assert!(w.buf.is_empty());
+            w.write_all(&data).unwrap();
+        }
+        assert_eq!(again, first);
+        assert_eq!(pool.idle(), 2);
+    }
+
+    #[test]
+    fn test_writer_current_ratio() {
+        let mut writer = Writer::with_block_size(Vec::new(), 64 * 1024);
+        assert_eq!(writer.current_ratio(), None);
+        let text = b"the same line, again and again and again\n".repeat(5000);
+        writer.write_all(&text[..10_000]).unwrap();
+        assert_eq!(writer.current_ratio(), None);
+
+        writer.write_all(&text[10_000..]).unwrap();
+        let ratio = writer.current_ratio().unwrap();
+        assert!(ratio < 0.05, "{}", ratio);
+
+        // Incompressible data pulls it towards 1
+        let mut seed = 9u32;
+        let noise: Vec<u8> = (0..1_000_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        writer.write_all(&noise).unwrap();
+        writer.flush().unwrap();
+        let ratio = writer.current_ratio().unwrap();
+        assert!(ratio > 0.8 && ratio < 1.0, "{}", ratio);
+        assert_eq!(
+            ratio,
+            writer.get_ref().len() as f64 / (text.len() + noise.len()) as f64
+        );
+    }
+
+    #[test]
+    fn test_writer_with_no_header() {
+        use crate::Reader;
+        use std::io::Read;
+
+        let text = b"the same line, again and again and again\n".repeat(500);
+        let mut compressed = Vec::new();
+        {
+            let mut writer = Writer::with_no_header(&mut compressed);
+            writer.write_all(&text).unwrap();
+        }
+        assert!(!compressed.starts_with(MAGIC_CHUNK));
+
+        let mut out = Vec::new();
+        Reader::with_ignore_stream_id(&compressed[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, text);
+
+        out.clear();
+        assert!(Reader::new(&compressed[..]).read_to_end(&mut out).is_err());
+
+        // Padding counts only the bytes actually written
+        let mut padded = Vec::new();
+        {
+            let mut writer = Writer::with_padding(&mut padded, 1024);
+            writer.no_header = true;
+            writer.write_all(&text).unwrap();
+        }
+        assert_eq!(padded.len() % 1024, 0);
+        out.clear();
+        Reader::with_ignore_stream_id(&padded[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, text);
+    }
+
+    #[test]
+    fn test_writer_index_to_pipe() {
+        use crate::Index;
+
+        /// Write-only sink that records how much it had received
+        struct Pipe {
+            data: Vec<u8>,
+        }
+        impl Write for Pipe {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.data.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data: Vec<u8> = (0..8_000_000u32)
+            .map(|i| ((i / 13) % 191) as u8 ^ (i >> 20) as u8)
+            .collect();
+        for padding in [0, 4096] {
+            let mut writer = Writer::with_index_and_block_size(Pipe { data: Vec::new() }, 1 << 20);
+            if padding > 0 {
+                writer.set_padding(padding);
+            }
+            let (first, second) = data.split_at(data.len() / 2);
+            writer.write_all(first).unwrap();
+            // Blocks are passed on as they fill, not held for the index
+            let streamed = writer.get_ref().data.len();
+            assert!(streamed > 0);
+            writer.write_all(second).unwrap();
+            writer.close().unwrap();
+            let stream = std::mem::take(&mut writer.get_mut().data);
+            if padding > 0 {
+                assert_eq!(stream.len() % padding, 0);
+            }
+
+            // The index is the last chunk, before any padding
+            let mut end = stream.len();
+            if padding > 0 {
+                let mut pos = 0;
+                while stream[pos] != CHUNK_TYPE_PADDING {
+                    let len =
+                        u32::from_le_bytes([stream[pos + 1], stream[pos + 2], stream[pos + 3], 0]);
+                    pos += CHUNK_HEADER_SIZE + len as usize;
+                }
+                end = pos;
+            }
+            let len = u32::from_le_bytes(stream[end - 10..end - 6].try_into().unwrap());
+            let mut index = Index::new();
+            index.load(&stream[end - len as usize..end]).unwrap();
+            assert_eq!(index.total_uncompressed, data.len() as i64);
+            assert!(streamed < end);
+        }
+    }
+
+    #[test]
+    fn test_writer_take_index() {
+        use crate::index::S2_INDEX_TRAILER;
+        use crate::Reader;
+        use std::io::Read;
+
+        let data: Vec<u8> = (0..5_000_000u32)
+            .map(|i| ((i / 7) % 211) as u8 ^ (i >> 19) as u8)
+            .collect();
+        let mut compressed = Vec::new();
+        let index = {
+            let mut writer = Writer::with_index_and_block_size(&mut compressed, 256 * 1024);
+            writer.write_all(&data).unwrap();
+            writer.take_index().unwrap().unwrap()
+        };
+        assert!(!compressed.ends_with(S2_INDEX_TRAILER));
+        assert_eq!(index.total_uncompressed, data.len() as i64);
+        assert_eq!(index.total_compressed, compressed.len() as i64);
+
+        for offset in [0, 1_234_567, 4_999_999] {
+            let (comp, uncomp) = index.find(offset as i64).unwrap();
+            let mut reader = Reader::with_ignore_stream_id(&compressed[comp as usize..]);
+            let mut skip = vec![0u8; offset - uncomp as usize];
+            reader.read_exact(&mut skip).unwrap();
+            let mut byte = [0u8];
+            reader.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], data[offset], "{}", offset);
+        }
+
+        assert!(Writer::new(Vec::new()).take_index().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_writer_flush_aligned() {
+        use crate::Reader;
+        use std::io::Read;
+
+        let mut writer = Writer::with_index_and_block_size(Vec::new(), 64 * 1024);
+        assert_eq!(writer.flush_aligned(4096).unwrap(), 0);
+
+        let mut data = Vec::new();
+        for (i, len) in [100, 4093, 70_000, 1].into_iter().enumerate() {
+            let chunk: Vec<u8> = (0..len).map(|j| (j * 31 + i) as u8).collect();
+            writer.write_all(&chunk).unwrap();
+            data.extend_from_slice(&chunk);
+            let end = writer.flush_aligned(4096).unwrap();
+            assert_eq!(end % 4096, 0);
+            assert_eq!(end, writer.get_ref().len() as u64);
+            // Already aligned: nothing more to add
+            assert_eq!(writer.flush_aligned(4096).unwrap(), end);
+        }
+        assert_eq!(
+            writer.flush_aligned(1).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        writer.close().unwrap();
+
+        let mut out = Vec::new();
+        Reader::new(&writer.get_ref()[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+
+        // Padding that would fit a bare frame header gets a body, so a
+        // stream cut off after it doesn't look finished
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"record one").unwrap();
+        writer.flush().unwrap();
+        let alignment = writer.get_ref().len() + CHUNK_HEADER_SIZE;
+        let end = writer.flush_aligned(alignment).unwrap();
+        assert_eq!(end, 2 * alignment as u64);
+        let mut reader = Reader::new(&writer.get_ref()[..]);
+        reader.expect_eof_marker();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_encode_chunked() {
+        use crate::Reader;
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i / 3 % 251) as u8).collect();
+
+        let compressed = encode_chunked(&data, 4096);
+        let mut stream = Vec::new();
+        {
+            let mut writer = Writer::with_block_size(&mut stream, 4096);
+            writer.write_all(&data).unwrap();
+        }
+        assert_eq!(compressed, stream);
+
+        let mut out = Vec::new();
+        let mut reader = Reader::new(&compressed[..]);
+        let mut blocks = 0;
+        while let Some(block) = reader.read_block().unwrap() {
+            assert!(block.len() <= 4096);
+            out.extend_from_slice(&block);
+            blocks += 1;
+        }
+        assert_eq!(out, data);
+        assert_eq!(blocks, data.len().div_ceil(4096));
+
+        // Block sizes are clamped as with Writer::with_block_size
+        assert_eq!(encode_chunked(&data, 1), compressed);
+        assert_eq!(
+            encode_chunked(&data, usize::MAX),
+            encode_chunked(&data, 4 << 20)
+        );
+
+        // Empty input is a bare stream identifier
+        let empty = encode_chunked(&[], 4096);
+        assert_eq!(empty, MAGIC_CHUNK);
+        let mut out = Vec::new();
+        Reader::new(&empty[..]).read_to_end(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
 }
@@ -1,7 +1,7 @@
 // Copyright 2024 Karpeles Lab Inc.
 // Property-based tests using proptest
 
-use minlz::{decode, encode, encode_best, encode_better, Reader, Writer};
+use minlz::{analyze_block, decode, encode, encode_best, encode_better, Reader, Writer};
 use proptest::prelude::*;
 use std::io::{Read, Write as _};
 
@@ -136,4 +136,27 @@ proptest! {
 
         prop_assert_eq!(data, decompressed);
     }
+
+    #[test]
+    fn prop_no_zero_offset_copies(
+        seed in prop::collection::vec(any::<u8>(), 1..64),
+        repeats in 1usize..200,
+        noise in prop::collection::vec(any::<u8>(), 0..256),
+    ) {
+        // Mix repetitive and random data so every encoder emits copies.
+        let mut data = seed.repeat(repeats);
+        data.extend_from_slice(&noise);
+        data.extend_from_slice(&seed.repeat(repeats / 2 + 1));
+
+        for (name, block) in [
+            ("standard", encode(&data)),
+            ("better", encode_better(&data)),
+            ("best", encode_best(&data)),
+        ] {
+            // analyze_block rejects copies with a zero offset as corrupt
+            let analysis = analyze_block(&block);
+            prop_assert!(analysis.is_ok(), "{} emitted an invalid copy: {:?}", name, analysis);
+            prop_assert_eq!(&decode(&block).expect("decode failed"), &data);
+        }
+    }
 }
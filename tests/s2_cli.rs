@@ -0,0 +1,251 @@
+// Copyright 2024 Karpeles Lab Inc.
+// Integration tests for the s2c/s2d CLI tools.
+//
+// The `CARGO_BIN_EXE_*` env vars only exist when the binaries are built, which
+// requires the `cli` feature; gate the whole test on it.
+#![cfg(feature = "cli")]
+
+use minlz::{make_dict, Reader};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+fn pipe(exe: &str, args: &[&str], input: &[u8]) -> (Vec<u8>, bool) {
+    let mut child = Command::new(exe)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input)
+        .expect("write stdin");
+    let out = child.wait_with_output().expect("wait");
+    (out.stdout, out.status.success())
+}
+
+fn s2c(args: &[&str], input: &[u8]) -> (Vec<u8>, bool) {
+    pipe(env!("CARGO_BIN_EXE_s2c"), args, input)
+}
+fn s2d(args: &[&str], input: &[u8]) -> (Vec<u8>, bool) {
+    pipe(env!("CARGO_BIN_EXE_s2d"), args, input)
+}
+
+/// Write `data` to a file unique to this test, returning its path
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("s2_cli_{}_{}", std::process::id(), name));
+    std::fs::write(&path, data).expect("write temp file");
+    path
+}
+
+const DICT_SOURCE: &[u8] =
+    b"{\"level\":\"info\",\"service\":\"api\",\"message\":\"request handled\"}";
+
+fn records() -> Vec<u8> {
+    (0..2000u32)
+        .flat_map(|i| {
+            format!(
+                "{{\"level\":\"info\",\"service\":\"api\",\"message\":\"request handled\",\"id\":{}}}\n",
+                i
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+#[test]
+fn s2c_dict_stream() {
+    let dict_path = temp_file(
+        "stream.dict",
+        &make_dict(DICT_SOURCE, None).unwrap().to_bytes(),
+    );
+    let data = records();
+
+    let (comp, ok) = s2c(&["--dict", dict_path.to_str().unwrap(), "-"], &data);
+    assert!(ok, "s2c --dict failed");
+
+    let dict = make_dict(DICT_SOURCE, None).unwrap();
+    let mut back = Vec::new();
+    Reader::with_dict(&comp[..], dict)
+        .read_to_end(&mut back)
+        .unwrap();
+    assert_eq!(back, data);
+
+    std::fs::remove_file(dict_path).ok();
+}
+
+#[test]
+fn s2c_dict_verify() {
+    let dict_path = temp_file(
+        "verify.dict",
+        &make_dict(DICT_SOURCE, None).unwrap().to_bytes(),
+    );
+    let input = temp_file("verify.txt", &records());
+    let output = input.with_extension("txt.s2");
+    let dict = dict_path.to_str().unwrap();
+
+    // Verification decodes with the dictionary the output was written with
+    for mode in [&[][..], &["--block"][..]] {
+        let mut args = mode.to_vec();
+        args.extend(["--dict", dict, "--verify", "-o"]);
+        args.extend([output.to_str().unwrap(), input.to_str().unwrap()]);
+        let (_, ok) = s2c(&args, b"");
+        assert!(ok, "s2c {:?} --dict --verify failed", mode);
+    }
+
+    for path in [dict_path, input, output] {
+        std::fs::remove_file(path).ok();
+    }
+}
+
+#[test]
+fn s2c_dict_block() {
+    let dict_path = temp_file(
+        "block.dict",
+        &make_dict(DICT_SOURCE, None).unwrap().to_bytes(),
+    );
+    let data = records();
+
+    for level in ["--faster", "--slower"] {
+        let (comp, ok) = s2c(
+            &["--block", level, "--dict", dict_path.to_str().unwrap(), "-"],
+            &data,
+        );
+        assert!(ok, "s2c --block {level} --dict failed");
+        let dict = make_dict(DICT_SOURCE, None).unwrap();
+        assert_eq!(minlz::decode_with_dict(&comp, &dict).unwrap(), data);
+    }
+
+    std::fs::remove_file(dict_path).ok();
+}
+
+#[test]
+fn s2c_rejects_invalid_dict() {
+    let dict_path = temp_file("invalid.dict", b"\x00tiny");
+    let (_, ok) = s2c(&["--dict", dict_path.to_str().unwrap(), "-"], b"data");
+    assert!(!ok);
+    std::fs::remove_file(dict_path).ok();
+}
+
+#[test]
+fn s2d_dict_requires_matching_dict() {
+    let dict_path = temp_file(
+        "match.dict",
+        &make_dict(DICT_SOURCE, None).unwrap().to_bytes(),
+    );
+    let other_path = temp_file(
+        "other.dict",
+        &make_dict(b"an unrelated dictionary of some other text entirely", None)
+            .unwrap()
+            .to_bytes(),
+    );
+    let dict = dict_path.to_str().unwrap();
+    let other = other_path.to_str().unwrap();
+    let data = records();
+
+    // Stream mode: only the matching dictionary decodes; others fail the
+    // CRC check (or the block decode) instead of producing garbage.
+    let (comp, ok) = s2c(&["--dict", dict, "-"], &data);
+    assert!(ok);
+    let (back, ok) = s2d(&["--dict", dict, "-"], &comp);
+    assert!(ok, "s2d --dict failed");
+    assert_eq!(back, data);
+    assert!(!s2d(&["-"], &comp).1);
+    assert!(!s2d(&["--dict", other, "-"], &comp).1);
+
+    // Block mode
+    let (comp, ok) = s2c(&["--block", "--faster", "--dict", dict, "-"], &data);
+    assert!(ok);
+    let (back, ok) = s2d(&["--block", "--dict", dict, "-"], &comp);
+    assert!(ok, "s2d --block --dict failed");
+    assert_eq!(back, data);
+    assert!(!s2d(&["--block", "-"], &comp).1);
+
+    std::fs::remove_file(dict_path).ok();
+    std::fs::remove_file(other_path).ok();
+}
+
+#[test]
+fn s2d_bench_with_dict() {
+    let dict_path = temp_file(
+        "bench.dict",
+        &make_dict(DICT_SOURCE, None).unwrap().to_bytes(),
+    );
+    let dict = dict_path.to_str().unwrap();
+    let (comp, ok) = s2c(&["--dict", dict, "-"], &records());
+    assert!(ok);
+    let comp_path = temp_file("bench.s2", &comp);
+    let input = comp_path.to_str().unwrap();
+
+    // The dictionary is loaded once, before the timed runs
+    assert!(s2d(&["--bench", "3", "--dict", dict, input], b"").1);
+    let missing = format!("{}.missing", dict);
+    assert!(!s2d(&["--bench", "3", "--dict", &missing, input], b"").1);
+
+    std::fs::remove_file(dict_path).ok();
+    std::fs::remove_file(comp_path).ok();
+}
+
+#[test]
+fn s2c_cpu_writes_index() {
+    let data: Vec<u8> = records().repeat(20);
+    assert!(data.len() > 2 << 20);
+    let path = temp_file("cpu_index", &data);
+    let file = path.to_str().unwrap();
+
+    let (serial, ok) = s2c(&["-c", "--blocksize", "256K", file], b"");
+    assert!(ok);
+    let (parallel, ok) = s2c(&["-c", "--blocksize", "256K", "--cpu", "4", file], b"");
+    assert!(ok);
+    assert_eq!(parallel, serial);
+    assert!(parallel.ends_with(b"\x00xdi2s"), "index trailer missing");
+
+    let mut back = Vec::new();
+    Reader::new(&parallel[..]).read_to_end(&mut back).unwrap();
+    assert_eq!(back, data);
+
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn mmap_matches_buffered() {
+    let data: Vec<u8> = records().repeat(40);
+    assert!(data.len() > 4 << 20);
+    let path = temp_file("mmap", &data);
+    let file = path.to_str().unwrap();
+
+    for mode in [&[][..], &["--block"][..], &["--slower"][..]] {
+        let (buffered, ok) = s2c(&[&["-c"][..], mode, &[file]].concat(), b"");
+        assert!(ok, "s2c {mode:?} failed");
+        let (mapped, ok) = s2c(&[&["-c", "--mmap"][..], mode, &[file]].concat(), b"");
+        assert!(ok, "s2c --mmap {mode:?} failed");
+        assert_eq!(mapped, buffered, "s2c {mode:?}");
+
+        let comp_path = temp_file("mmap.s2", &mapped);
+        let comp_file = comp_path.to_str().unwrap();
+        let decode_mode = if mode == ["--block"] { mode } else { &[] };
+        let (back, ok) = s2d(
+            &[&["-c", "--mmap"][..], decode_mode, &[comp_file]].concat(),
+            b"",
+        );
+        assert!(ok, "s2d --mmap {mode:?} failed");
+        assert!(back == data, "s2d --mmap {mode:?} output differs");
+        std::fs::remove_file(comp_path).ok();
+    }
+
+    // An empty file maps to an empty slice
+    let empty = temp_file("mmap_empty", b"");
+    let (buffered, ok) = s2c(&["-c", empty.to_str().unwrap()], b"");
+    assert!(ok);
+    let (mapped, ok) = s2c(&["-c", "--mmap", empty.to_str().unwrap()], b"");
+    assert!(ok);
+    assert_eq!(mapped, buffered);
+
+    std::fs::remove_file(empty).ok();
+    std::fs::remove_file(path).ok();
+}